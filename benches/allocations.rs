@@ -0,0 +1,152 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use criterion::{
+    black_box, criterion_group, criterion_main,
+    measurement::{Measurement, ValueFormatter},
+    BatchSize, Criterion, Throughput,
+};
+use json0_rs::{operation::Operation, Json0};
+use serde_json::{json, Value};
+
+/// Counts the bytes allocated by the process.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size as u64, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Measures the bytes allocated instead of the elapsed time.
+struct Allocated;
+
+impl Measurement for Allocated {
+    type Intermediate = u64;
+    type Value = u64;
+
+    fn start(&self) -> u64 {
+        ALLOCATED.load(Ordering::Relaxed)
+    }
+
+    fn end(&self, start: u64) -> u64 {
+        ALLOCATED.load(Ordering::Relaxed) - start
+    }
+
+    fn add(&self, v1: &u64, v2: &u64) -> u64 {
+        v1 + v2
+    }
+
+    fn zero(&self) -> u64 {
+        0
+    }
+
+    fn to_f64(&self, value: &u64) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        &AllocatedFormatter
+    }
+}
+
+struct AllocatedFormatter;
+
+impl ValueFormatter for AllocatedFormatter {
+    fn scale_values(&self, _typical_value: f64, _values: &mut [f64]) -> &'static str {
+        "B"
+    }
+
+    fn scale_throughputs(
+        &self,
+        _typical_value: f64,
+        throughput: &Throughput,
+        values: &mut [f64],
+    ) -> &'static str {
+        if let Throughput::Elements(n) = throughput {
+            values.iter_mut().for_each(|v| *v /= *n as f64);
+        }
+        "B/elem"
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "B"
+    }
+}
+
+/// A document with a list of n numbers under "list".
+fn large_list(n: usize) -> Value {
+    json!({ "list": (0..n).collect::<Vec<usize>>() })
+}
+
+/// An operation of n components on the list of `large_list`.
+fn list_operation(json0: &Json0, n: usize) -> Operation {
+    let components = (0..n)
+        .map(|i| match i % 3 {
+            0 => json!({"p": ["list", i], "li": i}),
+            1 => json!({"p": ["list", i], "ld": i, "li": -1}),
+            _ => json!({"p": ["list", i], "lm": i + 1}),
+        })
+        .collect::<Vec<Value>>();
+    json0
+        .operation_factory()
+        .from_value(Value::Array(components))
+        .unwrap()
+}
+
+/// An operation of n list inserts on the list of `large_list`.
+fn list_inserts(json0: &Json0, n: usize) -> Operation {
+    let components = (0..n)
+        .map(|i| json!({"p": ["list", i * 7 % 10_000], "li": i}))
+        .collect::<Vec<Value>>();
+    json0
+        .operation_factory()
+        .from_value(Value::Array(components))
+        .unwrap()
+}
+
+fn apply_on_large_list(c: &mut Criterion<Allocated>) {
+    let json0 = Json0::new();
+    let value = large_list(10_000);
+    let op = list_operation(&json0, 1000);
+    c.bench_function("apply 1k components on a 10k list", |bench| {
+        bench.iter_batched(
+            || (value.clone(), vec![op.clone()]),
+            |(mut value, ops)| json0.apply(&mut value, ops).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn transform_on_large_list(c: &mut Criterion<Allocated>) {
+    let json0 = Json0::new();
+    let a = list_inserts(&json0, 1000);
+    let b = list_inserts(&json0, 1000);
+    c.bench_function("transform 1k x 1k components on a list", |bench| {
+        bench.iter(|| json0.transform(black_box(&a), black_box(&b)).unwrap())
+    });
+}
+
+criterion_group!(
+    name = benches;
+    config = Criterion::default().with_measurement(Allocated);
+    targets = apply_on_large_list, transform_on_large_list
+);
+criterion_main!(benches);
@@ -0,0 +1,40 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use json0_rs::{operation::Operation, Json0};
+use serde_json::{json, Value};
+
+/// An operation of n components, each inserting into a list under its own top
+/// level key.
+fn list_inserts(json0: &Json0, prefix: &str, n: usize) -> Operation {
+    let components = (0..n)
+        .map(|i| json!({"p": [format!("{prefix}{}", i % 100), i / 100], "li": i}))
+        .collect::<Vec<Value>>();
+    json0
+        .operation_factory()
+        .from_value(Value::Array(components))
+        .unwrap()
+}
+
+fn transform_disjoint_subtrees(c: &mut Criterion) {
+    let json0 = Json0::new();
+    let a = list_inserts(&json0, "a", 1000);
+    let b = list_inserts(&json0, "b", 1000);
+    c.bench_function("transform 1k x 1k on disjoint subtrees", |bench| {
+        bench.iter(|| json0.transform(black_box(&a), black_box(&b)).unwrap())
+    });
+}
+
+fn transform_shared_subtrees(c: &mut Criterion) {
+    let json0 = Json0::new();
+    let a = list_inserts(&json0, "k", 1000);
+    let b = list_inserts(&json0, "k", 1000);
+    c.bench_function("transform 1k x 1k on shared subtrees", |bench| {
+        bench.iter(|| json0.transform(black_box(&a), black_box(&b)).unwrap())
+    });
+}
+
+criterion_group!(
+    benches,
+    transform_disjoint_subtrees,
+    transform_shared_subtrees
+);
+criterion_main!(benches);
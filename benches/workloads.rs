@@ -0,0 +1,91 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use json0_rs::{
+    operation::Operation,
+    path::Path,
+    testing::{
+        nested_document, random_document, random_history, random_list_operation,
+        random_text_operation, Rng,
+    },
+    Json0,
+};
+use serde_json::json;
+
+fn apply_on_deep_document(c: &mut Criterion) {
+    let json0 = Json0::new();
+    let (value, path) = nested_document(100, 100);
+    let op = random_list_operation(&path, 100, 1000, &mut Rng::new(1));
+    c.bench_function("apply 1k list components 100 levels deep", |bench| {
+        bench.iter_batched(
+            || (value.clone(), vec![op.clone()]),
+            |(mut value, ops)| json0.apply(&mut value, ops).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    c.bench_function("apply_batch 1k list components 100 levels deep", |bench| {
+        bench.iter_batched(
+            || (value.clone(), vec![op.clone()]),
+            |(mut value, ops)| json0.apply_batch(&mut value, ops).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn transform_on_long_list(c: &mut Criterion) {
+    let json0 = Json0::new();
+    let path = Path::try_from(r#"["list"]"#).unwrap();
+    let a = random_list_operation(&path, 100_000, 1000, &mut Rng::new(1));
+    let b = random_list_operation(&path, 100_000, 1000, &mut Rng::new(2));
+    c.bench_function("transform 1k x 1k components on a 100k list", |bench| {
+        bench.iter(|| json0.transform(black_box(&a), black_box(&b)).unwrap())
+    });
+}
+
+fn text_heavy(c: &mut Criterion) {
+    let json0 = Json0::new();
+    let text = "lorem ipsum dolor sit amet ".repeat(1000);
+    let value = json!({ "text": text });
+    let path = Path::try_from(r#"["text"]"#).unwrap();
+    let a = random_text_operation(&path, &text, 1000, &mut Rng::new(1));
+    let b = random_text_operation(&path, &text, 100, &mut Rng::new(2));
+    c.bench_function("apply 1k text components on a 27k text", |bench| {
+        bench.iter_batched(
+            || (value.clone(), vec![a.clone()]),
+            |(mut value, ops)| json0.apply(&mut value, ops).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    c.bench_function("transform 1k x 100 text components", |bench| {
+        bench.iter(|| json0.transform(black_box(&a), black_box(&b)).unwrap())
+    });
+}
+
+fn compose_many(c: &mut Criterion) {
+    let mut rng = Rng::new(1);
+    let doc = random_document(&mut rng);
+    let (history, _) = random_history(&doc, 10_000, &mut rng);
+    // keep operations of 10k components in total
+    let mut components = 0;
+    let history = history
+        .into_iter()
+        .take_while(|op| {
+            components += op.len();
+            components <= 10_000
+        })
+        .collect::<Vec<Operation>>();
+    c.bench_function("compose 10k components", |bench| {
+        bench.iter_batched(
+            || history.clone(),
+            |history| Operation::compose_all(history).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(
+    benches,
+    apply_on_deep_document,
+    transform_on_long_list,
+    text_heavy,
+    compose_many
+);
+criterion_main!(benches);
@@ -0,0 +1,60 @@
+//! Derive macros for json0-rs, enabled by its `derive` feature.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput};
+
+/// Implement `SubTypeFunctions` on top of the `TypedSubTypeFunctions` of the
+/// type, converting operands between `Value` and the typed operand.
+#[proc_macro_derive(SubTypeFunctions)]
+pub fn derive_sub_type_functions(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let expanded = quote! {
+        impl #impl_generics ::json0_rs::SubTypeFunctions for #name #ty_generics #where_clause {
+            fn invert(
+                &self,
+                path: &::json0_rs::path::Path,
+                sub_type_operand: &::json0_rs::__private::serde_json::Value,
+            ) -> ::json0_rs::Result<::json0_rs::__private::serde_json::Value> {
+                ::json0_rs::__private::typed::invert(self, path, sub_type_operand)
+            }
+
+            fn merge(
+                &self,
+                base_operand: &::json0_rs::__private::serde_json::Value,
+                other_operand: &::json0_rs::__private::serde_json::Value,
+            ) -> ::std::option::Option<::json0_rs::__private::serde_json::Value> {
+                ::json0_rs::__private::typed::merge(self, base_operand, other_operand)
+            }
+
+            fn transform(
+                &self,
+                new: &::json0_rs::__private::serde_json::Value,
+                base: &::json0_rs::__private::serde_json::Value,
+                side: ::json0_rs::TransformSide,
+            ) -> ::json0_rs::Result<::std::vec::Vec<::json0_rs::__private::serde_json::Value>> {
+                ::json0_rs::__private::typed::transform(self, new, base, side)
+            }
+
+            fn apply(
+                &self,
+                val: ::std::option::Option<&::json0_rs::__private::serde_json::Value>,
+                sub_type_operand: &::json0_rs::__private::serde_json::Value,
+            ) -> ::json0_rs::__private::typed::ApplyResult<
+                ::std::option::Option<::json0_rs::__private::serde_json::Value>,
+            > {
+                ::json0_rs::__private::typed::apply(self, val, sub_type_operand)
+            }
+
+            fn validate_operand(
+                &self,
+                val: &::json0_rs::__private::serde_json::Value,
+            ) -> ::json0_rs::Result<()> {
+                ::json0_rs::__private::typed::validate_operand(self, val)
+            }
+        }
+    };
+    expanded.into()
+}
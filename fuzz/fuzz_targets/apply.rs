@@ -0,0 +1,29 @@
+#![no_main]
+
+use json0_rs::{ApplyOptions, Json0};
+use libfuzzer_sys::fuzz_target;
+use serde_json::Value;
+
+// Input is a JSON array of a document and an operation. Applying any valid
+// operation either fails or changes the document, and undoing the change
+// restores it.
+fuzz_target!(|data: &[u8]| {
+    let Ok(Value::Array(input)) = serde_json::from_slice::<Value>(data) else {
+        return;
+    };
+    let [doc, op] = <[Value; 2]>::try_from(input).unwrap_or_default();
+    let json0 = Json0::new();
+    let Ok(operation) = json0.operation_factory().from_value(op) else {
+        return;
+    };
+    let mut value = doc.clone();
+    let options = ApplyOptions::new().atomic(true);
+    let Ok(undo) = json0.apply_with(&mut value, vec![operation], &options) else {
+        assert_eq!(doc, value, "failed apply changed the document");
+        return;
+    };
+    json0
+        .apply(&mut value, vec![undo])
+        .expect("undo operation applies");
+    assert_eq!(doc, value);
+});
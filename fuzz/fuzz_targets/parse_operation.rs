@@ -0,0 +1,21 @@
+#![no_main]
+
+use json0_rs::operation::OperationFactory;
+use libfuzzer_sys::fuzz_target;
+use serde_json::Value;
+
+// Any JSON either fails to parse or parses to an operation which is written
+// back to an equal operation.
+fuzz_target!(|data: &[u8]| {
+    let Ok(value) = serde_json::from_slice::<Value>(data) else {
+        return;
+    };
+    let factory = OperationFactory::default();
+    let Ok(operation) = factory.from_value(value) else {
+        return;
+    };
+    let reparsed = factory
+        .from_value(operation.to_value())
+        .expect("written operation parses");
+    assert_eq!(operation, reparsed);
+});
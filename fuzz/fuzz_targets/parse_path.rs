@@ -0,0 +1,15 @@
+#![no_main]
+
+use json0_rs::path::Path;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(input) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(path) = Path::try_from(input) else {
+        return;
+    };
+    let reparsed = Path::try_from(&path.to_value()).expect("written path parses");
+    assert_eq!(path, reparsed);
+});
@@ -0,0 +1,32 @@
+#![no_main]
+
+use json0_rs::Json0;
+use libfuzzer_sys::fuzz_target;
+use serde_json::{json, Value};
+
+// Input is a JSON array of a text, an offset and a text to delete. Deleting
+// either fails or removes exactly the text at the offset.
+fuzz_target!(|data: &[u8]| {
+    let Ok(Value::Array(input)) = serde_json::from_slice::<Value>(data) else {
+        return;
+    };
+    let [Value::String(text), Value::Number(p), Value::String(d)] = input.as_slice() else {
+        return;
+    };
+    let Some(p) = p.as_u64().map(|p| p as usize) else {
+        return;
+    };
+    let json0 = Json0::new();
+    let op = json!([{"p": ["t"], "t": "text", "o": {"p": p, "d": d}}]);
+    let Ok(operation) = json0.operation_factory().from_value(op) else {
+        return;
+    };
+    let mut value = json!({ "t": text });
+    if json0.apply(&mut value, vec![operation]).is_err() {
+        assert_eq!(json!({ "t": text }), value, "failed delete changed the text");
+        return;
+    }
+    assert_eq!(Some(d.as_str()), text.get(p..p + d.len()));
+    let expected = format!("{}{}", &text[..p], &text[p + d.len()..]);
+    assert_eq!(json!({ "t": expected }), value);
+});
@@ -0,0 +1,17 @@
+#![no_main]
+
+use json0_rs::{
+    testing::{check_convergence, random_document, random_operation, Rng},
+    Json0,
+};
+use libfuzzer_sys::fuzz_target;
+
+// Input seeds a pair of concurrent operations on a random document, which
+// must converge after transformed against each other.
+fuzz_target!(|seed: u64| {
+    let mut rng = Rng::new(seed);
+    let doc = random_document(&mut rng);
+    let a = random_operation(&doc, &mut rng);
+    let b = random_operation(&doc, &mut rng);
+    check_convergence(&Json0::new(), &doc, &a, &b);
+});
@@ -0,0 +1,302 @@
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{Map, Value};
+
+use crate::{
+    error::{JsonError, Result},
+    operation::{Operation, OperationComponent, OperationFactory, Operator},
+    path::PathElement,
+    sub_type::SubType,
+};
+
+// Operator kinds on the wire
+const NOOP: u8 = 0;
+const NUMBER_ADD: u8 = 1;
+const SUB_TYPE: u8 = 2;
+const LIST_INSERT: u8 = 3;
+const LIST_DELETE: u8 = 4;
+const LIST_REPLACE: u8 = 5;
+const LIST_MOVE: u8 = 6;
+const OBJECT_INSERT: u8 = 7;
+const OBJECT_DELETE: u8 = 8;
+const OBJECT_REPLACE: u8 = 9;
+const OBJECT_MOVE: u8 = 10;
+const TEST: u8 = 11;
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum WirePathElement {
+    Index(usize),
+    End(i64),
+    Key(String),
+}
+
+/// A component is encoded as a tuple of path, operator kind and operands,
+/// instead of a map keyed by operator names like the JSON form.
+#[derive(Serialize, Deserialize)]
+struct WireComponent(
+    Vec<WirePathElement>,
+    u8,
+    #[serde(serialize_with = "serialize_values")] Vec<Value>,
+);
+
+#[derive(Serialize, Deserialize)]
+struct WireOperation {
+    c: Vec<WireComponent>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_meta"
+    )]
+    m: Option<Value>,
+}
+
+/// Serializes numbers in value as native numbers of the format. With
+/// serde_json's arbitrary_precision they are serialized as a private struct
+/// otherwise. Numbers out of the range of i64, u64 and f64 lose precision.
+struct WireValue<'a>(&'a Value);
+
+impl Serialize for WireValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Number(n) => {
+                if let Some(u) = n.as_u64() {
+                    serializer.serialize_u64(u)
+                } else if let Some(i) = n.as_i64() {
+                    serializer.serialize_i64(i)
+                } else {
+                    serializer.serialize_f64(n.as_f64().unwrap_or(f64::NAN))
+                }
+            }
+            Value::Array(a) => serializer.collect_seq(a.iter().map(WireValue)),
+            Value::Object(o) => serializer.collect_map(o.iter().map(|(k, v)| (k, WireValue(v)))),
+            v => v.serialize(serializer),
+        }
+    }
+}
+
+fn serialize_values<S: Serializer>(
+    values: &[Value],
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_seq(values.iter().map(WireValue))
+}
+
+fn serialize_meta<S: Serializer>(
+    meta: &Option<Value>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    match meta {
+        Some(m) => serializer.serialize_some(&WireValue(m)),
+        None => serializer.serialize_none(),
+    }
+}
+
+impl From<&OperationComponent> for WireComponent {
+    fn from(component: &OperationComponent) -> Self {
+        let path = component
+            .path
+            .get_elements()
+            .iter()
+            .map(|pe| match pe {
+                PathElement::Index(i) => WirePathElement::Index(*i),
+                PathElement::End(k) => WirePathElement::End(-(*k as i64) - 1),
+                PathElement::Key(k) => WirePathElement::Key(k.to_string()),
+            })
+            .collect();
+        let (kind, operands) = match &component.operator {
+            Operator::Noop() => (NOOP, vec![]),
+            Operator::SubType(SubType::NumberAdd, o, _) => (NUMBER_ADD, vec![o.clone()]),
+            Operator::SubType(t, o, _) => (SUB_TYPE, vec![Value::String(t.to_string()), o.clone()]),
+            Operator::ListInsert(i) => (LIST_INSERT, vec![Value::clone(i)]),
+            Operator::ListDelete(d) => (LIST_DELETE, vec![Value::clone(d)]),
+            Operator::ListReplace(i, d) => (LIST_REPLACE, vec![Value::clone(i), Value::clone(d)]),
+            Operator::ListMove(m) => (LIST_MOVE, vec![Value::from(*m)]),
+            Operator::ObjectInsert(i) => (OBJECT_INSERT, vec![Value::clone(i)]),
+            Operator::ObjectDelete(d) => (OBJECT_DELETE, vec![Value::clone(d)]),
+            Operator::ObjectReplace(i, d) => {
+                (OBJECT_REPLACE, vec![Value::clone(i), Value::clone(d)])
+            }
+            Operator::ObjectMove(k) => (OBJECT_MOVE, vec![Value::String(k.clone())]),
+            Operator::Test(v) => (TEST, vec![Value::clone(v)]),
+        };
+        WireComponent(path, kind, operands)
+    }
+}
+
+impl WireComponent {
+    /// Convert back to the JSON form, so decoding goes through the same
+    /// validation and limits as parsing JSON.
+    fn into_value(self) -> Result<Value> {
+        let WireComponent(path, kind, operands) = self;
+        let keys: &[&str] = match kind {
+            NOOP => &[],
+            NUMBER_ADD => &["na"],
+            SUB_TYPE => &["t", "o"],
+            LIST_INSERT => &["li"],
+            LIST_DELETE => &["ld"],
+            LIST_REPLACE => &["li", "ld"],
+            LIST_MOVE => &["lm"],
+            OBJECT_INSERT => &["oi"],
+            OBJECT_DELETE => &["od"],
+            OBJECT_REPLACE => &["oi", "od"],
+            OBJECT_MOVE => &["om"],
+            TEST => &["test"],
+            _ => {
+                return Err(JsonError::EncodingError(format!(
+                    "unknown operator kind: {kind}"
+                )))
+            }
+        };
+        if keys.len() != operands.len() {
+            return Err(JsonError::EncodingError(format!(
+                "operator kind: {kind} expects {} operands, got {}",
+                keys.len(),
+                operands.len()
+            )));
+        }
+
+        let mut obj = Map::new();
+        let path = path
+            .into_iter()
+            .map(|pe| match pe {
+                WirePathElement::Index(i) => Value::from(i),
+                WirePathElement::End(i) => Value::from(i),
+                WirePathElement::Key(k) => Value::String(k),
+            })
+            .collect();
+        obj.insert("p".into(), Value::Array(path));
+        for (k, v) in keys.iter().zip(operands) {
+            obj.insert(k.to_string(), v);
+        }
+        Ok(Value::Object(obj))
+    }
+}
+
+impl From<&Operation> for WireOperation {
+    fn from(operation: &Operation) -> Self {
+        WireOperation {
+            c: operation.iter().map(WireComponent::from).collect(),
+            m: operation.meta().map(|m| m.to_value()),
+        }
+    }
+}
+
+impl WireOperation {
+    fn into_value(self) -> Result<Value> {
+        let op = Value::Array(
+            self.c
+                .into_iter()
+                .map(|c| c.into_value())
+                .collect::<Result<Vec<Value>>>()?,
+        );
+        Ok(match self.m {
+            Some(meta) => {
+                let mut obj = Map::new();
+                obj.insert("op".into(), op);
+                obj.insert("meta".into(), meta);
+                Value::Object(obj)
+            }
+            None => op,
+        })
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl Operation {
+    pub fn to_cbor(&self) -> Result<Vec<u8>> {
+        let mut out = vec![];
+        ciborium::ser::into_writer(&WireOperation::from(self), &mut out)
+            .map_err(|e| JsonError::EncodingError(e.to_string()))?;
+        Ok(out)
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl OperationFactory {
+    pub fn from_cbor(&self, bytes: &[u8]) -> Result<Operation> {
+        let wire: WireOperation = ciborium::de::from_reader(bytes)
+            .map_err(|e| JsonError::EncodingError(e.to_string()))?;
+        self.from_value(wire.into_value()?)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl Operation {
+    pub fn to_msgpack(&self) -> Result<Vec<u8>> {
+        rmp_serde::to_vec(&WireOperation::from(self))
+            .map_err(|e| JsonError::EncodingError(e.to_string()))
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl OperationFactory {
+    pub fn from_msgpack(&self, bytes: &[u8]) -> Result<Operation> {
+        let wire: WireOperation =
+            rmp_serde::from_slice(bytes).map_err(|e| JsonError::EncodingError(e.to_string()))?;
+        self.from_value(wire.into_value()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operation::OperationMeta;
+    use test_log::test;
+
+    fn sample_operations() -> Vec<Operation> {
+        let f = OperationFactory::default();
+        vec![
+            r#"[{"p":["a",1],"li":{"x":[1,2.5,null]}},{"p":["a",0],"ld":true,"li":"s"}]"#,
+            r#"[{"p":["b"],"oi":"v","od":{"k":-1}},{"p":["c",3],"lm":0},{"p":["c",-1],"li":1},{"p":["e"],"om":"f"},{"p":["e"],"test":1}]"#,
+            r#"[{"p":["n"],"na":-2},{"p":["t"],"t":"text","o":{"p":1,"i":"hi"}}]"#,
+            r#"{"op":[{"p":["d"],"od":[]}],"meta":{"author":"u1","timestamp":7}}"#,
+        ]
+        .into_iter()
+        .map(|s| f.from_value(serde_json::from_str(s).unwrap()).unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn test_wire_round_trip() {
+        let f = OperationFactory::default();
+        for op in sample_operations() {
+            let decoded = f
+                .from_value(WireOperation::from(&op).into_value().unwrap())
+                .unwrap();
+            assert_eq!(op.to_value(), decoded.to_value());
+        }
+        let meta = sample_operations().pop().unwrap();
+        assert_eq!(
+            Some(&OperationMeta::new().author("u1").timestamp(7)),
+            meta.meta()
+        );
+    }
+
+    #[test]
+    fn test_unknown_kind() {
+        let wire = WireComponent(vec![WirePathElement::Key("a".into())], 42, vec![]);
+        assert_matches!(wire.into_value(), Err(JsonError::EncodingError(_)));
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor_round_trip() {
+        let f = OperationFactory::default();
+        for op in sample_operations() {
+            let bytes = op.to_cbor().unwrap();
+            assert!(bytes.len() < op.to_value().to_string().len());
+            assert_eq!(op.to_value(), f.from_cbor(&bytes).unwrap().to_value());
+        }
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack_round_trip() {
+        let f = OperationFactory::default();
+        for op in sample_operations() {
+            let bytes = op.to_msgpack().unwrap();
+            assert!(bytes.len() < op.to_value().to_string().len());
+            assert_eq!(op.to_value(), f.from_msgpack(&bytes).unwrap().to_value());
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Operations on documents and operations written as JSON strings, shared by
+//! the bindings for other languages.
+
+use serde_json::Value;
+
+use crate::{error::JsonError, Json0, Result};
+
+thread_local! {
+    static JSON0: Json0 = Json0::new();
+}
+
+pub(crate) fn apply(snapshot: &str, op: &str) -> Result<String> {
+    let mut snapshot = parse_json(snapshot)?;
+    JSON0.with(|json0| {
+        let op = json0.operation_factory().from_str(op)?;
+        json0.apply(&mut snapshot, vec![op])
+    })?;
+    Ok(snapshot.to_string())
+}
+
+/// Transform op against other_op, side is `"left"` or `"right"`.
+pub(crate) fn transform(op: &str, other_op: &str, side: &str) -> Result<String> {
+    JSON0.with(|json0| {
+        let op = json0.operation_factory().from_str(op)?;
+        let other_op = json0.operation_factory().from_str(other_op)?;
+        let transformed = match side {
+            "left" => json0.transform_left(&op, &other_op)?,
+            "right" => json0.transform_right(&op, &other_op)?,
+            _ => {
+                return Err(JsonError::InvalidOperation(format!(
+                    "side must be \"left\" or \"right\", but is: \"{side}\""
+                )))
+            }
+        };
+        Ok(transformed.to_string())
+    })
+}
+
+pub(crate) fn compose(op1: &str, op2: &str) -> Result<String> {
+    JSON0.with(|json0| {
+        let op1 = json0.operation_factory().from_str(op1)?;
+        let op2 = json0.operation_factory().from_str(op2)?;
+        Ok(op1.composed(&op2)?.to_string())
+    })
+}
+
+#[cfg(feature = "ffi")]
+pub(crate) fn invert(op: &str) -> Result<String> {
+    JSON0.with(|json0| {
+        let op = json0.operation_factory().from_str(op)?;
+        Ok(op.invert()?.to_string())
+    })
+}
+
+fn parse_json(input: &str) -> Result<Value> {
+    serde_json::from_str(input)
+        .map_err(|e| JsonError::InvalidOperation(format!("invalid JSON: {input}, reason: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_json_string_api() {
+        assert_eq!(
+            r#"{"l":[0,1],"t":"abc"}"#,
+            apply(
+                r#"{"l":[1],"t":"ab"}"#,
+                r#"[{"p":["l",0],"li":0},{"p":["t"],"t":"text","o":{"p":2,"i":"c"}}]"#
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            r#"[{"p":["l",1],"li":"a"}]"#,
+            transform(
+                r#"[{"p":["l",0],"li":"a"}]"#,
+                r#"[{"p":["l",0],"li":"b"}]"#,
+                "right"
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            r#"[{"p":["l",0],"li":"a"}]"#,
+            transform(
+                r#"[{"p":["l",0],"li":"a"}]"#,
+                r#"[{"p":["l",0],"li":"b"}]"#,
+                "left"
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            r#"[{"p":["n"],"na":3}]"#,
+            compose(r#"[{"p":["n"],"na":1}]"#, r#"[{"p":["n"],"na":2}]"#).unwrap()
+        );
+        #[cfg(feature = "ffi")]
+        assert_eq!(
+            r#"[{"p":["k"],"od":2},{"p":["n"],"na":-1}]"#,
+            invert(r#"[{"p":["n"],"na":1},{"p":["k"],"oi":2}]"#).unwrap()
+        );
+
+        assert_matches!(apply("{", "[]"), Err(JsonError::InvalidOperation(_)));
+        assert_matches!(
+            transform("[]", "[]", "up"),
+            Err(JsonError::InvalidOperation(_))
+        );
+        assert_matches!(
+            apply("{}", r#"[{"p":["a"],"test":1}]"#)
+                .unwrap_err()
+                .root_cause(),
+            JsonError::ApplyOperationError(_)
+        );
+    }
+}
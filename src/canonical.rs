@@ -0,0 +1,349 @@
+use std::mem;
+
+use serde_json::{Map, Number, Value};
+use sha2::{Digest, Sha256};
+
+use crate::error::{JsonError, Result};
+
+// Integers above this can not be represented exactly by f64
+const MAX_SAFE_INTEGER: f64 = 9007199254740991.0;
+
+/// Rewrite numbers in value to their canonical form. Float numbers which
+/// hold an integral value are turned into integers, so `1.0` and `1` are
+/// canonicalized to the same value and `-0.0` becomes `0`.
+pub(crate) fn canonicalize_value(value: &mut Value) {
+    match value {
+        Value::Number(n) => {
+            if let Some(c) = canonical_number(n) {
+                *n = c;
+            }
+        }
+        Value::Array(arr) => arr.iter_mut().for_each(canonicalize_value),
+        Value::Object(obj) => obj.values_mut().for_each(canonicalize_value),
+        _ => {}
+    }
+}
+
+fn canonical_number(n: &Number) -> Option<Number> {
+    if n.is_i64() || n.is_u64() {
+        return None;
+    }
+    let f = n.as_f64()?;
+    if f.fract() == 0.0 && f.abs() <= MAX_SAFE_INTEGER {
+        return Some(Number::from(f as i64));
+    }
+    None
+}
+
+/// How numbers are compared when checking whether two values are equal, like
+/// the values declared to be removed by a component against the values on
+/// its path, or the old and new values of a replace.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum NumberEquality {
+    /// Numbers are equal only if they are written the same, so `1.0` is not
+    /// equal to `1`.
+    #[default]
+    Exact,
+    /// Numbers are equal if they have the same value, so `1.0` equals `1`.
+    IntegerCoercing,
+    /// Numbers are equal if they differ by no more than the epsilon.
+    Epsilon(f64),
+}
+
+impl NumberEquality {
+    /// Whether a and b are equal, with numbers in them compared by this
+    /// policy and the other values compared exactly.
+    pub fn values_eq(&self, a: &Value, b: &Value) -> bool {
+        match (self, a, b) {
+            (NumberEquality::Exact, _, _) => a == b,
+            (_, Value::Number(x), Value::Number(y)) => self.numbers_eq(x, y),
+            (_, Value::Array(x), Value::Array(y)) => {
+                x.len() == y.len() && x.iter().zip(y).all(|(x, y)| self.values_eq(x, y))
+            }
+            (_, Value::Object(x), Value::Object(y)) => {
+                x.len() == y.len()
+                    && x.iter()
+                        .all(|(k, v)| y.get(k).is_some_and(|w| self.values_eq(v, w)))
+            }
+            _ => a == b,
+        }
+    }
+
+    fn numbers_eq(&self, x: &Number, y: &Number) -> bool {
+        if x == y {
+            return true;
+        }
+        if let (Some(a), Some(b)) = (x.as_i64(), y.as_i64()) {
+            return a == b;
+        }
+        if let (Some(a), Some(b)) = (x.as_u64(), y.as_u64()) {
+            return a == b;
+        }
+        let (Some(a), Some(b)) = (x.as_f64(), y.as_f64()) else {
+            return false;
+        };
+        match self {
+            NumberEquality::Epsilon(e) => (a - b).abs() <= *e,
+            _ => a == b,
+        }
+    }
+}
+
+/// Serialize value to a compact JSON string with object keys sorted, so equal
+/// values always produce identical output no matter how their maps are ordered.
+pub fn to_canonical_string(value: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+/// Options of `normalize_value`. Keys are sorted and numbers canonicalized by
+/// default, and non-finite numbers are kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizeOptions {
+    sort_keys: bool,
+    canonical_numbers: bool,
+    reject_non_finite: bool,
+}
+
+impl Default for NormalizeOptions {
+    fn default() -> Self {
+        NormalizeOptions {
+            sort_keys: true,
+            canonical_numbers: true,
+            reject_non_finite: false,
+        }
+    }
+}
+
+impl NormalizeOptions {
+    pub fn new() -> NormalizeOptions {
+        NormalizeOptions::default()
+    }
+
+    /// Reorder the keys of objects in ascending order. Only matters with the
+    /// `preserve-order` feature, without which objects are always sorted.
+    pub fn sort_keys(mut self, sort_keys: bool) -> Self {
+        self.sort_keys = sort_keys;
+        self
+    }
+
+    /// Turn float numbers holding an integral value into integers, like
+    /// `canonicalize_value`.
+    pub fn canonical_numbers(mut self, canonical_numbers: bool) -> Self {
+        self.canonical_numbers = canonical_numbers;
+        self
+    }
+
+    /// Fail on numbers which are not finite as f64, like the ones out of the
+    /// range of f64 kept by the `arbitrary-precision` feature.
+    pub fn reject_non_finite(mut self, reject_non_finite: bool) -> Self {
+        self.reject_non_finite = reject_non_finite;
+        self
+    }
+}
+
+/// Rewrite value to the canonical form of options, for both ends of a
+/// connection to hash or diff identical values. `-0.0` always becomes `0.0`,
+/// or `0` with canonical numbers.
+pub fn normalize_value(value: &mut Value, options: &NormalizeOptions) -> Result<()> {
+    match value {
+        Value::Number(n) => {
+            let f = n.as_f64();
+            if options.reject_non_finite && !f.is_some_and(f64::is_finite) {
+                return Err(JsonError::EncodingError(format!(
+                    "number: {n} is not a finite number"
+                )));
+            }
+            if options.canonical_numbers {
+                if let Some(c) = canonical_number(n) {
+                    *n = c;
+                }
+            } else if f == Some(0.0) && n.is_f64() {
+                *n = Number::from_f64(0.0).unwrap();
+            }
+        }
+        Value::Array(arr) => {
+            for v in arr.iter_mut() {
+                normalize_value(v, options)?;
+            }
+        }
+        Value::Object(obj) => {
+            for v in obj.values_mut() {
+                normalize_value(v, options)?;
+            }
+            if options.sort_keys {
+                let mut entries = mem::take(obj).into_iter().collect::<Vec<_>>();
+                entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+                *obj = entries.into_iter().collect::<Map<String, Value>>();
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// SHA-256 digest of the canonical string of value with its numbers
+/// canonicalized, so replicas holding equal documents get the same checksum
+/// regardless of map order and of how their numbers were written.
+pub fn document_checksum(value: &Value) -> [u8; 32] {
+    let mut value = value.clone();
+    // only rejecting non-finite numbers fails, which is off by default
+    _ = normalize_value(&mut value, &NormalizeOptions::default());
+    Sha256::digest(to_canonical_string(&value).as_bytes()).into()
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Array(arr) => {
+            out.push('[');
+            for (i, v) in arr.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(v, out);
+            }
+            out.push(']');
+        }
+        Value::Object(obj) => {
+            let mut keys: Vec<&String> = obj.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, k) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(k.clone()).to_string());
+                out.push(':');
+                write_canonical(&obj[k], out);
+            }
+            out.push('}');
+        }
+        _ => out.push_str(&value.to_string()),
+    }
+}
+
+/// Length in bytes of the compact JSON encoding of value, computed without
+/// serializing it.
+pub(crate) fn encoded_len(value: &Value) -> usize {
+    match value {
+        Value::Null => 4,
+        Value::Bool(b) => {
+            if *b {
+                4
+            } else {
+                5
+            }
+        }
+        Value::Number(n) => n.to_string().len(),
+        Value::String(s) => string_encoded_len(s),
+        Value::Array(arr) => {
+            2 + arr.len().saturating_sub(1) + arr.iter().map(encoded_len).sum::<usize>()
+        }
+        Value::Object(obj) => {
+            2 + obj.len().saturating_sub(1)
+                + obj
+                    .iter()
+                    .map(|(k, v)| string_encoded_len(k) + 1 + encoded_len(v))
+                    .sum::<usize>()
+        }
+    }
+}
+
+fn string_encoded_len(s: &str) -> usize {
+    2 + s
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' | '\u{8}' | '\u{c}' | '\n' | '\r' | '\t' => 2,
+            c if (c as u32) < 0x20 => 6,
+            c => c.len_utf8(),
+        })
+        .sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_canonicalize_value() {
+        let mut v: Value =
+            serde_json::from_str(r#"{"b":[1.0, -0.0, 1.5], "a":{"d":2, "c":"x"}}"#).unwrap();
+        canonicalize_value(&mut v);
+        assert_eq!(
+            r#"{"a":{"c":"x","d":2},"b":[1,0,1.5]}"#,
+            to_canonical_string(&v)
+        );
+    }
+
+    #[test]
+    fn test_normalize_value() {
+        let mut v: Value =
+            serde_json::from_str(r#"{"b":[1.0, -0.0, 1.5], "a":{"d":-0.0, "c":"x"}}"#).unwrap();
+        let mut raw = v.clone();
+        normalize_value(&mut v, &NormalizeOptions::default()).unwrap();
+        assert_eq!(r#"{"a":{"c":"x","d":0},"b":[1,0,1.5]}"#, v.to_string());
+
+        normalize_value(&mut raw, &NormalizeOptions::new().canonical_numbers(false)).unwrap();
+        assert_eq!(
+            r#"{"a":{"c":"x","d":0.0},"b":[1.0,0.0,1.5]}"#,
+            to_canonical_string(&raw)
+        );
+
+        let mut v: Value = serde_json::from_str(r#"{"z":1, "y":{"x":2, "w":3}}"#).unwrap();
+        normalize_value(&mut v, &NormalizeOptions::default()).unwrap();
+        assert_eq!(r#"{"y":{"w":3,"x":2},"z":1}"#, v.to_string());
+
+        let mut v = serde_json::json!([1, 2.5]);
+        normalize_value(&mut v, &NormalizeOptions::new().reject_non_finite(true)).unwrap();
+    }
+
+    #[cfg(feature = "arbitrary-precision")]
+    #[test]
+    fn test_normalize_value_non_finite() {
+        let mut v: Value = serde_json::from_str(r#"{"n":[1e400]}"#).unwrap();
+        normalize_value(&mut v.clone(), &NormalizeOptions::default()).unwrap();
+        assert_matches!(
+            normalize_value(&mut v, &NormalizeOptions::new().reject_non_finite(true)),
+            Err(JsonError::EncodingError(_))
+        );
+    }
+
+    #[test]
+    fn test_document_checksum() {
+        let a: Value =
+            serde_json::from_str(r#"{"a":[1.0, "x"], "b":{"c":null, "d":-0.0}}"#).unwrap();
+        let b: Value = serde_json::from_str(r#"{"b":{"d":0, "c":null}, "a":[1, "x"]}"#).unwrap();
+        let c: Value = serde_json::from_str(r#"{"a":[1, "y"], "b":{"c":null, "d":0}}"#).unwrap();
+        assert_eq!(document_checksum(&a), document_checksum(&b));
+        assert_ne!(document_checksum(&a), document_checksum(&c));
+    }
+
+    #[test]
+    fn test_number_equality() {
+        let v = |s: &str| serde_json::from_str::<Value>(s).unwrap();
+        let (a, b) = (
+            v(r#"{"n":[1, 2.0], "s":"x"}"#),
+            v(r#"{"s":"x", "n":[1.0, 2]}"#),
+        );
+        assert!(!NumberEquality::Exact.values_eq(&a, &b));
+        assert!(NumberEquality::IntegerCoercing.values_eq(&a, &b));
+        assert!(NumberEquality::Epsilon(0.0).values_eq(&a, &b));
+        assert!(!NumberEquality::IntegerCoercing.values_eq(&v("0.3"), &v("0.30000000000000004")));
+        assert!(NumberEquality::Epsilon(1e-9).values_eq(&v("0.3"), &v("0.30000000000000004")));
+        assert!(!NumberEquality::Epsilon(1e-9).values_eq(&v("0.3"), &v("0.31")));
+        assert!(!NumberEquality::IntegerCoercing.values_eq(&v("[1]"), &v("[1, 1]")));
+        assert!(!NumberEquality::IntegerCoercing.values_eq(&v(r#"{"a":1}"#), &v(r#"{"b":1}"#)));
+        assert!(!NumberEquality::IntegerCoercing.values_eq(&v("1"), &v(r#""1""#)));
+        assert!(!NumberEquality::Epsilon(0.5).values_eq(&v("-1"), &v("18446744073709551615")));
+    }
+
+    #[test]
+    fn test_encoded_len() {
+        let v: Value =
+            serde_json::from_str(r#"{"a":[1, null, true, 2.5], "b\\n":"x\"\u0001y", "c":{}}"#)
+                .unwrap();
+        assert_eq!(v.to_string().len(), encoded_len(&v));
+    }
+}
@@ -0,0 +1,213 @@
+use serde_json::Value;
+
+use crate::{
+    error::Result,
+    operation::{Operation, OperationComponent, Operator},
+    path::{Path, PathElement},
+    sub_type::SubType,
+    Json0,
+};
+
+impl Json0 {
+    /// Operation turning local into authoritative, for a replica found
+    /// diverged to catch up without reloading the whole document. Only the
+    /// differing parts are replaced: objects are diffed by key, lists by
+    /// element after their common prefix and suffix, and strings by text edits
+    /// when the text sub type is registered.
+    pub fn make_catchup(&self, local: &Value, authoritative: &Value) -> Result<Operation> {
+        let mut operation = Operation::default();
+        self.diff_value(&mut Path::root(), local, authoritative, &mut operation)?;
+        Ok(operation)
+    }
+
+    /// Transform pending, the operations made on local_base and not yet
+    /// acknowledged, to apply after the catch-up operation from local_base to
+    /// authoritative. Pending operations from the first one failing to apply
+    /// on authoritative are discarded, as the ones after it may depend on it,
+    /// and so are the ones left with nothing to do. Returns the kept operations, which are to be applied on and resent from
+    /// authoritative.
+    pub fn resync_pending(
+        &self,
+        local_base: &Value,
+        authoritative: &Value,
+        pending: &[Operation],
+    ) -> Result<Vec<Operation>> {
+        let catchup = self.make_catchup(local_base, authoritative)?;
+        let rebased = self.rebase(pending, &[catchup])?;
+        let mut value = authoritative.clone();
+        let mut kept = vec![];
+        for operation in rebased {
+            if operation.is_empty() {
+                continue;
+            }
+            if self.apply(&mut value, vec![operation.clone()]).is_err() {
+                break;
+            }
+            kept.push(operation);
+        }
+        Ok(kept)
+    }
+
+    fn diff_value(
+        &self,
+        path: &mut Path,
+        local: &Value,
+        authoritative: &Value,
+        operation: &mut Operation,
+    ) -> Result<()> {
+        match (local, authoritative) {
+            _ if local == authoritative => {}
+            (Value::Object(l), Value::Object(a)) => {
+                for (k, v) in l {
+                    path.push(PathElement::key(k));
+                    match a.get(k) {
+                        Some(av) => self.diff_value(path, v, av, operation)?,
+                        None => operation.push_validated(OperationComponent::new(
+                            path.clone(),
+                            Operator::ObjectDelete(v.clone().into()),
+                        )?)?,
+                    }
+                    path.pop();
+                }
+                for (k, v) in a.iter().filter(|(k, _)| !l.contains_key(*k)) {
+                    path.push(PathElement::key(k));
+                    operation.push_validated(OperationComponent::new(
+                        path.clone(),
+                        Operator::ObjectInsert(v.clone().into()),
+                    )?)?;
+                    path.pop();
+                }
+            }
+            (Value::Array(l), Value::Array(a)) => {
+                let prefix = l.iter().zip(a).take_while(|(x, y)| x == y).count();
+                let suffix = l[prefix..]
+                    .iter()
+                    .rev()
+                    .zip(a[prefix..].iter().rev())
+                    .take_while(|(x, y)| x == y)
+                    .count();
+                let (l, a) = (&l[prefix..l.len() - suffix], &a[prefix..a.len() - suffix]);
+                let paired = l.len().min(a.len());
+                for i in 0..paired {
+                    path.push(PathElement::Index(prefix + i));
+                    self.diff_value(path, &l[i], &a[i], operation)?;
+                    path.pop();
+                }
+                // delete from the last one so the indexes of the others are kept
+                for i in (paired..l.len()).rev() {
+                    path.push(PathElement::Index(prefix + i));
+                    operation.push_validated(OperationComponent::new(
+                        path.clone(),
+                        Operator::ListDelete(l[i].clone().into()),
+                    )?)?;
+                    path.pop();
+                }
+                for (i, v) in a.iter().enumerate().skip(paired) {
+                    path.push(PathElement::Index(prefix + i));
+                    operation.push_validated(OperationComponent::new(
+                        path.clone(),
+                        Operator::ListInsert(v.clone().into()),
+                    )?)?;
+                    path.pop();
+                }
+            }
+            (Value::String(l), Value::String(a))
+                if self.functions.get(&SubType::Text).is_some() =>
+            {
+                for c in self
+                    .operation_factory()
+                    .text_operation_from_diff(path.clone(), l, a)?
+                {
+                    operation.push_validated(c)?;
+                }
+            }
+            _ => {
+                let operator = match path.last() {
+                    Some(PathElement::Index(_)) => {
+                        Operator::ListReplace(authoritative.clone().into(), local.clone().into())
+                    }
+                    _ => {
+                        Operator::ObjectReplace(authoritative.clone().into(), local.clone().into())
+                    }
+                };
+                operation.push_validated(OperationComponent::new(path.clone(), operator)?)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sub_type::SubTypeFunctionsHolder;
+    use serde_json::json;
+    use test_log::test;
+
+    #[test]
+    fn test_make_catchup() {
+        let json0 = Json0::new();
+        let local = json!({
+            "a": 1,
+            "b": {"c": [1, 2, 3, 4], "d": "hello"},
+            "e": [{"k": 1}, "x"],
+            "f": true
+        });
+        let authoritative = json!({
+            "a": 1,
+            "b": {"c": [1, 5, 4], "d": "hello world"},
+            "e": [{"k": 2}, "x", "y"],
+            "g": null
+        });
+        let op = json0.make_catchup(&local, &authoritative).unwrap();
+        assert_eq!(
+            json!([
+                {"p": ["b", "c", 1], "ld": 2, "li": 5},
+                {"p": ["b", "c", 2], "ld": 3},
+                {"p": ["b", "d"], "t": "text", "o": {"p": 5, "i": " world"}},
+                {"p": ["e", 0, "k"], "od": 1, "oi": 2},
+                {"p": ["e", 2], "li": "y"},
+                {"p": ["f"], "od": true},
+                {"p": ["g"], "oi": null}
+            ]),
+            op.to_value()
+        );
+        let mut value = local.clone();
+        json0.apply(&mut value, vec![op]).unwrap();
+        assert_eq!(authoritative, value);
+
+        assert!(json0.make_catchup(&local, &local).unwrap().is_empty());
+        let op = json0.make_catchup(&local, &json!([1])).unwrap();
+        assert_eq!(json!([{"p": [], "od": local, "oi": [1]}]), op.to_value());
+
+        // strings are replaced without the text sub type
+        let json0 = Json0::with_subtypes(SubTypeFunctionsHolder::empty());
+        let op = json0
+            .make_catchup(&json!({"s": "ab"}), &json!({"s": "abc"}))
+            .unwrap();
+        assert_eq!(
+            json!([{"p": ["s"], "od": "ab", "oi": "abc"}]),
+            op.to_value()
+        );
+    }
+
+    #[test]
+    fn test_resync_pending() {
+        let json0 = Json0::new();
+        let parse = |s: &str| json0.operation_factory().from_str(s).unwrap();
+        let local_base = json!({"l": [1, 2], "o": {"k": 1}});
+        let authoritative = json!({"l": [0, 1, 2], "n": 1});
+        let pending = vec![
+            parse(r#"[{"p":["l",1],"li":3}]"#),
+            parse(r#"[{"p":["o","j"],"oi":2}]"#),
+            parse(r#"[{"p":["l",0],"ld":1}]"#),
+        ];
+        let kept = json0
+            .resync_pending(&local_base, &authoritative, &pending)
+            .unwrap();
+        assert_eq!(2, kept.len());
+        let mut value = authoritative.clone();
+        json0.apply(&mut value, kept).unwrap();
+        assert_eq!(json!({"l": [0, 3, 2], "n": 1}), value);
+    }
+}
@@ -0,0 +1,161 @@
+use std::{cmp::Ordering, collections::BTreeMap};
+
+use serde_json::{Map, Value};
+
+use crate::error::JsonError;
+
+/// Counters of the operations seen from each actor, for ordering operations
+/// from multiple leaders. Actors not in the clock have counter 0.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VectorClock {
+    counters: BTreeMap<String, u64>,
+}
+
+impl VectorClock {
+    pub fn new() -> VectorClock {
+        VectorClock::default()
+    }
+
+    pub fn get(&self, actor: &str) -> u64 {
+        self.counters.get(actor).copied().unwrap_or(0)
+    }
+
+    pub fn set<S: Into<String>>(mut self, actor: S, counter: u64) -> Self {
+        let actor = actor.into();
+        if counter == 0 {
+            self.counters.remove(&actor);
+        } else {
+            self.counters.insert(actor, counter);
+        }
+        self
+    }
+
+    /// Count a new operation from actor. Returns the new counter of actor.
+    pub fn increment(&mut self, actor: &str) -> u64 {
+        let counter = self.counters.entry(actor.to_string()).or_default();
+        *counter += 1;
+        *counter
+    }
+
+    /// Take the larger counter of each actor from other.
+    pub fn merge(&mut self, other: &VectorClock) {
+        for (actor, counter) in &other.counters {
+            let c = self.counters.entry(actor.clone()).or_default();
+            *c = (*c).max(*counter);
+        }
+    }
+
+    pub fn actors(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.counters.iter().map(|(a, c)| (a.as_str(), *c))
+    }
+
+    /// Every counter is not larger than the one of other and some is smaller,
+    /// so the operation of this clock was seen before the one of other.
+    pub fn happened_before(&self, other: &VectorClock) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Less)
+    }
+
+    /// Neither of the clocks happened before the other and they're not equal,
+    /// so their operations need to be transformed against each other.
+    pub fn concurrent_with(&self, other: &VectorClock) -> bool {
+        self.partial_cmp(other).is_none()
+    }
+
+    pub fn to_value(&self) -> Value {
+        Value::Object(
+            self.counters
+                .iter()
+                .map(|(a, c)| (a.clone(), Value::from(*c)))
+                .collect::<Map<String, Value>>(),
+        )
+    }
+}
+
+impl PartialOrd for VectorClock {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let mut less = false;
+        let mut greater = false;
+        for actor in self.counters.keys().chain(other.counters.keys()) {
+            match self.get(actor).cmp(&other.get(actor)) {
+                Ordering::Less => less = true,
+                Ordering::Greater => greater = true,
+                Ordering::Equal => {}
+            }
+        }
+        match (less, greater) {
+            (false, false) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Less),
+            (false, true) => Some(Ordering::Greater),
+            (true, true) => None,
+        }
+    }
+}
+
+impl TryFrom<&Value> for VectorClock {
+    type Error = JsonError;
+
+    fn try_from(value: &Value) -> std::result::Result<Self, Self::Error> {
+        let obj = value.as_object().ok_or_else(|| {
+            JsonError::InvalidOperation(format!("vector clock: {value} is not a JSON object"))
+        })?;
+        let mut clock = VectorClock::new();
+        for (actor, counter) in obj {
+            let counter = counter.as_u64().ok_or_else(|| {
+                JsonError::InvalidOperation(format!(
+                    "counter: {counter} of actor: {actor} in vector clock is not a non-negative integer"
+                ))
+            })?;
+            clock = clock.set(actor.as_str(), counter);
+        }
+        Ok(clock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_vector_clock_causality() {
+        let a = VectorClock::new().set("a", 1);
+        let ab = VectorClock::new().set("a", 1).set("b", 1);
+        let b = VectorClock::new().set("b", 2);
+
+        assert!(a.happened_before(&ab));
+        assert!(!ab.happened_before(&a));
+        assert!(!a.happened_before(&a));
+        assert!(a.concurrent_with(&b));
+        assert!(ab.concurrent_with(&b));
+        assert!(!a.concurrent_with(&a));
+        assert_eq!(Some(Ordering::Equal), a.partial_cmp(&a.clone().set("c", 0)));
+
+        let mut merged = ab.clone();
+        merged.merge(&b);
+        assert_eq!(VectorClock::new().set("a", 1).set("b", 2), merged);
+        assert!(ab.happened_before(&merged) && b.happened_before(&merged));
+        assert_eq!(2, merged.increment("a"));
+        assert_eq!(1, merged.increment("c"));
+        assert_eq!(
+            vec![("a", 2), ("b", 2), ("c", 1)],
+            merged.actors().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_vector_clock_value() {
+        let clock = VectorClock::new().set("a", 3).set("b", 1);
+        assert_eq!(serde_json::json!({"a": 3, "b": 1}), clock.to_value());
+        assert_eq!(clock, VectorClock::try_from(&clock.to_value()).unwrap());
+        for v in [
+            serde_json::json!([]),
+            serde_json::json!({"a": -1}),
+            serde_json::json!({"a": "1"}),
+        ] {
+            assert_matches!(
+                VectorClock::try_from(&v).unwrap_err(),
+                JsonError::InvalidOperation(_)
+            );
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use serde_json::Value;
+
+use crate::{
+    error::{JsonError, Result},
+    operation::{Operation, OperationFactory},
+};
+
+// number of leading path elements shared with the previous component
+const SHARED: &str = "s";
+
+/// Replace the path of each component of the JSON form with the part after
+/// the prefix it shares with the path of the component before it.
+fn delta_encode(components: &mut [Value]) {
+    let mut last: Vec<Value> = vec![];
+    for component in components.iter_mut() {
+        let Some(obj) = component.as_object_mut() else {
+            continue;
+        };
+        let Some(Value::Array(path)) = obj.get_mut("p") else {
+            continue;
+        };
+        let shared = last
+            .iter()
+            .zip(path.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let full = std::mem::take(path);
+        path.extend_from_slice(&full[shared..]);
+        last = full;
+        if shared > 0 {
+            obj.insert(SHARED.into(), Value::from(shared));
+        }
+    }
+}
+
+fn delta_decode(components: &mut [Value]) -> Result<()> {
+    let mut last: Vec<Value> = vec![];
+    for component in components.iter_mut() {
+        let Some(obj) = component.as_object_mut() else {
+            continue;
+        };
+        let shared = match obj.remove(SHARED) {
+            None => 0,
+            Some(s) => s
+                .as_u64()
+                .and_then(|s| usize::try_from(s).ok())
+                .filter(|s| *s <= last.len())
+                .ok_or_else(|| {
+                    JsonError::EncodingError(format!(
+                        "invalid shared path length: {} after path of length: {}",
+                        s,
+                        last.len()
+                    ))
+                })?,
+        };
+        let Some(Value::Array(path)) = obj.get_mut("p") else {
+            continue;
+        };
+        let mut full = last[..shared].to_vec();
+        full.append(path);
+        path.clone_from(&full);
+        last = full;
+    }
+    Ok(())
+}
+
+impl Operation {
+    /// Encode to a compact JSON text for transport. Like `to_value` but the
+    /// path of each component only keeps the elements after the prefix it
+    /// shares with the path of the component before it, with the length of
+    /// the prefix in `"s"`. Decode it by `OperationFactory::decode_compact`.
+    pub fn encode_compact(&self) -> String {
+        let mut value = self.to_value();
+        match &mut value {
+            Value::Array(components) => delta_encode(components),
+            Value::Object(envelope) => {
+                if let Some(Value::Array(components)) = envelope.get_mut("op") {
+                    delta_encode(components);
+                }
+            }
+            _ => {}
+        }
+        value.to_string()
+    }
+}
+
+impl OperationFactory {
+    /// Decode an operation encoded by `Operation::encode_compact`.
+    pub fn decode_compact(&self, s: &str) -> Result<Operation> {
+        let mut value: Value =
+            serde_json::from_str(s).map_err(|e| JsonError::EncodingError(e.to_string()))?;
+        match &mut value {
+            Value::Array(components) => delta_decode(components)?,
+            Value::Object(envelope) => {
+                if let Some(Value::Array(components)) = envelope.get_mut("op") {
+                    delta_decode(components)?;
+                }
+            }
+            _ => {}
+        }
+        self.from_value(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_compact_round_trip() {
+        let f = OperationFactory::default();
+        for s in [
+            r#"[{"p":["a","b",0],"li":1},{"p":["a","b",1],"li":2},{"p":["a","c"],"oi":3},{"p":["d"],"na":1}]"#,
+            r#"[{"p":["a",-1],"li":1},{"p":["a",-1],"li":2},{"p":[],"oi":{}}]"#,
+            r#"{"op":[{"p":["x","y"],"od":[]},{"p":["x","z"],"oi":1}],"meta":{"author":"u1"}}"#,
+            r#"[]"#,
+        ] {
+            let op = f.from_str(s).unwrap();
+            assert_eq!(op, f.decode_compact(&op.encode_compact()).unwrap());
+        }
+
+        let op = f
+            .from_str(r#"[{"p":["a","b",0],"li":1},{"p":["a","b",1],"li":2},{"p":["c"],"oi":3}]"#)
+            .unwrap();
+        let compact: Value = serde_json::from_str(&op.encode_compact()).unwrap();
+        assert_eq!(
+            serde_json::json!([
+                {"p":["a","b",0],"li":1},
+                {"p":[1],"s":2,"li":2},
+                {"p":["c"],"oi":3}
+            ]),
+            compact
+        );
+    }
+
+    #[test]
+    fn test_decode_compact_invalid_shared() {
+        let f = OperationFactory::default();
+        for s in [
+            r#"[{"p":["a"],"oi":1},{"p":["b"],"s":2,"oi":2}]"#,
+            r#"[{"p":["a"],"oi":1},{"p":["b"],"s":"1","oi":2}]"#,
+            r#"[{"p":["a"],"oi":1"#,
+        ] {
+            assert_matches!(
+                f.decode_compact(s).unwrap_err(),
+                JsonError::EncodingError(_)
+            );
+        }
+    }
+}
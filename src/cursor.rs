@@ -0,0 +1,219 @@
+use std::mem;
+
+use serde_json::Value;
+
+use crate::{
+    error::{JsonError, Result},
+    json::{Appliable, Routable},
+    operation::Operator,
+    path::{Path, PathElement},
+};
+
+/// A position in a document to read and edit the values around it without
+/// routing from the root for every edit. Moving the cursor down or up costs
+/// O(1) no matter how deep it is.
+///
+/// The value under the cursor is detached from its ancestors while the cursor
+/// is on it, and they are put back together when the cursor leaves it or is
+/// dropped.
+pub struct Cursor<'a> {
+    root: &'a mut Value,
+    // detached ancestors of current, each with where its child goes back
+    ancestors: Vec<(Value, PathElement)>,
+    current: Value,
+}
+
+impl<'a> Cursor<'a> {
+    /// Create a cursor on path of root. Returns None if there's nothing on
+    /// path.
+    pub fn new(root: &'a mut Value, path: &Path) -> Result<Option<Cursor<'a>>> {
+        let current = mem::take(root);
+        let mut cursor = Cursor {
+            root,
+            ancestors: vec![],
+            current,
+        };
+        for elem in path.get_elements() {
+            if !cursor.descend(elem.clone())? {
+                return Ok(None);
+            }
+        }
+        Ok(Some(cursor))
+    }
+
+    /// Move to the child elem of the current value. Returns false and stays
+    /// if there's no such child.
+    pub fn descend<E: Into<PathElement>>(&mut self, elem: E) -> Result<bool> {
+        let elem = elem.into();
+        let Some(child) = take_child(&mut self.current, &elem)? else {
+            return Ok(false);
+        };
+        let parent = mem::replace(&mut self.current, child);
+        self.ancestors.push((parent, elem));
+        Ok(true)
+    }
+
+    /// Move to the parent of the current value. Returns false if it's on the
+    /// root.
+    pub fn parent(&mut self) -> bool {
+        let Some((parent, elem)) = self.ancestors.pop() else {
+            return false;
+        };
+        let child = mem::replace(&mut self.current, parent);
+        put_child(&mut self.current, &elem, child);
+        true
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.current
+    }
+
+    /// Path of the current value from the root.
+    pub fn path(&self) -> Path {
+        self.ancestors
+            .iter()
+            .map(|(_, elem)| elem.clone())
+            .collect()
+    }
+
+    /// Apply operator on the path of the current value, like a component on
+    /// `path()`. The cursor stays on the same path afterwards, which may hold
+    /// another value like the next element after `ld`, or moves to the parent
+    /// if there's nothing left on it, like after `od` or `om`.
+    pub fn apply_here(&mut self, operator: Operator) -> Result<()> {
+        let Some((parent, elem)) = self.ancestors.pop() else {
+            return Ok(self.current.apply(Path::root(), operator)?);
+        };
+        let child = mem::replace(&mut self.current, parent);
+        put_child(&mut self.current, &elem, child);
+        let result = self
+            .current
+            .apply(Path::from_iter([elem.clone()]), operator)
+            .map_err(JsonError::from);
+        // stays on the parent if there's nothing left on elem, or the parent
+        // is replaced by a value elem can't route
+        let _ = self.descend(elem);
+        result
+    }
+}
+
+impl Drop for Cursor<'_> {
+    fn drop(&mut self) {
+        while self.parent() {}
+        *self.root = mem::take(&mut self.current);
+    }
+}
+
+fn take_child(value: &mut Value, elem: &PathElement) -> Result<Option<Value>> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    let path = Path::from_iter([elem.clone()]);
+    Ok(value.route_get_mut(&path)?.map(mem::take))
+}
+
+fn put_child(value: &mut Value, elem: &PathElement, child: Value) {
+    let path = Path::from_iter([elem.clone()]);
+    if let Ok(Some(slot)) = value.route_get_mut(&path) {
+        *slot = child;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::JsonError, json::RouteError, operation::OperationFactory};
+    use serde_json::json;
+    use test_log::test;
+
+    // operator of a component, whose path is ignored by apply_here
+    fn operator(f: &OperationFactory, component: Value) -> Operator {
+        f.from_value(json!([component]))
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap()
+            .operator
+    }
+
+    #[test]
+    fn test_cursor_navigation() {
+        let mut value = json!({"a": [1, {"b": "c"}], "n": null});
+        {
+            let mut cursor = Cursor::new(&mut value, &path!["a", 1]).unwrap().unwrap();
+            assert_eq!(&json!({"b": "c"}), cursor.value());
+            assert_eq!(path!["a", 1], cursor.path());
+
+            assert!(cursor.descend("b").unwrap());
+            assert_eq!(&json!("c"), cursor.value());
+            assert_matches!(
+                cursor.descend(0).unwrap_err(),
+                JsonError::RouteError(RouteError::ReachLeafNode(_))
+            );
+
+            assert!(cursor.parent());
+            assert!(cursor.parent());
+            assert!(!cursor.descend(5).unwrap());
+            assert_matches!(
+                cursor.descend("k").unwrap_err(),
+                JsonError::RouteError(RouteError::ExpectIndexPath { .. })
+            );
+            assert!(cursor.parent());
+            assert!(!cursor.parent());
+            assert_eq!(path![], cursor.path());
+            assert!(cursor.descend("n").unwrap());
+            assert!(!cursor.descend("k").unwrap());
+        }
+        assert_eq!(json!({"a": [1, {"b": "c"}], "n": null}), value);
+
+        assert!(Cursor::new(&mut value, &path!["a", 2]).unwrap().is_none());
+        assert_eq!(json!({"a": [1, {"b": "c"}], "n": null}), value);
+    }
+
+    #[test]
+    fn test_cursor_apply_here() {
+        let f = OperationFactory::default();
+        let mut value = json!({"a": [1, 2, 3], "o": {"k": 1}});
+        {
+            let mut cursor = Cursor::new(&mut value, &path!["a", 0]).unwrap().unwrap();
+            cursor
+                .apply_here(operator(&f, json!({"p": [0], "na": 10})))
+                .unwrap();
+            assert_eq!(&json!(11), cursor.value());
+            cursor
+                .apply_here(operator(&f, json!({"p": [0], "li": 0})))
+                .unwrap();
+            assert_eq!(&json!(0), cursor.value());
+            cursor
+                .apply_here(operator(&f, json!({"p": [0], "ld": 0})))
+                .unwrap();
+            assert_eq!(&json!(11), cursor.value());
+            assert_eq!(path!["a", 0], cursor.path());
+
+            assert!(cursor.parent());
+            assert!(cursor.parent());
+            assert!(cursor.descend("o").unwrap());
+            assert!(cursor.descend("k").unwrap());
+            cursor
+                .apply_here(operator(&f, json!({"p": ["k"], "od": 1})))
+                .unwrap();
+            assert_eq!(path!["o"], cursor.path());
+            assert_eq!(&json!({}), cursor.value());
+            assert!(cursor.descend("j").is_ok_and(|found| !found));
+
+            let err = cursor
+                .apply_here(operator(&f, json!({"p": ["k"], "test": 1})))
+                .unwrap_err();
+            assert_matches!(err, JsonError::ApplyOperationError(_));
+            assert_eq!(path!["o"], cursor.path());
+        }
+        assert_eq!(json!({"a": [11, 2, 3], "o": {}}), value);
+
+        let mut cursor = Cursor::new(&mut value, &path![]).unwrap().unwrap();
+        cursor
+            .apply_here(operator(&f, json!({"p": [], "oi": [1]})))
+            .unwrap();
+        drop(cursor);
+        assert_eq!(json!([1]), value);
+    }
+}
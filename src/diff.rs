@@ -0,0 +1,242 @@
+/// A run of characters in a text diff.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Edit {
+    Equal(String),
+    Delete(String),
+    Insert(String),
+}
+
+/// Minimal edits turning old into new, computed by the linear space Myers
+/// diff algorithm on characters. Adjacent edits of the same kind are merged
+/// into one.
+pub(crate) fn diff(old: &str, new: &str) -> Vec<Edit> {
+    let a = old.chars().collect::<Vec<char>>();
+    let b = new.chars().collect::<Vec<char>>();
+    let mut edits = vec![];
+    diff_chars(&a, &b, &mut edits);
+    edits
+}
+
+fn diff_chars(a: &[char], b: &[char], edits: &mut Vec<Edit>) {
+    let prefix = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    push(edits, Edit::Equal(a[..prefix].iter().collect()));
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    if a_mid.is_empty() || b_mid.is_empty() {
+        push(edits, Edit::Delete(a_mid.iter().collect()));
+        push(edits, Edit::Insert(b_mid.iter().collect()));
+    } else {
+        match middle_snake(a_mid, b_mid) {
+            Some((x, y)) => {
+                diff_chars(&a_mid[..x], &b_mid[..y], edits);
+                diff_chars(&a_mid[x..], &b_mid[y..], edits);
+            }
+            None => {
+                push(edits, Edit::Delete(a_mid.iter().collect()));
+                push(edits, Edit::Insert(b_mid.iter().collect()));
+            }
+        }
+    }
+    push(edits, Edit::Equal(a[a.len() - suffix..].iter().collect()));
+}
+
+fn push(edits: &mut Vec<Edit>, edit: Edit) {
+    match (edits.last_mut(), edit) {
+        (_, Edit::Equal(s) | Edit::Delete(s) | Edit::Insert(s)) if s.is_empty() => {}
+        (Some(Edit::Equal(l)), Edit::Equal(s))
+        | (Some(Edit::Delete(l)), Edit::Delete(s))
+        | (Some(Edit::Insert(l)), Edit::Insert(s)) => l.push_str(&s),
+        (_, edit) => edits.push(edit),
+    }
+}
+
+/// Where the shortest edit paths searched from both ends of a and b meet,
+/// which splits the diff into two halves diffed on their own. Only keeps the
+/// furthest reaching paths of the current edit distance, so memory is linear
+/// in the lengths. None if a and b have nothing in common.
+fn middle_snake(a: &[char], b: &[char]) -> Option<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max_d = (n + m + 1) / 2;
+    let offset = max_d;
+    let len = 2 * max_d + 2;
+    let mut forward = vec![-1isize; len as usize];
+    let mut backward = vec![-1isize; len as usize];
+    forward[(offset + 1) as usize] = 0;
+    backward[(offset + 1) as usize] = 0;
+    let delta = n - m;
+    // paths from the front meet the ones from the back on odd delta
+    let front = delta % 2 != 0;
+    // diagonals skipped as their paths went past the end of a or b
+    let (mut k1_start, mut k1_end, mut k2_start, mut k2_end) = (0, 0, 0, 0);
+    for d in 0..max_d {
+        for k1 in (-d + k1_start..=d - k1_end).step_by(2) {
+            let i = (offset + k1) as usize;
+            let mut x1 = if k1 == -d || (k1 != d && forward[i - 1] < forward[i + 1]) {
+                forward[i + 1]
+            } else {
+                forward[i - 1] + 1
+            };
+            let mut y1 = x1 - k1;
+            while x1 < n && y1 < m && a[x1 as usize] == b[y1 as usize] {
+                x1 += 1;
+                y1 += 1;
+            }
+            forward[i] = x1;
+            if x1 > n {
+                k1_end += 2;
+            } else if y1 > m {
+                k1_start += 2;
+            } else if front {
+                let j = offset + delta - k1;
+                if j >= 0 && j < len && backward[j as usize] != -1 && x1 >= n - backward[j as usize]
+                {
+                    return Some((x1 as usize, y1 as usize));
+                }
+            }
+        }
+        for k2 in (-d + k2_start..=d - k2_end).step_by(2) {
+            let i = (offset + k2) as usize;
+            let mut x2 = if k2 == -d || (k2 != d && backward[i - 1] < backward[i + 1]) {
+                backward[i + 1]
+            } else {
+                backward[i - 1] + 1
+            };
+            let mut y2 = x2 - k2;
+            while x2 < n && y2 < m && a[(n - x2 - 1) as usize] == b[(m - y2 - 1) as usize] {
+                x2 += 1;
+                y2 += 1;
+            }
+            backward[i] = x2;
+            if x2 > n {
+                k2_end += 2;
+            } else if y2 > m {
+                k2_start += 2;
+            } else if !front {
+                let j = offset + delta - k2;
+                if j >= 0 && j < len && forward[j as usize] != -1 {
+                    let x1 = forward[j as usize];
+                    let y1 = offset + x1 - j;
+                    if x1 >= n - x2 {
+                        return Some((x1 as usize, y1 as usize));
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    fn patch(old: &str, edits: &[Edit]) -> String {
+        let mut out = String::new();
+        let mut rest = old;
+        for e in edits {
+            match e {
+                Edit::Equal(s) => {
+                    assert!(rest.starts_with(s.as_str()));
+                    out.push_str(s);
+                    rest = &rest[s.len()..];
+                }
+                Edit::Delete(s) => {
+                    assert!(rest.starts_with(s.as_str()));
+                    rest = &rest[s.len()..];
+                }
+                Edit::Insert(s) => out.push_str(s),
+            }
+        }
+        assert!(rest.is_empty());
+        out
+    }
+
+    #[test]
+    fn test_diff() {
+        let cases = [
+            ("", ""),
+            ("", "abc"),
+            ("abc", ""),
+            ("abcabba", "cbabac"),
+            ("hello world", "hello brave new world"),
+            ("日本語のテキスト", "日本のテキストです"),
+        ];
+        for (old, new) in cases {
+            assert_eq!(new, patch(old, &diff(old, new)));
+        }
+
+        assert_eq!(
+            vec![
+                Edit::Equal("ab".into()),
+                Edit::Delete("c".into()),
+                Edit::Insert("x".into()),
+                Edit::Equal("d".into())
+            ],
+            diff("abcd", "abxd")
+        );
+        // abcabba -> cbabac takes 5 edits at least
+        assert_eq!(5, changed_chars(&diff("abcabba", "cbabac")));
+    }
+
+    fn changed_chars(edits: &[Edit]) -> usize {
+        edits
+            .iter()
+            .map(|e| match e {
+                Edit::Equal(_) => 0,
+                Edit::Delete(s) | Edit::Insert(s) => s.chars().count(),
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_diff_is_minimal() {
+        // fewest changed chars is the chars out of the longest common subsequence
+        let lcs = |a: &[char], b: &[char]| {
+            let mut row = vec![0; b.len() + 1];
+            for x in a {
+                let mut diag = 0;
+                for (j, y) in b.iter().enumerate() {
+                    let up = row[j + 1];
+                    row[j + 1] = if x == y { diag + 1 } else { up.max(row[j]) };
+                    diag = up;
+                }
+            }
+            row[b.len()]
+        };
+        let mut rng = crate::testing::Rng::new(11);
+        let chars = ['a', 'b', 'c', 'é'];
+        for _ in 0..500 {
+            let mut text = |len: usize| {
+                (0..rng.below(len))
+                    .map(|_| chars[rng.below(chars.len())])
+                    .collect::<String>()
+            };
+            let (old, new) = (text(12), text(12));
+            let edits = diff(&old, &new);
+            assert_eq!(new, patch(&old, &edits));
+            let (a, b) = (
+                old.chars().collect::<Vec<_>>(),
+                new.chars().collect::<Vec<_>>(),
+            );
+            assert_eq!(a.len() + b.len() - 2 * lcs(&a, &b), changed_chars(&edits));
+        }
+    }
+
+    #[test]
+    fn test_diff_large_dissimilar_texts() {
+        let mut rng = crate::testing::Rng::new(3);
+        let mut text = |len: usize| {
+            (0..len)
+                .map(|_| char::from(b'a' + rng.below(20) as u8))
+                .collect::<String>()
+        };
+        let (old, new) = (text(5000), text(5000));
+        assert_eq!(new, patch(&old, &diff(&old, &new)));
+    }
+}
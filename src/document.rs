@@ -0,0 +1,275 @@
+use std::borrow::Cow;
+
+use serde_json::Value;
+
+/// A JSON tree operations can be applied to with `Json0::apply_document`,
+/// without converting it to `serde_json::Value` first. `Value` is the default
+/// implementation.
+///
+/// Object methods are only called on nodes for which `is_object` returns
+/// true, and array methods only on nodes for which `is_array` returns true.
+pub trait JsonDocument: Sized {
+    /// Build a node from an operand of an operation.
+    fn from_value(value: Value) -> Self;
+
+    /// The node as a `Value`, used for sub types, tests and errors.
+    fn to_value(&self) -> Cow<'_, Value>;
+
+    /// Whether this node equals value, for test components.
+    fn eq_value(&self, value: &Value) -> bool {
+        *self.to_value() == *value
+    }
+
+    fn is_object(&self) -> bool;
+
+    fn is_array(&self) -> bool;
+
+    fn is_null(&self) -> bool;
+
+    fn get_key(&self, key: &str) -> Option<&Self>;
+
+    fn get_key_mut(&mut self, key: &str) -> Option<&mut Self>;
+
+    /// Insert or replace the value of key.
+    fn insert_key(&mut self, key: String, value: Self);
+
+    fn remove_key(&mut self, key: &str) -> Option<Self>;
+
+    fn get_index(&self, index: usize) -> Option<&Self>;
+
+    fn get_index_mut(&mut self, index: usize) -> Option<&mut Self>;
+
+    fn array_len(&self) -> usize;
+
+    /// Insert value at index, shifting the elements after it. index is never
+    /// greater than the length of the array.
+    fn insert_index(&mut self, index: usize, value: Self);
+
+    fn remove_index(&mut self, index: usize) -> Self;
+}
+
+impl JsonDocument for Value {
+    fn from_value(value: Value) -> Self {
+        value
+    }
+
+    fn to_value(&self) -> Cow<'_, Value> {
+        Cow::Borrowed(self)
+    }
+
+    fn eq_value(&self, value: &Value) -> bool {
+        self == value
+    }
+
+    fn is_object(&self) -> bool {
+        self.is_object()
+    }
+
+    fn is_array(&self) -> bool {
+        self.is_array()
+    }
+
+    fn is_null(&self) -> bool {
+        self.is_null()
+    }
+
+    fn get_key(&self, key: &str) -> Option<&Self> {
+        self.as_object()?.get(key)
+    }
+
+    fn get_key_mut(&mut self, key: &str) -> Option<&mut Self> {
+        self.as_object_mut()?.get_mut(key)
+    }
+
+    fn insert_key(&mut self, key: String, value: Self) {
+        if let Value::Object(obj) = self {
+            obj.insert(key, value);
+        }
+    }
+
+    fn remove_key(&mut self, key: &str) -> Option<Self> {
+        let obj = self.as_object_mut()?;
+        // keep the order of the other keys with preserve_order
+        #[cfg(feature = "preserve-order")]
+        return obj.shift_remove(key);
+        #[cfg(not(feature = "preserve-order"))]
+        obj.remove(key)
+    }
+
+    fn get_index(&self, index: usize) -> Option<&Self> {
+        self.as_array()?.get(index)
+    }
+
+    fn get_index_mut(&mut self, index: usize) -> Option<&mut Self> {
+        self.as_array_mut()?.get_mut(index)
+    }
+
+    fn array_len(&self) -> usize {
+        self.as_array().map_or(0, |a| a.len())
+    }
+
+    fn insert_index(&mut self, index: usize, value: Self) {
+        if let Value::Array(array) = self {
+            array.insert(index, value);
+        }
+    }
+
+    fn remove_index(&mut self, index: usize) -> Self {
+        match self {
+            Value::Array(array) => array.remove(index),
+            _ => Value::Null,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::{error::JsonError, Json0};
+    use test_log::test;
+
+    /// A tree of an application's own, with leaves kept as text.
+    #[derive(Debug, Clone, PartialEq)]
+    enum Node {
+        Leaf(String),
+        Object(BTreeMap<String, Node>),
+        Array(Vec<Node>),
+    }
+
+    impl JsonDocument for Node {
+        fn from_value(value: Value) -> Self {
+            match value {
+                Value::Object(obj) => Node::Object(
+                    obj.into_iter()
+                        .map(|(k, v)| (k, Node::from_value(v)))
+                        .collect(),
+                ),
+                Value::Array(array) => {
+                    Node::Array(array.into_iter().map(Node::from_value).collect())
+                }
+                v => Node::Leaf(v.to_string()),
+            }
+        }
+
+        fn to_value(&self) -> Cow<'_, Value> {
+            Cow::Owned(match self {
+                Node::Leaf(s) => serde_json::from_str(s).unwrap(),
+                Node::Object(obj) => Value::Object(
+                    obj.iter()
+                        .map(|(k, v)| (k.clone(), v.to_value().into_owned()))
+                        .collect(),
+                ),
+                Node::Array(array) => {
+                    Value::Array(array.iter().map(|v| v.to_value().into_owned()).collect())
+                }
+            })
+        }
+
+        fn is_object(&self) -> bool {
+            matches!(self, Node::Object(_))
+        }
+
+        fn is_array(&self) -> bool {
+            matches!(self, Node::Array(_))
+        }
+
+        fn is_null(&self) -> bool {
+            self == &Node::Leaf("null".into())
+        }
+
+        fn get_key(&self, key: &str) -> Option<&Self> {
+            match self {
+                Node::Object(obj) => obj.get(key),
+                _ => None,
+            }
+        }
+
+        fn get_key_mut(&mut self, key: &str) -> Option<&mut Self> {
+            match self {
+                Node::Object(obj) => obj.get_mut(key),
+                _ => None,
+            }
+        }
+
+        fn insert_key(&mut self, key: String, value: Self) {
+            if let Node::Object(obj) = self {
+                obj.insert(key, value);
+            }
+        }
+
+        fn remove_key(&mut self, key: &str) -> Option<Self> {
+            match self {
+                Node::Object(obj) => obj.remove(key),
+                _ => None,
+            }
+        }
+
+        fn get_index(&self, index: usize) -> Option<&Self> {
+            match self {
+                Node::Array(array) => array.get(index),
+                _ => None,
+            }
+        }
+
+        fn get_index_mut(&mut self, index: usize) -> Option<&mut Self> {
+            match self {
+                Node::Array(array) => array.get_mut(index),
+                _ => None,
+            }
+        }
+
+        fn array_len(&self) -> usize {
+            match self {
+                Node::Array(array) => array.len(),
+                _ => 0,
+            }
+        }
+
+        fn insert_index(&mut self, index: usize, value: Self) {
+            if let Node::Array(array) = self {
+                array.insert(index, value);
+            }
+        }
+
+        fn remove_index(&mut self, index: usize) -> Self {
+            match self {
+                Node::Array(array) => array.remove(index),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply_document() {
+        let json0 = Json0::new();
+        let value = serde_json::json!({"a": [1, 2, {"t": "ab"}], "o": {"k": 1}, "n": 1});
+        let op = json0
+            .operation_factory()
+            .from_value(serde_json::json!([
+                {"p": ["a", 0], "ld": 1},
+                {"p": ["a", -1], "li": 3},
+                {"p": ["a", 1, "t"], "t": "text", "o": {"p": 1, "i": "x"}},
+                {"p": ["a", 0], "lm": 2},
+                {"p": ["o", "k"], "om": "j"},
+                {"p": ["o", "j"], "test": 1},
+                {"p": ["n"], "na": 2},
+            ]))
+            .unwrap();
+
+        let mut node = Node::from_value(value.clone());
+        json0.apply_document(&mut node, vec![op.clone()]).unwrap();
+        let mut expected = value;
+        json0.apply(&mut expected, vec![op]).unwrap();
+        assert_eq!(expected, node.to_value().into_owned());
+
+        let failed = json0
+            .operation_factory()
+            .from_value(serde_json::json!([{"p": ["o", "j"], "test": 2}]))
+            .unwrap();
+        let err = json0.apply_document(&mut node, vec![failed]).unwrap_err();
+        assert_matches!(err.root_cause(), JsonError::ApplyOperationError(_));
+        assert_eq!(expected, node.to_value().into_owned());
+    }
+}
@@ -1,23 +1,236 @@
+use std::fmt::Display;
+
 use thiserror::Error;
 
 use crate::{
     json::{ApplyOperationError, RouteError},
-    path::PathError,
+    operation::OperationComponent,
+    path::{Path, PathError},
 };
 
+/// Max length of the operator summary in `ErrorContext`.
+const MAX_OPERATOR_SUMMARY_LEN: usize = 100;
+
 #[derive(Error, Debug)]
 #[error("{}")]
 pub enum JsonError {
-    #[error("{0}")]
+    #[error("[{}] {0}", ErrorCode::RouteFailed)]
     RouteError(#[from] RouteError),
-    #[error("{0}")]
-    ApplyOperationError(#[from] ApplyOperationError),
-    #[error("Invalid operation, reason: \"{0}\"")]
+    #[error("[{}] {0}", .0.code())]
+    ApplyOperationError(#[source] Box<ApplyOperationError>),
+    #[error("[{}] Invalid operation, reason: \"{0}\"", ErrorCode::InvalidOperation)]
     InvalidOperation(String),
-    #[error("{0}")]
-    PathError(#[from] PathError),
-    #[error("Sub type name: {0} conflict with internal sub type name")]
+    #[error("[{}] {0}", ErrorCode::InvalidPath)]
+    PathError(PathError),
+    #[error(
+        "[{}] Sub type name: {0} conflict with internal sub type name",
+        ErrorCode::ConflictSubType
+    )]
     ConflictSubType(String),
+    #[error("[{}] Operation rejected, reason: \"{0}\"", ErrorCode::Rejected)]
+    Rejected(String),
+    #[error("[{}] Invalid JSON schema, reason: \"{0}\"", ErrorCode::InvalidSchema)]
+    InvalidSchema(String),
+    #[error(
+        "[{}] Encode or decode operation failed, reason: \"{0}\"",
+        ErrorCode::EncodingFailed
+    )]
+    EncodingError(String),
+    #[error(
+        "[{}] Operation exceeds limit of {limit}: {actual} > {max}",
+        ErrorCode::LimitExceeded
+    )]
+    LimitExceeded {
+        limit: &'static str,
+        max: usize,
+        actual: usize,
+    },
+    #[error(
+        "[{}] Expect {expected} on path: {path}, but found {found}",
+        ErrorCode::TypeMismatch
+    )]
+    TypeMismatch {
+        path: Box<Path>,
+        expected: &'static str,
+        found: &'static str,
+    },
+    #[error("{source}, at {context}")]
+    Context {
+        context: Box<ErrorContext>,
+        source: Box<JsonError>,
+    },
+}
+
+/// Machine readable kind of a `JsonError`. Codes and their names never change
+/// once released, so they can be mapped to responses of APIs without matching
+/// on error messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u16)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    /// Path of a component does not exist in the document
+    RouteFailed = 1,
+    /// Operator can not apply on the value on its path
+    InvalidApplyTarget = 2,
+    /// Test component does not match the value on its path
+    TestFailed = 3,
+    /// Sub type operand is invalid for the value on its path
+    InvalidSubtypeOperation = 4,
+    InvalidOperation = 5,
+    InvalidPath = 6,
+    ConflictSubType = 7,
+    /// Rejected by an apply hook
+    Rejected = 8,
+    InvalidSchema = 9,
+    EncodingFailed = 10,
+    LimitExceeded = 11,
+    /// Value on a path is not of the type read
+    TypeMismatch = 12,
+}
+
+impl ErrorCode {
+    /// Numeric code, like for FFI consumers.
+    pub fn as_u16(&self) -> u16 {
+        *self as u16
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::RouteFailed => "route_failed",
+            ErrorCode::InvalidApplyTarget => "invalid_apply_target",
+            ErrorCode::TestFailed => "test_failed",
+            ErrorCode::InvalidSubtypeOperation => "invalid_subtype_operation",
+            ErrorCode::InvalidOperation => "invalid_operation",
+            ErrorCode::InvalidPath => "invalid_path",
+            ErrorCode::ConflictSubType => "conflict_sub_type",
+            ErrorCode::Rejected => "rejected",
+            ErrorCode::InvalidSchema => "invalid_schema",
+            ErrorCode::EncodingFailed => "encoding_failed",
+            ErrorCode::LimitExceeded => "limit_exceeded",
+            ErrorCode::TypeMismatch => "type_mismatch",
+        }
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl JsonError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            JsonError::RouteError(_) => ErrorCode::RouteFailed,
+            JsonError::ApplyOperationError(e) => e.code(),
+            JsonError::InvalidOperation(_) => ErrorCode::InvalidOperation,
+            JsonError::PathError(_) => ErrorCode::InvalidPath,
+            JsonError::ConflictSubType(_) => ErrorCode::ConflictSubType,
+            JsonError::Rejected(_) => ErrorCode::Rejected,
+            JsonError::InvalidSchema(_) => ErrorCode::InvalidSchema,
+            JsonError::EncodingError(_) => ErrorCode::EncodingFailed,
+            JsonError::LimitExceeded { .. } => ErrorCode::LimitExceeded,
+            JsonError::TypeMismatch { .. } => ErrorCode::TypeMismatch,
+            JsonError::Context { source, .. } => source.code(),
+        }
+    }
+
+    /// The error without the context of where it happened.
+    pub fn root_cause(&self) -> &JsonError {
+        match self {
+            JsonError::Context { source, .. } => source.root_cause(),
+            e => e,
+        }
+    }
+
+    /// Which component failed, if known.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            JsonError::Context { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Attach the context of the failed component, unless the error already
+    /// has one.
+    pub(crate) fn with_context<F: FnOnce() -> ErrorContext>(self, context: F) -> JsonError {
+        match self {
+            JsonError::Context { .. } => self,
+            e => JsonError::Context {
+                context: Box::new(context()),
+                source: Box::new(e),
+            },
+        }
+    }
+}
+
+impl From<ApplyOperationError> for JsonError {
+    fn from(e: ApplyOperationError) -> Self {
+        JsonError::ApplyOperationError(Box::new(e))
+    }
+}
+
+/// Paths over the max path depth fail like operations over the limits of
+/// `OperationLimits`.
+impl From<PathError> for JsonError {
+    fn from(e: PathError) -> Self {
+        match e {
+            PathError::TooDeep { max, actual } => JsonError::LimitExceeded {
+                limit: "path depth",
+                max,
+                actual,
+            },
+            e => JsonError::PathError(e),
+        }
+    }
+}
+
+/// Where in the operations applied or transformed an error happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorContext {
+    /// Index of the operation among the operations applied, or 0 for the
+    /// operation and 1 for the base operation of a transform
+    pub operation_index: usize,
+    /// Index of the component in its operation
+    pub component_index: usize,
+    /// Full path of the component
+    pub path: Path,
+    /// The operator of the component, shortened if it carries a large value
+    pub operator: String,
+}
+
+impl ErrorContext {
+    pub(crate) fn new(
+        operation_index: usize,
+        component_index: usize,
+        component: &OperationComponent,
+    ) -> ErrorContext {
+        let mut operator = component.operator.to_string();
+        if operator.len() > MAX_OPERATOR_SUMMARY_LEN {
+            let mut end = MAX_OPERATOR_SUMMARY_LEN;
+            while !operator.is_char_boundary(end) {
+                end -= 1;
+            }
+            operator.truncate(end);
+            operator.push_str("...");
+        }
+        ErrorContext {
+            operation_index,
+            component_index,
+            path: component.path.clone(),
+            operator,
+        }
+    }
+}
+
+impl Display for ErrorContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "operation {} component {} on path {} ({})",
+            self.operation_index, self.component_index, self.path, self.operator
+        )
+    }
 }
 
 pub type Result<T> = std::result::Result<T, JsonError>;
@@ -0,0 +1,215 @@
+//! C API operating on documents and operations written as UTF-8 JSON strings,
+//! to embed json0 in other runtimes. Build it as a C library with
+//! `cargo rustc --release --features ffi --crate-type cdylib`.
+//!
+//! Every function returns `JSON0_OK` and writes the result to `out` on
+//! success, or returns an error code and writes the error message to `out`.
+//! Error codes other than the ones defined here are the numeric values of
+//! `ErrorCode`. Strings written to `out` are owned by the caller and must be
+//! freed with `json0_string_free`. Input strings are only borrowed during the
+//! call.
+
+use std::{
+    ffi::{c_char, CStr, CString},
+    panic::{catch_unwind, UnwindSafe},
+};
+
+use crate::{bindings, error::JsonError};
+
+pub const JSON0_OK: i32 = 0;
+/// An argument is null or not valid UTF-8.
+pub const JSON0_INVALID_ARGUMENT: i32 = -1;
+/// json0 panicked, which is a bug.
+pub const JSON0_PANIC: i32 = -2;
+
+/// Apply op on snapshot and write the new snapshot to out.
+///
+/// # Safety
+///
+/// snapshot and op must be null or nul terminated strings, out must be a
+/// valid pointer to write a string pointer to.
+#[no_mangle]
+pub unsafe extern "C" fn json0_apply(
+    snapshot: *const c_char,
+    op: *const c_char,
+    out: *mut *mut c_char,
+) -> i32 {
+    call(out, || {
+        bindings::apply(to_str(snapshot)?, to_str(op)?).map_err(Into::into)
+    })
+}
+
+/// Transform op against other_op with side `"left"` or `"right"`, which wins
+/// when both of them insert on the same position, and write the transformed
+/// op to out.
+///
+/// # Safety
+///
+/// Same as `json0_apply`.
+#[no_mangle]
+pub unsafe extern "C" fn json0_transform(
+    op: *const c_char,
+    other_op: *const c_char,
+    side: *const c_char,
+    out: *mut *mut c_char,
+) -> i32 {
+    call(out, || {
+        bindings::transform(to_str(op)?, to_str(other_op)?, to_str(side)?).map_err(Into::into)
+    })
+}
+
+/// Compose op1 followed by op2 into one operation and write it to out.
+///
+/// # Safety
+///
+/// Same as `json0_apply`.
+#[no_mangle]
+pub unsafe extern "C" fn json0_compose(
+    op1: *const c_char,
+    op2: *const c_char,
+    out: *mut *mut c_char,
+) -> i32 {
+    call(out, || {
+        bindings::compose(to_str(op1)?, to_str(op2)?).map_err(Into::into)
+    })
+}
+
+/// Write the operation undoing op to out.
+///
+/// # Safety
+///
+/// Same as `json0_apply`.
+#[no_mangle]
+pub unsafe extern "C" fn json0_invert(op: *const c_char, out: *mut *mut c_char) -> i32 {
+    call(out, || bindings::invert(to_str(op)?).map_err(Into::into))
+}
+
+/// Free a string written by json0. Freeing null does nothing.
+///
+/// # Safety
+///
+/// s must be null or a string written by json0 which is not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn json0_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Error of a call with its code.
+struct CallError {
+    code: i32,
+    message: String,
+}
+
+impl From<JsonError> for CallError {
+    fn from(e: JsonError) -> Self {
+        CallError {
+            code: e.code().as_u16() as i32,
+            message: e.to_string(),
+        }
+    }
+}
+
+unsafe fn call<F>(out: *mut *mut c_char, f: F) -> i32
+where
+    F: FnOnce() -> std::result::Result<String, CallError> + UnwindSafe,
+{
+    if out.is_null() {
+        return JSON0_INVALID_ARGUMENT;
+    }
+    let (code, output) = match catch_unwind(f) {
+        Ok(Ok(s)) => (JSON0_OK, s),
+        Ok(Err(e)) => (e.code, e.message),
+        Err(_) => (JSON0_PANIC, "json0 panicked".to_string()),
+    };
+    // JSON escapes nul, but error messages may quote keys with nul
+    *out = CString::new(output.replace('\0', "\\u0000"))
+        .unwrap_or_default()
+        .into_raw();
+    code
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> std::result::Result<&'a str, CallError> {
+    if s.is_null() {
+        return Err(CallError {
+            code: JSON0_INVALID_ARGUMENT,
+            message: "argument is null".to_string(),
+        });
+    }
+    CStr::from_ptr(s).to_str().map_err(|e| CallError {
+        code: JSON0_INVALID_ARGUMENT,
+        message: format!("argument is not valid UTF-8: {e}"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+    use test_log::test;
+
+    fn take(out: *mut c_char) -> String {
+        let s = unsafe { CStr::from_ptr(out) }.to_str().unwrap().to_string();
+        unsafe { json0_string_free(out) };
+        s
+    }
+
+    #[test]
+    fn test_ffi() {
+        let c = |s: &str| CString::new(s).unwrap();
+        let mut out = std::ptr::null_mut();
+
+        let code = unsafe {
+            json0_apply(
+                c(r#"{"n":1}"#).as_ptr(),
+                c(r#"[{"p":["n"],"na":2}]"#).as_ptr(),
+                &mut out,
+            )
+        };
+        assert_eq!(JSON0_OK, code);
+        assert_eq!(r#"{"n":3}"#, take(out));
+
+        let code = unsafe {
+            json0_transform(
+                c(r#"[{"p":[0],"li":1}]"#).as_ptr(),
+                c(r#"[{"p":[0],"li":2}]"#).as_ptr(),
+                c("right").as_ptr(),
+                &mut out,
+            )
+        };
+        assert_eq!(JSON0_OK, code);
+        assert_eq!(r#"[{"p":[1],"li":1}]"#, take(out));
+
+        let code = unsafe {
+            json0_compose(
+                c(r#"[{"p":["k"],"oi":1}]"#).as_ptr(),
+                c(r#"[{"p":["k"],"od":1}]"#).as_ptr(),
+                &mut out,
+            )
+        };
+        assert_eq!(JSON0_OK, code);
+        assert_eq!("[]", take(out));
+
+        let code = unsafe { json0_invert(c(r#"[{"p":[0],"lm":1}]"#).as_ptr(), &mut out) };
+        assert_eq!(JSON0_OK, code);
+        assert_eq!(r#"[{"p":[1],"lm":0}]"#, take(out));
+
+        let code = unsafe {
+            json0_apply(
+                c("{}").as_ptr(),
+                c(r#"[{"p":["a"],"test":1}]"#).as_ptr(),
+                &mut out,
+            )
+        };
+        assert_eq!(ErrorCode::TestFailed.as_u16() as i32, code);
+        assert!(take(out).contains("Test failed"));
+
+        let code = unsafe { json0_invert(std::ptr::null(), &mut out) };
+        assert_eq!(JSON0_INVALID_ARGUMENT, code);
+        assert_eq!("argument is null", take(out));
+        let code = unsafe { json0_invert(c("[]").as_ptr(), std::ptr::null_mut()) };
+        assert_eq!(JSON0_INVALID_ARGUMENT, code);
+        unsafe { json0_string_free(std::ptr::null_mut()) };
+    }
+}
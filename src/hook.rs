@@ -0,0 +1,270 @@
+#[cfg(feature = "schema")]
+use jsonschema::JSONSchema;
+use serde_json::Value;
+
+use crate::{
+    error::{JsonError, Result},
+    operation::{OperationComponent, Operator},
+    path::{PathElement, PathPattern},
+};
+
+/// Hook called around every component applied by `Json0`. Hooks are called in
+/// the order they are added.
+pub trait ApplyHook {
+    /// Called before component is applied on value. Returns the component to
+    /// apply, which may be rewritten. Returning an error vetoes the component
+    /// and fails the apply.
+    fn before_component(
+        &self,
+        _value: &Value,
+        component: OperationComponent,
+    ) -> Result<OperationComponent> {
+        Ok(component)
+    }
+
+    /// Called after component is applied on value. Returning an error reverts
+    /// the component and fails the apply.
+    fn after_component(&self, _value: &Value, _component: &OperationComponent) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called after all components of an operation are applied on value.
+    /// Returning an error reverts the operation and fails the apply.
+    fn after_operation(&self, _value: &Value) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Rejects components writing paths not allowed by path pattern rules. A
+/// component writes its path, and the destination of a move. Writing a path
+/// is denied if a deny rule matches it, its ancestor or its descendant, as
+/// writing it replaces all its descendants. Otherwise it's allowed if an
+/// allow rule matches it or its ancestor, or by default.
+pub struct AclHook {
+    allow_by_default: bool,
+    allowed: Vec<PathPattern>,
+    denied: Vec<PathPattern>,
+}
+
+impl AclHook {
+    pub fn allow_by_default() -> AclHook {
+        AclHook {
+            allow_by_default: true,
+            allowed: vec![],
+            denied: vec![],
+        }
+    }
+
+    pub fn deny_by_default() -> AclHook {
+        AclHook {
+            allow_by_default: false,
+            allowed: vec![],
+            denied: vec![],
+        }
+    }
+
+    pub fn allow(mut self, pattern: PathPattern) -> Self {
+        self.allowed.push(pattern);
+        self
+    }
+
+    pub fn deny(mut self, pattern: PathPattern) -> Self {
+        self.denied.push(pattern);
+        self
+    }
+
+    fn check(&self, component: &OperationComponent) -> Result<()> {
+        let mut written = vec![component.path.clone()];
+        let dest = match &component.operator {
+            Operator::ListMove(to) => Some(PathElement::Index(*to)),
+            Operator::ObjectMove(to) => Some(PathElement::key(to)),
+            _ => None,
+        };
+        if let Some(dest) = dest {
+            let mut path = component.path.clone();
+            path.pop();
+            path.push(dest);
+            written.push(path);
+        }
+        for path in written.iter() {
+            if let Some(p) = self
+                .denied
+                .iter()
+                .find(|p| p.matches_ancestor_or_self(path) || p.may_match_self_or_descendant(path))
+            {
+                return Err(JsonError::Rejected(format!(
+                    "{component} writes {path} denied by {p}"
+                )));
+            }
+            let allowed = self.allow_by_default
+                || self
+                    .allowed
+                    .iter()
+                    .any(|p| p.matches_ancestor_or_self(path));
+            if !allowed {
+                return Err(JsonError::Rejected(format!(
+                    "{component} writes {path} which is not allowed"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ApplyHook for AclHook {
+    fn before_component(
+        &self,
+        _value: &Value,
+        component: OperationComponent,
+    ) -> Result<OperationComponent> {
+        self.check(&component)?;
+        Ok(component)
+    }
+}
+
+/// Rejects operations producing a document which is not valid against a JSON
+/// Schema. The document is validated after each operation rather than each
+/// component, so components of an operation may pass through invalid states.
+#[cfg(feature = "schema")]
+pub struct SchemaHook {
+    schema: JSONSchema,
+}
+
+#[cfg(feature = "schema")]
+impl SchemaHook {
+    pub fn new(schema: &Value) -> Result<SchemaHook> {
+        let schema =
+            JSONSchema::compile(schema).map_err(|e| JsonError::InvalidSchema(e.to_string()))?;
+        Ok(SchemaHook { schema })
+    }
+}
+
+#[cfg(feature = "schema")]
+impl ApplyHook for SchemaHook {
+    fn after_operation(&self, value: &Value) -> Result<()> {
+        self.schema.validate(value).map_err(|errors| {
+            JsonError::Rejected(
+                errors
+                    .map(|e| format!("{} at \"{}\"", e, e.instance_path))
+                    .collect::<Vec<String>>()
+                    .join(", "),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "schema")]
+    use crate::ApplyOptions;
+    use crate::Json0;
+    use test_log::test;
+
+    #[test]
+    fn test_acl_hook() {
+        let pattern = |s: &str| PathPattern::try_from(s).unwrap();
+        let mut json0 = Json0::new();
+        json0.add_apply_hook(Box::new(
+            AclHook::deny_by_default()
+                .allow(pattern(r#"["users", "*", "profile"]"#))
+                .allow(pattern(r#"["drafts", "**"]"#))
+                .deny(pattern(r#"["users", "*", "profile", "verified"]"#)),
+        ));
+        let mut value = serde_json::json!({
+            "users": [{"profile": {"name": "a", "verified": true}}],
+            "drafts": {"k": 1},
+        });
+        let mut apply = |v: Value| {
+            let op = json0.operation_factory().from_value(v).unwrap();
+            json0.apply(&mut value, vec![op])
+        };
+
+        apply(serde_json::json!([{"p": ["users", 0, "profile", "name"], "od": "a", "oi": "b"}]))
+            .unwrap();
+        apply(serde_json::json!([{"p": ["drafts", "k"], "om": "j"}])).unwrap();
+        apply(serde_json::json!([{"p": ["drafts"], "od": {"j": 1}, "oi": {}}])).unwrap();
+        // writes a denied path
+        assert_matches!(
+            apply(serde_json::json!([{"p": ["users", 0, "profile", "verified"], "od": true}]))
+                .unwrap_err()
+                .root_cause(),
+            JsonError::Rejected(_)
+        );
+        // replaces a denied path
+        assert_matches!(
+            apply(serde_json::json!([{"p": ["users", 0, "profile"], "od": {}}]))
+                .unwrap_err()
+                .root_cause(),
+            JsonError::Rejected(_)
+        );
+        // not allowed
+        assert_matches!(
+            apply(serde_json::json!([{"p": ["users", 0, "role"], "oi": "admin"}]))
+                .unwrap_err()
+                .root_cause(),
+            JsonError::Rejected(_)
+        );
+        assert_matches!(
+            apply(serde_json::json!([{"p": ["users", 0, "profile", "name"], "om": "verified"}]))
+                .unwrap_err()
+                .root_cause(),
+            JsonError::Rejected(_)
+        );
+        assert_eq!(
+            serde_json::json!({
+                "users": [{"profile": {"name": "b", "verified": true}}],
+                "drafts": {},
+            }),
+            value
+        );
+    }
+
+    #[cfg(feature = "schema")]
+    #[test]
+    fn test_schema_hook() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {"n": {"type": "integer", "maximum": 10}},
+            "required": ["n"]
+        });
+        let mut json0 = Json0::new();
+        json0.add_apply_hook(Box::new(SchemaHook::new(&schema).unwrap()));
+        let parse = |v: Value| json0.operation_factory().from_value(v).unwrap();
+
+        let mut value = serde_json::json!({"n": 1});
+        // intermediate state without n is allowed
+        json0
+            .apply(
+                &mut value,
+                vec![parse(serde_json::json!([
+                    {"p":["n"],"od":1},
+                    {"p":["n"],"oi":5}
+                ]))],
+            )
+            .unwrap();
+        assert_eq!(serde_json::json!({"n": 5}), value);
+
+        let ops = vec![
+            parse(serde_json::json!([{"p":["m"],"oi":1}])),
+            parse(serde_json::json!([{"p":["n"],"na":6}])),
+        ];
+        assert_matches!(
+            json0.apply(&mut value, ops.clone()),
+            Err(JsonError::Rejected(_))
+        );
+        assert_eq!(serde_json::json!({"n": 5, "m": 1}), value);
+
+        let mut value = serde_json::json!({"n": 5});
+        assert_matches!(
+            json0.apply_with(&mut value, ops, &ApplyOptions::new().atomic(true)),
+            Err(JsonError::Rejected(_))
+        );
+        assert_eq!(serde_json::json!({"n": 5}), value);
+
+        assert_matches!(
+            SchemaHook::new(&serde_json::json!({"type": 1})).err(),
+            Some(JsonError::InvalidSchema(_))
+        );
+    }
+}
@@ -1,11 +1,18 @@
-use std::mem;
+use std::{cell::RefCell, mem, sync::Arc};
 use thiserror::Error;
 
 use crate::{
-    operation::Operator,
-    path::{Path, PathElement},
+    canonical::NumberEquality,
+    document::JsonDocument,
+    error::{ErrorCode, ErrorContext, JsonError, Result},
+    operation::{into_value, Operand, Operation, OperationComponent, Operator},
+    path::{Path, PathElement, PathSlice},
+    sub_type::{self, resolve_text_delete, NumberOverflow, SubType, SubTypeFunctions},
+    text_delta::TEXT_DELTA_SUB_TYPE_NAME,
+    tombstone::{tombstone_components, SoftDelete},
 };
 
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 #[derive(Error, Debug)]
@@ -13,6 +20,8 @@ use serde_json::Value;
 pub enum RouteError {
     #[error("Reach leaf node in json, but still has path: {0} remain")]
     ReachLeafNode(Path),
+    #[error("No value on path: {0}")]
+    MissingPath(Path),
     #[error("No more path to route into {json_value}")]
     NotEnoughPath { json_value: Value },
     #[error("Expect key path type to route into {json_value}, but next path is {next_path}")]
@@ -29,6 +38,57 @@ pub enum RouteError {
 
 pub type RouteResult<T> = std::result::Result<T, RouteError>;
 
+/// Where routing a path into a value ends.
+#[derive(Debug, PartialEq)]
+pub enum RouteOutcome<'a, D> {
+    /// The value on the path.
+    Found(&'a D),
+    /// There's no value on this path, the shortest prefix of the routed path
+    /// missing a value. The value on its parent is an object without the key,
+    /// a list shorter than the index, or null.
+    MissingAt(Path),
+    /// The value on path at can't be routed into by next_path, as it's a leaf
+    /// value, or an object or a list which next_path is not a key or an index
+    /// of.
+    TypeMismatch {
+        at: Path,
+        next_path: PathElement,
+        value: &'a D,
+    },
+}
+
+impl<D: JsonDocument> RouteOutcome<'_, D> {
+    pub fn is_found(&self) -> bool {
+        matches!(self, RouteOutcome::Found(_))
+    }
+
+    /// The error `route_get` fails with on paths, the routed path.
+    fn into_error(self, paths: PathSlice) -> Option<RouteError> {
+        let RouteOutcome::TypeMismatch {
+            at,
+            next_path,
+            value,
+        } = self
+        else {
+            return None;
+        };
+        let json_value = value.to_value().into_owned();
+        Some(if value.is_object() {
+            RouteError::ExpectKeyPath {
+                json_value,
+                next_path,
+            }
+        } else if value.is_array() {
+            RouteError::ExpectIndexPath {
+                json_value,
+                next_path,
+            }
+        } else {
+            RouteError::ReachLeafNode(paths.split_at(at.len()).1.to_path())
+        })
+    }
+}
+
 #[derive(Error, Debug)]
 #[error("{}")]
 pub enum ApplyOperationError {
@@ -36,316 +96,1223 @@ pub enum ApplyOperationError {
     RouteError(#[from] RouteError),
     #[error("Can not apply operator: {operator} on value: {target_value}, reason: \"{reason}\"")]
     InvalidApplyTarget {
-        operator: Operator,
-        target_value: Value,
+        operator: Box<Operator>,
+        target_value: Box<Value>,
         reason: String,
     },
     #[error("Can not apply subtype operation: {{type: {subtype_name}, operand: {subtype_operand}}} on value: {target_value}, reason: \"{reason}\"")]
     InvalidApplySubtypeOperationTarget {
         subtype_name: String,
-        target_value: Value,
-        subtype_operand: Value,
+        target_value: Box<Value>,
+        subtype_operand: Box<Value>,
         reason: String,
     },
+    #[error("Test failed on path: {path}, expect: {expected}, actual: {}", .actual.as_ref().map(|v| v.to_string()).unwrap_or("nothing".into()))]
+    TestFailed {
+        path: Box<Path>,
+        expected: Box<Value>,
+        actual: Option<Box<Value>>,
+    },
     #[error("Invalid subtype operator: {{type: {subtype_name}, operand: {subtype_operand}}}, can not apply it on value: {target_value}, reason: \"{reason}\"")]
     InvalidSubtypeOperator {
         subtype_name: String,
-        subtype_operand: Value,
-        target_value: Value,
+        subtype_operand: Box<Value>,
+        target_value: Box<Value>,
         reason: String,
     },
+    /// Text to delete is not the text on its offset in bytes. actual is None
+    /// if the range to delete is out of the text or splits a char.
+    #[error("Text to delete: {expected:?} at offset: {offset} does not match text of length: {text_len}, which has {} there", .actual.as_ref().map(|a| format!("{:?}", a)).unwrap_or("nothing".into()))]
+    TextDeleteMismatch {
+        offset: usize,
+        expected: String,
+        actual: Option<String>,
+        text_len: usize,
+    },
+}
+
+impl ApplyOperationError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ApplyOperationError::RouteError(_) => ErrorCode::RouteFailed,
+            ApplyOperationError::InvalidApplyTarget { .. }
+            | ApplyOperationError::InvalidApplySubtypeOperationTarget { .. } => {
+                ErrorCode::InvalidApplyTarget
+            }
+            ApplyOperationError::TestFailed { .. } => ErrorCode::TestFailed,
+            ApplyOperationError::InvalidSubtypeOperator { .. }
+            | ApplyOperationError::TextDeleteMismatch { .. } => ErrorCode::InvalidSubtypeOperation,
+        }
+    }
 }
 
 pub type ApplyResult<T> = std::result::Result<T, ApplyOperationError>;
 
-pub trait Routable {
-    fn route_get(&self, paths: &Path) -> RouteResult<Option<&Value>>;
+/// How list components on indexes out of the range of their list are
+/// applied. An index is out of range if there's no element on it, except that
+/// `li` can insert right after the last element, and `lm` can move to the
+/// index of any element.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BoundsPolicy {
+    /// Insert and move to the end of the list instead, and do nothing for the
+    /// other components on missing elements.
+    #[default]
+    Clamp,
+    /// Fail the component.
+    Error,
+    /// Skip the component.
+    Ignore,
+}
 
-    fn route_get_mut(&mut self, paths: &Path) -> RouteResult<Option<&mut Value>>;
+/// Options to control how operations are applied by `Json0::apply_with`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ApplyOptions {
+    upsert: bool,
+    atomic: bool,
+    strict: bool,
+    bounds: BoundsPolicy,
+    prune_empty: Option<Path>,
+    number_equality: NumberEquality,
+    number_overflow: NumberOverflow,
+    soft_delete: Option<SoftDelete>,
 }
 
-pub trait Appliable {
-    fn apply(&mut self, paths: Path, operator: Operator) -> ApplyResult<()>;
+impl ApplyOptions {
+    pub fn new() -> ApplyOptions {
+        ApplyOptions::default()
+    }
+
+    /// Create missing intermediate objects on the path of inserts and sub type
+    /// operations instead of failing.
+    pub fn upsert(mut self, upsert: bool) -> Self {
+        self.upsert = upsert;
+        self
+    }
+
+    pub fn is_upsert(&self) -> bool {
+        self.upsert
+    }
+
+    /// Revert all the changes already made on value when apply fails.
+    pub fn atomic(mut self, atomic: bool) -> Self {
+        self.atomic = atomic;
+        self
+    }
+
+    pub fn is_atomic(&self) -> bool {
+        self.atomic
+    }
+
+    /// Reject components whose declared values to remove, `od`, `ld` and the
+    /// old value of replaces, are not the values actually on their paths,
+    /// including inserts declaring no value where there's one. The inverses
+    /// of such components restore the declared values rather than the
+    /// removed ones.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Set how list components on out of range indexes are applied. They are
+    /// clamped by default.
+    pub fn bounds(mut self, bounds: BoundsPolicy) -> Self {
+        self.bounds = bounds;
+        self
+    }
+
+    pub fn bounds_policy(&self) -> BoundsPolicy {
+        self.bounds
+    }
+
+    /// Set how numbers are compared in strict mode, which compares them
+    /// exactly by default.
+    pub fn number_equality(mut self, number_equality: NumberEquality) -> Self {
+        self.number_equality = number_equality;
+        self
+    }
+
+    pub fn number_equality_policy(&self) -> NumberEquality {
+        self.number_equality
+    }
+
+    /// Set how `na` handles a sum out of the range of i64, in place of the
+    /// registered NumberAdd. Operations wrap by default, which is the only
+    /// policy keeping concurrent adds convergent, so apply the others only on
+    /// values not edited concurrently.
+    pub fn number_overflow(mut self, number_overflow: NumberOverflow) -> Self {
+        self.number_overflow = number_overflow;
+        self
+    }
+
+    pub fn number_overflow_policy(&self) -> NumberOverflow {
+        self.number_overflow
+    }
+
+    /// Remove the empty objects and lists under path after the operations are
+    /// applied, as `Json0::prune_empty` does. The removals are reverted by the
+    /// returned operation like the other changes, and are returned by
+    /// `Json0::apply_with_pruned` to be sent to other sites.
+    pub fn prune_empty(mut self, under: Option<Path>) -> Self {
+        self.prune_empty = under;
+        self
+    }
+
+    pub fn prune_empty_under(&self) -> Option<&Path> {
+        self.prune_empty.as_ref()
+    }
+
+    /// Keep the values removed by `od` and `ld` as tombstones, see
+    /// `SoftDelete`. The root of the value applied on must be an object.
+    pub fn soft_delete(mut self, soft_delete: Option<SoftDelete>) -> Self {
+        self.soft_delete = soft_delete;
+        self
+    }
+
+    pub fn soft_delete_mode(&self) -> Option<&SoftDelete> {
+        self.soft_delete.as_ref()
+    }
 }
 
-impl Routable for Value {
-    fn route_get(&self, paths: &Path) -> RouteResult<Option<&Value>> {
-        match self {
-            Value::Array(array) => array.route_get(paths),
-            Value::Object(obj) => obj.route_get(paths),
-            Value::Null => Ok(None),
-            _ => {
-                if paths.is_empty() {
-                    Ok(Some(self))
-                } else {
-                    Err(RouteError::ReachLeafNode(paths.clone()))
+/// Changes made by `Json0::apply_verbose`. Paths and indexes are those at the
+/// time each change is made.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ApplyReport {
+    /// Outermost paths of the changed values.
+    pub changed_paths: Vec<Path>,
+    /// Values inserted with the path they are inserted on.
+    pub inserted: Vec<(Path, Value)>,
+    /// Values removed with the path they are removed from.
+    pub removed: Vec<(Path, Value)>,
+    /// Shifts of list indexes caused by inserting, deleting or moving elements.
+    pub index_shifts: Vec<IndexShift>,
+    /// One event for each applied component in the order they are applied.
+    pub events: Vec<ChangeEvent>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexShift {
+    /// Elements from index on are shifted right by one.
+    Inserted { list: Path, index: usize },
+    /// Elements after index are shifted left by one.
+    Deleted { list: Path, index: usize },
+    /// Element is moved from one index to another, elements in between are
+    /// shifted toward the old index.
+    Moved { list: Path, from: usize, to: usize },
+}
+
+/// A change made by an applied component, serialized with its kind in
+/// `"type"` so change feeds can be consumed without knowing json0.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChangeEvent {
+    Inserted {
+        path: Path,
+        value: Value,
+    },
+    Removed {
+        path: Path,
+        value: Value,
+    },
+    Replaced {
+        path: Path,
+        old: Value,
+        new: Value,
+    },
+    Moved {
+        from: Path,
+        to: Path,
+    },
+    /// Edited by the text or text-delta sub type. Null if there was no text.
+    TextEdited {
+        path: Path,
+        old: Value,
+        new: Value,
+    },
+    NumberAdded {
+        path: Path,
+        old: Value,
+        new: Value,
+    },
+}
+
+/// Wraps an applier to report changes made by the applied components.
+pub(crate) struct Reporting<'a, A> {
+    inner: &'a A,
+    report: RefCell<ApplyReport>,
+}
+
+impl<'a, A: ComponentApplier> Reporting<'a, A> {
+    pub fn new(inner: &'a A) -> Reporting<'a, A> {
+        Reporting {
+            inner,
+            report: RefCell::new(ApplyReport::default()),
+        }
+    }
+
+    pub fn into_report(self) -> ApplyReport {
+        let mut report = self.report.into_inner();
+        report.changed_paths = Path::outermost_of(mem::take(&mut report.changed_paths));
+        report
+    }
+}
+
+impl<'a, A: ComponentApplier> ComponentApplier for Reporting<'a, A> {
+    fn prepare_component(
+        &self,
+        value: &Value,
+        op: OperationComponent,
+    ) -> Result<OperationComponent> {
+        self.inner.prepare_component(value, op)
+    }
+
+    fn apply_component(&self, value: &mut Value, op: OperationComponent) -> Result<()> {
+        if matches!(op.operator, Operator::Noop() | Operator::Test(_)) {
+            return self.inner.apply_component(value, op);
+        }
+
+        let path = resolve_end(value, &op.path);
+        let (parent, last) = path.split_at(path.len().saturating_sub(1));
+        let (removes, inserts) = match op.operator {
+            Operator::ListInsert(_) => (false, true),
+            Operator::ListDelete(_) | Operator::ObjectDelete(_) => (true, false),
+            Operator::ListReplace(_, _)
+            | Operator::ObjectInsert(_)
+            | Operator::ObjectReplace(_, _) => (true, true),
+            _ => (false, false),
+        };
+        let subtype = match &op.operator {
+            Operator::SubType(t, _, _) => Some(t.clone()),
+            _ => None,
+        };
+        let old = if removes || subtype.is_some() {
+            value.route_get(&path).ok().flatten().cloned()
+        } else {
+            None
+        };
+        let index = match last.get(0) {
+            Some(PathElement::Index(i)) => *i,
+            _ => 0,
+        };
+        let (shift, changed) = match &op.operator {
+            Operator::ListInsert(_) => (
+                Some(IndexShift::Inserted {
+                    list: parent,
+                    index,
+                }),
+                path.clone(),
+            ),
+            Operator::ListDelete(_) => (
+                Some(IndexShift::Deleted {
+                    list: parent,
+                    index,
+                }),
+                path.clone(),
+            ),
+            Operator::ListMove(to) => (
+                Some(IndexShift::Moved {
+                    list: parent.clone(),
+                    from: index,
+                    to: *to,
+                }),
+                parent,
+            ),
+            Operator::ObjectMove(to) => {
+                let mut to_path = parent;
+                to_path.push(PathElement::key(to));
+                (None, to_path)
+            }
+            _ => (None, path.clone()),
+        };
+        let moved_to = match &op.operator {
+            Operator::ListMove(to) => Some(changed.join(&Path::from((*to,)))),
+            Operator::ObjectMove(_) => Some(changed.clone()),
+            _ => None,
+        };
+        self.inner.apply_component(value, op)?;
+
+        let new = value.route_get(&path).ok().flatten().cloned();
+        let event = match (moved_to, subtype) {
+            (Some(to), _) => ChangeEvent::Moved {
+                from: path.clone(),
+                to,
+            },
+            (None, Some(subtype)) => {
+                let (path, old, new) = (
+                    path.clone(),
+                    old.clone().unwrap_or_default(),
+                    new.clone().unwrap_or_default(),
+                );
+                match subtype {
+                    SubType::NumberAdd => ChangeEvent::NumberAdded { path, old, new },
+                    SubType::Text => ChangeEvent::TextEdited { path, old, new },
+                    SubType::Custome(name) if name == TEXT_DELTA_SUB_TYPE_NAME => {
+                        ChangeEvent::TextEdited { path, old, new }
+                    }
+                    SubType::Custome(_) => ChangeEvent::Replaced { path, old, new },
                 }
             }
+            (None, None) => match old.clone() {
+                Some(old) if inserts => ChangeEvent::Replaced {
+                    path: path.clone(),
+                    old,
+                    new: new.clone().unwrap_or_default(),
+                },
+                Some(old) => ChangeEvent::Removed {
+                    path: path.clone(),
+                    value: old,
+                },
+                None => ChangeEvent::Inserted {
+                    path: path.clone(),
+                    value: new.clone().unwrap_or_default(),
+                },
+            },
+        };
+
+        let mut report = self.report.borrow_mut();
+        report.events.push(event);
+        if let Some(old) = old.filter(|_| removes) {
+            report.removed.push((path.clone(), old));
+        }
+        if inserts {
+            if let Some(new) = new {
+                report.inserted.push((path.clone(), new));
+            }
         }
+        report.index_shifts.extend(shift);
+        if changed != path {
+            // moved from path to another path
+            report.changed_paths.push(path);
+        }
+        report.changed_paths.push(changed);
+        Ok(())
     }
 
-    fn route_get_mut(&mut self, paths: &Path) -> RouteResult<Option<&mut Value>> {
-        match self {
-            Value::Array(array) => array.route_get_mut(paths),
-            Value::Object(obj) => obj.route_get_mut(paths),
+    fn finish_operation(&self, value: &Value) -> Result<()> {
+        self.inner.finish_operation(value)
+    }
+}
+
+/// Path with the negative index of a list insert resolved to the index where
+/// the value is inserted.
+fn resolve_end(value: &Value, path: &Path) -> Path {
+    let Some(PathElement::End(k)) = path.last() else {
+        return path.clone();
+    };
+    let (mut parent, _) = path.split_at(path.len() - 1);
+    let len = value
+        .route_get(&parent)
+        .ok()
+        .flatten()
+        .and_then(|p| p.as_array())
+        .map(|a| a.len())
+        .unwrap_or(0);
+    parent.push(PathElement::Index(len.saturating_sub(*k)));
+    parent
+}
+
+pub trait Routable: Sized {
+    /// Route paths into self, telling a missing value from a path which
+    /// can't be routed.
+    fn route<'p, P: Into<PathSlice<'p>>>(&self, paths: P) -> RouteOutcome<'_, Self>;
+
+    /// The value on paths, None if it's missing and an error if paths can't
+    /// be routed, like `route`.
+    fn route_get<'p, P: Into<PathSlice<'p>>>(&self, paths: P) -> RouteResult<Option<&Self>>;
+
+    fn route_get_mut<'p, P: Into<PathSlice<'p>>>(
+        &mut self,
+        paths: P,
+    ) -> RouteResult<Option<&mut Self>>;
+}
+
+pub trait Appliable: Sized {
+    fn apply(&mut self, paths: Path, operator: Operator) -> ApplyResult<()> {
+        self.apply_take(paths, operator).map(|_| ())
+    }
+
+    /// Apply operator and return the value it removed, which is the value
+    /// deleted, or replaced by another value.
+    fn apply_take(&mut self, paths: Path, operator: Operator) -> ApplyResult<Option<Self>>;
+}
+
+impl<D: JsonDocument> Routable for D {
+    fn route<'p, P: Into<PathSlice<'p>>>(&self, paths: P) -> RouteOutcome<'_, D> {
+        route(self, paths.into())
+    }
+
+    fn route_get<'p, P: Into<PathSlice<'p>>>(&self, paths: P) -> RouteResult<Option<&D>> {
+        let paths = paths.into();
+        match route(self, paths) {
+            RouteOutcome::Found(v) => Ok(Some(v)),
+            RouteOutcome::MissingAt(_) => Ok(None),
+            outcome => Err(outcome.into_error(paths).unwrap()),
+        }
+    }
+
+    fn route_get_mut<'p, P: Into<PathSlice<'p>>>(
+        &mut self,
+        paths: P,
+    ) -> RouteResult<Option<&mut D>> {
+        route_get_mut(self, paths.into())
+    }
+}
+
+// Routing works on borrowed path views, the routed value is only cloned into
+// the error when routing fails.
+
+fn route<'a, D: JsonDocument>(value: &'a D, paths: PathSlice) -> RouteOutcome<'a, D> {
+    let elements = paths.get_elements();
+    let mut value = value;
+    for (i, pe) in elements.iter().enumerate() {
+        let next = match pe {
+            _ if value.is_null() => None,
+            PathElement::Key(k) if value.is_object() => value.get_key(k.as_ref()),
+            PathElement::Index(index) if value.is_array() => value.get_index(*index),
             _ => {
-                if paths.is_empty() {
-                    Ok(Some(self))
-                } else {
-                    Err(RouteError::ReachLeafNode(paths.clone()))
+                return RouteOutcome::TypeMismatch {
+                    at: elements[..i].iter().cloned().collect(),
+                    next_path: pe.clone(),
+                    value,
                 }
             }
+        };
+        match next {
+            Some(v) => value = v,
+            None => return RouteOutcome::MissingAt(elements[..=i].iter().cloned().collect()),
         }
     }
+    RouteOutcome::Found(value)
+}
+
+fn route_get_mut<'a, D: JsonDocument>(
+    value: &'a mut D,
+    paths: PathSlice,
+) -> RouteResult<Option<&'a mut D>> {
+    if paths.is_root() {
+        return Ok(Some(value));
+    }
+    if value.is_array() {
+        route_get_mut_in_array(value, paths)
+    } else if value.is_object() {
+        route_get_mut_in_object(value, paths)
+    } else if value.is_null() {
+        Ok(None)
+    } else {
+        Err(RouteError::ReachLeafNode(paths.to_path()))
+    }
+}
+
+fn route_get_mut_in_object<'a, D: JsonDocument>(
+    obj: &'a mut D,
+    paths: PathSlice,
+) -> RouteResult<Option<&'a mut D>> {
+    let Some(PathElement::Key(k)) = paths.first() else {
+        return Err(not_routable(obj, paths, true));
+    };
+    obj.get_key_mut(k.as_ref())
+        .map_or(Ok(None), |v| route_get_mut(v, paths.next_level()))
+}
+
+fn route_get_mut_in_array<'a, D: JsonDocument>(
+    array: &'a mut D,
+    paths: PathSlice,
+) -> RouteResult<Option<&'a mut D>> {
+    let Some(PathElement::Index(i)) = paths.first() else {
+        return Err(not_routable(array, paths, false));
+    };
+    array
+        .get_index_mut(*i)
+        .map_or(Ok(None), |v| route_get_mut(v, paths.next_level()))
+}
+
+fn not_routable<D: JsonDocument>(value: &D, paths: PathSlice, expect_key: bool) -> RouteError {
+    let json_value = value.to_value().into_owned();
+    match paths.first() {
+        None => RouteError::NotEnoughPath { json_value },
+        Some(next_path) if expect_key => RouteError::ExpectKeyPath {
+            json_value,
+            next_path: next_path.clone(),
+        },
+        Some(next_path) => RouteError::ExpectIndexPath {
+            json_value,
+            next_path: next_path.clone(),
+        },
+    }
+}
+
+/// Value of the sub type operation on target, None if it leaves target as is.
+fn apply_sub_type<D: JsonDocument>(
+    target: Option<&D>,
+    operand: &Value,
+    f: &Arc<dyn SubTypeFunctions>,
+) -> ApplyResult<Option<D>> {
+    let target = target.map(|t| t.to_value());
+    Ok(f.apply(target.as_deref(), operand)?.map(D::from_value))
 }
 
-impl Routable for serde_json::Map<String, serde_json::Value> {
-    fn route_get(&self, paths: &Path) -> RouteResult<Option<&Value>> {
-        let k = paths.first_key_path().ok_or(if paths.is_empty() {
-            RouteError::NotEnoughPath {
-                json_value: Value::Object(self.clone()),
+impl<D: JsonDocument> Appliable for D {
+    fn apply_take(&mut self, paths: Path, op: Operator) -> ApplyResult<Option<D>> {
+        if let Operator::Test(expected) = op {
+            let actual = self.route_get(&paths)?;
+            if !actual.is_some_and(|a| a.eq_value(&expected)) {
+                return Err(ApplyOperationError::TestFailed {
+                    path: Box::new(paths),
+                    expected: Box::new(into_value(expected)),
+                    actual: actual.map(|a| Box::new(a.to_value().into_owned())),
+                });
             }
-        } else {
-            RouteError::ExpectKeyPath {
-                json_value: Value::Object(self.clone()),
-                next_path: paths.get(0).cloned().unwrap(),
+            return Ok(None);
+        }
+        if paths.is_root() {
+            return apply_on_root(self, op);
+        }
+        if paths.len() > 1 {
+            let last = paths.len() - 1;
+            if let Some(parent) = self.route_get_mut(paths.prefix(last))? {
+                return parent.apply_take(paths.suffix(last).to_path(), op);
             }
-        })?;
-        if let Some(v) = self.get(k) {
-            let next_level = paths.next_level();
-            if next_level.is_empty() {
-                Ok(Some(v))
-            } else {
-                v.route_get(&next_level)
+            let RouteOutcome::MissingAt(missing) = self.route(paths.prefix(last)) else {
+                unreachable!("parent is routed without error")
+            };
+            return Err(RouteError::MissingPath(missing).into());
+        }
+        if self.is_array() {
+            return apply_in_array(self, paths, op);
+        }
+        if self.is_object() {
+            return apply_in_object(self, paths, op);
+        }
+        match op {
+            Operator::SubType(_, op, f) => {
+                if let Some(v) = apply_sub_type(Some(&*self), &op, &f)? {
+                    *self = v;
+                }
+                Ok(None)
             }
-        } else {
-            Ok(None)
+            Operator::Noop() => Ok(None),
+            _ => Err(ApplyOperationError::InvalidApplyTarget {
+                operator: Box::new(op),
+                target_value: Box::new(self.to_value().into_owned()),
+                reason: "unexpected operator".to_string(),
+            }),
         }
     }
+}
 
-    fn route_get_mut(&mut self, paths: &Path) -> RouteResult<Option<&mut Value>> {
-        let k = paths.first_key_path().ok_or(if paths.is_empty() {
-            RouteError::NotEnoughPath {
-                json_value: Value::Object(self.clone()),
-            }
-        } else {
-            RouteError::ExpectKeyPath {
-                json_value: Value::Object(self.clone()),
-                next_path: paths.get(0).cloned().unwrap(),
+fn apply_on_root<D: JsonDocument>(root: &mut D, op: Operator) -> ApplyResult<Option<D>> {
+    match op {
+        Operator::Noop() => Ok(None),
+        Operator::SubType(_, op, f) => {
+            if let Some(v) = apply_sub_type(Some(&*root), &op, &f)? {
+                *root = v;
             }
-        })?;
-        if let Some(v) = self.get_mut(k) {
-            let next_level = paths.next_level();
-            if next_level.is_empty() {
-                Ok(Some(v))
-            } else {
-                v.route_get_mut(&next_level)
-            }
-        } else {
             Ok(None)
         }
+        Operator::ObjectInsert(v) | Operator::ObjectReplace(v, _) => {
+            let old = mem::replace(root, D::from_value(into_value(v)));
+            Ok((!old.is_null()).then_some(old))
+        }
+        Operator::ObjectDelete(_) => {
+            let old = mem::replace(root, D::from_value(Value::Null));
+            Ok((!old.is_null()).then_some(old))
+        }
+        _ => Err(ApplyOperationError::InvalidApplyTarget {
+            operator: Box::new(op),
+            target_value: Box::new(root.to_value().into_owned()),
+            reason: "unexpected operator on root path".to_string(),
+        }),
     }
 }
 
-impl Routable for Vec<serde_json::Value> {
-    fn route_get(&self, paths: &Path) -> RouteResult<Option<&Value>> {
-        let i = paths.first_index_path().ok_or(if paths.is_empty() {
-            RouteError::NotEnoughPath {
-                json_value: Value::Array(self.clone()),
+fn apply_in_object<D: JsonDocument>(
+    obj: &mut D,
+    paths: Path,
+    op: Operator,
+) -> ApplyResult<Option<D>> {
+    assert!(paths.len() == 1);
+    let Some(k) = paths.first_key_path() else {
+        return Err(ApplyOperationError::RouteError(RouteError::ExpectKeyPath {
+            json_value: obj.to_value().into_owned(),
+            next_path: paths.get(0).cloned().unwrap(),
+        }));
+    };
+    let target_value = obj.get_key(k);
+    match op {
+        Operator::Noop() => Ok(None),
+        Operator::SubType(_, op, f) => {
+            if let Some(v) = apply_sub_type(target_value, &op, &f)? {
+                obj.insert_key(k.to_string(), v);
             }
-        } else {
-            RouteError::ExpectKeyPath {
-                json_value: Value::Array(self.clone()),
-                next_path: paths.get(0).cloned().unwrap(),
+            Ok(None)
+        }
+        Operator::ObjectInsert(v) => {
+            let v = D::from_value(into_value(v));
+            if let Some(target) = obj.get_key_mut(k) {
+                return Ok(Some(mem::replace(target, v)));
             }
-        })?;
-        if let Some(v) = self.get(*i) {
-            let next_level = paths.next_level();
-            if next_level.is_empty() {
-                Ok(Some(v))
-            } else {
-                v.route_get(&next_level)
+            obj.insert_key(k.to_string(), v);
+            Ok(None)
+        }
+        Operator::ObjectDelete(_) => {
+            // we don't check the equality of the values
+            // because OT is hard to implement
+            // if target_v.eq(&delete_v) {
+            Ok(obj.remove_key(k))
+            // }
+        }
+        Operator::ObjectReplace(new_v, _) => {
+            // we don't check the equality of the values
+            // because OT is hard to implement
+            // if target_v.eq(&old_v) {
+            Ok(obj
+                .get_key_mut(k)
+                .map(|target| mem::replace(target, D::from_value(into_value(new_v)))))
+            // }
+        }
+        Operator::ObjectMove(new_k) => {
+            if target_value.is_none() || k == new_k {
+                return Ok(None);
             }
-        } else {
+            if obj.get_key(&new_k).is_some() {
+                return Err(ApplyOperationError::InvalidApplyTarget {
+                    reason: format!("key: \"{}\" to move to already exists", new_k),
+                    operator: Box::new(Operator::ObjectMove(new_k)),
+                    target_value: Box::new(obj.to_value().into_owned()),
+                });
+            }
+            // the moved key goes last, like deleting and assigning it
+            let v = obj.remove_key(k).unwrap();
+            obj.insert_key(new_k, v);
             Ok(None)
         }
+        _ => Err(ApplyOperationError::InvalidApplyTarget {
+            operator: Box::new(op),
+            target_value: Box::new(obj.to_value().into_owned()),
+            reason: "unexpected operator".to_string(),
+        }),
     }
+}
 
-    fn route_get_mut(&mut self, paths: &Path) -> RouteResult<Option<&mut Value>> {
-        let i = paths.first_index_path().ok_or(if paths.is_empty() {
-            RouteError::NotEnoughPath {
-                json_value: Value::Array(self.clone()),
-            }
-        } else {
+fn apply_in_array<D: JsonDocument>(
+    array: &mut D,
+    paths: Path,
+    op: Operator,
+) -> ApplyResult<Option<D>> {
+    assert!(paths.len() == 1);
+
+    let len = array.array_len();
+    if let Some(PathElement::End(k)) = paths.get(0) {
+        if let Operator::ListInsert(v) = op {
+            array.insert_index(len.saturating_sub(*k), D::from_value(into_value(v)));
+            return Ok(None);
+        }
+    }
+
+    let Some(index) = paths.first_index_path() else {
+        return Err(ApplyOperationError::RouteError(
             RouteError::ExpectIndexPath {
-                json_value: Value::Array(self.clone()),
+                json_value: array.to_value().into_owned(),
                 next_path: paths.get(0).cloned().unwrap(),
+            },
+        ));
+    };
+    let target_value = array.get_index(*index);
+    match op {
+        Operator::Noop() => Ok(None),
+        Operator::SubType(t, op, f) => {
+            if let Some(v) = apply_sub_type(target_value, &op, &f)? {
+                let Some(target) = array.get_index_mut(*index) else {
+                    return Err(ApplyOperationError::InvalidApplyTarget {
+                        operator: Box::new(Operator::SubType(t, op, f)),
+                        target_value: Box::new(array.to_value().into_owned()),
+                        reason: format!("index: {} is out of range", index),
+                    });
+                };
+                *target = v;
             }
-        })?;
-        if let Some(v) = self.get_mut(*i) {
-            let next_level = paths.next_level();
-            if next_level.is_empty() {
-                Ok(Some(v))
-            } else {
-                v.route_get_mut(&next_level)
+            Ok(None)
+        }
+        Operator::ListInsert(v) => {
+            array.insert_index((*index).min(len), D::from_value(into_value(v)));
+            Ok(None)
+        }
+        Operator::ListDelete(_) => {
+            if target_value.is_none() {
+                return Ok(None);
+            }
+            // we don't check the equality of the values
+            // because OT is hard to implement
+            // if target_v.eq(&delete_v) {
+            Ok(Some(array.remove_index(*index)))
+            // }
+        }
+        Operator::ListReplace(new_v, _) => {
+            // we don't check the equality of the values
+            // because OT is hard to implement
+            // if target_v.eq(&old_v) {
+            Ok(array
+                .get_index_mut(*index)
+                .map(|target| mem::replace(target, D::from_value(into_value(new_v)))))
+            // }
+        }
+        Operator::ListMove(new_index) => {
+            if target_value.is_some() && *index != new_index {
+                let v = array.remove_index(*index);
+                array.insert_index(new_index.min(len - 1), v);
             }
-        } else {
             Ok(None)
         }
+        _ => Err(ApplyOperationError::InvalidApplyTarget {
+            operator: Box::new(op),
+            target_value: Box::new(array.to_value().into_owned()),
+            reason: "unexpected operator".to_string(),
+        }),
     }
 }
 
-impl Appliable for Value {
-    fn apply(&mut self, paths: Path, op: Operator) -> ApplyResult<()> {
-        if paths.len() > 1 {
-            let (left, right) = paths.split_at(paths.len() - 1);
-            return self
-                .route_get_mut(&left)
-                .map_err(ApplyOperationError::RouteError)?
-                .ok_or(ApplyOperationError::RouteError(RouteError::ReachLeafNode(
-                    paths,
-                )))?
-                .apply(right, op);
+/// Apply list inserts and deletes on elements of array in one pass, instead
+/// of shifting the following elements for each of them. Each component's
+/// index is on the array as left by the components before it, like applying
+/// them one by one. Stops at the first component which is not an insert or a
+/// delete on an index, or is on an index before the one of the component
+/// before it, and returns the number of components applied.
+pub(crate) fn apply_list_edits<'a, I>(array: &mut Vec<Value>, ops: I) -> usize
+where
+    I: IntoIterator<Item = &'a OperationComponent>,
+{
+    let mut input = mem::take(array).into_iter();
+    let mut applied = 0;
+    for op in ops {
+        let Some(PathElement::Index(index)) = op.path.last() else {
+            break;
+        };
+        // elements before output's end can't be changed in one pass
+        if *index < array.len() {
+            break;
         }
-        match self {
-            Value::Array(array) => array.apply(paths, op),
-            Value::Object(obj) => obj.apply(paths, op),
-            _ => match op {
-                Operator::SubType(_, op, f) => {
-                    if let Some(v) = f.apply(Some(self), &op)? {
-                        _ = mem::replace(self, v);
-                    }
-                    Ok(())
-                }
-                Operator::Noop() => Ok(()),
-                _ => Err(ApplyOperationError::InvalidApplyTarget {
-                    operator: op,
-                    target_value: self.clone(),
-                    reason: "unexpected operator".to_string(),
-                }),
-            },
+        match &op.operator {
+            Operator::ListInsert(v) => {
+                array.extend(input.by_ref().take(index - array.len()));
+                array.push(into_value(v.clone()));
+            }
+            Operator::ListDelete(_) => {
+                array.extend(input.by_ref().take(index - array.len()));
+                input.next();
+            }
+            _ => break,
         }
+        applied += 1;
     }
+    array.extend(input);
+    applied
 }
 
-impl Appliable for serde_json::Map<String, serde_json::Value> {
-    fn apply(&mut self, paths: Path, op: Operator) -> ApplyResult<()> {
-        assert!(paths.len() == 1);
+/// Applies components one by one for `apply_with_undo`.
+pub(crate) trait ComponentApplier {
+    /// Called before anything is done for op, may rewrite op.
+    fn prepare_component(
+        &self,
+        value: &Value,
+        op: OperationComponent,
+    ) -> Result<OperationComponent>;
 
-        let k = paths
-            .first_key_path()
-            .ok_or(ApplyOperationError::RouteError(RouteError::ExpectKeyPath {
-                json_value: Value::Object(self.clone()),
-                next_path: paths.get(0).cloned().unwrap(),
-            }))?;
-        let target_value = self.get(k);
-        match &op {
-            Operator::Noop() => Ok(()),
-            Operator::SubType(_, op, f) => {
-                if let Some(v) = f.apply(target_value, op)? {
-                    self.insert(k.clone(), v);
-                }
-                Ok(())
+    /// Apply op on value. Value must be left unchanged on error.
+    fn apply_component(&self, value: &mut Value, op: OperationComponent) -> Result<()>;
+
+    /// Called after all components of an operation are applied. The operation
+    /// is reverted on error.
+    fn finish_operation(&self, _value: &Value) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Apply operations on value and returns an operation which reverts all the
+/// changes made. The undo operation is built from the values actually found in
+/// value, so it also reverts intermediate objects created in upsert mode.
+pub(crate) fn apply_with_undo<A: ComponentApplier>(
+    value: &mut Value,
+    operations: Vec<Operation>,
+    options: &ApplyOptions,
+    applier: &A,
+) -> Result<Operation> {
+    apply_pruning_with_undo(value, operations, options, applier).map(|(undo, _)| undo)
+}
+
+/// Like `apply_with_undo`, and also returns the operation applied to prune
+/// empty containers by `ApplyOptions::prune_empty`.
+pub(crate) fn apply_pruning_with_undo<A: ComponentApplier>(
+    value: &mut Value,
+    operations: Vec<Operation>,
+    options: &ApplyOptions,
+    applier: &A,
+) -> Result<(Operation, Operation)> {
+    let mut undo = vec![];
+    let mut pruned = Operation::default();
+    let mut result = apply_recording_undo(value, operations, options, applier, &mut undo);
+    if let (Ok(()), Some(under)) = (&result, &options.prune_empty) {
+        result = prune_empty_operation(value, under).and_then(|prune| {
+            pruned = prune.clone();
+            apply_recording_undo(value, vec![prune], options, applier, &mut undo)
+        });
+    }
+    if let Err(e) = result {
+        if options.atomic {
+            // undo only contains changes which are applied successfully
+            for op in undo.into_iter().rev() {
+                _ = value.apply(op.path, op.operator);
             }
-            Operator::ObjectInsert(v) => {
-                self.insert(k.clone(), v.clone());
-                Ok(())
+        }
+        return Err(e);
+    }
+    let undo = undo
+        .into_iter()
+        .rev()
+        .filter_map(|op| op.not_noop())
+        .collect::<Vec<OperationComponent>>()
+        .into();
+    Ok((undo, pruned))
+}
+
+fn apply_recording_undo<A: ComponentApplier>(
+    value: &mut Value,
+    operations: Vec<Operation>,
+    options: &ApplyOptions,
+    applier: &A,
+    undo: &mut Vec<OperationComponent>,
+) -> Result<()> {
+    for (i, operation) in operations.into_iter().enumerate() {
+        let start = undo.len();
+        let timestamp = operation.meta().and_then(|m| m.timestamp);
+        for (j, op) in operation.into_iter().enumerate() {
+            let component = op.clone();
+            apply_recording_component(value, op, timestamp, options, applier, undo)
+                .map_err(|e| e.with_context(|| ErrorContext::new(i, j, &component)))?;
+        }
+        if let Err(e) = applier.finish_operation(value) {
+            for op in undo.drain(start..).rev() {
+                _ = value.apply(op.path, op.operator);
             }
-            Operator::ObjectDelete(_) => {
-                if target_value.is_some() {
-                    // we don't check the equality of the values
-                    // because OT is hard to implement
-                    // if target_v.eq(&delete_v) {
-                    self.remove(k);
-                    // }
-                }
-                Ok(())
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Timestamp is the one in the meta of the operation of op, if any.
+fn apply_recording_component<A: ComponentApplier>(
+    value: &mut Value,
+    op: OperationComponent,
+    timestamp: Option<u64>,
+    options: &ApplyOptions,
+    applier: &A,
+    undo: &mut Vec<OperationComponent>,
+) -> Result<()> {
+    let mut op = applier.prepare_component(value, op)?;
+    if options.number_overflow != NumberOverflow::Wrap {
+        if let Operator::SubType(SubType::NumberAdd, _, f) = &mut op.operator {
+            *f = sub_type::number_add(options.number_overflow);
+        }
+    }
+    if options.upsert {
+        if let Some(created) = create_missing_parents(value, &op)? {
+            undo.push(created.invert()?);
+        }
+    }
+    if options.strict {
+        check_pre_image(value, &op, options.number_equality)?;
+    }
+    if options.bounds != BoundsPolicy::Clamp {
+        if let Some(list) = out_of_bounds(value, &op)? {
+            if options.bounds == BoundsPolicy::Ignore {
+                return Ok(());
             }
-            Operator::ObjectReplace(new_v, _) => {
-                if target_value.is_some() {
-                    // we don't check the equality of the values
-                    // because OT is hard to implement
-                    // if target_v.eq(&old_v) {
-                    self.insert(k.clone(), new_v.clone());
-                    // }
-                }
-                Ok(())
+            return Err(ApplyOperationError::InvalidApplyTarget {
+                operator: Box::new(op.operator),
+                target_value: Box::new(list.clone()),
+                reason: format!("index on path: {} is out of range", op.path),
             }
-            _ => Err(ApplyOperationError::InvalidApplyTarget {
-                operator: op,
-                target_value: Value::Object(self.clone()),
-                reason: "unexpected operator".to_string(),
-            }),
+            .into());
         }
     }
+    let tombstone = match &options.soft_delete {
+        Some(soft_delete) => tombstone_components(value, &op, timestamp, soft_delete)?,
+        None => vec![],
+    };
+    let undo_op = undo_component(value, &op)?;
+    applier.apply_component(value, op)?;
+    undo.push(undo_op);
+    for op in tombstone {
+        apply_recording_component(value, op, timestamp, options, applier, undo)?;
+    }
+    Ok(())
 }
 
-impl Appliable for Vec<serde_json::Value> {
-    fn apply(&mut self, paths: Path, op: Operator) -> ApplyResult<()> {
-        assert!(paths.len() == 1);
+/// The list of op if op is a list component on an index out of its range.
+fn out_of_bounds<'a>(value: &'a Value, op: &OperationComponent) -> Result<Option<&'a Value>> {
+    let (Some(parent), Some(PathElement::Index(index))) = (op.path.parent(), op.path.last()) else {
+        return Ok(None);
+    };
+    let Some(list) = value.route_get(parent)? else {
+        return Ok(None);
+    };
+    let Some(len) = list.as_array().map(|a| a.len()) else {
+        return Ok(None);
+    };
+    let out = match op.operator {
+        Operator::ListInsert(_) => *index > len,
+        Operator::ListMove(to) => *index >= len || to >= len,
+        Operator::ListDelete(_) | Operator::ListReplace(_, _) => *index >= len,
+        _ => false,
+    };
+    Ok(out.then_some(list))
+}
 
-        let index = paths
-            .first_index_path()
-            .ok_or(ApplyOperationError::RouteError(
-                RouteError::ExpectIndexPath {
-                    json_value: Value::Array(self.clone()),
-                    next_path: paths.get(0).cloned().unwrap(),
-                },
-            ))?;
-        let target_value = self.get(*index);
-        match op {
-            Operator::Noop() => Ok(()),
-            Operator::SubType(_, op, f) => {
-                if let Some(v) = f.apply(target_value, &op)? {
-                    self[*index] = v;
-                }
-                Ok(())
-            }
-            Operator::ListInsert(v) => {
-                if *index > self.len() {
-                    self.push(v.clone())
-                } else {
-                    self.insert(*index, v.clone());
-                }
-                Ok(())
+fn check_pre_image(
+    value: &Value,
+    op: &OperationComponent,
+    number_equality: NumberEquality,
+) -> Result<()> {
+    let declared = match &op.operator {
+        Operator::ListDelete(v)
+        | Operator::ListReplace(_, v)
+        | Operator::ObjectDelete(v)
+        | Operator::ObjectReplace(_, v) => Some(&**v),
+        Operator::ObjectInsert(_) => None,
+        _ => return Ok(()),
+    };
+    let actual = value.route_get(&op.path)?;
+    let matched = match (actual, declared) {
+        (Some(a), Some(d)) => number_equality.values_eq(a, d),
+        (a, d) => a == d,
+    };
+    if matched {
+        return Ok(());
+    }
+    Err(ApplyOperationError::InvalidApplyTarget {
+        operator: Box::new(op.operator.clone()),
+        target_value: Box::new(actual.cloned().unwrap_or(Value::Null)),
+        reason: format!(
+            "declared value to remove: {} is not the value on path: {}",
+            declared.map_or("nothing".into(), |v| v.to_string()),
+            op.path
+        ),
+    }
+    .into())
+}
+
+/// Apply every component of operations, skipping the failed ones. Returns an
+/// operation of the applied components and the failures with the index of
+/// the failed component among all components. When an operation is rejected
+/// as a whole after its components are applied, they are reverted and the
+/// failure is reported on the index of its first component.
+pub(crate) fn apply_partial<A: ComponentApplier>(
+    value: &mut Value,
+    operations: Vec<Operation>,
+    applier: &A,
+) -> (Operation, Vec<(usize, JsonError)>) {
+    let mut applied = vec![];
+    let mut failures = vec![];
+    let mut index = 0;
+    for (i, operation) in operations.into_iter().enumerate() {
+        let first = index;
+        let start = applied.len();
+        let mut undo = vec![];
+        for (j, op) in operation.into_iter().enumerate() {
+            let component = op.clone();
+            let result = applier.prepare_component(value, op).and_then(|op| {
+                let undo_op = undo_component(value, &op)?;
+                applier.apply_component(value, op.clone())?;
+                undo.push(undo_op);
+                Ok(op)
+            });
+            match result {
+                Ok(op) => applied.push(op),
+                Err(e) => failures.push((
+                    index,
+                    e.with_context(|| ErrorContext::new(i, j, &component)),
+                )),
             }
-            Operator::ListDelete(_) => {
-                if target_value.is_some() {
-                    // we don't check the equality of the values
-                    // because OT is hard to implement
-                    // if target_v.eq(&delete_v) {
-                    self.remove(*index);
-                    // }
-                }
-                Ok(())
+            index += 1;
+        }
+        if let Err(e) = applier.finish_operation(value) {
+            for op in undo.into_iter().rev() {
+                _ = value.apply(op.path, op.operator);
             }
-            Operator::ListReplace(new_v, _) => {
-                if target_value.is_some() {
-                    // we don't check the equality of the values
-                    // because OT is hard to implement
-                    // if target_v.eq(&old_v) {
-                    self[*index] = new_v.clone();
-                    // }
-                }
-                Ok(())
+            applied.truncate(start);
+            failures.retain(|(i, _)| *i < first);
+            failures.push((first, e));
+        }
+    }
+    (
+        applied
+            .into_iter()
+            .filter_map(|op| op.not_noop())
+            .collect::<Vec<OperationComponent>>()
+            .into(),
+        failures,
+    )
+}
+
+fn create_missing_parents(
+    value: &mut Value,
+    op: &OperationComponent,
+) -> Result<Option<OperationComponent>> {
+    if !matches!(
+        op.operator,
+        Operator::ObjectInsert(_) | Operator::SubType(_, _, _)
+    ) || op.path.len() < 2
+    {
+        return Ok(None);
+    }
+
+    let parent = op.path.prefix(op.path.len() - 1);
+    let prefix = match value.route(parent) {
+        RouteOutcome::Found(_) => return Ok(None),
+        RouteOutcome::MissingAt(prefix) => prefix,
+        outcome => return Err(outcome.into_error(parent).unwrap().into()),
+    };
+    let (_, missing) = parent.split_at(prefix.len());
+
+    let mut created = Value::Object(serde_json::Map::new());
+    for pe in missing.get_elements().iter().rev() {
+        let PathElement::Key(k) = pe else {
+            return Err(ApplyOperationError::InvalidApplyTarget {
+                operator: Box::new(op.operator.clone()),
+                target_value: Box::new(value.clone()),
+                reason: format!("can not create missing list on path: {}", op.path),
             }
-            Operator::ListMove(new_index) => {
-                if let Some(target_v) = target_value {
-                    if *index != new_index {
-                        let new_v = target_v.clone();
-                        self.remove(*index);
-                        self.insert(new_index, new_v);
-                    }
-                }
-                Ok(())
+            .into());
+        };
+        let mut obj = serde_json::Map::new();
+        obj.insert(k.to_string(), created);
+        created = Value::Object(obj);
+    }
+    if let Some(PathElement::Index(_)) = prefix.last() {
+        return Err(ApplyOperationError::InvalidApplyTarget {
+            operator: Box::new(op.operator.clone()),
+            target_value: Box::new(value.clone()),
+            reason: format!("can not create missing list element on path: {}", op.path),
+        }
+        .into());
+    }
+
+    let insert = OperationComponent::new(prefix, Operator::ObjectInsert(created.into()))?;
+    value.apply(insert.path.clone(), insert.operator.clone())?;
+    Ok(Some(insert))
+}
+
+/// Operation deleting the objects and lists under path which are empty or
+/// only contain such objects and lists. Each of them is deleted with a single
+/// component, and list elements are deleted from the last one so the indexes
+/// of the others stay valid.
+pub(crate) fn prune_empty_operation(value: &Value, under: &Path) -> Result<Operation> {
+    fn is_prunable(value: &Value) -> bool {
+        match value {
+            Value::Object(obj) => obj.values().all(is_prunable),
+            Value::Array(arr) => arr.iter().all(is_prunable),
+            _ => false,
+        }
+    }
+
+    fn collect(value: &Value, path: &mut Path, out: &mut Vec<OperationComponent>) -> Result<()> {
+        let children: Vec<(PathElement, &Value)> = match value {
+            Value::Object(obj) => obj.iter().map(|(k, v)| (PathElement::key(k), v)).collect(),
+            Value::Array(arr) => arr
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(i, v)| (PathElement::Index(i), v))
+                .collect(),
+            _ => return Ok(()),
+        };
+        for (pe, child) in children {
+            let operator = match pe {
+                PathElement::Index(_) => Operator::ListDelete(child.clone().into()),
+                _ => Operator::ObjectDelete(child.clone().into()),
+            };
+            path.push(pe);
+            if is_prunable(child) {
+                out.push(OperationComponent::new(path.clone(), operator)?);
+            } else {
+                collect(child, path, out)?;
             }
-            _ => Err(ApplyOperationError::InvalidApplyTarget {
-                operator: op,
-                target_value: Value::Array(self.clone()),
-                reason: "unexpected operator".to_string(),
-            }),
+            path.pop();
         }
+        Ok(())
+    }
+
+    let mut components = vec![];
+    if let Some(target) = value.route_get(under)? {
+        collect(target, &mut under.clone(), &mut components)?;
+    }
+    Ok(components.into())
+}
+
+/// op with its text delete of the length form turned into the form of the
+/// text it deletes from value. None if op is not such a delete or it's out of
+/// the text.
+pub(crate) fn resolve_text_delete_component(
+    value: &Value,
+    op: &OperationComponent,
+) -> Result<Option<OperationComponent>> {
+    let Operator::SubType(SubType::Text, operand, f) = &op.operator else {
+        return Ok(None);
+    };
+    let Some(operand) = resolve_text_delete(operand, value.route_get(&op.path)?) else {
+        return Ok(None);
+    };
+    OperationComponent::new(
+        op.path.clone(),
+        Operator::SubType(SubType::Text, operand, f.clone()),
+    )
+    .map(Some)
+}
+
+pub(crate) fn undo_component(value: &Value, op: &OperationComponent) -> Result<OperationComponent> {
+    if let Operator::ListInsert(v) = &op.operator {
+        // inserting beyond the end of list appends to it
+        let parent = op.path.prefix(op.path.len() - 1);
+        let len = value
+            .route_get(parent)?
+            .and_then(|p| p.as_array())
+            .map(|a| a.len())
+            .unwrap_or(0);
+        let index = match op.path.last() {
+            Some(PathElement::Index(i)) => *i,
+            Some(PathElement::End(k)) => len.saturating_sub(*k),
+            _ => 0,
+        };
+        let mut path = parent.to_path();
+        path.push(PathElement::Index(index.min(len)));
+        return OperationComponent::new(path, Operator::ListDelete(v.clone()));
     }
+
+    let prior = value.route_get(&op.path)?.cloned().map(Operand::from);
+    let operator = match (&op.operator, prior) {
+        (Operator::Noop(), _) => Operator::Noop(),
+        (Operator::SubType(_, _, _), _) => {
+            return match resolve_text_delete_component(value, op)? {
+                Some(resolved) => resolved.invert(),
+                None => op.invert(),
+            }
+        }
+        (Operator::ListDelete(_), Some(p)) => Operator::ListInsert(p),
+        (Operator::ListReplace(v, _), Some(p)) => Operator::ListReplace(p, v.clone()),
+        (Operator::ListMove(_), Some(_)) | (Operator::ObjectMove(_), Some(_)) => {
+            return op.invert()
+        }
+        (Operator::ObjectInsert(v), Some(p)) | (Operator::ObjectReplace(v, _), Some(p)) => {
+            Operator::ObjectReplace(p, v.clone())
+        }
+        (Operator::ObjectInsert(v), None) => Operator::ObjectDelete(v.clone()),
+        (Operator::ObjectDelete(_), Some(p)) => Operator::ObjectInsert(p),
+        _ => Operator::Noop(),
+    };
+    OperationComponent::new(op.path.clone(), operator)
 }
 
 #[cfg(test)]
@@ -419,4 +1386,90 @@ mod tests {
             r#"[7,8]"#
         );
     }
+
+    #[test]
+    fn test_route_get_root() {
+        let json: Value = serde_json::from_str(r#"{"level1":["a","b"]}"#).unwrap();
+        assert_eq!(&json, json.route_get(&Path::root()).unwrap().unwrap());
+        assert_eq!(
+            &Value::Null,
+            Value::Null.route_get(&Path::root()).unwrap().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_route_get_failed() {
+        let mut json: Value = serde_json::from_str(r#"{"level1":["a",{"b":1}]}"#).unwrap();
+
+        let paths = Path::try_from(r#"["level1", 1, "b", "c"]"#).unwrap();
+        assert_matches!(
+            json.route_get(&paths),
+            Err(RouteError::ReachLeafNode(p)) if p == Path::try_from(r#"["c"]"#).unwrap()
+        );
+        let paths = Path::try_from(r#"["level1", "a"]"#).unwrap();
+        assert_matches!(
+            json.route_get_mut(&paths),
+            Err(RouteError::ExpectIndexPath { next_path, .. }) if next_path == "a".into()
+        );
+        let paths = Path::try_from(r#"[0]"#).unwrap();
+        assert_matches!(
+            json.route_get(&paths),
+            Err(RouteError::ExpectKeyPath { json_value, .. }) if json_value == json
+        );
+        let paths = Path::try_from(r#"["level2", 0]"#).unwrap();
+        assert_matches!(json.route_get_mut(&paths), Ok(None));
+        let paths = Path::try_from(r#"["level1", 0, "a"]"#).unwrap();
+        assert_matches!(
+            json.route_get(&paths),
+            Err(RouteError::ReachLeafNode(p)) if p == Path::try_from(r#"["a"]"#).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_route_outcome() {
+        let json: Value = serde_json::from_str(r#"{"l":["a",{"b":1}],"n":null}"#).unwrap();
+        let p = |s: &str| Path::try_from(s).unwrap();
+
+        assert_eq!(RouteOutcome::Found(&json), json.route(&Path::root()));
+        assert_eq!(
+            RouteOutcome::Found(&Value::from(1)),
+            json.route(&p(r#"["l", 1, "b"]"#))
+        );
+        assert!(json.route(&p(r#"["l", 0]"#)).is_found());
+        assert_eq!(
+            RouteOutcome::MissingAt(p(r#"["m"]"#)),
+            json.route(&p(r#"["m", "x", 0]"#))
+        );
+        assert_eq!(
+            RouteOutcome::MissingAt(p(r#"["l", 5]"#)),
+            json.route(&p(r#"["l", 5, "x"]"#))
+        );
+        assert_eq!(
+            RouteOutcome::MissingAt(p(r#"["n", "x"]"#)),
+            json.route(&p(r#"["n", "x", "y"]"#))
+        );
+        assert_eq!(
+            RouteOutcome::TypeMismatch {
+                at: p(r#"["l"]"#),
+                next_path: "k".into(),
+                value: &json["l"],
+            },
+            json.route(&p(r#"["l", "k"]"#))
+        );
+        assert_matches!(
+            json.route(&p(r#"["l", 0, 1]"#)),
+            RouteOutcome::TypeMismatch { at, .. } if at == p(r#"["l", 0]"#)
+        );
+        // arrays expect index paths
+        assert_matches!(
+            json.route_get(&p(r#"["l", "k"]"#)),
+            Err(RouteError::ExpectIndexPath { .. })
+        );
+
+        let mut json = json;
+        assert_matches!(
+            json.apply(p(r#"["m", "x", "y"]"#), Operator::ObjectInsert(Value::from(1).into())),
+            Err(ApplyOperationError::RouteError(RouteError::MissingPath(m))) if m == p(r#"["m"]"#)
+        );
+    }
 }
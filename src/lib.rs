@@ -1,20 +1,68 @@
 use std::{rc::Rc, sync::Arc};
 
-use error::JsonError;
-use json::{Appliable, Routable};
-use operation::{Operation, OperationFactory};
-use path::Path;
+pub use canonical::{
+    document_checksum, normalize_value, to_canonical_string, NormalizeOptions, NumberEquality,
+};
+use common::Validation;
+use document::JsonDocument;
+use error::{ErrorContext, JsonError};
+use hook::ApplyHook;
+use json::{Appliable, ComponentApplier, Routable};
+pub use json::{ApplyOptions, ApplyReport, BoundsPolicy, ChangeEvent, IndexShift, RouteOutcome};
+#[cfg(feature = "derive")]
+pub use json0_rs_derive::SubTypeFunctions;
+use operation::{Operation, OperationComponent, OperationFactory};
+use path::{Path, PathPattern};
 use serde_json::Value;
-use sub_type::{SubTypeFunctions, SubTypeFunctionsHolder};
+pub use sub_type::{
+    NumberOverflow, SubType, SubTypeCapabilities, SubTypeFunctions, SubTypeFunctionsHolder,
+    SubTypeInfo, SubTypeSnapshot, TypedSubTypeFunctions,
+};
+pub use tombstone::SoftDelete;
+pub use transformer::TransformSide;
 use transformer::Transformer;
+pub use transformer::{InsertOrder, TransformRule, TransformStep};
+use versioned::VersionedValue;
+use watch::{WatchEvent, WatchId, WatchRegistry};
 
+#[macro_use]
+mod macros;
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+mod binary;
+#[cfg(any(feature = "wasm", feature = "ffi"))]
+mod bindings;
+mod canonical;
+mod catchup;
+pub mod clock;
 mod common;
+mod compact;
+pub mod cursor;
+mod diff;
+pub mod document;
 pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod hook;
 mod json;
 pub mod operation;
 pub mod path;
+#[cfg(feature = "persistent")]
+pub mod persistent;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "js-reference")]
+pub mod reference;
 mod sub_type;
+#[cfg(any(test, feature = "test-support"))]
+pub mod testing;
+pub mod text_delta;
+mod tombstone;
 mod transformer;
+pub mod versioned;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod watch;
+pub mod workspace;
 
 #[cfg(test)]
 #[macro_use]
@@ -22,15 +70,34 @@ extern crate assert_matches;
 
 pub type Result<T> = std::result::Result<T, JsonError>;
 
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::sub_type::typed;
+    pub use serde_json;
+}
+
+// lets the code derived by json0-rs-derive name this crate in its own tests
+#[cfg(all(test, feature = "derive"))]
+extern crate self as json0_rs;
+
 pub struct Json0 {
-    functions: Rc<SubTypeFunctionsHolder>,
+    functions: Arc<SubTypeFunctionsHolder>,
     transformer: Transformer,
     operation_faction: OperationFactory,
+    watchers: WatchRegistry,
+    hooks: Vec<Box<dyn ApplyHook>>,
+    number_equality: NumberEquality,
 }
 
 impl Json0 {
     pub fn new() -> Json0 {
-        let functions = Rc::new(SubTypeFunctionsHolder::new());
+        Json0::with_subtypes(SubTypeFunctionsHolder::new())
+    }
+
+    /// Create a `Json0` with only the sub types in functions, like without
+    /// the built-in ones from `SubTypeFunctionsHolder::empty`.
+    pub fn with_subtypes(functions: SubTypeFunctionsHolder) -> Json0 {
+        let functions = Arc::new(functions);
         let transformer = Transformer::new();
         let operation_faction = OperationFactory::new(functions.clone());
 
@@ -38,6 +105,9 @@ impl Json0 {
             functions,
             transformer,
             operation_faction,
+            watchers: WatchRegistry::default(),
+            hooks: vec![],
+            number_equality: NumberEquality::default(),
         }
     }
 
@@ -60,29 +130,443 @@ impl Json0 {
         self.functions.unregister_subtype(sub_type)
     }
 
+    /// Register alias as another name of sub type, like `text0` for `text`.
+    pub fn register_alias<S: AsRef<str>>(
+        &self,
+        alias: S,
+        sub_type: SubType,
+    ) -> Result<Option<SubType>> {
+        self.functions.register_alias(alias, sub_type)
+    }
+
+    pub fn unregister_alias<S: AsRef<str>>(&self, alias: S) -> Option<SubType> {
+        self.functions.unregister_alias(alias)
+    }
+
     pub fn clear_registered_subtype(&self) {
         self.functions.clear();
     }
 
+    /// Version, capabilities and aliases of the registered sub types, sorted
+    /// by name.
+    pub fn subtypes(&self) -> Vec<SubTypeInfo> {
+        self.functions.infos()
+    }
+
+    /// The registered sub types as of now.
+    pub fn subtype_snapshot(&self) -> SubTypeSnapshot {
+        self.functions.snapshot()
+    }
+
     pub fn operation_factory(&self) -> &OperationFactory {
         &self.operation_faction
     }
 
+    pub fn operation_factory_mut(&mut self) -> &mut OperationFactory {
+        &mut self.operation_faction
+    }
+
+    /// Register a callback which is called after every applied component
+    /// changing the value on path, including changes on its descendants and
+    /// shifting its index in a list.
+    pub fn watch<F>(&self, path: Path, callback: F) -> WatchId
+    where
+        F: Fn(&WatchEvent) + 'static,
+    {
+        self.watchers.watch(path, Rc::new(callback))
+    }
+
+    /// Register a callback called after each component changing a path
+    /// matched by pattern, with the matched path. See `watch`.
+    pub fn watch_pattern<F>(&self, pattern: PathPattern, callback: F) -> WatchId
+    where
+        F: Fn(&WatchEvent) + 'static,
+    {
+        self.watchers.watch_pattern(pattern, Rc::new(callback))
+    }
+
+    /// Remove a registered watcher. Returns false if it's not found.
+    pub fn unwatch(&self, id: WatchId) -> bool {
+        self.watchers.unwatch(id)
+    }
+
+    /// Order concurrent list inserts on the same position by policy instead
+    /// of by `TransformSide`, like by timestamps in the inserted values.
+    /// Policy is given the insert being transformed and the concurrent one,
+    /// and must give opposite answers when they are swapped for peers to
+    /// converge. It may return `InsertOrder::BySide` to leave the order to
+    /// `TransformSide`.
+    pub fn set_insert_order<F>(&mut self, policy: F)
+    where
+        F: Fn(&OperationComponent, &OperationComponent) -> InsertOrder + 'static,
+    {
+        self.transformer.set_insert_order(Some(Box::new(policy)));
+    }
+
+    /// Compare numbers by number_equality when transform and compose check
+    /// whether a component changes nothing or cancels another one out, so
+    /// `1.0` replaced by `1` can be taken as a noop. Strict apply is set by
+    /// `ApplyOptions::number_equality`. Numbers are compared exactly by
+    /// default.
+    pub fn set_number_equality(&mut self, number_equality: NumberEquality) {
+        self.number_equality = number_equality;
+        self.transformer.set_number_equality(number_equality);
+    }
+
+    pub fn add_apply_hook(&mut self, hook: Box<dyn ApplyHook>) {
+        self.hooks.push(hook);
+    }
+
     pub fn apply(&self, value: &mut Value, operations: Vec<Operation>) -> Result<()> {
-        for operation in operations {
-            for op in operation.into_iter() {
-                value
-                    .apply(op.path.clone(), op.operator)
-                    .map_err(JsonError::ApplyOperationError)?;
+        if !self.hooks.is_empty() {
+            // hooks may reject an operation after it's applied, which needs undo
+            return self
+                .apply_with(value, operations, &ApplyOptions::default())
+                .map(|_| ());
+        }
+        for (i, operation) in operations.into_iter().enumerate() {
+            for (j, op) in operation.into_iter().enumerate() {
+                let component = op.clone();
+                self.prepare_component(value, op)
+                    .and_then(|op| self.apply_component(value, op))
+                    .map_err(|e| e.with_context(|| ErrorContext::new(i, j, &component)))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `apply` but faster for operations with many consecutive
+    /// components under the same parent, like edits on the fields of an
+    /// object or the elements of a list. The parent is routed from the root
+    /// once for all of them instead of once for each, and list inserts and
+    /// deletes on ascending indexes are applied in one pass over the list.
+    /// Components are still applied as if one by one. Falls back to `apply`
+    /// when there are hooks or watchers.
+    pub fn apply_batch(&self, value: &mut Value, operations: Vec<Operation>) -> Result<()> {
+        if !self.hooks.is_empty() || !self.watchers.is_empty() {
+            return self.apply(value, operations);
+        }
+        let components = operations
+            .into_iter()
+            .enumerate()
+            .flat_map(|(i, operation)| {
+                operation
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(j, op)| (i, j, op))
+            })
+            .collect::<Vec<(usize, usize, OperationComponent)>>();
+        let mut start = 0;
+        while start < components.len() {
+            let parent = components[start].2.path.parent();
+            let end = start
+                + components[start..]
+                    .iter()
+                    .take_while(|(_, _, op)| parent.is_some() && op.path.parent() == parent)
+                    .count()
+                    .max(1);
+            let group = &components[start..end];
+            start = end;
+            let target = match parent {
+                Some(parent) => value.route_get_mut(parent).ok().flatten(),
+                None => None,
+            };
+            // let apply report errors on paths which can't be routed
+            let Some(target) = target else {
+                for (i, j, op) in group {
+                    value
+                        .apply(op.path.clone(), op.operator.clone())
+                        .map_err(|e| {
+                            JsonError::from(e).with_context(|| ErrorContext::new(*i, *j, op))
+                        })?;
+                }
+                continue;
+            };
+            let mut k = 0;
+            while k < group.len() {
+                if let Value::Array(array) = target {
+                    let applied = json::apply_list_edits(array, group[k..].iter().map(|c| &c.2));
+                    if applied > 0 {
+                        k += applied;
+                        continue;
+                    }
+                }
+                let (i, j, op) = &group[k];
+                let last = op.path.last().cloned().into_iter().collect::<Path>();
+                target.apply(last, op.operator.clone()).map_err(|e| {
+                    JsonError::from(e).with_context(|| ErrorContext::new(*i, *j, op))
+                })?;
+                k += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply operations on a document of another representation than `Value`.
+    /// Apply hooks and watchers work on `Value` and are not called.
+    pub fn apply_document<D: JsonDocument>(
+        &self,
+        document: &mut D,
+        operations: Vec<Operation>,
+    ) -> Result<()> {
+        for (i, operation) in operations.into_iter().enumerate() {
+            for (j, op) in operation.into_iter().enumerate() {
+                let component = op.clone();
+                document.apply(op.path, op.operator).map_err(|e| {
+                    JsonError::from(e).with_context(|| ErrorContext::new(i, j, &component))
+                })?;
             }
         }
         Ok(())
     }
 
+    /// Apply operations and return the values removed from value, with the
+    /// path of the component removing each of them. A value is removed by
+    /// deleting it, or replacing it by another value. Removed values are moved
+    /// out of value rather than cloned, so apply hooks and watchers, which need
+    /// them to undo or report the change, are not called.
+    pub fn apply_take(
+        &self,
+        value: &mut Value,
+        operations: Vec<Operation>,
+    ) -> Result<Vec<(Path, Value)>> {
+        let mut removed = vec![];
+        for (i, operation) in operations.into_iter().enumerate() {
+            for (j, op) in operation.into_iter().enumerate() {
+                let component = op.clone();
+                let taken = value.apply_take(op.path, op.operator).map_err(|e| {
+                    JsonError::from(e).with_context(|| ErrorContext::new(i, j, &component))
+                })?;
+                removed.extend(taken.map(|v| (component.path, v)));
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Apply operations with options. Returns an operation which reverts the
+    /// changes made on value.
+    pub fn apply_with(
+        &self,
+        value: &mut Value,
+        operations: Vec<Operation>,
+        options: &ApplyOptions,
+    ) -> Result<Operation> {
+        json::apply_with_undo(value, operations, options, self)
+    }
+
+    /// Like `apply_with`, and also returns the `od` and `ld` components which
+    /// removed the empty containers left by operations, as set by
+    /// `ApplyOptions::prune_empty`. Other sites apply them after operations to
+    /// stay in sync. Returns the revert operation first.
+    pub fn apply_with_pruned(
+        &self,
+        value: &mut Value,
+        operations: Vec<Operation>,
+        options: &ApplyOptions,
+    ) -> Result<(Operation, Operation)> {
+        json::apply_pruning_with_undo(value, operations, options, self)
+    }
+
+    /// Apply operations each built on the version of doc it's paired with.
+    /// Each of them is transformed against the operations applied on doc
+    /// since its version, which win ties, and then applied atomically and
+    /// added to the history of doc. Returns the transformed operations in
+    /// order, which are the ones to send to the other peers. Stops at the
+    /// first failure, with the operations before it applied. Operations with
+    /// the author and seq in meta of an applied one are retries and skipped.
+    pub fn apply_versioned(
+        &self,
+        doc: &mut VersionedValue,
+        operations: Vec<(u64, Operation)>,
+    ) -> Result<Vec<Operation>> {
+        let mut applied = Vec::with_capacity(operations.len());
+        for (version, operation) in operations {
+            if version > doc.version() {
+                return Err(JsonError::Rejected(format!(
+                    "operation is built on version: {} after the current version: {}",
+                    version,
+                    doc.version()
+                )));
+            }
+            if operation.meta().is_some_and(|m| doc.is_applied(m)) {
+                continue;
+            }
+            let operation = self
+                .transformer
+                .rebase(&[operation], doc.history_since(version))?
+                .pop()
+                .unwrap_or_default();
+            self.apply_with(
+                doc.value_mut(),
+                vec![operation.clone()],
+                &ApplyOptions::new().atomic(true),
+            )?;
+            doc.push_history(operation.clone());
+            applied.push(operation);
+        }
+        Ok(applied)
+    }
+
+    /// Apply operations and report the changes made on value.
+    pub fn apply_verbose(
+        &self,
+        value: &mut Value,
+        operations: Vec<Operation>,
+    ) -> Result<ApplyReport> {
+        let reporting = json::Reporting::new(self);
+        json::apply_with_undo(value, operations, &ApplyOptions::default(), &reporting)?;
+        Ok(reporting.into_report())
+    }
+
+    /// Remove the objects and lists under path, excluding the one on path,
+    /// which are empty or only contain such objects and lists, as deletes
+    /// leave them behind. Returns the applied operation of `od` and `ld`
+    /// components, so the removals can be inverted and sent to other sites.
+    pub fn prune_empty(&self, value: &mut Value, under: &Path) -> Result<Operation> {
+        let operation = json::prune_empty_operation(value, under)?;
+        if !operation.is_empty() {
+            self.apply(value, vec![operation.clone()])?;
+        }
+        Ok(operation)
+    }
+
+    /// Apply every component of operations, skipping the ones failed to apply
+    /// instead of stopping at the first failure. Returns an operation of the
+    /// applied components and the failures with the index of the failed
+    /// component counted over all the components of operations.
+    pub fn apply_partial(
+        &self,
+        value: &mut Value,
+        operations: Vec<Operation>,
+    ) -> (Operation, Vec<(usize, JsonError)>) {
+        json::apply_partial(value, operations, self)
+    }
+
+    /// Check that operation is undone by its inverse on doc, by applying both
+    /// of them on a copy of doc and comparing the result with doc. It fails
+    /// when the values operation declares to remove are not the ones on doc,
+    /// as the inverse restores the declared values. Hooks and watchers are not
+    /// called.
+    pub fn verify_invertible(&self, doc: &Value, operation: &Operation) -> Result<()> {
+        let applied = operation.applied_to(doc)?;
+        let restored = operation.invert()?.applied_to(&applied)?;
+        if restored != *doc {
+            return Err(JsonError::InvalidOperation(format!(
+                "operation: {operation} is not inverted on document: {doc}, its inverse gives: {restored}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Turn text deletes of the length form in operation, like
+    /// `{"p":1,"d":2}`, into the form of the text they delete from doc, which
+    /// can be inverted and composed with inserts. Each component is resolved
+    /// against doc as left by the components before it, by applying them on a
+    /// copy of doc. Hooks and watchers are not called.
+    pub fn resolve_text_deletes(&self, doc: &Value, operation: &Operation) -> Result<Operation> {
+        let mut value = doc.clone();
+        let mut resolved = Operation::default();
+        resolved.set_meta(operation.meta().cloned());
+        for (j, op) in operation.iter().enumerate() {
+            let op = json::resolve_text_delete_component(&value, op)?.unwrap_or_else(|| op.clone());
+            value
+                .apply(op.path.clone(), op.operator.clone())
+                .map_err(|e| JsonError::from(e).with_context(|| ErrorContext::new(0, j, &op)))?;
+            resolved.push_validated(op)?;
+        }
+        Ok(resolved)
+    }
+
+    /// Check whether operation can be applied on value without changing value
+    /// or notifying watchers. Operation is applied on a copy of value, so
+    /// apply hooks are called as well.
+    pub fn check(&self, value: &Value, operation: &Operation) -> Result<()> {
+        operation.validates()?;
+        let mut copy = value.clone();
+        json::apply_with_undo(
+            &mut copy,
+            vec![operation.clone()],
+            &ApplyOptions::default(),
+            &DryRun(self),
+        )
+        .map(|_| ())
+    }
+
+    fn apply_with_hooks(&self, value: &mut Value, op: OperationComponent) -> Result<()> {
+        if self.hooks.is_empty() {
+            return value.apply(op.path, op.operator).map_err(JsonError::from);
+        }
+
+        let undo = json::undo_component(value, &op)?;
+        value.apply(op.path.clone(), op.operator.clone())?;
+        if let Err(e) = self
+            .hooks
+            .iter()
+            .try_for_each(|hook| hook.after_component(value, &op))
+        {
+            _ = value.apply(undo.path, undo.operator);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Map a position in the list or text on path through operation. See
+    /// `Operation::map_position`.
+    pub fn map_position(
+        &self,
+        path: &Path,
+        offset: usize,
+        operation: &Operation,
+    ) -> Option<(Path, usize)> {
+        operation.map_position(path, offset)
+    }
+
     pub fn get_by_path<'a>(&self, value: &'a mut Value, paths: &Path) -> Result<Option<&'a Value>> {
         value.route_get(paths).map_err(JsonError::RouteError)
     }
 
+    /// Route path into value, telling where a missing value is missing from
+    /// and which part of path can't be routed into value.
+    pub fn route<'a>(&self, value: &'a Value, path: &Path) -> RouteOutcome<'a, Value> {
+        value.route(path)
+    }
+
+    /// String on path of value. None if there's nothing on path, and
+    /// `JsonError::TypeMismatch` if it's not a string.
+    pub fn get_str<'a>(&self, value: &'a Value, path: &Path) -> Result<Option<&'a str>> {
+        get_typed(value, path, "string", Value::as_str)
+    }
+
+    /// Like `get_str` but for integers fit in i64.
+    pub fn get_i64(&self, value: &Value, path: &Path) -> Result<Option<i64>> {
+        get_typed(value, path, "integer", Value::as_i64)
+    }
+
+    /// Like `get_str` but for numbers, converted to f64.
+    pub fn get_f64(&self, value: &Value, path: &Path) -> Result<Option<f64>> {
+        get_typed(value, path, "number", Value::as_f64)
+    }
+
+    /// Like `get_str` but for booleans.
+    pub fn get_bool(&self, value: &Value, path: &Path) -> Result<Option<bool>> {
+        get_typed(value, path, "boolean", Value::as_bool)
+    }
+
+    /// Like `get_str` but for the length of a list.
+    pub fn get_array_len(&self, value: &Value, path: &Path) -> Result<Option<usize>> {
+        get_typed(value, path, "array", |v| v.as_array().map(|a| a.len()))
+    }
+
+    /// Compose a and b into one operation which has the same effect as
+    /// applying a and then b, like `apply(value, vec![a, b])`. b must be made
+    /// on the value a is applied on. The result is normalized, so components
+    /// canceling each other are removed.
+    pub fn compose(&self, a: &Operation, b: &Operation) -> Result<Operation> {
+        let mut composed = a.clone();
+        composed.compose_with(b.clone(), self.number_equality)?;
+        Ok(composed.normalize_with(self.number_equality))
+    }
+
     pub fn transform(
         &self,
         operation: &Operation,
@@ -90,6 +574,129 @@ impl Json0 {
     ) -> Result<(Operation, Operation)> {
         self.transformer.transform(operation, base_operation)
     }
+
+    /// Like `transform` but also returns the steps taken to transform each
+    /// pair of components, to explain how the results are derived.
+    pub fn transform_traced(
+        &self,
+        operation: &Operation,
+        base_operation: &Operation,
+    ) -> Result<(Operation, Operation, Vec<TransformStep>)> {
+        self.transformer.transform_traced(operation, base_operation)
+    }
+
+    /// Rebase a branch of operations made after onto's parent onto the end
+    /// of onto, like `git rebase`. Returns the operations of branch to apply
+    /// after onto, in order. Operations of onto win ties over branch.
+    pub fn rebase(&self, branch: &[Operation], onto: &[Operation]) -> Result<Vec<Operation>> {
+        self.transformer.rebase(branch, onto)
+    }
+
+    /// Like `transform` but ties are won as side tells, instead of always by
+    /// operation. See `TransformSide`.
+    pub fn transform_with_side(
+        &self,
+        operation: &Operation,
+        base_operation: &Operation,
+        side: TransformSide,
+    ) -> Result<(Operation, Operation)> {
+        self.transformer
+            .transform_with_side(operation, base_operation, side)
+    }
+
+    /// Like `transform` but ties, like both operations inserting on the same
+    /// position, are broken by priority instead of by argument order. The
+    /// operation with the smaller priority wins, and operation wins if the
+    /// priorities are equal. Peers transforming with a unique priority for
+    /// each client, like a client id, converge no matter which operation is
+    /// passed first.
+    pub fn transform_with_priority<P: Ord>(
+        &self,
+        operation: &Operation,
+        base_operation: &Operation,
+        operation_priority: P,
+        base_priority: P,
+    ) -> Result<(Operation, Operation)> {
+        let side = if operation_priority <= base_priority {
+            TransformSide::Left
+        } else {
+            TransformSide::Right
+        };
+        self.transformer
+            .transform_with_side(operation, base_operation, side)
+    }
+
+    /// Transform two concurrent operations against each other, following the
+    /// `transformX` convention. Left wins when both of them insert on the same
+    /// position. Returns left transformed against right and right transformed
+    /// against left.
+    pub fn transform_x(
+        &self,
+        left: &Operation,
+        right: &Operation,
+    ) -> Result<(Operation, Operation)> {
+        self.transformer.transform(left, right)
+    }
+
+    /// Transform operation against a concurrent operation other, with operation
+    /// winning when both of them insert on the same position. Same as the first
+    /// result of `transform_x(operation, other)`.
+    pub fn transform_left(&self, operation: &Operation, other: &Operation) -> Result<Operation> {
+        self.transformer
+            .transform_side(operation, other, TransformSide::Left)
+    }
+
+    /// Transform operation against a concurrent operation other, with other
+    /// winning when both of them insert on the same position. Same as the
+    /// second result of `transform_x(other, operation)`.
+    pub fn transform_right(&self, operation: &Operation, other: &Operation) -> Result<Operation> {
+        self.transformer
+            .transform_side(operation, other, TransformSide::Right)
+    }
+}
+
+impl ComponentApplier for Json0 {
+    fn prepare_component(
+        &self,
+        value: &Value,
+        op: OperationComponent,
+    ) -> Result<OperationComponent> {
+        self.hooks
+            .iter()
+            .try_fold(op, |op, hook| hook.before_component(value, op))
+    }
+
+    fn apply_component(&self, value: &mut Value, op: OperationComponent) -> Result<()> {
+        self.watchers
+            .apply(value, op, |v, op| self.apply_with_hooks(v, op))
+    }
+
+    fn finish_operation(&self, value: &Value) -> Result<()> {
+        self.hooks
+            .iter()
+            .try_for_each(|hook| hook.after_operation(value))
+    }
+}
+
+/// Applies components like `Json0` but without notifying watchers.
+struct DryRun<'a>(&'a Json0);
+
+impl<'a> ComponentApplier for DryRun<'a> {
+    fn prepare_component(
+        &self,
+        value: &Value,
+        op: OperationComponent,
+    ) -> Result<OperationComponent> {
+        self.0.prepare_component(value, op)
+    }
+
+    fn apply_component(&self, value: &mut Value, op: OperationComponent) -> Result<()> {
+        self.0.apply_with_hooks(value, op)
+    }
+
+    fn finish_operation(&self, value: &Value) -> Result<()> {
+        self.0.finish_operation(value)
+    }
 }
 
 impl Default for Json0 {
@@ -98,11 +705,38 @@ impl Default for Json0 {
     }
 }
 
+fn get_typed<'a, T>(
+    value: &'a Value,
+    path: &Path,
+    expected: &'static str,
+    read: impl Fn(&'a Value) -> Option<T>,
+) -> Result<Option<T>> {
+    let Some(v) = value.route_get(path)? else {
+        return Ok(None);
+    };
+    read(v).map(Some).ok_or_else(|| JsonError::TypeMismatch {
+        path: Box::new(path.clone()),
+        expected,
+        found: match v {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        },
+    })
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::error::ErrorCode;
+    use crate::json::{ApplyOperationError, ApplyResult};
+    use crate::operation::OperationMeta;
     use crate::path::AppendPath;
 
     use super::*;
+    use itertools::Itertools;
     use serde_json::Map;
     use test_log::test;
 
@@ -126,4 +760,2044 @@ mod tests {
         let expect_value: Value = serde_json::from_str("{\"key\":\"world\"}").unwrap();
         assert_eq!(expect_value, json_to_operate);
     }
+
+    #[test]
+    fn test_transform_keeps_operation_meta() {
+        let json0 = Json0::new();
+        let left = json0
+            .operation_factory()
+            .from_value(serde_json::from_str(r#"{"p":[0],"li":"a"}"#).unwrap())
+            .unwrap()
+            .with_meta(OperationMeta::new().author("left"));
+        let right = json0
+            .operation_factory()
+            .from_value(serde_json::from_str(r#"{"p":[0],"li":"b"}"#).unwrap())
+            .unwrap()
+            .with_meta(OperationMeta::new().author("right"));
+
+        let (l, r) = json0.transform(&left, &right).unwrap();
+        assert_eq!(left.meta(), l.meta());
+        assert_eq!(right.meta(), r.meta());
+    }
+
+    #[test]
+    fn test_apply_with_upsert() {
+        let json0 = Json0::new();
+        let op = json0
+            .operation_factory()
+            .from_value(serde_json::from_str(r#"{"p":["a","b","c"],"oi":1}"#).unwrap())
+            .unwrap();
+        let origin: Value = serde_json::from_str(r#"{"x":1}"#).unwrap();
+
+        let mut value = origin.clone();
+        assert!(json0
+            .apply_with(&mut value, vec![op.clone()], &ApplyOptions::new())
+            .is_err());
+
+        let undo = json0
+            .apply_with(
+                &mut value,
+                vec![op.clone()],
+                &ApplyOptions::new().upsert(true),
+            )
+            .unwrap();
+        let expect: Value = serde_json::from_str(r#"{"x":1,"a":{"b":{"c":1}}}"#).unwrap();
+        assert_eq!(expect, value);
+        assert_eq!(2, undo.len());
+
+        json0.apply(&mut value, vec![undo]).unwrap();
+        assert_eq!(origin, value);
+
+        // type mismatch on path still fails
+        let mut value: Value = serde_json::from_str(r#"{"a":1}"#).unwrap();
+        assert!(json0
+            .apply_with(&mut value, vec![op], &ApplyOptions::new().upsert(true))
+            .is_err());
+    }
+
+    #[test]
+    fn test_apply_with_returns_undo() {
+        let json0 = Json0::new();
+        let origin: Value = serde_json::from_str(r#"{"l":[1,2],"o":{"k":"v"},"n":1}"#).unwrap();
+        let op = json0
+            .operation_factory()
+            .from_value(
+                serde_json::from_str(
+                    r#"[{"p":["l",9],"li":3},{"p":["l",0],"ld":1},{"p":["o","k"],"od":"x"},
+                        {"p":["o","j"],"oi":1},{"p":["n"],"na":2},{"p":["l",0],"lm":1}]"#,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+        let mut value = origin.clone();
+        let undo = json0
+            .apply_with(&mut value, vec![op], &ApplyOptions::default())
+            .unwrap();
+        let expect: Value = serde_json::from_str(r#"{"l":[3,2],"o":{"j":1},"n":3}"#).unwrap();
+        assert_eq!(expect, value);
+
+        json0.apply(&mut value, vec![undo]).unwrap();
+        assert_eq!(origin, value);
+    }
+
+    #[test]
+    fn test_apply_atomic_with_failed_test() {
+        let json0 = Json0::new();
+        let origin: Value = serde_json::from_str(r#"{"version":1,"l":[1]}"#).unwrap();
+        let guarded = |version: i64| {
+            json0
+                .operation_factory()
+                .operation_builder()
+                .at(path!["l", 1])
+                .list_insert(2)
+                .then()
+                .at(path!["version"])
+                .test(version)
+                .then()
+                .at(path!["version"])
+                .number_add_int(1)
+                .build()
+                .unwrap()
+        };
+
+        let mut value = origin.clone();
+        let err = json0
+            .apply_with(&mut value, vec![guarded(2)], &ApplyOptions::new())
+            .unwrap_err();
+        assert_matches!(
+            err.root_cause(),
+            JsonError::ApplyOperationError(e) if matches!(**e, ApplyOperationError::TestFailed { .. })
+        );
+        let context = err.context().unwrap();
+        assert_eq!((0, 1), (context.operation_index, context.component_index));
+        assert_eq!(path!["version"], context.path);
+        assert_eq!(r#"{"test":2}"#, context.operator);
+        assert_ne!(origin, value);
+
+        let mut value = origin.clone();
+        assert!(json0
+            .apply_with(
+                &mut value,
+                vec![guarded(2)],
+                &ApplyOptions::new().atomic(true)
+            )
+            .is_err());
+        assert_eq!(origin, value);
+
+        json0
+            .apply_with(
+                &mut value,
+                vec![guarded(1)],
+                &ApplyOptions::new().atomic(true),
+            )
+            .unwrap();
+        let expect: Value = serde_json::from_str(r#"{"version":2,"l":[1,2]}"#).unwrap();
+        assert_eq!(expect, value);
+    }
+
+    #[test]
+    fn test_watch() {
+        let json0 = Json0::new();
+        let events = Rc::new(std::cell::RefCell::new(vec![]));
+        let recorder = events.clone();
+        let id = json0.watch(Path::try_from(r#"["o", "k"]"#).unwrap(), move |e| {
+            recorder
+                .borrow_mut()
+                .push((e.old_value.cloned(), e.new_value.cloned()));
+        });
+
+        let mut value: Value = serde_json::from_str(r#"{"o":{"k":1},"l":[1]}"#).unwrap();
+        let op = json0
+            .operation_factory()
+            .from_value(
+                serde_json::from_str(
+                    r#"[{"p":["o","k"],"na":2},{"p":["l",0],"ld":1},{"p":["o"],"od":{"k":3}}]"#,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        json0
+            .apply_with(&mut value, vec![op.clone()], &ApplyOptions::default())
+            .unwrap();
+        assert_eq!(
+            vec![
+                (Some(Value::from(1)), Some(Value::from(3))),
+                (Some(Value::from(3)), None)
+            ],
+            *events.borrow()
+        );
+
+        assert!(json0.unwatch(id));
+        assert!(!json0.unwatch(id));
+        let mut value: Value = serde_json::from_str(r#"{"o":{"k":1},"l":[1]}"#).unwrap();
+        json0.apply(&mut value, vec![op]).unwrap();
+        assert_eq!(2, events.borrow().len());
+    }
+
+    #[test]
+    fn test_apply_batch() {
+        let json0 = Json0::new();
+        let doc = serde_json::json!({"a": {"b": {"l": [1, 2, 3], "n": 1}}, "t": "x"});
+        let ops = vec![
+            json0
+                .operation_factory()
+                .from_str(
+                    r#"[{"p":["a","b","l",0],"ld":1},{"p":["a","b","l",1],"li":4},
+                        {"p":["a","b","l",0],"lm":2},{"p":["a","b","n"],"na":2},
+                        {"p":["a","b","m"],"oi":{}},{"p":["a","b","m","k"],"oi":1}]"#,
+                )
+                .unwrap(),
+            json0
+                .operation_factory()
+                .from_str(r#"[{"p":["t"],"t":"text","o":{"p":1,"i":"y"}},{"p":[],"test":null}]"#)
+                .unwrap(),
+        ];
+        let mut expected = doc.clone();
+        json0.apply(&mut expected, ops.clone()).unwrap_err();
+        let mut value = doc.clone();
+        let err = json0.apply_batch(&mut value, ops.clone()).unwrap_err();
+        assert_eq!(expected, value);
+        assert_eq!((1, 1), {
+            let c = err.context().unwrap();
+            (c.operation_index, c.component_index)
+        });
+
+        let ops = ops.into_iter().take(1).collect::<Vec<Operation>>();
+        let mut expected = doc.clone();
+        json0.apply(&mut expected, ops.clone()).unwrap();
+        let mut value = doc.clone();
+        json0.apply_batch(&mut value, ops).unwrap();
+        assert_eq!(expected, value);
+        assert_eq!(
+            serde_json::json!({"l": [4, 3, 2], "n": 3, "m": {"k": 1}}),
+            value["a"]["b"]
+        );
+
+        let missing = json0
+            .operation_factory()
+            .from_str(r#"[{"p":["x","y"],"oi":1}]"#)
+            .unwrap();
+        assert_eq!(
+            json0
+                .apply(&mut doc.clone(), vec![missing.clone()])
+                .unwrap_err()
+                .code(),
+            json0
+                .apply_batch(&mut doc.clone(), vec![missing])
+                .unwrap_err()
+                .code()
+        );
+    }
+
+    #[test]
+    fn test_apply_batch_list_edits() {
+        let json0 = Json0::new();
+        let doc = serde_json::json!({"l": [0, 1, 2, 3, 4, 5]});
+        for op in [
+            r#"[{"p":["l",1],"ld":1},{"p":["l",1],"ld":2},{"p":["l",2],"li":"a"},{"p":["l",4],"li":"b"}]"#,
+            r#"[{"p":["l",0],"li":"a"},{"p":["l",0],"li":"b"},{"p":["l",9],"li":"c"},{"p":["l",9],"ld":5}]"#,
+            // goes back to an index before the last edit
+            r#"[{"p":["l",3],"li":"a"},{"p":["l",1],"ld":1},{"p":["l",4],"ld":4},{"p":["l",4],"li":"b"}]"#,
+            r#"[{"p":["l",2],"li":"a"},{"p":["l",3],"lm":0},{"p":["l",5],"ld":4},{"p":["l",-1],"li":"b"}]"#,
+        ] {
+            let op = json0.operation_factory().from_str(op).unwrap();
+            let mut expected = doc.clone();
+            json0.apply(&mut expected, vec![op.clone()]).unwrap();
+            let mut value = doc.clone();
+            json0.apply_batch(&mut value, vec![op]).unwrap();
+            assert_eq!(expected, value);
+        }
+    }
+
+    #[test]
+    fn test_number_equality() {
+        let mut json0 = Json0::new();
+        let parse = |s: &str| OperationFactory::default().from_str(s).unwrap();
+        let insert = parse(r#"[{"p":["l",0],"li":{"n":1}}]"#);
+        let delete = parse(r#"[{"p":["l",0],"ld":{"n":1.0}}]"#);
+        let replace = parse(r#"[{"p":["n"],"od":1.0,"oi":1}]"#);
+        let other = parse(r#"[{"p":["n"],"od":1.0,"oi":2}]"#);
+
+        assert_eq!(2, json0.compose(&insert, &delete).unwrap().len());
+        let (_, transformed) = json0.transform(&replace, &other).unwrap();
+        assert!(transformed.is_empty());
+
+        json0.set_number_equality(NumberEquality::IntegerCoercing);
+        assert!(json0.compose(&insert, &delete).unwrap().is_empty());
+        let (transformed, _) = json0.transform(&other, &replace).unwrap();
+        assert_eq!(other, transformed);
+        let (_, transformed) = json0.transform(&replace, &other).unwrap();
+        assert_eq!(other, transformed);
+
+        let mut value = serde_json::json!({"n": 1, "l": []});
+        let strict = ApplyOptions::new().strict(true);
+        assert!(json0
+            .apply_with(&mut value, vec![other.clone()], &strict)
+            .is_err());
+        json0
+            .apply_with(
+                &mut value,
+                vec![other],
+                &strict.number_equality(NumberEquality::Epsilon(1e-9)),
+            )
+            .unwrap();
+        assert_eq!(serde_json::json!({"n": 2, "l": []}), value);
+    }
+
+    #[test]
+    fn test_typed_getters() {
+        let json0 = Json0::new();
+        let value = serde_json::json!({"s": "x", "i": -2, "f": 1.5, "b": true, "l": [1, 2]});
+        let p = |s: &str| Path::try_from(s).unwrap();
+        assert_eq!(Some("x"), json0.get_str(&value, &p(r#"["s"]"#)).unwrap());
+        assert_eq!(Some(-2), json0.get_i64(&value, &p(r#"["i"]"#)).unwrap());
+        assert_eq!(Some(1.5), json0.get_f64(&value, &p(r#"["f"]"#)).unwrap());
+        assert_eq!(Some(-2.0), json0.get_f64(&value, &p(r#"["i"]"#)).unwrap());
+        assert_eq!(Some(true), json0.get_bool(&value, &p(r#"["b"]"#)).unwrap());
+        assert_eq!(
+            Some(2),
+            json0.get_array_len(&value, &p(r#"["l"]"#)).unwrap()
+        );
+        assert_eq!(Some(2), json0.get_i64(&value, &p(r#"["l", 1]"#)).unwrap());
+        assert_eq!(None, json0.get_str(&value, &p(r#"["m"]"#)).unwrap());
+        assert_eq!(None, json0.get_i64(&value, &p(r#"["l", 5]"#)).unwrap());
+
+        let e = json0.get_i64(&value, &p(r#"["f"]"#)).unwrap_err();
+        assert_eq!(ErrorCode::TypeMismatch, e.code());
+        assert_eq!(
+            r#"[type_mismatch] Expect integer on path: ["f"], but found number"#,
+            e.to_string()
+        );
+        assert_matches!(
+            json0.get_str(&value, &p(r#"["l"]"#)).unwrap_err(),
+            JsonError::TypeMismatch {
+                expected: "string",
+                found: "array",
+                ..
+            }
+        );
+        assert_matches!(
+            json0.get_str(&value, &p(r#"["s", "k"]"#)).unwrap_err(),
+            JsonError::RouteError(_)
+        );
+    }
+
+    #[test]
+    fn test_compose() {
+        let json0 = Json0::new();
+        let parse = |s: &str| json0.operation_factory().from_str(s).unwrap();
+        let doc = serde_json::json!({"a": 1, "l": [1, 2]});
+        let a = parse(r#"[{"p":["a"],"na":1},{"p":["l",0],"li":0},{"p":["b"],"oi":1}]"#);
+        let b = parse(r#"[{"p":["a"],"na":2},{"p":["l",2],"ld":2},{"p":["b"],"od":1}]"#);
+
+        let composed = json0.compose(&a, &b).unwrap();
+        assert_eq!(
+            serde_json::json!([{"p":["a"],"na":3},{"p":["l",0],"li":0},{"p":["l",2],"ld":2}]),
+            composed.to_value()
+        );
+        let mut expected = doc.clone();
+        json0.apply(&mut expected, vec![a.clone(), b]).unwrap();
+        let mut value = doc.clone();
+        json0.apply(&mut value, vec![composed]).unwrap();
+        assert_eq!(expected, value);
+
+        assert_eq!(a, json0.compose(&a, &Operation::default()).unwrap());
+    }
+
+    #[test]
+    fn test_apply_versioned() {
+        let json0 = Json0::new();
+        let parse = |s: &str| json0.operation_factory().from_str(s).unwrap();
+        let mut doc = VersionedValue::new(serde_json::json!({"l": [], "n": 0}));
+
+        let applied = json0
+            .apply_versioned(
+                &mut doc,
+                vec![
+                    (0, parse(r#"[{"p":["l",0],"li":"a"}]"#)),
+                    (0, parse(r#"[{"p":["l",0],"li":"b"},{"p":["n"],"na":1}]"#)),
+                    (1, parse(r#"[{"p":["l",1],"li":"c"}]"#)),
+                ],
+            )
+            .unwrap();
+        assert_eq!(3, doc.version());
+        assert_eq!(
+            &serde_json::json!({"l": ["a", "b", "c"], "n": 1}),
+            doc.value()
+        );
+        assert_eq!(
+            parse(r#"[{"p":["l",1],"li":"b"},{"p":["n"],"na":1}]"#),
+            applied[1]
+        );
+        assert_eq!(applied.as_slice(), doc.history_since(0));
+        assert_eq!(&applied[2..], doc.history_since(2));
+        assert!(doc.history_since(10).is_empty());
+
+        assert_matches!(
+            json0
+                .apply_versioned(&mut doc, vec![(4, parse(r#"[{"p":["n"],"na":1}]"#))])
+                .unwrap_err(),
+            JsonError::Rejected(_)
+        );
+        let failed = parse(r#"[{"p":["n"],"na":1},{"p":["n"],"test":0}]"#);
+        assert!(json0.apply_versioned(&mut doc, vec![(3, failed)]).is_err());
+        assert_eq!(3, doc.version());
+        assert_eq!(&serde_json::json!(1), &doc.value()["n"]);
+
+        let submit = |seq: u64| {
+            parse(r#"[{"p":["n"],"na":1}]"#).with_meta(OperationMeta::new().author("c1").seq(seq))
+        };
+        let applied = json0
+            .apply_versioned(&mut doc, vec![(3, submit(1)), (3, submit(1))])
+            .unwrap();
+        assert_eq!(1, applied.len());
+        assert!(json0
+            .apply_versioned(&mut doc, vec![(3, submit(1))])
+            .unwrap()
+            .is_empty());
+        assert_eq!(4, doc.version());
+        assert_eq!(&serde_json::json!(2), &doc.value()["n"]);
+        json0
+            .apply_versioned(&mut doc, vec![(4, submit(2))])
+            .unwrap();
+        assert_eq!(&serde_json::json!(3), &doc.value()["n"]);
+        assert!(doc.is_applied(&OperationMeta::new().author("c1").seq(2)));
+        assert!(!doc.is_applied(&OperationMeta::new().author("c2").seq(2)));
+        assert!(!doc.is_applied(&OperationMeta::new().seq(2)));
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let json0 = Json0::new();
+        let mut server = VersionedValue::new(serde_json::json!({"n": 1, "l": []}));
+        let mut client = server.clone();
+        let op = json0
+            .operation_factory()
+            .from_str(r#"[{"p":["n"],"na":1}]"#)
+            .unwrap();
+        json0
+            .apply_versioned(&mut server, vec![(0, op.clone())])
+            .unwrap();
+        let checksum = server.checksum();
+
+        let resynced = std::cell::Cell::new(false);
+        assert_eq!(
+            None,
+            client.verify_checksum(1, &checksum, |_| resynced.set(true))
+        );
+        json0.apply_versioned(&mut client, vec![(0, op)]).unwrap();
+        assert_eq!(
+            Some(true),
+            client.verify_checksum(1, &checksum, |_| resynced.set(true))
+        );
+        assert!(!resynced.get());
+
+        let drifted = json0
+            .operation_factory()
+            .from_str(r#"[{"p":["l",0],"li":1}]"#)
+            .unwrap();
+        json0.apply(client.value_mut(), vec![drifted]).unwrap();
+        assert_eq!(
+            Some(false),
+            client.verify_checksum(1, &checksum, |doc| {
+                assert_eq!(&serde_json::json!([1]), &doc.value()["l"]);
+                resynced.set(true)
+            })
+        );
+        assert!(resynced.get());
+    }
+
+    #[test]
+    fn test_history_blame() {
+        let json0 = Json0::new();
+        let mut doc = VersionedValue::new(serde_json::json!({"a": "x", "b": 1, "l": [1]}));
+        for (author, op) in [
+            ("alice", serde_json::json!([{"p": ["l", 0], "li": 0}])),
+            (
+                "bob",
+                serde_json::json!([{"p": ["l", 1], "ld": 1, "li": 2}]),
+            ),
+            ("carol", serde_json::json!([{"p": ["l", 0], "li": "z"}])),
+            (
+                "dave",
+                serde_json::json!([{"p": ["a"], "od": "x", "oi": "y"}]),
+            ),
+        ] {
+            let op = json0
+                .operation_factory()
+                .from_value(op)
+                .unwrap()
+                .with_meta(OperationMeta::new().author(author));
+            let version = doc.version();
+            json0
+                .apply_versioned(&mut doc, vec![(version, op)])
+                .unwrap();
+        }
+        assert_eq!(&serde_json::json!(["z", 0, 2]), &doc.value()["l"]);
+
+        let path = |s: &str| Path::try_from(s).unwrap();
+        assert_eq!(Some("bob"), doc.last_editor_of(&path(r#"["l", 2]"#)));
+        assert_eq!(Some("alice"), doc.last_editor_of(&path(r#"["l", 1]"#)));
+        assert_eq!(Some("carol"), doc.last_editor_of(&path(r#"["l", 0]"#)));
+        assert_eq!(Some("carol"), doc.last_editor_of(&path(r#"["l"]"#)));
+        assert_eq!(Some("dave"), doc.last_editor_of(&path(r#"["a"]"#)));
+        assert_eq!(Some(3), doc.blame(&Path::root()).map(|(v, _)| v));
+        assert!(doc.blame(&path(r#"["b"]"#)).is_none());
+
+        let versions = |p: &str, range| {
+            doc.ops_touching(&path(p), range)
+                .into_iter()
+                .map(|(v, _)| v)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![0, 1, 2], versions(r#"["l"]"#, 0..4));
+        assert_eq!(vec![1], versions(r#"["l"]"#, 1..2));
+        assert_eq!(vec![1], versions(r#"["l", 2]"#, 0..4));
+        assert_eq!(vec![3], versions(r#"["a"]"#, 0..4));
+    }
+
+    #[test]
+    fn test_apply_bounds_policy() {
+        let json0 = Json0::new();
+        let parse = |v: Value| json0.operation_factory().from_value(v).unwrap();
+        let doc = serde_json::json!({"l": [1, 2]});
+        let apply = |op: Value, bounds: BoundsPolicy| {
+            let mut value = doc.clone();
+            json0
+                .apply_with(
+                    &mut value,
+                    vec![parse(op)],
+                    &ApplyOptions::new().bounds(bounds),
+                )
+                .map(|_| value)
+        };
+
+        for (op, clamped) in [
+            (
+                serde_json::json!([{"p": ["l", 5], "li": 3}]),
+                serde_json::json!({"l": [1, 2, 3]}),
+            ),
+            (
+                serde_json::json!([{"p": ["l", 0], "lm": 5}]),
+                serde_json::json!({"l": [2, 1]}),
+            ),
+            (serde_json::json!([{"p": ["l", 2], "ld": 3}]), doc.clone()),
+            (
+                serde_json::json!([{"p": ["l", 2], "ld": 3, "li": 4}]),
+                doc.clone(),
+            ),
+            (serde_json::json!([{"p": ["l", 2], "lm": 0}]), doc.clone()),
+        ] {
+            assert_eq!(clamped, apply(op.clone(), BoundsPolicy::Clamp).unwrap());
+            assert_eq!(doc, apply(op.clone(), BoundsPolicy::Ignore).unwrap());
+            let err = apply(op, BoundsPolicy::Error).unwrap_err();
+            assert_eq!(ErrorCode::InvalidApplyTarget, err.code());
+        }
+
+        let in_range = serde_json::json!([{"p": ["l", 2], "li": 3}, {"p": ["l", 0], "lm": 2}]);
+        let expected = serde_json::json!({"l": [2, 3, 1]});
+        for bounds in [
+            BoundsPolicy::Clamp,
+            BoundsPolicy::Error,
+            BoundsPolicy::Ignore,
+        ] {
+            assert_eq!(expected, apply(in_range.clone(), bounds).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_verify_invertible_and_strict_apply() {
+        let json0 = Json0::new();
+        let parse = |v: Value| json0.operation_factory().from_value(v).unwrap();
+        let doc = serde_json::json!({"l": [1, 2, 3], "o": {"k": "v"}, "t": "ab", "n": 1});
+
+        let good = parse(serde_json::json!([
+            {"p": ["l", 0], "ld": 1},
+            {"p": ["l", 0], "lm": 1},
+            {"p": ["o", "k"], "od": "v", "oi": "w"},
+            {"p": ["o", "k"], "om": "j"},
+            {"p": ["t"], "t": "text", "o": {"p": 1, "i": "x"}},
+            {"p": ["n"], "na": 1},
+        ]));
+        json0.verify_invertible(&doc, &good).unwrap();
+        // adding to nothing inserts the number, which the inverse doesn't delete
+        assert_matches!(
+            json0.verify_invertible(&doc, &parse(serde_json::json!([{"p": ["m"], "na": 1}]))),
+            Err(JsonError::InvalidOperation(_))
+        );
+        let mut value = doc.clone();
+        json0
+            .apply_with(&mut value, vec![good], &ApplyOptions::new().strict(true))
+            .unwrap();
+
+        for bad in [
+            serde_json::json!([{"p": ["l", 0], "ld": 2}]),
+            serde_json::json!([{"p": ["o", "k"], "od": "x", "oi": "w"}]),
+            serde_json::json!([{"p": ["o", "k"], "oi": "w"}]),
+            serde_json::json!([{"p": ["o", "x"], "od": 1}]),
+        ] {
+            let bad = parse(bad);
+            assert_matches!(
+                json0.verify_invertible(&doc, &bad),
+                Err(JsonError::InvalidOperation(_))
+            );
+            // not strict by default
+            json0
+                .apply_with(&mut doc.clone(), vec![bad.clone()], &ApplyOptions::new())
+                .unwrap();
+            let mut value = doc.clone();
+            let err = json0
+                .apply_with(&mut value, vec![bad], &ApplyOptions::new().strict(true))
+                .unwrap_err();
+            assert_eq!(ErrorCode::InvalidApplyTarget, err.code());
+            assert_eq!(doc, value);
+        }
+    }
+
+    #[test]
+    fn test_rebase() {
+        let json0 = Json0::new();
+        let parse = |v: Value| json0.operation_factory().from_value(v).unwrap();
+        let base = serde_json::json!({"l": ["a", "b"], "t": "xy"});
+        let branch = vec![
+            parse(serde_json::json!([{"p": ["l", 0], "li": "c"}])),
+            // depends on the previous operation of branch
+            parse(serde_json::json!([{"p": ["l", 0], "ld": "c", "li": "d"}])),
+            parse(serde_json::json!([{"p": ["t"], "t": "text", "o": {"p": 2, "i": "z"}}])),
+        ];
+        let onto = vec![
+            parse(serde_json::json!([{"p": ["l", 0], "li": "e"}])),
+            parse(serde_json::json!([{"p": ["l", 2], "ld": "b"}])),
+            parse(serde_json::json!([{"p": ["t"], "t": "text", "o": {"p": 0, "i": "w"}}])),
+        ];
+
+        let rebased = json0.rebase(&branch, &onto).unwrap();
+        assert_eq!(branch.len(), rebased.len());
+        let mut value = base.clone();
+        json0.apply(&mut value, onto.clone()).unwrap();
+        json0.apply(&mut value, rebased).unwrap();
+        assert_eq!(
+            serde_json::json!({"l": ["e", "d", "a"], "t": "wxyz"}),
+            value
+        );
+
+        // same as transforming the composed branch against the composed onto
+        let branch_op = Operation::compose_all(branch.clone()).unwrap();
+        let onto_op = Operation::compose_all(onto.clone()).unwrap();
+        let transformed = json0.transform_right(&branch_op, &onto_op).unwrap();
+        let mut expected = base;
+        json0
+            .apply(&mut expected, vec![onto_op, transformed])
+            .unwrap();
+        assert_eq!(expected, value);
+
+        assert!(json0.rebase(&[], &onto).unwrap().is_empty());
+        assert_eq!(branch, json0.rebase(&branch, &[]).unwrap());
+    }
+
+    #[test]
+    fn test_apply_take() {
+        let json0 = Json0::new();
+        let mut value = serde_json::json!({"l": [{"big": [1, 2]}, 2], "o": {"k": "v", "j": 1}});
+        let op = json0
+            .operation_factory()
+            .from_value(serde_json::json!([
+                {"p": ["l", 0], "ld": {}},
+                {"p": ["l", 0], "li": 3, "ld": 2},
+                {"p": ["o", "k"], "od": "x"},
+                {"p": ["o", "j"], "oi": 2},
+                {"p": ["o", "n"], "oi": 3},
+                {"p": ["o", "n"], "om": "m"},
+                {"p": ["o", "missing"], "od": 1},
+                {"p": ["l", 0], "na": 1},
+            ]))
+            .unwrap();
+        let removed = json0.apply_take(&mut value, vec![op]).unwrap();
+        assert_eq!(
+            vec![
+                (path!["l", 0], serde_json::json!({"big": [1, 2]})),
+                (path!["l", 0], serde_json::json!(2)),
+                (path!["o", "k"], serde_json::json!("v")),
+                (path!["o", "j"], serde_json::json!(1)),
+            ],
+            removed
+        );
+        assert_eq!(serde_json::json!({"l": [4], "o": {"j": 2, "m": 3}}), value);
+
+        let op = json0
+            .operation_factory()
+            .from_value(serde_json::json!([{"p": [], "od": null}]))
+            .unwrap();
+        let removed = json0.apply_take(&mut value, vec![op.clone()]).unwrap();
+        assert_eq!(
+            vec![(
+                Path::root(),
+                serde_json::json!({"l": [4], "o": {"j": 2, "m": 3}})
+            )],
+            removed
+        );
+        assert!(json0.apply_take(&mut value, vec![op]).unwrap().is_empty());
+
+        let failed = json0
+            .operation_factory()
+            .from_value(serde_json::json!([{"p": ["a"], "test": 1}]))
+            .unwrap();
+        let err = json0.apply_take(&mut value, vec![failed]).unwrap_err();
+        assert_eq!(Some(0), err.context().map(|c| c.component_index));
+    }
+
+    #[test]
+    fn test_watch_pattern() {
+        let json0 = Json0::new();
+        let events = Rc::new(std::cell::RefCell::new(vec![]));
+        let recorder = events.clone();
+        let pattern = PathPattern::try_from(r#"["users", "*", "email"]"#).unwrap();
+        json0.watch_pattern(pattern, move |e| {
+            recorder.borrow_mut().push((
+                e.path.to_string(),
+                e.old_value.cloned(),
+                e.new_value.cloned(),
+            ));
+        });
+
+        let mut value = serde_json::json!({"users": [{"email": "a"}, {"email": "b"}], "n": 1});
+        let op = json0
+            .operation_factory()
+            .from_value(serde_json::json!([
+                {"p": ["users", 1, "email"], "od": "b", "oi": "c"},
+                {"p": ["n"], "na": 1},
+                {"p": ["users", 0], "li": {"email": "d"}},
+                {"p": ["users", 2], "ld": {"email": "c"}},
+            ]))
+            .unwrap();
+        json0.apply(&mut value, vec![op]).unwrap();
+        let event = |p: &str, old: Option<&str>, new: Option<&str>| {
+            (p.to_string(), old.map(Value::from), new.map(Value::from))
+        };
+        assert_eq!(
+            vec![
+                event(r#"["users", 1, "email"]"#, Some("b"), Some("c")),
+                event(r#"["users", 0, "email"]"#, Some("a"), Some("d")),
+                event(r#"["users", 1, "email"]"#, Some("c"), Some("a")),
+                event(r#"["users", 2, "email"]"#, None, Some("c")),
+                event(r#"["users", 2, "email"]"#, Some("c"), None),
+            ],
+            *events.borrow()
+        );
+    }
+
+    #[test]
+    fn test_apply_hook() {
+        struct Guard;
+        impl ApplyHook for Guard {
+            fn before_component(
+                &self,
+                _value: &Value,
+                component: OperationComponent,
+            ) -> Result<OperationComponent> {
+                if component.path.first_key_path() == Some("locked") {
+                    return Err(JsonError::Rejected("locked".into()));
+                }
+                // numbers are always inserted as strings
+                match &component.operator {
+                    operation::Operator::ObjectInsert(v) if v.is_number() => {
+                        OperationComponent::new(
+                            component.path,
+                            operation::Operator::ObjectInsert(Value::String(v.to_string()).into()),
+                        )
+                    }
+                    _ => Ok(component),
+                }
+            }
+
+            fn after_component(&self, value: &Value, _: &OperationComponent) -> Result<()> {
+                if value.as_object().map(|o| o.len()).unwrap_or(0) > 2 {
+                    return Err(JsonError::Rejected("too many keys".into()));
+                }
+                Ok(())
+            }
+        }
+
+        let mut json0 = Json0::new();
+        json0.add_apply_hook(Box::new(Guard));
+        let parse = |s: &str| {
+            json0
+                .operation_factory()
+                .from_value(serde_json::from_str(s).unwrap())
+                .unwrap()
+        };
+
+        let mut value: Value = serde_json::from_str(r#"{"locked":1}"#).unwrap();
+        json0
+            .apply(&mut value, vec![parse(r#"[{"p":["a"],"oi":1}]"#)])
+            .unwrap();
+        assert_eq!(serde_json::json!({"a":"1","locked":1}), value);
+
+        assert_matches!(
+            json0
+                .apply(&mut value, vec![parse(r#"[{"p":["locked"],"od":1}]"#)])
+                .unwrap_err()
+                .root_cause(),
+            JsonError::Rejected(_)
+        );
+        let err = json0
+            .apply_with(
+                &mut value,
+                vec![parse(
+                    r#"[{"p":["a"],"od":"1"},{"p":["b"],"oi":true},{"p":["c"],"oi":true}]"#,
+                )],
+                &ApplyOptions::new().atomic(true),
+            )
+            .unwrap_err();
+        assert_matches!(err.root_cause(), JsonError::Rejected(_));
+        assert_eq!(2, err.context().unwrap().component_index);
+        assert_eq!(serde_json::json!({"a":"1","locked":1}), value);
+    }
+
+    #[test]
+    fn test_apply_verbose() {
+        let json0 = Json0::new();
+        let mut value: Value =
+            serde_json::from_str(r#"{"l":[1,2],"o":{"k":"v"},"n":1,"t":0}"#).unwrap();
+        let op = json0
+            .operation_factory()
+            .from_value(
+                serde_json::from_str(
+                    r#"[{"p":["l",-1],"li":3},{"p":["l",0],"ld":1},{"p":["o","k"],"oi":"w"},
+                        {"p":["o","k"],"om":"j"},{"p":["n"],"na":2},{"p":["l",0],"lm":1},
+                        {"p":["t"],"test":0}]"#,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+        let report = json0.apply_verbose(&mut value, vec![op]).unwrap();
+        let p = |s: &str| Path::try_from(s).unwrap();
+        assert_eq!(
+            vec![
+                p(r#"["l"]"#),
+                p(r#"["n"]"#),
+                p(r#"["o","j"]"#),
+                p(r#"["o","k"]"#)
+            ],
+            report.changed_paths
+        );
+        assert_eq!(
+            vec![
+                (p(r#"["l",2]"#), Value::from(3)),
+                (p(r#"["o","k"]"#), Value::from("w"))
+            ],
+            report.inserted
+        );
+        assert_eq!(
+            vec![
+                (p(r#"["l",0]"#), Value::from(1)),
+                (p(r#"["o","k"]"#), Value::from("v"))
+            ],
+            report.removed
+        );
+        assert_eq!(
+            vec![
+                IndexShift::Inserted {
+                    list: p(r#"["l"]"#),
+                    index: 2
+                },
+                IndexShift::Deleted {
+                    list: p(r#"["l"]"#),
+                    index: 0
+                },
+                IndexShift::Moved {
+                    list: p(r#"["l"]"#),
+                    from: 0,
+                    to: 1
+                }
+            ],
+            report.index_shifts
+        );
+        assert_eq!(
+            serde_json::json!([
+                {"type": "inserted", "path": ["l", 2], "value": 3},
+                {"type": "removed", "path": ["l", 0], "value": 1},
+                {"type": "replaced", "path": ["o", "k"], "old": "v", "new": "w"},
+                {"type": "moved", "from": ["o", "k"], "to": ["o", "j"]},
+                {"type": "number_added", "path": ["n"], "old": 1, "new": 3},
+                {"type": "moved", "from": ["l", 0], "to": ["l", 1]}
+            ]),
+            serde_json::to_value(&report.events).unwrap()
+        );
+        let events: Vec<ChangeEvent> =
+            serde_json::from_value(serde_json::to_value(&report.events).unwrap()).unwrap();
+        assert_eq!(report.events, events);
+
+        let mut value = serde_json::json!({"t": "ab"});
+        let op = json0
+            .operation_factory()
+            .from_value(serde_json::json!([{"p": ["t"], "t": "text", "o": {"p": 1, "i": "x"}}]))
+            .unwrap();
+        let report = json0.apply_verbose(&mut value, vec![op]).unwrap();
+        assert_eq!(
+            vec![ChangeEvent::TextEdited {
+                path: p(r#"["t"]"#),
+                old: Value::from("ab"),
+                new: Value::from("axb")
+            }],
+            report.events
+        );
+    }
+
+    #[test]
+    fn test_prune_empty() {
+        let json0 = Json0::new();
+        let mut value = serde_json::json!({
+            "a": {"b": {}, "c": [[], {"d": []}], "e": 1, "f": [1, {}, 2, []]},
+            "g": {}
+        });
+        let origin = value.clone();
+        let op = json0
+            .prune_empty(&mut value, &Path::try_from(r#"["a"]"#).unwrap())
+            .unwrap();
+        assert_eq!(
+            serde_json::json!({"a": {"e": 1, "f": [1, 2]}, "g": {}}),
+            value
+        );
+        assert_eq!(
+            json0
+                .operation_factory()
+                .from_value(serde_json::json!([
+                    {"p": ["a", "b"], "od": {}},
+                    {"p": ["a", "c"], "od": [[], {"d": []}]},
+                    {"p": ["a", "f", 3], "ld": []},
+                    {"p": ["a", "f", 1], "ld": {}}
+                ]))
+                .unwrap(),
+            op
+        );
+        json0.apply(&mut value, vec![op.invert().unwrap()]).unwrap();
+        assert_eq!(origin, value);
+
+        let op = json0.prune_empty(&mut value, &Path::root()).unwrap();
+        assert_eq!(5, op.len());
+        assert_eq!(serde_json::json!({"a": {"e": 1, "f": [1, 2]}}), value);
+        assert!(json0
+            .prune_empty(&mut value, &Path::try_from(r#"["x"]"#).unwrap())
+            .unwrap()
+            .is_empty());
+
+        let op = json0
+            .operation_factory()
+            .from_value(serde_json::json!([{"p": ["a", "b", 0], "ld": 1}]))
+            .unwrap();
+        let options = ApplyOptions::new().prune_empty(Some(Path::root()));
+        let mut value = serde_json::json!({"a": {"b": [1]}, "c": 2});
+        let (undo, pruned) = json0
+            .apply_with_pruned(&mut value, vec![op.clone()], &options)
+            .unwrap();
+        assert_eq!(serde_json::json!({"c": 2}), value);
+        assert_eq!(
+            json0
+                .operation_factory()
+                .from_value(serde_json::json!([{"p": ["a"], "od": {"b": []}}]))
+                .unwrap(),
+            pruned
+        );
+
+        // other sites apply the pruning after the operation
+        let mut peer = serde_json::json!({"a": {"b": [1]}, "c": 2});
+        json0.apply(&mut peer, vec![op.clone(), pruned]).unwrap();
+        assert_eq!(value, peer);
+
+        json0.apply(&mut value, vec![undo]).unwrap();
+        assert_eq!(serde_json::json!({"a": {"b": [1]}, "c": 2}), value);
+
+        let mut value = serde_json::json!({"a": {"b": [1]}});
+        json0.apply_with(&mut value, vec![op], &options).unwrap();
+        assert_eq!(serde_json::json!({}), value);
+    }
+
+    #[test]
+    fn test_check() {
+        let json0 = Json0::new();
+        let value: Value = serde_json::from_str(r#"{"l":[1],"n":1,"s":"a"}"#).unwrap();
+        let watched = Rc::new(std::cell::Cell::new(false));
+        let w = watched.clone();
+        json0.watch(Path::root(), move |_| w.set(true));
+        let check = |s: &str| {
+            json0.check(
+                &value,
+                &json0
+                    .operation_factory()
+                    .from_value(serde_json::from_str(s).unwrap())
+                    .unwrap(),
+            )
+        };
+
+        assert!(check(r#"[{"p":["l",0],"ld":1},{"p":["l",0],"li":2},{"p":["n"],"na":1}]"#).is_ok());
+        // later components see the changes of earlier ones
+        assert!(check(r#"[{"p":["n"],"od":1},{"p":["n"],"oi":"x"},{"p":["n"],"na":1}]"#).is_err());
+        assert!(check(r#"[{"p":["x","y"],"oi":1}]"#).is_err());
+        assert!(check(r#"[{"p":["s"],"na":1}]"#).is_err());
+        assert!(check(r#"[{"p":["n"],"test":2}]"#).is_err());
+        assert!(!watched.get());
+    }
+
+    #[test]
+    fn test_text_delete() {
+        let json0 = Json0::new();
+        let parse = |v: Value| json0.operation_factory().from_value(v).unwrap();
+        let delete = |text: &str, p: usize, d: &str| {
+            let mut value = serde_json::json!({ "t": text });
+            json0
+                .apply(
+                    &mut value,
+                    vec![parse(
+                        serde_json::json!([{"p": ["t"], "t": "text", "o": {"p": p, "d": d}}]),
+                    )],
+                )
+                .map(|_| value["t"].as_str().unwrap().to_string())
+                .map_err(|e| match e {
+                    JsonError::Context { source, .. } => *source,
+                    e => e,
+                })
+        };
+
+        assert_eq!("abef", delete("abcdef", 2, "cd").unwrap());
+        assert_eq!("", delete("ab", 0, "ab").unwrap());
+        let err = delete("abcdef", 2, "cx").unwrap_err();
+        assert_eq!(ErrorCode::InvalidSubtypeOperation, err.code());
+        let JsonError::ApplyOperationError(err) = err else {
+            panic!("unexpected error: {err}");
+        };
+        assert_matches!(
+            *err,
+            ApplyOperationError::TextDeleteMismatch {
+                offset: 2,
+                expected,
+                actual: Some(actual),
+                text_len: 6
+            } if expected == "cx" && actual == "cd"
+        );
+        for (text, p, d) in [("abc", 2, "cd"), ("abc", 7, "a"), ("é", 1, "a")] {
+            assert_matches!(
+                delete(text, p, d).unwrap_err(),
+                JsonError::ApplyOperationError(e) if matches!(
+                    *e,
+                    ApplyOperationError::TextDeleteMismatch { actual: None, .. }
+                )
+            );
+        }
+        assert!(json0
+            .operation_factory()
+            .from_str(r#"[{"p":["t"],"t":"text","o":{"p":-1,"d":"a"}}]"#)
+            .is_err());
+
+        // random deletes on random texts either fail or delete what they say
+        let mut rng = testing::Rng::new(7);
+        let chars = ['a', 'b', 'é', '中', '😀'];
+        for _ in 0..1000 {
+            let text = (0..rng.below(8))
+                .map(|_| chars[rng.below(chars.len())])
+                .collect::<String>();
+            let p = rng.below(text.len() + 3);
+            let d = (0..rng.below(4))
+                .map(|_| chars[rng.below(chars.len())])
+                .collect::<String>();
+            match delete(&text, p, &d) {
+                Ok(deleted) => {
+                    assert_eq!(&text[p..p + d.len()], d);
+                    assert_eq!(format!("{}{}", &text[..p], &text[p + d.len()..]), deleted);
+                }
+                Err(e) => assert_matches!(
+                    e,
+                    JsonError::ApplyOperationError(e)
+                        if matches!(*e, ApplyOperationError::TextDeleteMismatch { .. })
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn test_text_delete_by_length() {
+        let json0 = Json0::new();
+        let parse = |s: &str| json0.operation_factory().from_str(s).unwrap();
+        let doc = serde_json::json!({"t": "abcdé"});
+        let op = parse(
+            r#"[{"p":["t"],"t":"text","o":{"p":1,"d":2}},{"p":["t"],"t":"text","o":{"p":1,"d":3}}]"#,
+        );
+
+        let mut value = doc.clone();
+        json0.apply(&mut value, vec![op.clone()]).unwrap();
+        assert_eq!(serde_json::json!({"t": "a"}), value);
+
+        let mut value = doc.clone();
+        let undo = json0
+            .apply_with(&mut value, vec![op.clone()], &ApplyOptions::new())
+            .unwrap();
+        json0.apply(&mut value, vec![undo]).unwrap();
+        assert_eq!(doc, value);
+
+        let resolved = json0.resolve_text_deletes(&doc, &op).unwrap();
+        assert_eq!(
+            parse(
+                r#"[{"p":["t"],"t":"text","o":{"p":1,"d":"bc"}},{"p":["t"],"t":"text","o":{"p":1,"d":"dé"}}]"#
+            ),
+            resolved
+        );
+        assert!(op.invert().is_err());
+        assert!(resolved.invert().is_ok());
+
+        // out of the text or splitting a char
+        for o in [r#"{"p":1,"d":9}"#, r#"{"p":1,"d":4}"#] {
+            let op = parse(&format!(r#"[{{"p":["t"],"t":"text","o":{}}}]"#, o));
+            let mut value = doc.clone();
+            assert_eq!(
+                ErrorCode::InvalidSubtypeOperation,
+                json0
+                    .apply(&mut value, vec![op.clone()])
+                    .unwrap_err()
+                    .code()
+            );
+            assert!(json0.resolve_text_deletes(&doc, &op).is_err());
+        }
+    }
+
+    #[test]
+    fn test_transform_text_delete_by_length() {
+        let json0 = Json0::new();
+        let parse = |o: Value| {
+            json0
+                .operation_factory()
+                .from_value(serde_json::json!([{"p":["t"],"t":"text","o":o}]))
+                .unwrap()
+        };
+        let doc = serde_json::json!({"t": "abcdef"});
+        for (a, b, expected) in [
+            (
+                serde_json::json!({"p":1,"d":3}),
+                serde_json::json!({"p":2,"i":"XY"}),
+                "aXYef",
+            ),
+            (
+                serde_json::json!({"p":1,"d":3}),
+                serde_json::json!({"p":0,"i":"X"}),
+                "Xaef",
+            ),
+            (
+                serde_json::json!({"p":1,"d":3}),
+                serde_json::json!({"p":2,"d":3}),
+                "af",
+            ),
+            (
+                serde_json::json!({"p":1,"d":3}),
+                serde_json::json!({"p":3,"d":"def"}),
+                "a",
+            ),
+            (
+                serde_json::json!({"p":4,"d":2}),
+                serde_json::json!({"p":0,"d":2}),
+                "cd",
+            ),
+        ] {
+            let (a, b) = (parse(a), parse(b));
+            let (a1, b1) = json0.transform(&a, &b).unwrap();
+            let mut v1 = doc.clone();
+            json0.apply(&mut v1, vec![a.clone(), b1]).unwrap();
+            let mut v2 = doc.clone();
+            json0.apply(&mut v2, vec![b, a1]).unwrap();
+            assert_eq!(serde_json::json!({ "t": expected }), v1);
+            assert_eq!(v1, v2);
+        }
+
+        // deleted by length stays of the length form
+        let (a1, _) = json0
+            .transform(
+                &parse(serde_json::json!({"p":4,"d":2})),
+                &parse(serde_json::json!({"p":0,"i":"X"})),
+            )
+            .unwrap();
+        assert_eq!(parse(serde_json::json!({"p":5,"d":2})), a1);
+    }
+
+    #[test]
+    fn test_error_context() {
+        let json0 = Json0::new();
+        let mut value = serde_json::json!({"a": 1});
+        let long = "x".repeat(200);
+        let op = json0
+            .operation_factory()
+            .from_value(serde_json::json!([{"p":["b"],"oi":1},{"p":["a","c"],"oi":long}]))
+            .unwrap();
+        let err = json0
+            .apply(&mut value, vec![Operation::default(), op])
+            .unwrap_err();
+        let context = err.context().unwrap();
+        assert_eq!((1, 1), (context.operation_index, context.component_index));
+        assert_eq!(path!["a", "c"], context.path);
+        assert!(context.operator.starts_with(r#"{"oi":"xxx"#));
+        assert!(context.operator.ends_with("..."));
+        assert!(context.operator.len() < 110);
+        assert!(err.to_string().ends_with(&format!(
+            r#"at operation 1 component 1 on path ["a", "c"] ({})"#,
+            context.operator
+        )));
+        assert_matches!(err.root_cause(), JsonError::ApplyOperationError(_));
+        assert_eq!(ErrorCode::InvalidApplyTarget, err.code());
+        assert_eq!(2, err.code().as_u16());
+        assert!(err
+            .to_string()
+            .starts_with("[invalid_apply_target] Can not apply"));
+
+        let err = json0
+            .operation_factory()
+            .from_value(serde_json::json!([{"p":["a"],"na":"x"}]))
+            .unwrap_err();
+        assert_eq!(ErrorCode::InvalidOperation, err.code());
+        assert!(err.to_string().starts_with("[invalid_operation] "));
+    }
+
+    #[test]
+    fn test_apply_partial() {
+        let json0 = Json0::new();
+        let parse = |s: &str| {
+            json0
+                .operation_factory()
+                .from_value(serde_json::from_str(s).unwrap())
+                .unwrap()
+        };
+        let mut value: Value = serde_json::from_str(r#"{"n":1,"s":"a"}"#).unwrap();
+        let (applied, failures) = json0.apply_partial(
+            &mut value,
+            vec![
+                parse(r#"[{"p":["n"],"na":1},{"p":["s"],"na":1}]"#),
+                parse(r#"[{"p":["x","y"],"oi":1},{"p":["s"],"oi":"b"}]"#),
+            ],
+        );
+        assert_eq!(r#"{"n":2,"s":"b"}"#, value.to_string());
+        assert_eq!(
+            serde_json::json!([{"p":["n"],"na":1},{"p":["s"],"oi":"b"}]),
+            applied.to_value()
+        );
+        assert_eq!(vec![1, 2], failures.iter().map(|(i, _)| *i).collect_vec());
+        assert_matches!(
+            failures[0].1.root_cause(),
+            JsonError::ApplyOperationError(_)
+        );
+        assert_eq!(
+            (0, 1),
+            failures[0]
+                .1
+                .context()
+                .map(|c| (c.operation_index, c.component_index))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_transform_x_left_right() {
+        let json0 = Json0::new();
+        let parse = |s: &str| {
+            json0
+                .operation_factory()
+                .from_value(serde_json::from_str(s).unwrap())
+                .unwrap()
+        };
+        let cases = [
+            (r#"[{"p":[0],"li":"a"}]"#, r#"[{"p":[0],"li":"b"}]"#),
+            (
+                r#"[{"p":[0],"li":"a"},{"p":[2],"li":"c"}]"#,
+                r#"[{"p":[0],"li":"b"},{"p":[1],"ld":1},{"p":[0],"lm":1}]"#,
+            ),
+        ];
+        for (l, r) in cases {
+            let (left, right) = (parse(l), parse(r));
+            let (l2, r2) = json0.transform_x(&left, &right).unwrap();
+            assert_eq!(l2, json0.transform_left(&left, &right).unwrap());
+            assert_eq!(r2, json0.transform_right(&right, &left).unwrap());
+
+            let mut a: Value = serde_json::from_str("[1, 2, 3]").unwrap();
+            let mut b = a.clone();
+            json0.apply(&mut a, vec![left, r2]).unwrap();
+            json0.apply(&mut b, vec![right, l2]).unwrap();
+            assert_eq!(a, b);
+        }
+    }
+
+    #[test]
+    fn test_transform_with_priority() {
+        let json0 = Json0::new();
+        let parse = |s: &str| {
+            json0
+                .operation_factory()
+                .from_value(serde_json::from_str(s).unwrap())
+                .unwrap()
+        };
+        let a = parse(r#"[{"p":[0],"li":"a"}]"#);
+        let b = parse(r#"[{"p":[0],"li":"b"}]"#);
+
+        let (a1, b1) = json0
+            .transform_with_priority(&a, &b, "client-2", "client-1")
+            .unwrap();
+        let (b2, a2) = json0
+            .transform_with_priority(&b, &a, "client-1", "client-2")
+            .unwrap();
+        assert_eq!((&a1, &b1), (&a2, &b2));
+
+        let mut v1 = Value::Array(vec![]);
+        json0.apply(&mut v1, vec![a, b1]).unwrap();
+        let mut v2 = Value::Array(vec![]);
+        json0.apply(&mut v2, vec![b, a1]).unwrap();
+        assert_eq!(v1, v2);
+        assert_eq!(r#"["b","a"]"#, v1.to_string());
+    }
+
+    #[test]
+    fn test_transform_symmetric() {
+        let json0 = Json0::new();
+        let parse = |s: &str| json0.operation_factory().from_str(s).unwrap();
+        let a = parse(r#"[{"p":["l",0],"li":"a"},{"p":["t"],"t":"text","o":{"p":0,"i":"a"}}]"#);
+        let b = parse(r#"[{"p":["l",0],"li":"b"},{"p":["t"],"t":"text","o":{"p":0,"i":"b"}}]"#);
+        let doc = serde_json::json!({"l": [], "t": ""});
+
+        let (a1, b1) = json0
+            .transform_with_side(
+                &a,
+                &b,
+                TransformSide::Symmetric {
+                    key: 2,
+                    base_key: 1,
+                },
+            )
+            .unwrap();
+        let (b2, a2) = json0
+            .transform_with_side(
+                &b,
+                &a,
+                TransformSide::Symmetric {
+                    key: 1,
+                    base_key: 2,
+                },
+            )
+            .unwrap();
+        assert_eq!((&a1, &b1), (&a2, &b2));
+
+        let mut v1 = doc.clone();
+        json0.apply(&mut v1, vec![a.clone(), b1]).unwrap();
+        let mut v2 = doc.clone();
+        json0.apply(&mut v2, vec![b.clone(), a1]).unwrap();
+        assert_eq!(v1, v2);
+        assert_eq!(serde_json::json!({"l": ["b", "a"], "t": "ba"}), v1);
+
+        assert_eq!(TransformSide::Right, TransformSide::Right.resolve(&a, &b));
+
+        // equal keys are broken by the operations
+        let side = TransformSide::Symmetric {
+            key: 1,
+            base_key: 1,
+        };
+        assert_eq!(TransformSide::Left, side.resolve(&a, &b));
+        assert_eq!(TransformSide::Right, side.resolve(&b, &a));
+        let (a1, _) = json0.transform_with_side(&a, &b, side).unwrap();
+        let (b1, _) = json0.transform_with_side(&b, &a, side).unwrap();
+        let mut v1 = doc.clone();
+        json0.apply(&mut v1, vec![a.clone(), b1]).unwrap();
+        let mut v2 = doc.clone();
+        json0.apply(&mut v2, vec![b.clone(), a1]).unwrap();
+        assert_eq!(v1, v2);
+        assert_eq!(serde_json::json!({"l": ["a", "b"], "t": "ab"}), v1);
+        assert_eq!(
+            json0.transform_left(&a, &b).unwrap(),
+            json0
+                .transformer
+                .transform_side(
+                    &a,
+                    &b,
+                    TransformSide::Symmetric {
+                        key: 0,
+                        base_key: 1
+                    }
+                )
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_insert_order() {
+        let mut json0 = Json0::new();
+        json0.set_insert_order(|op, other| {
+            let ts = |c: &OperationComponent| {
+                c.operator
+                    .inserted_value()
+                    .and_then(|v| v.get("ts"))
+                    .and_then(|v| v.as_u64())
+            };
+            match (ts(op), ts(other)) {
+                (Some(a), Some(b)) if a < b => InsertOrder::Before,
+                (Some(a), Some(b)) if a > b => InsertOrder::After,
+                _ => InsertOrder::BySide,
+            }
+        });
+        let parse = |s: &str| json0.operation_factory().from_str(s).unwrap();
+        let doc = serde_json::json!({"l": []});
+
+        for (a, b, expected) in [
+            (
+                r#"[{"p":["l",0],"li":{"ts":2}}]"#,
+                r#"[{"p":["l",0],"li":{"ts":1}}]"#,
+                serde_json::json!({"l": [{"ts":1}, {"ts":2}]}),
+            ),
+            (
+                r#"[{"p":["l",-1],"li":{"ts":2}}]"#,
+                r#"[{"p":["l",-1],"li":{"ts":1}}]"#,
+                serde_json::json!({"l": [{"ts":1}, {"ts":2}]}),
+            ),
+            // left wins without timestamps
+            (
+                r#"[{"p":["l",0],"li":"a"}]"#,
+                r#"[{"p":["l",0],"li":"b"}]"#,
+                serde_json::json!({"l": ["a", "b"]}),
+            ),
+        ] {
+            let (a, b) = (parse(a), parse(b));
+            let (a1, b1) = json0.transform(&a, &b).unwrap();
+            let mut v1 = doc.clone();
+            json0.apply(&mut v1, vec![a.clone(), b1]).unwrap();
+            let mut v2 = doc.clone();
+            json0.apply(&mut v2, vec![b.clone(), a1]).unwrap();
+            assert_eq!(expected, v1);
+            assert_eq!(expected, v2);
+        }
+    }
+
+    #[test]
+    fn test_transform_operations_on_many_subtrees() {
+        let json0 = Json0::new();
+        let parse = |v: Value| json0.operation_factory().from_value(v).unwrap();
+        let a = parse(serde_json::json!([
+            {"p":["a",0],"li":"a0"},
+            {"p":["b","x"],"oi":1},
+            {"p":["a",2],"ld":2},
+            {"p":["c","n"],"na":1},
+            {"p":["b","y"],"om":"z"}
+        ]));
+        let b = parse(serde_json::json!([
+            {"p":["b","w"],"oi":2},
+            {"p":["a",1],"li":"b1"},
+            {"p":["c"],"od":{"n":1}},
+            {"p":["a",0],"lm":2},
+            {"p":["d"],"oi":4}
+        ]));
+        let origin = serde_json::json!({"a":[0,1,2,3],"b":{"y":1},"c":{"n":1}});
+
+        let (a1, b1) = json0.transform(&a, &b).unwrap();
+        let mut v1 = origin.clone();
+        json0.apply(&mut v1, vec![a, b1]).unwrap();
+        let mut v2 = origin;
+        json0.apply(&mut v2, vec![b, a1]).unwrap();
+        assert_eq!(v1, v2);
+    }
+
+    #[test]
+    fn test_transform_against_deleting_and_putting_back_equal_value() {
+        let json0 = Json0::new();
+        let parse = |v: Value| json0.operation_factory().from_value(v).unwrap();
+        let a = parse(serde_json::json!([{"p":["d",0],"li":1}]));
+        let b = parse(serde_json::json!([{"p":["d"],"od":[]},{"p":["d"],"oi":[]}]));
+        let origin = serde_json::json!({"d":[]});
+
+        let (a1, b1) = json0.transform(&a, &b).unwrap();
+        let mut v1 = origin.clone();
+        json0.apply(&mut v1, vec![a, b1]).unwrap();
+        let mut v2 = origin.clone();
+        json0.apply(&mut v2, vec![b, a1]).unwrap();
+        assert_eq!(origin, v1);
+        assert_eq!(origin, v2);
+    }
+
+    #[test]
+    fn test_transform_append_against_index() {
+        let json0 = Json0::new();
+        let parse = |v: Value| json0.operation_factory().from_value(v).unwrap();
+        let origin = serde_json::json!({"l":["a","b","c"]});
+        let a = parse(serde_json::json!([{"p":["l",-1],"li":"X"}]));
+        for (b, expected) in [
+            (
+                serde_json::json!([{"p":["l",3],"li":"Y"}]),
+                serde_json::json!(["a", "b", "c", "Y", "X"]),
+            ),
+            (
+                serde_json::json!([{"p":["l",0],"li":"Y"}]),
+                serde_json::json!(["Y", "a", "b", "c", "X"]),
+            ),
+            (
+                serde_json::json!([{"p":["l",2],"ld":"c"}]),
+                serde_json::json!(["a", "b", "X"]),
+            ),
+            (
+                serde_json::json!([{"p":["l",2],"ld":"c","li":"Y"}]),
+                serde_json::json!(["a", "b", "Y", "X"]),
+            ),
+            (
+                serde_json::json!([{"p":["l",0],"lm":2}]),
+                serde_json::json!(["b", "c", "a", "X"]),
+            ),
+        ] {
+            let b = parse(b);
+            let (a1, b1) = json0.transform(&a, &b).unwrap();
+            let mut v1 = origin.clone();
+            json0.apply(&mut v1, vec![a.clone(), b1]).unwrap();
+            let mut v2 = origin.clone();
+            json0.apply(&mut v2, vec![b, a1]).unwrap();
+            assert_eq!(expected, v1["l"]);
+            assert_eq!(expected, v2["l"]);
+        }
+
+        // concurrent appends, then indexes into the rest of the list
+        let b = parse(serde_json::json!([
+            {"p":["l",3],"li":"Y"},{"p":["l",-1],"li":"Z"},{"p":["l",0],"ld":"a"}
+        ]));
+        for side in [TransformSide::Left, TransformSide::Right] {
+            let (a1, b1) = json0.transform_with_side(&a, &b, side).unwrap();
+            let mut v1 = origin.clone();
+            json0.apply(&mut v1, vec![a.clone(), b1]).unwrap();
+            let mut v2 = origin.clone();
+            json0.apply(&mut v2, vec![b.clone(), a1]).unwrap();
+            assert_eq!(v1, v2);
+        }
+        assert_eq!(
+            serde_json::json!({"l":["b","c","Y","X","Z"]}),
+            crate::testing::check_convergence(&json0, &origin, &a, &b)
+        );
+        assert_eq!(
+            serde_json::json!({"l":["b","c","Y","Z","X"]}),
+            crate::testing::check_convergence(&json0, &origin, &b, &a)
+        );
+
+        // positions from the end against indexes before them
+        let a = parse(serde_json::json!([{"p":["l",-2],"li":"X"}]));
+        for (b, expected) in [
+            (
+                serde_json::json!([{"p":["l",0],"li":"Y"}]),
+                serde_json::json!({"l":["Y","a","b","X","c"]}),
+            ),
+            (
+                serde_json::json!([{"p":["l",0],"ld":"a"}]),
+                serde_json::json!({"l":["b","X","c"]}),
+            ),
+            (
+                serde_json::json!([{"p":["l",-1],"li":"Y"},{"p":["l",1],"ld":"b"}]),
+                serde_json::json!({"l":["a","X","c","Y"]}),
+            ),
+        ] {
+            let b = parse(b);
+            assert_eq!(
+                expected,
+                crate::testing::check_convergence(&json0, &origin, &a, &b)
+            );
+            assert_eq!(
+                expected,
+                crate::testing::check_convergence(&json0, &origin, &b, &a)
+            );
+        }
+    }
+
+    #[test]
+    fn test_transform_object_move() {
+        let json0 = Json0::new();
+        let parse = |v: Value| json0.operation_factory().from_value(v).unwrap();
+        for (doc, a, b, expected) in [
+            // the move carries the value replacing the moved one
+            (
+                serde_json::json!({"a":5}),
+                serde_json::json!([{"p":["a"],"om":"b"}]),
+                serde_json::json!([{"p":["a"],"oi":"t","od":5}]),
+                serde_json::json!({"b":"t"}),
+            ),
+            // move wins over insert to the destination
+            (
+                serde_json::json!({"e":12}),
+                serde_json::json!([{"p":["b"],"oi":null}]),
+                serde_json::json!([{"p":["e"],"om":"b"},{"p":["e"],"oi":false}]),
+                serde_json::json!({"b":12,"e":false}),
+            ),
+            (
+                serde_json::json!({"c":null}),
+                serde_json::json!([{"p":["a"],"oi":""}]),
+                serde_json::json!([{"p":["a"],"oi":49},{"p":["a"],"om":"b"}]),
+                serde_json::json!({"b":"","c":null}),
+            ),
+            // edits under the moved key follow it
+            (
+                serde_json::json!({"a":{"x":[1]},"n":0}),
+                serde_json::json!([{"p":["a","x",0],"li":0},{"p":["a","y"],"oi":2},{"p":["n"],"na":1}]),
+                serde_json::json!([{"p":["a"],"om":"b"},{"p":["b","x",1],"li":2}]),
+                serde_json::json!({"b":{"x":[0,1,2],"y":2},"n":1}),
+            ),
+            // deleting the moved key wins
+            (
+                serde_json::json!({"a":1,"b":2}),
+                serde_json::json!([{"p":["a"],"om":"c"},{"p":["b"],"om":"a"}]),
+                serde_json::json!([{"p":["a"],"od":1}]),
+                serde_json::json!({"a":2}),
+            ),
+            // both move the same key, or to the same key, left wins
+            (
+                serde_json::json!({"a":1,"b":2}),
+                serde_json::json!([{"p":["a"],"om":"c"},{"p":["c"],"na":1}]),
+                serde_json::json!([{"p":["a"],"om":"d"},{"p":["b"],"om":"c"}]),
+                serde_json::json!({"b":2,"c":2}),
+            ),
+            (
+                serde_json::json!({"a":1,"b":2}),
+                serde_json::json!([{"p":["a"],"om":"c"}]),
+                serde_json::json!([{"p":["b"],"om":"c"},{"p":["c"],"na":1}]),
+                serde_json::json!({"b":3,"c":1}),
+            ),
+        ] {
+            let (a, b) = (parse(a), parse(b));
+            assert_eq!(
+                expected,
+                crate::testing::check_convergence(&json0, &doc, &a, &b)
+            );
+        }
+    }
+
+    #[test]
+    fn test_transform_traced() {
+        let json0 = Json0::new();
+        let parse = |v: Value| json0.operation_factory().from_value(v).unwrap();
+        let a = parse(serde_json::json!([
+            {"p":["l",1],"li":"a"},
+            {"p":["o","k"],"oi":1}
+        ]));
+        let b = parse(serde_json::json!([
+            {"p":["l",0],"li":"b"},
+            {"p":["o"],"od":{}}
+        ]));
+
+        let (a1, b1, steps) = json0.transform_traced(&a, &b).unwrap();
+        assert_eq!(json0.transform(&a, &b).unwrap(), (a1.clone(), b1));
+        assert_eq!(4, steps.len());
+
+        let step = &steps[0];
+        assert_eq!(TransformRule::ListInsert, step.rule);
+        assert_eq!(a[0], step.operation);
+        assert_eq!(b[0], step.base);
+        assert_eq!(vec![a1[0].clone()], step.result);
+        assert_eq!(
+            r#"ListInsert: {"p":["l",1],"li":"a"} against {"p":["l",0],"li":"b"} => [{"p":["l",2],"li":"a"}]"#,
+            step.to_string()
+        );
+        assert_eq!(TransformRule::ListInsert, steps[1].rule);
+        assert_eq!(b[0], steps[1].operation);
+
+        // the inserted key is under the deleted object
+        assert_eq!(TransformRule::ObjectDelete, steps[2].rule);
+        assert!(steps[2].result.is_empty());
+        assert_eq!(TransformRule::Consume, steps[3].rule);
+    }
+
+    #[cfg(feature = "arbitrary-precision")]
+    #[test]
+    fn test_number_add_arbitrary_precision() {
+        let json0 = Json0::new();
+        let parse = |s: &str| {
+            json0
+                .operation_factory()
+                .from_value(serde_json::from_str(s).unwrap())
+                .unwrap()
+        };
+        let mut value: Value =
+            serde_json::from_str(r#"{"u":18446744073709551615,"d":0.1}"#).unwrap();
+        let mut op = parse(r#"[{"p":["u"],"na":18446744073709551615},{"p":["d"],"na":0.2}]"#);
+        json0.apply(&mut value, vec![op.clone()]).unwrap();
+        assert_eq!(
+            r#"{"d":0.3,"u":36893488147419103230}"#,
+            to_canonical_string(&value)
+        );
+
+        let inverted = op.invert().unwrap();
+        assert_eq!(
+            r#"[{"p":["d"],"na":-0.2},{"p":["u"],"na":-18446744073709551615}]"#,
+            inverted.to_string()
+        );
+        json0.apply(&mut value, vec![inverted]).unwrap();
+        assert_eq!(
+            r#"{"d":0.1,"u":18446744073709551615}"#,
+            to_canonical_string(&value)
+        );
+
+        op.compose(parse(r#"[{"p":["d"],"na":0.1}]"#)).unwrap();
+        assert_eq!(
+            r#"[{"p":["u"],"na":18446744073709551615},{"p":["d"],"na":0.3}]"#,
+            op.to_string()
+        );
+    }
+
+    #[cfg(not(feature = "arbitrary-precision"))]
+    #[test]
+    fn test_number_add_overflow() {
+        let json0 = Json0::new();
+        let parse = |s: &str| {
+            json0
+                .operation_factory()
+                .from_value(serde_json::from_str(s).unwrap())
+                .unwrap()
+        };
+        let doc = serde_json::json!({ "n": i64::MAX - 1 });
+        let apply_with = |s: &str, options: &ApplyOptions| {
+            let mut value = doc.clone();
+            json0
+                .apply_with(&mut value, vec![parse(s)], options)
+                .map(|_| value)
+        };
+        let apply = |s: &str| apply_with(s, &ApplyOptions::new());
+        let add = r#"[{"p":["n"],"na":2}]"#;
+        let sub = r#"[{"p":["n"],"na":-5}]"#;
+        assert_eq!(serde_json::json!({"n": i64::MIN}), apply(add).unwrap());
+
+        // concurrent adds past the bounds converge
+        assert_eq!(
+            serde_json::json!({"n": i64::MAX - 4}),
+            crate::testing::check_convergence(&json0, &doc, &parse(add), &parse(sub))
+        );
+        let mut op = parse(&format!(r#"[{{"p":["n"],"na":{}}}]"#, i64::MAX));
+        op.compose(parse(add)).unwrap();
+        assert_eq!(1, op.len());
+        assert_eq!(
+            serde_json::json!({"n": -1}),
+            apply(&op.to_string()).unwrap()
+        );
+        let min = format!(r#"[{{"p":["n"],"na":{}}}]"#, i64::MIN);
+        assert_eq!(
+            serde_json::json!(i64::MIN),
+            parse(&min)[0].invert().unwrap().to_value()["na"]
+        );
+
+        // other policies are only taken by a local apply with them
+        let saturate = ApplyOptions::new().number_overflow(NumberOverflow::Saturate);
+        assert_eq!(
+            serde_json::json!({"n": i64::MAX}),
+            apply_with(add, &saturate).unwrap()
+        );
+        let promote = ApplyOptions::new().number_overflow(NumberOverflow::PromoteToFloat);
+        assert_eq!(
+            serde_json::json!({"n": i64::MAX as f64 + 1.0}),
+            apply_with(add, &promote).unwrap()
+        );
+        let error = ApplyOptions::new()
+            .number_overflow(NumberOverflow::Error)
+            .atomic(true);
+        let err = apply_with(add, &error).unwrap_err();
+        assert_eq!(ErrorCode::InvalidApplyTarget, err.code());
+        assert!(err.to_string().contains("overflows i64"));
+        assert_eq!(
+            serde_json::json!({"n": i64::MAX}),
+            apply_with(r#"[{"p":["n"],"na":1}]"#, &error).unwrap()
+        );
+        assert_eq!(serde_json::json!({"n": i64::MIN}), apply(add).unwrap());
+    }
+
+    #[test]
+    fn test_custom_subtype_registry() {
+        // text which replaces the whole string with its operand
+        struct ReplaceText;
+        impl SubTypeFunctions for ReplaceText {
+            fn invert(&self, _: &Path, _: &Value) -> Result<Value> {
+                Err(JsonError::InvalidOperation("not invertible".into()))
+            }
+
+            fn merge(&self, _: &Value, other_operand: &Value) -> Option<Value> {
+                Some(other_operand.clone())
+            }
+
+            fn transform(&self, new: &Value, _: &Value, _: TransformSide) -> Result<Vec<Value>> {
+                Ok(vec![new.clone()])
+            }
+
+            fn apply(
+                &self,
+                _: Option<&Value>,
+                operand: &Value,
+            ) -> json::ApplyResult<Option<Value>> {
+                Ok(Some(operand.clone()))
+            }
+
+            fn validate_operand(&self, val: &Value) -> Result<()> {
+                match val {
+                    Value::String(_) => Ok(()),
+                    _ => Err(JsonError::InvalidOperation(format!(
+                        "{val} is not a string"
+                    ))),
+                }
+            }
+        }
+
+        let json0 = Json0::with_subtypes(SubTypeFunctionsHolder::empty());
+        let parse = |s: &str| {
+            json0
+                .operation_factory()
+                .from_value(serde_json::from_str(s).unwrap())
+        };
+        assert_matches!(
+            parse(r#"[{"p":["n"],"na":1}]"#),
+            Err(JsonError::InvalidOperation(_))
+        );
+        let text = r#"[{"p":["t"],"t":"text","o":"b"}]"#;
+        assert_matches!(parse(text), Err(JsonError::InvalidOperation(_)));
+
+        assert!(json0
+            .register_subtype("text", ReplaceText)
+            .unwrap()
+            .is_none());
+        let mut value = serde_json::json!({"t": "a"});
+        json0.apply(&mut value, vec![parse(text).unwrap()]).unwrap();
+        assert_eq!(serde_json::json!({"t": "b"}), value);
+
+        assert!(json0.unregister_subtype("text").is_some());
+        assert_matches!(parse(text), Err(JsonError::InvalidOperation(_)));
+    }
+
+    #[test]
+    fn test_subtype_alias() {
+        let json0 = Json0::new();
+        let parse = |s: &str| {
+            json0
+                .operation_factory()
+                .from_value(serde_json::from_str(s).unwrap())
+        };
+        let text0 = r#"[{"p":["t"],"t":"text0","o":{"p":1,"i":"a"}}]"#;
+        assert_matches!(parse(text0), Err(JsonError::InvalidOperation(_)));
+
+        assert_eq!(None, json0.register_alias("text0", SubType::Text).unwrap());
+        json0
+            .register_alias("rich-text", SubType::Custome("text0".into()))
+            .unwrap();
+        let a = parse(text0).unwrap();
+        let b = parse(r#"[{"p":["t"],"t":"rich-text","o":{"p":0,"i":"b"}}]"#).unwrap();
+        assert_eq!(
+            serde_json::json!([{"p":["t"],"t":"text","o":{"p":1,"i":"a"}}]),
+            a.to_value()
+        );
+        let (a1, _) = json0.transform(&a, &b).unwrap();
+        assert_eq!(
+            serde_json::json!([{"p":["t"],"t":"text","o":{"p":2,"i":"a"}}]),
+            a1.to_value()
+        );
+
+        assert_matches!(
+            json0.register_alias("text", SubType::NumberAdd),
+            Err(JsonError::ConflictSubType(_))
+        );
+        assert_matches!(
+            json0.register_alias("x", SubType::Custome("x".into())),
+            Err(JsonError::ConflictSubType(_))
+        );
+        assert_eq!(Some(SubType::Text), json0.unregister_alias("text0"));
+        assert_matches!(parse(text0), Err(JsonError::InvalidOperation(_)));
+    }
+
+    #[test]
+    fn test_subtype_snapshot() {
+        let json0 = Json0::new();
+        let snapshot = json0.subtype_snapshot();
+        assert!(Arc::ptr_eq(&snapshot, &json0.subtype_snapshot()));
+
+        json0.unregister_subtype("text");
+        assert!(snapshot.contains_key(&SubType::Text));
+        let after = json0.subtype_snapshot();
+        assert!(!after.contains_key(&SubType::Text));
+        assert!(after.contains_key(&SubType::NumberAdd));
+
+        json0.clear_registered_subtype();
+        assert!(json0.subtype_snapshot().is_empty());
+        assert_eq!(3, snapshot.len());
+    }
+
+    #[test]
+    fn test_subtype_registry_across_threads() {
+        let holder = Arc::new(SubTypeFunctionsHolder::new());
+        let text0 = r#"[{"p":["s"],"t":"text0","o":[{"p":0,"i":"a"}]}]"#;
+        std::thread::scope(|scope| {
+            let writer = holder.clone();
+            scope.spawn(move || {
+                for _ in 0..100 {
+                    writer.register_alias("text0", SubType::Text).unwrap();
+                    writer.unregister_alias("text0");
+                }
+            });
+            for _ in 0..4 {
+                let reader = holder.clone();
+                scope.spawn(move || {
+                    let factory = OperationFactory::new(reader.clone());
+                    for _ in 0..100 {
+                        assert_eq!(3, reader.infos().len());
+                        assert!(reader.get(&SubType::Text).is_some());
+                        // parsed with or without the alias
+                        assert_matches!(
+                            factory.from_value(serde_json::from_str(text0).unwrap()),
+                            Ok(_) | Err(JsonError::InvalidOperation(_))
+                        );
+                    }
+                });
+            }
+        });
+        holder.clear();
+        assert!(holder.snapshot().is_empty());
+    }
+
+    #[test]
+    fn test_subtypes() {
+        struct Reverse {}
+
+        impl SubTypeFunctions for Reverse {
+            fn invert(&self, _: &Path, operand: &Value) -> Result<Value> {
+                Ok(operand.clone())
+            }
+            fn merge(&self, _: &Value, _: &Value) -> Option<Value> {
+                None
+            }
+            fn transform(&self, new: &Value, _: &Value, _: TransformSide) -> Result<Vec<Value>> {
+                Ok(vec![new.clone()])
+            }
+            fn apply(&self, val: Option<&Value>, _: &Value) -> ApplyResult<Option<Value>> {
+                Ok(val.cloned())
+            }
+            fn validate_operand(&self, _: &Value) -> Result<()> {
+                Ok(())
+            }
+            fn version(&self) -> u32 {
+                2
+            }
+        }
+
+        let json0 = Json0::new();
+        json0.register_subtype("reverse", Reverse {}).unwrap();
+        json0
+            .register_alias("rev", SubType::Custome("reverse".into()))
+            .unwrap();
+        json0.register_alias("text0", SubType::Text).unwrap();
+        assert_eq!(
+            vec![
+                SubType::NumberAdd,
+                SubType::Custome("reverse".into()),
+                SubType::Text,
+                SubType::Custome("text-delta".into())
+            ],
+            json0.functions.registered()
+        );
+
+        let infos = json0.subtypes();
+        assert_eq!(
+            vec!["na", "reverse", "text", "text-delta"],
+            infos.iter().map(|i| i.name()).collect_vec()
+        );
+        assert_eq!(
+            serde_json::json!({"name": "reverse", "aliases": ["rev"], "version": 2, "compose": false, "invert": true}),
+            infos[1].to_value()
+        );
+        assert_eq!(vec!["text0".to_string()], infos[2].aliases);
+        assert!(infos[2].capabilities.compose);
+        assert!(infos.iter().all(|i| i.capabilities.invert));
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_derive_sub_type_functions() {
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Scale {
+            by: u32,
+        }
+
+        #[derive(SubTypeFunctions)]
+        struct ScaleSubType;
+
+        impl TypedSubTypeFunctions for ScaleSubType {
+            type Operand = Scale;
+
+            fn invert(&self, _: &Path, _: &Scale) -> Result<Scale> {
+                Err(JsonError::InvalidOperation(
+                    "scale is not invertible".into(),
+                ))
+            }
+
+            fn merge(&self, base: &Scale, other: &Scale) -> Option<Scale> {
+                Some(Scale {
+                    by: base.by.checked_mul(other.by)?,
+                })
+            }
+
+            fn transform(&self, new: &Scale, _: &Scale, _: TransformSide) -> Result<Vec<Scale>> {
+                Ok(vec![Scale { by: new.by }])
+            }
+
+            fn apply(&self, val: Option<&Value>, operand: &Scale) -> Result<Option<Value>> {
+                match val.and_then(|v| v.as_i64()) {
+                    Some(n) => Ok(Some((n * operand.by as i64).into())),
+                    None => Err(JsonError::InvalidOperation("not a number".into())),
+                }
+            }
+
+            fn validate(&self, operand: &Scale) -> Result<()> {
+                match operand.by {
+                    0 => Err(JsonError::InvalidOperation("scale by 0".into())),
+                    _ => Ok(()),
+                }
+            }
+        }
+
+        let json0 = Json0::new();
+        json0.register_subtype("scale", ScaleSubType).unwrap();
+        let parse = |s: &str| {
+            json0
+                .operation_factory()
+                .from_value(serde_json::from_str(s).unwrap())
+        };
+        let mut op = parse(r#"[{"p":["n"],"t":"scale","o":{"by":2}}]"#).unwrap();
+        op.compose(parse(r#"[{"p":["n"],"t":"scale","o":{"by":3}}]"#).unwrap())
+            .unwrap();
+        assert_eq!(
+            serde_json::json!([{"p":["n"],"t":"scale","o":{"by":6}}]),
+            op.to_value()
+        );
+        let mut value = serde_json::json!({"n": 2});
+        json0.apply(&mut value, vec![op.clone()]).unwrap();
+        assert_eq!(serde_json::json!({"n": 12}), value);
+
+        assert_matches!(
+            parse(r#"[{"p":["n"],"t":"scale","o":{"by":"x"}}]"#),
+            Err(JsonError::InvalidOperation(_))
+        );
+        assert_matches!(
+            parse(r#"[{"p":["n"],"t":"scale","o":{"by":0}}]"#),
+            Err(JsonError::InvalidOperation(_))
+        );
+        let mut value = serde_json::json!({"n": "a"});
+        assert_eq!(
+            ErrorCode::InvalidApplyTarget,
+            json0.apply(&mut value, vec![op]).unwrap_err().code()
+        );
+    }
+
+    #[cfg(feature = "preserve-order")]
+    #[test]
+    fn test_preserve_order() {
+        let json0 = Json0::new();
+        let parse = |s: &str| {
+            json0
+                .operation_factory()
+                .from_value(serde_json::from_str(s).unwrap())
+                .unwrap()
+        };
+        let mut value: Value = serde_json::from_str(r#"{"d":1,"c":2,"b":3,"a":4}"#).unwrap();
+        json0
+            .apply(
+                &mut value,
+                vec![parse(
+                    r#"[{"p":["c"],"od":2},{"p":["e"],"oi":5},{"p":["d"],"om":"f"}]"#,
+                )],
+            )
+            .unwrap();
+        assert_eq!(r#"{"b":3,"a":4,"e":5,"f":1}"#, value.to_string());
+        assert_eq!(r#"{"a":4,"b":3,"e":5,"f":1}"#, to_canonical_string(&value));
+    }
 }
@@ -0,0 +1,46 @@
+/// Build a [`Path`](crate::path::Path) from a list of keys and indexes.
+///
+/// ```
+/// use json0_rs::path;
+///
+/// let p = path!["users", 0, "name"];
+/// assert_eq!(r#"["users", 0, "name"]"#, p.to_string());
+/// ```
+#[macro_export]
+macro_rules! path {
+    () => {
+        $crate::path::Path::from(::std::vec::Vec::new())
+    };
+    ($($elem:expr),+ $(,)?) => {
+        $crate::path::Path::from(::std::vec![$($crate::path::PathElement::from($elem)),+])
+    };
+}
+
+/// Build an [`Operation`](crate::operation::Operation) from a JSON literal,
+/// evaluating to `Result<Operation>`.
+///
+/// Without a factory the operation is parsed by a default
+/// [`OperationFactory`](crate::operation::OperationFactory) which only knows
+/// the built-in sub types. Pass a factory as the second argument to resolve
+/// custom sub types.
+///
+/// ```
+/// use json0_rs::op;
+///
+/// let op = op!({"p": ["users", 0, "name"], "oi": "x"}).unwrap();
+/// assert_eq!(1, op.len());
+///
+/// let json0 = json0_rs::Json0::new();
+/// let op = op!([{"p": ["a"], "na": 1}], json0.operation_factory()).unwrap();
+/// assert_eq!(1, op.len());
+/// ```
+#[macro_export]
+macro_rules! op {
+    ($json:tt) => {
+        $crate::operation::OperationFactory::default()
+            .from_value($crate::__private::serde_json::json!($json))
+    };
+    ($json:tt, $factory:expr) => {
+        $factory.from_value($crate::__private::serde_json::json!($json))
+    };
+}
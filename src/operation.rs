@@ -1,39 +1,59 @@
 use std::{
     cell::Cell,
+    collections::{BTreeSet, HashMap},
     fmt::{Debug, Display},
-    mem,
-    ops::{Deref, DerefMut},
-    rc::Rc,
+    io, mem,
+    ops::Index,
+    str::FromStr,
     sync::Arc,
     vec,
 };
 
 use crate::{
+    canonical::{canonicalize_value, encoded_len, to_canonical_string, NumberEquality},
+    clock::VectorClock,
     common::Validation,
+    diff::{diff, Edit},
     error::JsonError,
-    error::Result,
-    path::{AppendPath, Path, PathBuilder, PathElement},
+    error::{ErrorContext, Result},
+    json::{Appliable, Routable},
+    path::{AppendPath, Path, PathBuilder, PathElement, PathError},
     sub_type::{SubType, SubTypeFunctions, SubTypeFunctionsHolder},
+    text_delta::{TextDelta, TEXT_DELTA_SUB_TYPE_NAME},
 };
-use itertools::Itertools;
 use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+/// Operand value of an operator. Operands are shared among the clones of an
+/// operator, so cloning, inverting or transforming operations carrying large
+/// values doesn't copy the values.
+pub type Operand = Arc<Value>;
+
+/// Take the value out of operand, which is only copied if it's still shared.
+pub(crate) fn into_value(operand: Operand) -> Value {
+    Arc::try_unwrap(operand).unwrap_or_else(|o| (*o).clone())
+}
 
 pub enum Operator {
     Noop(),
     SubType(SubType, Value, Arc<dyn SubTypeFunctions>),
-    ListInsert(Value),
-    ListDelete(Value),
+    ListInsert(Operand),
+    ListDelete(Operand),
     // Replace value from last value to first value in json array.
     // First value is the new value.
     // Last value is the old value.
-    ListReplace(Value, Value),
+    ListReplace(Operand, Operand),
     ListMove(usize),
-    ObjectInsert(Value),
-    ObjectDelete(Value),
+    ObjectInsert(Operand),
+    ObjectDelete(Operand),
     // Replace value from last value to first value in json object.
     // First value is the new value.
     // Last value is the old value.
-    ObjectReplace(Value, Value),
+    ObjectReplace(Operand, Operand),
+    // Move value to another key in the same json object.
+    ObjectMove(String),
+    // Assert the value on path equals to the operand. Apply fails if not.
+    Test(Operand),
 }
 
 impl Debug for Operator {
@@ -58,6 +78,8 @@ impl Debug for Operator {
                 .field(arg0)
                 .field(arg1)
                 .finish(),
+            Self::ObjectMove(arg0) => f.debug_tuple("ObjectMove").field(arg0).finish(),
+            Self::Test(arg0) => f.debug_tuple("Test").field(arg0).finish(),
         }
     }
 }
@@ -73,6 +95,8 @@ impl PartialEq for Operator {
             (Self::ObjectInsert(l0), Self::ObjectInsert(r0)) => l0 == r0,
             (Self::ObjectDelete(l0), Self::ObjectDelete(r0)) => l0 == r0,
             (Self::ObjectReplace(l0, l1), Self::ObjectReplace(r0, r1)) => l0 == r0 && l1 == r1,
+            (Self::ObjectMove(l0), Self::ObjectMove(r0)) => l0 == r0,
+            (Self::Test(l0), Self::Test(r0)) => l0 == r0,
             _ => core::mem::discriminant(self) == core::mem::discriminant(other),
         }
     }
@@ -92,6 +116,82 @@ impl Clone for Operator {
             Self::ObjectInsert(arg0) => Self::ObjectInsert(arg0.clone()),
             Self::ObjectDelete(arg0) => Self::ObjectDelete(arg0.clone()),
             Self::ObjectReplace(arg0, arg1) => Self::ObjectReplace(arg0.clone(), arg1.clone()),
+            Self::ObjectMove(arg0) => Self::ObjectMove(arg0.clone()),
+            Self::Test(arg0) => Self::Test(arg0.clone()),
+        }
+    }
+}
+
+/// The variant of an [`Operator`] without its operands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OperatorKind {
+    Noop,
+    SubType,
+    ListInsert,
+    ListDelete,
+    ListReplace,
+    ListMove,
+    ObjectInsert,
+    ObjectDelete,
+    ObjectReplace,
+    ObjectMove,
+    Test,
+}
+
+impl Operator {
+    pub fn kind(&self) -> OperatorKind {
+        match self {
+            Operator::Noop() => OperatorKind::Noop,
+            Operator::SubType(..) => OperatorKind::SubType,
+            Operator::ListInsert(_) => OperatorKind::ListInsert,
+            Operator::ListDelete(_) => OperatorKind::ListDelete,
+            Operator::ListReplace(..) => OperatorKind::ListReplace,
+            Operator::ListMove(_) => OperatorKind::ListMove,
+            Operator::ObjectInsert(_) => OperatorKind::ObjectInsert,
+            Operator::ObjectDelete(_) => OperatorKind::ObjectDelete,
+            Operator::ObjectReplace(..) => OperatorKind::ObjectReplace,
+            Operator::ObjectMove(_) => OperatorKind::ObjectMove,
+            Operator::Test(_) => OperatorKind::Test,
+        }
+    }
+
+    /// The value put on the path, including the new value of a replace.
+    pub fn inserted_value(&self) -> Option<&Value> {
+        match self {
+            Operator::ListInsert(i)
+            | Operator::ObjectInsert(i)
+            | Operator::ListReplace(i, _)
+            | Operator::ObjectReplace(i, _) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// The value taken off the path, including the old value of a replace.
+    pub fn deleted_value(&self) -> Option<&Value> {
+        match self {
+            Operator::ListDelete(d)
+            | Operator::ObjectDelete(d)
+            | Operator::ListReplace(_, d)
+            | Operator::ObjectReplace(_, d) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// Where `lm` or `om` moves the value to, as the last element of its new
+    /// path.
+    pub fn move_target(&self) -> Option<PathElement> {
+        match self {
+            Operator::ListMove(i) => Some(PathElement::Index(*i)),
+            Operator::ObjectMove(k) => Some(PathElement::from(k.as_str())),
+            _ => None,
+        }
+    }
+
+    /// Sub type and operand of a sub type operator.
+    pub fn subtype(&self) -> Option<(&SubType, &Value)> {
+        match self {
+            Operator::SubType(t, o, _) => Some((t, o)),
+            _ => None,
         }
     }
 }
@@ -108,6 +208,66 @@ impl Operator {
     }
 }
 
+impl Operator {
+    fn canonicalize_operands(&mut self) {
+        match self {
+            Operator::Noop() | Operator::ListMove(_) | Operator::ObjectMove(_) => {}
+            Operator::SubType(_, v, _) => canonicalize_value(v),
+            Operator::ListInsert(v)
+            | Operator::ListDelete(v)
+            | Operator::ObjectInsert(v)
+            | Operator::ObjectDelete(v)
+            | Operator::Test(v) => canonicalize_value(Arc::make_mut(v)),
+            Operator::ListReplace(i, d) | Operator::ObjectReplace(i, d) => {
+                canonicalize_value(Arc::make_mut(i));
+                canonicalize_value(Arc::make_mut(d));
+            }
+        }
+    }
+
+    fn write_to_map(&self, obj: &mut Map<String, Value>) {
+        match self {
+            Operator::Noop() => {}
+            Operator::SubType(SubType::NumberAdd, o, _) => {
+                obj.insert("na".into(), o.clone());
+            }
+            Operator::SubType(t, o, _) => {
+                obj.insert("t".into(), Value::String(t.to_string()));
+                obj.insert("o".into(), o.clone());
+            }
+            Operator::ListInsert(i) => {
+                obj.insert("li".into(), Value::clone(i));
+            }
+            Operator::ListDelete(d) => {
+                obj.insert("ld".into(), Value::clone(d));
+            }
+            Operator::ListReplace(i, d) => {
+                obj.insert("li".into(), Value::clone(i));
+                obj.insert("ld".into(), Value::clone(d));
+            }
+            Operator::ListMove(m) => {
+                obj.insert("lm".into(), Value::from(*m));
+            }
+            Operator::ObjectInsert(i) => {
+                obj.insert("oi".into(), Value::clone(i));
+            }
+            Operator::ObjectDelete(d) => {
+                obj.insert("od".into(), Value::clone(d));
+            }
+            Operator::ObjectReplace(i, d) => {
+                obj.insert("oi".into(), Value::clone(i));
+                obj.insert("od".into(), Value::clone(d));
+            }
+            Operator::ObjectMove(k) => {
+                obj.insert("om".into(), Value::String(k.clone()));
+            }
+            Operator::Test(v) => {
+                obj.insert("test".into(), Value::clone(v));
+            }
+        }
+    }
+}
+
 impl Validation for Operator {
     fn validates(&self) -> Result<()> {
         match self {
@@ -117,23 +277,34 @@ impl Validation for Operator {
     }
 }
 
+/// Writes the operator in its wire form, like `{"li":5}`.
 impl Display for Operator {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let s: String = match self {
-            Operator::Noop() => "".into(),
-            Operator::SubType(t, o, _) => format!("t: {}, o: {}", t, o),
-            Operator::ListInsert(i) => format!("li: {}", i),
-            Operator::ListDelete(d) => format!("ld: {}", d),
-            Operator::ListReplace(i, d) => format!("li: {}, ld: {}", i, d),
-            Operator::ListMove(m) => format!("lm: {}", m),
-            Operator::ObjectInsert(i) => format!("oi: {}", i),
-            Operator::ObjectDelete(d) => format!("od: {}", d),
-            Operator::ObjectReplace(i, d) => {
-                format!("oi: {}, od: {}", i, d)
+        f.write_str("{")?;
+        self.write_fields(f)?;
+        f.write_str("}")
+    }
+}
+
+impl Operator {
+    /// Writes the fields of the wire form, like `"li":5,"ld":4`.
+    fn write_fields(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operator::Noop() => Ok(()),
+            Operator::SubType(SubType::NumberAdd, o, _) => write!(f, r#""na":{}"#, o),
+            Operator::SubType(t, o, _) => {
+                write!(f, r#""t":{},"o":{}"#, Value::String(t.to_string()), o)
             }
-        };
-        f.write_str(&s)?;
-        Ok(())
+            Operator::ListInsert(i) => write!(f, r#""li":{}"#, i),
+            Operator::ListDelete(d) => write!(f, r#""ld":{}"#, d),
+            Operator::ListReplace(i, d) => write!(f, r#""li":{},"ld":{}"#, i, d),
+            Operator::ListMove(m) => write!(f, r#""lm":{}"#, m),
+            Operator::ObjectInsert(i) => write!(f, r#""oi":{}"#, i),
+            Operator::ObjectDelete(d) => write!(f, r#""od":{}"#, d),
+            Operator::ObjectReplace(i, d) => write!(f, r#""oi":{},"od":{}"#, i, d),
+            Operator::ObjectMove(k) => write!(f, r#""om":{}"#, Value::String(k.clone())),
+            Operator::Test(v) => write!(f, r#""test":{}"#, v),
+        }
     }
 }
 
@@ -165,6 +336,112 @@ impl OperationComponent {
         }
     }
 
+    /// Whether this component and other can be applied in any order with the
+    /// same result. They must operate on disjoint values, and neither of them
+    /// may shift the position of the other by inserting, deleting or moving an
+    /// element in a container on the path of the other.
+    pub fn is_independent_of(&self, other: &OperationComponent) -> bool {
+        if self.path.is_prefix_of(&other.path) || other.path.is_prefix_of(&self.path) {
+            return false;
+        }
+        !self.shifts_siblings_of(other) && !other.shifts_siblings_of(self)
+    }
+
+    /// Whether applying this component could change the value at path. Changing
+    /// a descendant of path changes it too, and inserting, deleting or moving
+    /// list elements changes every element whose index is shifted.
+    pub fn affects(&self, path: &Path) -> bool {
+        let Some(affected) = self.affected_path() else {
+            return false;
+        };
+        if path.is_prefix_of(&affected) {
+            return true;
+        }
+        if !affected.is_prefix_of(path) {
+            return false;
+        }
+        let (lo, hi) = match (&self.operator, self.path.last()) {
+            (Operator::ListInsert(_) | Operator::ListDelete(_), Some(PathElement::Index(i))) => {
+                (*i, usize::MAX)
+            }
+            (Operator::ListMove(to), Some(PathElement::Index(from))) => {
+                (*from.min(to), *from.max(to))
+            }
+            (Operator::ObjectMove(to), Some(PathElement::Key(from))) => {
+                return matches!(path.get(affected.len()), Some(PathElement::Key(k)) if k == from || **k == **to);
+            }
+            _ => return true,
+        };
+        match path.get(affected.len()) {
+            Some(PathElement::Index(i)) => lo <= *i && *i <= hi,
+            _ => true,
+        }
+    }
+
+    /// The path of the value changed by this component. It is the parent of
+    /// path for operators shifting the position of other elements. None if
+    /// the component changes nothing.
+    pub fn affected_path(&self) -> Option<Path> {
+        match self.operator {
+            Operator::Noop() | Operator::Test(_) => None,
+            Operator::ListInsert(_)
+            | Operator::ListDelete(_)
+            | Operator::ListMove(_)
+            | Operator::ObjectMove(_) => Some(
+                self.path
+                    .prefix(self.path.len().saturating_sub(1))
+                    .to_path(),
+            ),
+            _ => Some(self.path.clone()),
+        }
+    }
+
+    fn shifts_siblings_of(&self, other: &OperationComponent) -> bool {
+        match self.operator {
+            Operator::ListInsert(_)
+            | Operator::ListDelete(_)
+            | Operator::ListMove(_)
+            | Operator::ObjectMove(_) => {
+                let elements = self.path.get_elements();
+                let parent = &elements[..elements.len().saturating_sub(1)];
+                other.path.get_elements().starts_with(parent)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether applying this component changes nothing, like replacing a value
+    /// with an identical one or moving a value to where it is.
+    pub fn is_equivalent_to_noop(&self) -> bool {
+        self.is_equivalent_to_noop_with(NumberEquality::Exact)
+    }
+
+    /// Like `is_equivalent_to_noop` with the numbers of a replace compared by
+    /// number_equality.
+    pub fn is_equivalent_to_noop_with(&self, number_equality: NumberEquality) -> bool {
+        match &self.operator {
+            Operator::Noop() => true,
+            Operator::SubType(_, _, _) | Operator::Test(_) => false,
+            Operator::ListInsert(_)
+            | Operator::ListDelete(_)
+            | Operator::ObjectInsert(_)
+            | Operator::ObjectDelete(_) => false,
+            Operator::ListReplace(new_v, old_v) | Operator::ObjectReplace(new_v, old_v) => {
+                number_equality.values_eq(new_v, old_v)
+            }
+            Operator::ListMove(lm) => self
+                .path
+                .last()
+                .map(|p| p == &PathElement::Index(*lm))
+                .unwrap_or(false),
+            Operator::ObjectMove(om) => self
+                .path
+                .last()
+                .map(|p| matches!(p, PathElement::Key(k) if **k == **om))
+                .unwrap_or(false),
+        }
+    }
+
     pub fn not_noop(self) -> Option<OperationComponent> {
         if let Operator::Noop() = self.operator {
             None
@@ -178,7 +455,8 @@ impl OperationComponent {
 
         let mut path = self.path.clone();
         let operator = match &self.operator {
-            Operator::Noop() => Operator::Noop(),
+            // nothing to revert for an assertion
+            Operator::Noop() | Operator::Test(_) => Operator::Noop(),
             Operator::SubType(t, o, f) => {
                 let new_operand = f.invert(&path, o)?;
                 Operator::SubType(t.clone(), new_operand, f.clone())
@@ -203,21 +481,197 @@ impl OperationComponent {
             Operator::ObjectReplace(new_v, old_v) => {
                 Operator::ObjectReplace(old_v.clone(), new_v.clone())
             }
+            Operator::ObjectMove(new) => {
+                let old_p = path.replace(path.len() - 1, PathElement::key(new));
+                if let Some(PathElement::Key(k)) = old_p {
+                    Operator::ObjectMove(k.to_string())
+                } else {
+                    panic!(
+                        "invalid om operation: {self}, last path in operation is not key path type"
+                    );
+                }
+            }
         };
         OperationComponent::new(path, operator)
     }
 
-    /**
-     *
-     */
+    fn restrict(&self, prefix: &Path) -> Option<OperationComponent> {
+        if let Some(path) = self.path.strip_prefix(prefix) {
+            let operator = match &self.operator {
+                Operator::ListReplace(new_v, old_v) if path.is_root() => {
+                    Operator::ObjectReplace(new_v.clone(), old_v.clone())
+                }
+                Operator::ListInsert(v) if path.is_root() => Operator::ObjectInsert(v.clone()),
+                Operator::ListDelete(v) if path.is_root() => Operator::ObjectDelete(v.clone()),
+                o if path.is_root() && !is_root_operator(o) => return None,
+                o => o.clone(),
+            };
+            return Some(OperationComponent { path, operator });
+        }
+
+        // value at path is replaced as a whole, take the part of it under prefix
+        let suffix = prefix.relative_to(&self.path)?;
+        let (new_v, old_v) = match &self.operator {
+            Operator::ObjectInsert(new_v) => (Some(new_v), None),
+            Operator::ObjectDelete(old_v) => (None, Some(old_v)),
+            Operator::ObjectReplace(new_v, old_v) | Operator::ListReplace(new_v, old_v) => {
+                (Some(new_v), Some(old_v))
+            }
+            _ => return None,
+        };
+        let new_v = new_v.and_then(|v| v.route_get(suffix).ok().flatten());
+        let old_v = old_v.and_then(|v| v.route_get(suffix).ok().flatten());
+        let operator = match (new_v, old_v) {
+            (Some(n), Some(o)) => Operator::ObjectReplace(n.clone().into(), o.clone().into()),
+            (Some(n), None) => Operator::ObjectInsert(n.clone().into()),
+            (None, Some(o)) => Operator::ObjectDelete(o.clone().into()),
+            (None, None) => return None,
+        };
+        Some(OperationComponent {
+            path: Path::root(),
+            operator,
+        })
+    }
+
+    /// Map position offset in the list or text on path through this
+    /// component. Returns false if the list or text is removed.
+    fn map_position(&self, path: &mut Path, offset: &mut usize) -> bool {
+        let depth = self.path.len();
+        if depth == 0 {
+            return matches!(
+                self.operator,
+                Operator::Noop() | Operator::Test(_) | Operator::SubType(_, _, _)
+            );
+        }
+        if depth == path.len() + 1 && self.path.prefix(depth - 1) == *path {
+            // operates on an element of the list
+            if let Some(PathElement::Index(i)) = self.path.last() {
+                *offset = match self.operator {
+                    Operator::ListInsert(_) if *i <= *offset => *offset + 1,
+                    Operator::ListDelete(_) if *i < *offset => *offset - 1,
+                    Operator::ListMove(to) => {
+                        let o = if *i < *offset { *offset - 1 } else { *offset };
+                        if to <= o {
+                            o + 1
+                        } else {
+                            o
+                        }
+                    }
+                    _ => *offset,
+                };
+            }
+            return true;
+        }
+        if self.path.eq(path) {
+            match &self.operator {
+                Operator::SubType(SubType::Text, o, _) => {
+                    map_text_offset(o, offset);
+                    return true;
+                }
+                Operator::SubType(SubType::Custome(t), o, _) if t == TEXT_DELTA_SUB_TYPE_NAME => {
+                    if let Ok(delta) = TextDelta::try_from(o) {
+                        for o in delta.to_offset_operands() {
+                            map_text_offset(&o, offset);
+                        }
+                    }
+                    return true;
+                }
+                _ => {}
+            }
+        }
+        if depth > path.len() || !self.path.prefix(depth - 1).is_prefix_of(&*path) {
+            return true;
+        }
+
+        // operates on path or the sibling of an element on path
+        let elem = path.get(depth - 1).cloned();
+        let target = self.path.last().cloned();
+        match (&self.operator, elem, target) {
+            (Operator::ListInsert(_), Some(PathElement::Index(j)), Some(PathElement::Index(i)))
+                if i <= j =>
+            {
+                path.replace(depth - 1, PathElement::Index(j + 1));
+            }
+            (Operator::ListDelete(_), Some(PathElement::Index(j)), Some(PathElement::Index(i))) => {
+                if i == j {
+                    return false;
+                }
+                if i < j {
+                    path.replace(depth - 1, PathElement::Index(j - 1));
+                }
+            }
+            (Operator::ListMove(to), Some(PathElement::Index(j)), Some(PathElement::Index(i))) => {
+                let mut j2 = j;
+                if i == j {
+                    j2 = *to;
+                } else {
+                    if j2 > i {
+                        j2 -= 1;
+                    }
+                    if j2 >= *to {
+                        j2 += 1;
+                    }
+                }
+                path.replace(depth - 1, PathElement::Index(j2));
+            }
+            (Operator::ObjectMove(to), Some(PathElement::Key(j)), Some(PathElement::Key(i)))
+                if i == j =>
+            {
+                path.replace(depth - 1, PathElement::key(to));
+            }
+            (
+                Operator::ListReplace(_, _)
+                | Operator::ObjectInsert(_)
+                | Operator::ObjectDelete(_)
+                | Operator::ObjectReplace(_, _),
+                elem,
+                target,
+            ) if elem == target => return false,
+            _ => {}
+        }
+        true
+    }
+
+    fn prefix_with(&self, prefix: &Path) -> OperationComponent {
+        let mut path = prefix.clone();
+        self.path
+            .get_elements()
+            .iter()
+            .for_each(|e| path.push(e.clone()));
+        let operator = match (&self.operator, prefix.last()) {
+            // object operators on root turn into list operators on a list element
+            (o, Some(PathElement::Index(_))) if self.path.is_root() => match o {
+                Operator::ObjectInsert(v) => Operator::ListInsert(v.clone()),
+                Operator::ObjectDelete(v) => Operator::ListDelete(v.clone()),
+                Operator::ObjectReplace(new_v, old_v) => {
+                    Operator::ListReplace(new_v.clone(), old_v.clone())
+                }
+                o => o.clone(),
+            },
+            (o, _) => o.clone(),
+        };
+        OperationComponent { path, operator }
+    }
+
     pub fn merge(&mut self, op: OperationComponent) -> Option<OperationComponent> {
+        self.merge_with(op, NumberEquality::Exact)
+    }
+
+    /// Like `merge` with the values inserted by self and removed by op
+    /// compared by number_equality.
+    pub fn merge_with(
+        &mut self,
+        op: OperationComponent,
+        number_equality: NumberEquality,
+    ) -> Option<OperationComponent> {
+        let eq = |a: &Operand, b: &Operand| number_equality.values_eq(a, b);
         if let Some(new_operator) = match &self.operator {
             Operator::Noop() => Some(op.operator.clone()),
             Operator::SubType(t, base_v, f) => {
                 let mut ret = None;
                 if let Operator::SubType(other_t, other_v, _) = &op.operator {
                     if t.eq(other_t) {
-                        if let Some(next_v) = f.merge(base_v, other_v) {
+                        if let Some(next_v) = f.compose(base_v, other_v) {
                             ret = Some(Operator::SubType(t.clone(), next_v, f.clone()))
                         }
                     }
@@ -227,14 +681,14 @@ impl OperationComponent {
 
             Operator::ListInsert(v1) => match &op.operator {
                 Operator::ListDelete(v2) => {
-                    if v1.eq(v2) {
+                    if eq(v1, v2) {
                         Some(Operator::Noop())
                     } else {
                         None
                     }
                 }
                 Operator::ListReplace(new_v, old_v) => {
-                    if old_v.eq(v1) {
+                    if eq(old_v, v1) {
                         Some(Operator::ListInsert(new_v.clone()))
                     } else {
                         None
@@ -244,14 +698,14 @@ impl OperationComponent {
             },
             Operator::ListReplace(new_v1, old_v1) => match &op.operator {
                 Operator::ListDelete(v2) => {
-                    if new_v1.eq(v2) {
+                    if eq(new_v1, v2) {
                         Some(Operator::ListDelete(old_v1.clone()))
                     } else {
                         None
                     }
                 }
                 Operator::ListReplace(new_v2, old_v2) => {
-                    if new_v1.eq(old_v2) {
+                    if eq(new_v1, old_v2) {
                         Some(Operator::ListReplace(new_v2.clone(), old_v1.clone()))
                     } else {
                         None
@@ -261,14 +715,14 @@ impl OperationComponent {
             },
             Operator::ObjectInsert(v1) => match &op.operator {
                 Operator::ObjectDelete(v2) => {
-                    if v1.eq(v2) {
+                    if eq(v1, v2) {
                         Some(Operator::Noop())
                     } else {
                         None
                     }
                 }
                 Operator::ObjectReplace(new_v2, old_v2) => {
-                    if v1.eq(old_v2) {
+                    if eq(v1, old_v2) {
                         Some(Operator::ObjectInsert(new_v2.clone()))
                     } else {
                         None
@@ -277,19 +731,19 @@ impl OperationComponent {
                 _ => None,
             },
             Operator::ObjectDelete(v1) => match &op.operator {
-                Operator::ObjectInsert(v2) => Some(Operator::ObjectReplace(v1.clone(), v2.clone())),
+                Operator::ObjectInsert(v2) => Some(Operator::ObjectReplace(v2.clone(), v1.clone())),
                 _ => None,
             },
             Operator::ObjectReplace(new_v1, old_v1) => match &op.operator {
                 Operator::ObjectDelete(v2) => {
-                    if new_v1.eq(v2) {
+                    if eq(new_v1, v2) {
                         Some(Operator::ObjectDelete(old_v1.clone()))
                     } else {
                         None
                     }
                 }
                 Operator::ObjectReplace(new_v2, old_v2) => {
-                    if new_v1.eq(old_v2) {
+                    if eq(new_v1, old_v2) {
                         Some(Operator::ObjectReplace(new_v2.clone(), old_v1.clone()))
                     } else {
                         None
@@ -299,6 +753,16 @@ impl OperationComponent {
             },
             _ => None,
         } {
+            // a replace of a value by an equal one is a noop to transform,
+            // while the merged components are not, as the delete drops the
+            // concurrent changes under path
+            if let Operator::ListReplace(new_v, old_v) | Operator::ObjectReplace(new_v, old_v) =
+                &new_operator
+            {
+                if self.operator != Operator::Noop() && eq(new_v, old_v) {
+                    return Some(op);
+                }
+            }
             _ = mem::replace(&mut self.operator, new_operator);
             return None;
         }
@@ -306,22 +770,106 @@ impl OperationComponent {
         Some(op)
     }
 
+    pub fn to_value(&self) -> Value {
+        let mut obj = Map::new();
+        obj.insert("p".into(), self.path.to_value());
+        self.operator.write_to_map(&mut obj);
+        Value::Object(obj)
+    }
+
     pub fn operate_path_len(&self) -> usize {
         match self.operator {
-            Operator::SubType(_, _, _) => self.path.clone().len(),
+            Operator::SubType(_, _, _) | Operator::Test(_) => self.path.len(),
             _ => {
                 let mut p = self.path.clone();
-                p.get_mut_elements().pop();
+                p.pop();
                 p.len()
             }
         }
     }
 }
 
+/// Map offset in a text through text operand o of the offset form.
+fn map_text_offset(o: &Value, offset: &mut usize) {
+    let p = o.get("p").and_then(|p| p.as_u64()).unwrap_or(0) as usize;
+    if let Some(i) = o.get("i").and_then(|i| i.as_str()) {
+        if p <= *offset {
+            *offset += i.len();
+        }
+    } else if let Some(d) = o.get("d") {
+        // the deleted text or its length
+        let len = d
+            .as_str()
+            .map_or(d.as_u64().unwrap_or(0) as usize, str::len);
+        if p < *offset {
+            *offset -= len.min(*offset - p);
+        }
+    }
+}
+
+fn is_root_operator(operator: &Operator) -> bool {
+    matches!(
+        operator,
+        Operator::Noop()
+            | Operator::SubType(_, _, _)
+            | Operator::Test(_)
+            | Operator::ObjectInsert(_)
+            | Operator::ObjectDelete(_)
+            | Operator::ObjectReplace(_, _)
+    )
+}
+
 impl Validation for OperationComponent {
     fn validates(&self) -> Result<()> {
-        if self.path.is_empty() {
-            return Err(JsonError::InvalidOperation("Path is empty".into()));
+        let end_path = self
+            .path
+            .get_elements()
+            .iter()
+            .position(|p| matches!(p, PathElement::End(_)));
+        // values removed or moved must be on the kind of path element of
+        // their container, inserts are checked when they are applied
+        match (&self.operator, self.path.last()) {
+            (Operator::ObjectMove(_), Some(PathElement::Key(_))) => {}
+            (Operator::ObjectMove(_), _) => {
+                return Err(JsonError::InvalidOperation(format!(
+                    "last element of path: {} in om operation is not a key",
+                    self.path
+                )));
+            }
+            (
+                o @ (Operator::ListDelete(_) | Operator::ListReplace(..) | Operator::ListMove(_)),
+                None | Some(PathElement::Key(_)),
+            ) => {
+                return Err(JsonError::InvalidOperation(format!(
+                    "last element of path: {} in list operator: {} is not an index",
+                    self.path, o
+                )));
+            }
+            (
+                o @ (Operator::ObjectDelete(_) | Operator::ObjectReplace(..)),
+                Some(PathElement::Index(_) | PathElement::End(_)),
+            ) => {
+                return Err(JsonError::InvalidOperation(format!(
+                    "last element of path: {} in object operator: {} is not a key",
+                    self.path, o
+                )));
+            }
+            _ => {}
+        }
+        if let Some(i) = end_path {
+            if i + 1 != self.path.len() || !matches!(self.operator, Operator::ListInsert(_)) {
+                return Err(JsonError::InvalidOperation(format!(
+                    "negative index in path: {} is only allowed at the end of list insert",
+                    self.path
+                )));
+            }
+        }
+        // only object and sub type operators can operate on the whole document
+        if self.path.is_root() && !is_root_operator(&self.operator) {
+            return Err(JsonError::InvalidOperation(format!(
+                "operator: {} can not operate on root path",
+                self.operator
+            )));
         }
 
         self.operator.validates()
@@ -337,156 +885,684 @@ impl Validation for Vec<OperationComponent> {
     }
 }
 
+/// Writes the component in its wire form, like `{"p":["a",0],"li":5}`.
 impl Display for OperationComponent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!(r#"{{"p": {}, {}}}"#, self.path, self.operator))?;
-        Ok(())
+        write!(f, r#"{{"p":{}"#, self.path.to_value())?;
+        if self.operator != Operator::Noop() {
+            f.write_str(",")?;
+            self.operator.write_fields(f)?;
+        }
+        f.write_str("}")
     }
 }
 
+/// Metadata attached to an [`Operation`] which is kept along with it through
+/// serialization, transform and compose.
 #[derive(Debug, Clone, PartialEq, Default)]
-pub struct Operation {
-    operations: Vec<OperationComponent>,
+pub struct OperationMeta {
+    pub author: Option<String>,
+    pub timestamp: Option<u64>,
+    pub id: Option<String>,
+    /// Sequence number of the operation among the ones from its author, so a
+    /// resubmitted operation can be told from a new one.
+    pub seq: Option<u64>,
+    /// Operations seen by the author when this operation is made, including
+    /// this one.
+    pub clock: Option<VectorClock>,
 }
 
-impl Operation {
-    pub fn new(operations: Vec<OperationComponent>) -> Result<Operation> {
-        operations.validates()?;
-        Ok(Operation { operations })
+impl OperationMeta {
+    pub fn new() -> OperationMeta {
+        OperationMeta::default()
     }
 
-    pub fn append(&mut self, op: OperationComponent) -> Result<()> {
-        if let Operator::ListMove(m) = op.operator {
-            if op
-                .path
-                .get(op.path.len() - 1)
-                .unwrap()
-                .eq(&PathElement::Index(m))
-            {
-                return Ok(());
-            }
-        }
-
-        if self.is_empty() {
-            self.push(op);
-            return Ok(());
-        }
-
-        let last = self.last_mut().unwrap();
-        if last.path.eq(&op.path) {
-            if let Some(o) = last.merge(op) {
-                self.push(o);
-            } else {
-                if last.operator.eq(&Operator::Noop()) {
-                    self.pop();
-                }
-                return Ok(());
-            }
-        } else {
-            self.push(op);
-        }
-
-        Ok(())
+    pub fn author<S: Into<String>>(mut self, author: S) -> Self {
+        self.author = Some(author.into());
+        self
     }
 
-    pub fn compose(&mut self, other: Operation) -> Result<()> {
-        for op in other.into_iter() {
-            self.append(op)?;
-        }
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
 
-        Ok(())
+    pub fn id<S: Into<String>>(mut self, id: S) -> Self {
+        self.id = Some(id.into());
+        self
     }
-}
 
-impl Deref for Operation {
-    type Target = Vec<OperationComponent>;
+    pub fn seq(mut self, seq: u64) -> Self {
+        self.seq = Some(seq);
+        self
+    }
 
-    fn deref(&self) -> &Self::Target {
-        &self.operations
+    pub fn clock(mut self, clock: VectorClock) -> Self {
+        self.clock = Some(clock);
+        self
     }
-}
 
-impl DerefMut for Operation {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.operations
+    pub fn to_value(&self) -> Value {
+        let mut obj = Map::new();
+        if let Some(author) = &self.author {
+            obj.insert("author".into(), Value::String(author.clone()));
+        }
+        if let Some(timestamp) = self.timestamp {
+            obj.insert("timestamp".into(), Value::from(timestamp));
+        }
+        if let Some(id) = &self.id {
+            obj.insert("id".into(), Value::String(id.clone()));
+        }
+        if let Some(seq) = self.seq {
+            obj.insert("seq".into(), Value::from(seq));
+        }
+        if let Some(clock) = &self.clock {
+            obj.insert("clock".into(), clock.to_value());
+        }
+        Value::Object(obj)
     }
 }
 
-impl IntoIterator for Operation {
-    type Item = OperationComponent;
-
-    type IntoIter = std::vec::IntoIter<Self::Item>;
-
-    fn into_iter(self) -> Self::IntoIter {
-        self.operations.into_iter()
+impl TryFrom<&Value> for OperationMeta {
+    type Error = JsonError;
+
+    fn try_from(value: &Value) -> std::result::Result<Self, Self::Error> {
+        let obj = value
+            .as_object()
+            .ok_or(JsonError::InvalidOperation(format!(
+                "operation meta: {} is not a JSON object",
+                value
+            )))?;
+
+        let mut meta = OperationMeta::default();
+        if let Some(author) = obj.get("author") {
+            meta.author = Some(
+                author
+                    .as_str()
+                    .ok_or(JsonError::InvalidOperation(format!(
+                        "author: {} in operation meta is not a string",
+                        author
+                    )))?
+                    .into(),
+            );
+        }
+        if let Some(timestamp) = obj.get("timestamp") {
+            meta.timestamp = Some(timestamp.as_u64().ok_or(JsonError::InvalidOperation(
+                format!(
+                    "timestamp: {} in operation meta is not a non-negative integer",
+                    timestamp
+                ),
+            ))?);
+        }
+        if let Some(id) = obj.get("id") {
+            meta.id = Some(
+                id.as_str()
+                    .ok_or(JsonError::InvalidOperation(format!(
+                        "id: {} in operation meta is not a string",
+                        id
+                    )))?
+                    .into(),
+            );
+        }
+        if let Some(seq) = obj.get("seq") {
+            meta.seq = Some(seq.as_u64().ok_or(JsonError::InvalidOperation(format!(
+                "seq: {} in operation meta is not a non-negative integer",
+                seq
+            )))?);
+        }
+        if let Some(clock) = obj.get("clock") {
+            meta.clock = Some(VectorClock::try_from(clock)?);
+        }
+        Ok(meta)
     }
 }
 
-impl Validation for Operation {
-    fn validates(&self) -> Result<()> {
-        self.operations.validates()
-    }
+/// Summary of an operation by `Operation::stats`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OperationStats {
+    /// Number of components of each operator kind.
+    pub counts: HashMap<OperatorKind, usize>,
+    /// Max number of elements in the path of a component.
+    pub max_path_depth: usize,
+    /// Sum of the lengths in bytes of the JSON encoding of the operands, in
+    /// the same way `OperationLimits::max_operand_bytes` measures them.
+    pub operand_bytes: usize,
+    /// First elements of the paths changed, including where `lm` and `om` on
+    /// the top level move to.
+    pub top_level_keys: BTreeSet<PathElement>,
+    /// Some component is on the root path and may change the whole document.
+    pub touches_root: bool,
 }
 
-impl From<OperationComponent> for Operation {
-    fn from(input: OperationComponent) -> Self {
-        Operation {
-            operations: vec![input],
-        }
+impl OperationStats {
+    pub fn count(&self, kind: OperatorKind) -> usize {
+        self.counts.get(&kind).copied().unwrap_or(0)
     }
-}
 
-impl From<Vec<OperationComponent>> for Operation {
-    fn from(operations: Vec<OperationComponent>) -> Self {
-        Operation { operations }
+    pub fn total(&self) -> usize {
+        self.counts.values().sum()
     }
 }
 
-impl Display for Operation {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str("[")?;
-        f.write_str(
-            self.operations
-                .iter()
-                .map(|op| op.to_string())
-                .join(",")
-                .as_str(),
-        )?;
-        f.write_str("]")?;
-        Ok(())
-    }
+/// Max size of each operation split by `Operation::split_max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitLimit {
+    /// Number of components, at least one.
+    Components(usize),
+    /// Length in bytes of the JSON encoding of the operation.
+    Bytes(usize),
 }
 
-pub struct ListOperationBuilder {
-    path_builder: Cell<PathBuilder>,
-    insert: Option<Value>,
-    delete: Option<Value>,
-    move_to: Option<usize>,
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Operation {
+    operations: Vec<OperationComponent>,
+    meta: Option<OperationMeta>,
 }
 
-impl ListOperationBuilder {
-    fn new() -> ListOperationBuilder {
-        ListOperationBuilder {
-            path_builder: Cell::new(PathBuilder::default()),
-            insert: None,
-            delete: None,
-            move_to: None,
-        }
+impl Operation {
+    pub fn new(operations: Vec<OperationComponent>) -> Result<Operation> {
+        operations.validates()?;
+        Ok(Operation {
+            operations,
+            meta: None,
+        })
     }
 
-    pub fn insert(mut self, val: Value) -> Self {
-        self.insert = Some(val);
+    pub fn with_meta(mut self, meta: OperationMeta) -> Self {
+        self.meta = Some(meta);
         self
     }
 
-    pub fn delete(mut self, val: Value) -> Self {
-        self.delete = Some(val);
-        self
+    pub fn meta(&self) -> Option<&OperationMeta> {
+        self.meta.as_ref()
     }
 
-    pub fn replace(mut self, old: Value, new: Value) -> Self {
-        self.insert = Some(new);
-        self.delete = Some(old);
+    pub fn set_meta(&mut self, meta: Option<OperationMeta>) {
+        self.meta = meta;
+    }
+
+    pub fn components(&self) -> &[OperationComponent] {
+        &self.operations
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, OperationComponent> {
+        self.operations.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&OperationComponent> {
+        self.operations.get(index)
+    }
+
+    /// Count the components and measure their paths and operands.
+    pub fn stats(&self) -> OperationStats {
+        let mut stats = OperationStats::default();
+        for c in &self.operations {
+            *stats.counts.entry(c.operator.kind()).or_default() += 1;
+            stats.max_path_depth = stats.max_path_depth.max(c.path.len());
+            stats.operand_bytes += match &c.operator {
+                Operator::Noop() => 0,
+                Operator::SubType(_, o, _) => encoded_len(o),
+                Operator::ListReplace(i, d) | Operator::ObjectReplace(i, d) => {
+                    encoded_len(i) + encoded_len(d)
+                }
+                Operator::ListInsert(v)
+                | Operator::ListDelete(v)
+                | Operator::ObjectInsert(v)
+                | Operator::ObjectDelete(v)
+                | Operator::Test(v) => encoded_len(v),
+                Operator::ListMove(i) => encoded_len(&Value::from(*i)),
+                Operator::ObjectMove(k) => encoded_len(&Value::from(k.as_str())),
+            };
+            match c.path.get(0) {
+                None => stats.touches_root = true,
+                Some(pe) => {
+                    stats.top_level_keys.insert(pe.clone());
+                    if c.path.len() == 1 {
+                        stats.top_level_keys.extend(c.operator.move_target());
+                    }
+                }
+            }
+        }
+        stats
+    }
+
+    /// Validate component and push it to the end as is. Unlike `append` it's
+    /// never merged with the components before it.
+    pub fn push_validated(&mut self, component: OperationComponent) -> Result<()> {
+        component.validates()?;
+        self.operations.push(component);
+        Ok(())
+    }
+
+    /// Serialize to the json0 wire form. An operation carrying meta is wrapped
+    /// as `{"op": [...], "meta": {...}}`.
+    pub fn to_value(&self) -> Value {
+        let op = Value::Array(self.operations.iter().map(|o| o.to_value()).collect());
+        match &self.meta {
+            Some(meta) => {
+                let mut obj = Map::new();
+                obj.insert("op".into(), op);
+                obj.insert("meta".into(), meta.to_value());
+                Value::Object(obj)
+            }
+            None => op,
+        }
+    }
+
+    /// Like `to_value` but serialized with object keys sorted, for hashing or
+    /// diffing operations whose operands were built in different key orders.
+    pub fn to_canonical_string(&self) -> String {
+        to_canonical_string(&self.to_value())
+    }
+
+    pub fn append(&mut self, op: OperationComponent) -> Result<()> {
+        self.append_with(op, NumberEquality::Exact)
+    }
+
+    /// Like `append` with values compared by number_equality when op is
+    /// merged with a component before it.
+    pub fn append_with(
+        &mut self,
+        op: OperationComponent,
+        number_equality: NumberEquality,
+    ) -> Result<()> {
+        if let Operator::ListMove(m) = op.operator {
+            if op
+                .path
+                .last()
+                .map(|p| p.eq(&PathElement::Index(m)))
+                .unwrap_or(false)
+            {
+                return Ok(());
+            }
+        }
+
+        // find the component on the same path to merge with. op can be moved
+        // before the components following it if they are independent of op
+        let mut target = None;
+        for (i, c) in self.iter().enumerate().rev() {
+            if c.path.eq(&op.path) {
+                target = Some(i);
+                break;
+            }
+            if !c.is_independent_of(&op) {
+                break;
+            }
+        }
+
+        let Some(i) = target else {
+            self.operations.push(op);
+            return Ok(());
+        };
+        if let Some(o) = self.operations[i].merge_with(op, number_equality) {
+            self.operations.push(o);
+        } else if self.operations[i].operator.eq(&Operator::Noop()) {
+            self.operations.remove(i);
+        }
+
+        Ok(())
+    }
+
+    pub fn compose(&mut self, other: Operation) -> Result<()> {
+        self.compose_with(other, NumberEquality::Exact)
+    }
+
+    /// Like `compose` with values compared by number_equality, so for example
+    /// inserting `1` and then deleting `1.0` cancel each other out with
+    /// `NumberEquality::IntegerCoercing`.
+    pub fn compose_with(
+        &mut self,
+        other: Operation,
+        number_equality: NumberEquality,
+    ) -> Result<()> {
+        if self.meta.is_none() {
+            self.meta = other.meta.clone();
+        }
+        for op in other.into_iter() {
+            self.append_with(op, number_equality)?;
+        }
+
+        Ok(())
+    }
+
+    /// Like `compose` but returns the composed operation, leaving self
+    /// unchanged.
+    pub fn composed(&self, other: &Operation) -> Result<Operation> {
+        let mut out = self.clone();
+        out.compose(other.clone())?;
+        Ok(out)
+    }
+
+    /// Compose operations in order into one operation.
+    pub fn compose_all<I: IntoIterator<Item = Operation>>(operations: I) -> Result<Operation> {
+        let mut out = Operation::default();
+        for op in operations {
+            out.compose(op)?;
+        }
+        Ok(out)
+    }
+
+    /// Split into operations of consecutive components within limit, to apply
+    /// or send in order. Meta is kept on the first one only, so it's counted
+    /// once. A component over the byte limit by itself is put alone.
+    pub fn split_max(&self, limit: SplitLimit) -> Vec<Operation> {
+        let mut chunks: Vec<Operation> = vec![];
+        let mut chunk = Operation {
+            operations: vec![],
+            meta: self.meta.clone(),
+        };
+        let mut bytes = encoded_len(&chunk.to_value());
+        for c in &self.operations {
+            let len = encoded_len(&c.to_value());
+            let sep = usize::from(!chunk.is_empty());
+            let full = match limit {
+                SplitLimit::Components(max) => chunk.len() >= max.max(1),
+                SplitLimit::Bytes(max) => !chunk.is_empty() && bytes + sep + len > max,
+            };
+            if full {
+                chunks.push(mem::take(&mut chunk));
+                bytes = encoded_len(&chunk.to_value());
+            }
+            bytes += usize::from(!chunk.is_empty()) + len;
+            chunk.operations.push(c.clone());
+        }
+        if !chunk.is_empty() || chunks.is_empty() {
+            chunks.push(chunk);
+        }
+        chunks
+    }
+
+    /// Whether applying this operation could change the value at path.
+    pub fn affects(&self, path: &Path) -> bool {
+        self.operations.iter().any(|op| op.affects(path))
+    }
+
+    /// The minimal sorted set of paths covering every value changed by this
+    /// operation. No path in the set is a prefix of another.
+    pub fn affected_paths(&self) -> Vec<Path> {
+        Path::outermost_of(self.operations.iter().filter_map(|op| op.affected_path()))
+    }
+
+    /// Components of this operation which change the value at prefix, with
+    /// paths made relative to prefix. Components which only shift the position
+    /// of the value, like inserting or moving list elements before it, are
+    /// left out. List components inserting, deleting or replacing the element
+    /// at prefix become the object components of the root. Replacing an
+    /// ancestor of prefix becomes a replacement of the part of the value under
+    /// prefix.
+    pub fn restrict(&self, prefix: &Path) -> Operation {
+        Operation {
+            operations: self
+                .operations
+                .iter()
+                .filter_map(|op| op.restrict(prefix))
+                .collect(),
+            meta: self.meta.clone(),
+        }
+    }
+
+    /// Map a position in a list or text, the offset in the value on path,
+    /// through this operation. A position is the gap before the element or
+    /// character at offset, so values inserted at it are put before it.
+    /// Returns the path and offset of the position after the operation is
+    /// applied, or None if the list or text is removed or replaced.
+    pub fn map_position(&self, path: &Path, offset: usize) -> Option<(Path, usize)> {
+        let mut path = path.clone();
+        let mut offset = offset;
+        for op in self.operations.iter() {
+            if !op.map_position(&mut path, &mut offset) {
+                return None;
+            }
+        }
+        Some((path, offset))
+    }
+
+    /// Returns this operation with prefix prepended to the path of every
+    /// component. The reverse of `restrict`.
+    pub fn prefix_with(&self, prefix: &Path) -> Operation {
+        Operation {
+            operations: self
+                .operations
+                .iter()
+                .map(|op| op.prefix_with(prefix))
+                .collect(),
+            meta: self.meta.clone(),
+        }
+    }
+
+    /// Split into operations of a single component each, all carrying the
+    /// meta of this operation.
+    pub fn shatter(&self) -> Vec<Operation> {
+        self.operations
+            .iter()
+            .map(|op| Operation {
+                operations: vec![op.clone()],
+                meta: self.meta.clone(),
+            })
+            .collect()
+    }
+
+    /// Concatenate the components of operations in order without merging
+    /// them, taking the first meta found. The reverse of `shatter`.
+    pub fn gather<I: IntoIterator<Item = Operation>>(operations: I) -> Operation {
+        let mut out = Operation::default();
+        for op in operations {
+            if out.meta.is_none() {
+                out.meta = op.meta;
+            }
+            out.operations.extend(op.operations);
+        }
+        out
+    }
+
+    /// Returns an equivalent operation with less components. Components which
+    /// change nothing are removed and adjacent components on the same path are
+    /// merged.
+    pub fn normalize(&self) -> Operation {
+        self.normalize_with(NumberEquality::Exact)
+    }
+
+    /// Like `normalize` with values compared by number_equality.
+    pub fn normalize_with(&self, number_equality: NumberEquality) -> Operation {
+        let mut out = Operation {
+            operations: vec![],
+            meta: self.meta.clone(),
+        };
+        for op in self.operations.iter() {
+            if op.is_equivalent_to_noop_with(number_equality) {
+                continue;
+            }
+            // append only fails on invalid component, which op is not
+            _ = out.append_with(op.clone(), number_equality);
+        }
+        out
+    }
+
+    /// The operation undoing this one, made of the inverses of its components
+    /// in reverse order. Meta is kept.
+    pub fn invert(&self) -> Result<Operation> {
+        let operations = self
+            .operations
+            .iter()
+            .rev()
+            .map(|op| op.invert())
+            .collect::<Result<Vec<OperationComponent>>>()?;
+        Ok(Operation {
+            operations,
+            meta: self.meta.clone(),
+        })
+    }
+
+    /// Whether applying this operation changes nothing on any document, like
+    /// an operation of noops, or inserting and then deleting a value. Test
+    /// components can fail the apply, and adding 0 inserts 0 if there's no
+    /// number, so they are not empty.
+    pub fn is_effectively_empty(&self) -> bool {
+        self.normalize().is_empty()
+    }
+
+    /// Whether this operation and other have the same effect on doc, by
+    /// applying both of them on copies of doc and comparing the results.
+    /// Fails if either of them fails to apply.
+    pub fn equivalent_on(&self, other: &Operation, doc: &Value) -> Result<bool> {
+        Ok(self.applied_to(doc)? == other.applied_to(doc)?)
+    }
+
+    /// doc with this operation applied, without hooks or watchers.
+    pub(crate) fn applied_to(&self, doc: &Value) -> Result<Value> {
+        let mut doc = doc.clone();
+        for (i, op) in self.operations.iter().enumerate() {
+            doc.apply(op.path.clone(), op.operator.clone())
+                .map_err(|e| JsonError::from(e).with_context(|| ErrorContext::new(0, i, op)))?;
+        }
+        Ok(doc)
+    }
+
+    /// Returns an equivalent operation in canonical form: noop components are
+    /// stripped and numbers in operands are normalized.
+    pub fn canonicalize(&self) -> Operation {
+        let operations = self
+            .operations
+            .iter()
+            .filter_map(|op| op.clone_not_noop())
+            .map(|mut op| {
+                op.operator.canonicalize_operands();
+                op
+            })
+            .collect();
+        Operation {
+            operations,
+            meta: self.meta.clone(),
+        }
+    }
+
+    /// SHA-256 digest of the canonical form of this operation's components.
+    /// Meta is not part of the content, so resubmitting the same edit under a
+    /// different meta yields the same hash.
+    pub fn content_hash(&self) -> [u8; 32] {
+        let components = Value::Array(
+            self.canonicalize()
+                .operations
+                .iter()
+                .map(|op| op.to_value())
+                .collect(),
+        );
+        Sha256::digest(to_canonical_string(&components).as_bytes()).into()
+    }
+}
+
+impl Index<usize> for Operation {
+    type Output = OperationComponent;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.operations[index]
+    }
+}
+
+impl<'a> IntoIterator for &'a Operation {
+    type Item = &'a OperationComponent;
+
+    type IntoIter = std::slice::Iter<'a, OperationComponent>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.operations.iter()
+    }
+}
+
+impl IntoIterator for Operation {
+    type Item = OperationComponent;
+
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.operations.into_iter()
+    }
+}
+
+impl Validation for Operation {
+    fn validates(&self) -> Result<()> {
+        self.operations.validates()
+    }
+}
+
+impl From<OperationComponent> for Operation {
+    fn from(input: OperationComponent) -> Self {
+        Operation {
+            operations: vec![input],
+            meta: None,
+        }
+    }
+}
+
+impl From<Vec<OperationComponent>> for Operation {
+    fn from(operations: Vec<OperationComponent>) -> Self {
+        Operation {
+            operations,
+            meta: None,
+        }
+    }
+}
+
+/// Writes the operation in its wire form, the same as `to_value`, so it can
+/// be parsed back by `OperationFactory::from_value`.
+impl Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.meta.is_some() {
+            f.write_str(r#"{"op":"#)?;
+        }
+        f.write_str("[")?;
+        for (i, op) in self.operations.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", op)?;
+        }
+        f.write_str("]")?;
+        if let Some(meta) = &self.meta {
+            write!(f, r#","meta":{}}}"#, meta.to_value())?;
+        }
+        Ok(())
+    }
+}
+
+pub struct ListOperationBuilder {
+    path_builder: Cell<PathBuilder>,
+    insert: Option<Value>,
+    delete: Option<Value>,
+    move_to: Option<usize>,
+}
+
+impl ListOperationBuilder {
+    fn new() -> ListOperationBuilder {
+        ListOperationBuilder {
+            path_builder: Cell::new(PathBuilder::default()),
+            insert: None,
+            delete: None,
+            move_to: None,
+        }
+    }
+
+    pub fn insert<V: Into<Value>>(mut self, val: V) -> Self {
+        self.insert = Some(val.into());
+        self
+    }
+
+    pub fn delete<V: Into<Value>>(mut self, val: V) -> Self {
+        self.delete = Some(val.into());
+        self
+    }
+
+    pub fn replace<O: Into<Value>, N: Into<Value>>(mut self, old: O, new: N) -> Self {
+        self.insert = Some(new.into());
+        self.delete = Some(old.into());
         self
     }
 
@@ -495,6 +1571,16 @@ impl ListOperationBuilder {
         self
     }
 
+    pub fn at<P>(self, path: P) -> Self
+    where
+        P: TryInto<Path>,
+        P::Error: Into<PathError>,
+    {
+        self.path_builder
+            .set(PathBuilder::default().append_path(path));
+        self
+    }
+
     pub fn build(self) -> Result<OperationComponent> {
         let path = self.path_builder.take().build()?;
         if let Some(new_index) = self.move_to {
@@ -503,13 +1589,16 @@ impl ListOperationBuilder {
 
         if let Some(del_val) = self.delete {
             if let Some(ins_val) = self.insert {
-                return OperationComponent::new(path, Operator::ListReplace(ins_val, del_val));
+                return OperationComponent::new(
+                    path,
+                    Operator::ListReplace(ins_val.into(), del_val.into()),
+                );
             }
-            return OperationComponent::new(path, Operator::ListDelete(del_val));
+            return OperationComponent::new(path, Operator::ListDelete(del_val.into()));
         }
 
         if let Some(ins_val) = self.insert {
-            return OperationComponent::new(path, Operator::ListInsert(ins_val));
+            return OperationComponent::new(path, Operator::ListInsert(ins_val.into()));
         }
 
         OperationComponent::new(path, Operator::Noop())
@@ -528,6 +1617,7 @@ pub struct ObjectOperationBuilder {
     path_builder: Cell<PathBuilder>,
     insert: Option<Value>,
     delete: Option<Value>,
+    move_to: Option<String>,
 }
 
 impl ObjectOperationBuilder {
@@ -536,37 +1626,59 @@ impl ObjectOperationBuilder {
             path_builder: Cell::new(PathBuilder::default()),
             insert: None,
             delete: None,
+            move_to: None,
         }
     }
 
-    pub fn insert(mut self, val: Value) -> Self {
-        self.insert = Some(val);
+    pub fn insert<V: Into<Value>>(mut self, val: V) -> Self {
+        self.insert = Some(val.into());
+        self
+    }
+
+    pub fn delete<V: Into<Value>>(mut self, val: V) -> Self {
+        self.delete = Some(val.into());
+        self
+    }
+
+    pub fn replace<O: Into<Value>, N: Into<Value>>(mut self, old: O, new: N) -> Self {
+        self.insert = Some(new.into());
+        self.delete = Some(old.into());
         self
     }
 
-    pub fn delete(mut self, val: Value) -> Self {
-        self.delete = Some(val);
+    pub fn move_to<K: Into<String>>(mut self, new_key: K) -> Self {
+        self.move_to = Some(new_key.into());
         self
     }
 
-    pub fn replace(mut self, old: Value, new: Value) -> Self {
-        self.insert = Some(new);
-        self.delete = Some(old);
+    pub fn at<P>(self, path: P) -> Self
+    where
+        P: TryInto<Path>,
+        P::Error: Into<PathError>,
+    {
+        self.path_builder
+            .set(PathBuilder::default().append_path(path));
         self
     }
 
     pub fn build(self) -> Result<OperationComponent> {
         let path = self.path_builder.take().build()?;
+        if let Some(new_key) = self.move_to {
+            return OperationComponent::new(path, Operator::ObjectMove(new_key));
+        }
 
         if let Some(del_val) = self.delete {
             if let Some(ins_val) = self.insert {
-                return OperationComponent::new(path, Operator::ObjectReplace(ins_val, del_val));
+                return OperationComponent::new(
+                    path,
+                    Operator::ObjectReplace(ins_val.into(), del_val.into()),
+                );
             }
-            return OperationComponent::new(path, Operator::ObjectDelete(del_val));
+            return OperationComponent::new(path, Operator::ObjectDelete(del_val.into()));
         }
 
         if let Some(ins_val) = self.insert {
-            return OperationComponent::new(path, Operator::ObjectInsert(ins_val));
+            return OperationComponent::new(path, Operator::ObjectInsert(ins_val.into()));
         }
 
         OperationComponent::new(path, Operator::Noop())
@@ -598,13 +1710,23 @@ impl NumberAddOperationBuilder {
         }
     }
 
-    pub fn add_int(mut self, num: i64) -> Self {
-        self.number_i64 = Some(num);
+    pub fn add_int<N: Into<i64>>(mut self, num: N) -> Self {
+        self.number_i64 = Some(num.into());
+        self
+    }
+
+    pub fn add_float<N: Into<f64>>(mut self, num: N) -> Self {
+        self.number_f64 = Some(num.into());
         self
     }
 
-    pub fn add_float(mut self, num: f64) -> Self {
-        self.number_f64 = Some(num);
+    pub fn at<P>(self, path: P) -> Self
+    where
+        P: TryInto<Path>,
+        P::Error: Into<PathError>,
+    {
+        self.path_builder
+            .set(PathBuilder::default().append_path(path));
         self
     }
 
@@ -630,7 +1752,7 @@ impl NumberAddOperationBuilder {
                 Operator::SubType(SubType::NumberAdd, o, self.sub_type_function),
             )
         } else {
-            return Err(JsonError::InvalidOperation("need a number to add".into()));
+            Err(JsonError::InvalidOperation("need a number to add".into()))
         }
     }
 }
@@ -662,8 +1784,8 @@ impl TextOperationBuilder {
         }
     }
 
-    pub fn insert_string(mut self, offset: usize, insert: String) -> Self {
-        self.insert_val = Some(insert);
+    pub fn insert_string<S: Into<String>>(mut self, offset: usize, insert: S) -> Self {
+        self.insert_val = Some(insert.into());
         self.offset = offset;
         self
     }
@@ -674,8 +1796,8 @@ impl TextOperationBuilder {
         self
     }
 
-    pub fn delete_string(mut self, offset: usize, delete: String) -> Self {
-        self.delete_val = Some(delete);
+    pub fn delete_string<S: Into<String>>(mut self, offset: usize, delete: S) -> Self {
+        self.delete_val = Some(delete.into());
         self.offset = offset;
         self
     }
@@ -686,6 +1808,16 @@ impl TextOperationBuilder {
         self
     }
 
+    pub fn at<P>(self, path: P) -> Self
+    where
+        P: TryInto<Path>,
+        P::Error: Into<PathError>,
+    {
+        self.path_builder
+            .set(PathBuilder::default().append_path(path));
+        self
+    }
+
     pub fn build(self) -> Result<OperationComponent> {
         let path = self.path_builder.take().build()?;
         // support insert/delete multipul strings
@@ -740,8 +1872,8 @@ impl SubTypeOperationBuilder {
         }
     }
 
-    pub fn sub_type_operand(mut self, val: Value) -> Self {
-        self.sub_type_operator = Some(val);
+    pub fn sub_type_operand<V: Into<Value>>(mut self, val: V) -> Self {
+        self.sub_type_operator = Some(val.into());
         self
     }
 
@@ -750,6 +1882,16 @@ impl SubTypeOperationBuilder {
         self
     }
 
+    pub fn at<P>(self, path: P) -> Self
+    where
+        P: TryInto<Path>,
+        P::Error: Into<PathError>,
+    {
+        self.path_builder
+            .set(PathBuilder::default().append_path(path));
+        self
+    }
+
     pub fn build(self) -> Result<OperationComponent> {
         let path = self.path_builder.take().build()?;
         if let Some(o) = self.sub_type_operator {
@@ -775,67 +1917,548 @@ impl AppendPath for SubTypeOperationBuilder {
         self
     }
 }
-pub struct OperationFactory {
-    sub_type_holder: Rc<SubTypeFunctionsHolder>,
+/// Builds an [`Operation`] with multiple components, such as:
+///
+/// ```
+/// use json0_rs::Json0;
+///
+/// let json0 = Json0::new();
+/// let op = json0
+///     .operation_factory()
+///     .operation_builder()
+///     .at(("a",))
+///     .object_insert(true)
+///     .then()
+///     .at(r#"["list", 0]"#)
+///     .list_delete(1)
+///     .build()
+///     .unwrap();
+/// assert_eq!(2, op.len());
+/// ```
+///
+/// Components are appended the same way as [`Operation::append`], so
+/// consecutive components on the same path are merged when possible.
+pub struct OperationBuilder {
+    sub_type_holder: Arc<SubTypeFunctionsHolder>,
+    operation: Operation,
+    error: Option<JsonError>,
 }
 
-impl OperationFactory {
-    pub fn new(sub_type_holder: Rc<SubTypeFunctionsHolder>) -> OperationFactory {
-        OperationFactory { sub_type_holder }
+impl OperationBuilder {
+    fn new(sub_type_holder: Arc<SubTypeFunctionsHolder>) -> OperationBuilder {
+        OperationBuilder {
+            sub_type_holder,
+            operation: Operation::default(),
+            error: None,
+        }
     }
 
-    /// Build an Operation by JSON Value
-    pub fn from_value(&self, value: Value) -> Result<Operation> {
-        let mut operations = vec![];
-        match value {
-            Value::Array(arr) => {
-                for v in arr {
-                    let op: OperationComponent = self.operation_component_from_value(v)?;
-                    operations.push(op);
-                }
-            }
-            _ => {
-                operations.push(self.operation_component_from_value(value)?);
-            }
+    pub fn at<P>(self, path: P) -> OperationComponentBuilder
+    where
+        P: TryInto<Path>,
+        P::Error: Into<PathError>,
+    {
+        match path.try_into() {
+            Ok(path) => OperationComponentBuilder {
+                builder: self,
+                path,
+                component: None,
+            },
+            Err(e) => OperationComponentBuilder {
+                builder: self,
+                path: Path::from(vec![]),
+                component: Some(Err(JsonError::PathError(e.into()))),
+            },
         }
-        Operation::new(operations)
     }
 
-    pub fn list_operation_builder(&self) -> ListOperationBuilder {
-        ListOperationBuilder::new()
+    /// Insert values one after another into the list on path, starting at
+    /// index.
+    pub fn list_insert_all<P, I>(self, list_path: P, index: usize, values: I) -> Self
+    where
+        P: TryInto<Path>,
+        P::Error: Into<PathError>,
+        I: IntoIterator,
+        I::Item: Into<Value>,
+    {
+        self.list_range(list_path, values, |i, v| {
+            (index + i, Operator::ListInsert(v.into()))
+        })
     }
 
-    pub fn object_operation_builder(&self) -> ObjectOperationBuilder {
-        ObjectOperationBuilder::new()
+    /// Delete a range of elements from the list on path, starting at index.
+    /// Values are the elements to be deleted. Every element is deleted at the
+    /// same index as the following elements shift left.
+    pub fn list_delete_range<P, I>(self, list_path: P, index: usize, values: I) -> Self
+    where
+        P: TryInto<Path>,
+        P::Error: Into<PathError>,
+        I: IntoIterator,
+        I::Item: Into<Value>,
+    {
+        self.list_range(list_path, values, |_, v| {
+            (index, Operator::ListDelete(v.into()))
+        })
     }
 
-    pub fn number_add_operation_builder(&self) -> NumberAddOperationBuilder {
-        let f = self
-            .sub_type_holder
-            .get(&SubType::NumberAdd)
-            .map(|f| f.value().clone())
-            .unwrap();
-        NumberAddOperationBuilder::new(f)
+    /// Move count elements starting at index from in the list on path, so
+    /// that the first of them ends up at index to, like `lm` for a single
+    /// element. It's made of one `lm` per element, so it's applied and
+    /// transformed like them, and a value inserted concurrently among them
+    /// stays where it was inserted.
+    pub fn list_move_range<P>(mut self, list_path: P, from: usize, count: usize, to: usize) -> Self
+    where
+        P: TryInto<Path>,
+        P::Error: Into<PathError>,
+    {
+        let list_path: Path = match list_path.try_into() {
+            Ok(p) => p,
+            Err(e) => return self.append(Err(JsonError::PathError(e.into()))),
+        };
+        for i in 0..count {
+            // moving right, every element is taken from the same index
+            let (from, to) = if to > from {
+                (from, to + count - 1)
+            } else {
+                (from + i, to + i)
+            };
+            let mut path = list_path.clone();
+            path.push(PathElement::Index(from));
+            self = self.append(OperationComponent::new(path, Operator::ListMove(to)));
+        }
+        self
     }
 
-    pub fn text_operation_builder(&self) -> TextOperationBuilder {
+    fn list_range<P, I, F>(mut self, list_path: P, values: I, f: F) -> Self
+    where
+        P: TryInto<Path>,
+        P::Error: Into<PathError>,
+        I: IntoIterator,
+        I::Item: Into<Value>,
+        F: Fn(usize, Value) -> (usize, Operator),
+    {
+        let list_path = match list_path.try_into() {
+            Ok(p) => p,
+            Err(e) => return self.append(Err(JsonError::PathError(e.into()))),
+        };
+        for (i, v) in values.into_iter().enumerate() {
+            let (index, operator) = f(i, v.into());
+            let mut path = list_path.clone();
+            path.push(PathElement::Index(index));
+            self = self.append(OperationComponent::new(path, operator));
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<Operation> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        Ok(self.operation)
+    }
+
+    fn append(mut self, component: Result<OperationComponent>) -> Self {
+        if self.error.is_none() {
+            if let Err(e) = component.and_then(|c| self.operation.append(c)) {
+                self.error = Some(e);
+            }
+        }
+        self
+    }
+
+    fn sub_type_functions(&self, sub_type: &SubType) -> Result<Arc<dyn SubTypeFunctions>> {
+        self.sub_type_holder.functions(sub_type)
+    }
+}
+
+/// Builds one component at a path for [`OperationBuilder`]. The last operator
+/// set wins. Use `then` to go on with the next component.
+pub struct OperationComponentBuilder {
+    builder: OperationBuilder,
+    path: Path,
+    component: Option<Result<OperationComponent>>,
+}
+
+impl OperationComponentBuilder {
+    pub fn object_insert<V: Into<Value>>(self, val: V) -> Self {
+        self.operator(Operator::ObjectInsert(Operand::new(val.into())))
+    }
+
+    pub fn object_delete<V: Into<Value>>(self, val: V) -> Self {
+        self.operator(Operator::ObjectDelete(Operand::new(val.into())))
+    }
+
+    pub fn object_replace<O: Into<Value>, N: Into<Value>>(self, old: O, new: N) -> Self {
+        self.operator(Operator::ObjectReplace(
+            Operand::new(new.into()),
+            Operand::new(old.into()),
+        ))
+    }
+
+    pub fn list_insert<V: Into<Value>>(self, val: V) -> Self {
+        self.operator(Operator::ListInsert(Operand::new(val.into())))
+    }
+
+    pub fn list_delete<V: Into<Value>>(self, val: V) -> Self {
+        self.operator(Operator::ListDelete(Operand::new(val.into())))
+    }
+
+    pub fn list_replace<O: Into<Value>, N: Into<Value>>(self, old: O, new: N) -> Self {
+        self.operator(Operator::ListReplace(
+            Operand::new(new.into()),
+            Operand::new(old.into()),
+        ))
+    }
+
+    pub fn list_move(self, new_index: usize) -> Self {
+        self.operator(Operator::ListMove(new_index))
+    }
+
+    pub fn object_move<K: Into<String>>(self, new_key: K) -> Self {
+        self.operator(Operator::ObjectMove(new_key.into()))
+    }
+
+    pub fn test<V: Into<Value>>(self, expected: V) -> Self {
+        self.operator(Operator::Test(Operand::new(expected.into())))
+    }
+
+    pub fn number_add_int(self, num: i64) -> Self {
+        self.set_component(|b| {
+            let f = b.builder.sub_type_functions(&SubType::NumberAdd)?;
+            b.number_add_builder(f).add_int(num).build()
+        })
+    }
+
+    pub fn number_add_float(self, num: f64) -> Self {
+        self.set_component(|b| {
+            let f = b.builder.sub_type_functions(&SubType::NumberAdd)?;
+            b.number_add_builder(f).add_float(num).build()
+        })
+    }
+
+    pub fn text_insert(self, offset: usize, insert: &str) -> Self {
+        self.set_component(|b| {
+            let f = b.builder.sub_type_functions(&SubType::Text)?;
+            b.text_builder(f).insert_str(offset, insert).build()
+        })
+    }
+
+    pub fn text_delete(self, offset: usize, delete: &str) -> Self {
+        self.set_component(|b| {
+            let f = b.builder.sub_type_functions(&SubType::Text)?;
+            b.text_builder(f).delete_str(offset, delete).build()
+        })
+    }
+
+    pub fn sub_type<S: AsRef<str>, V: Into<Value>>(self, sub_type_name: S, operand: V) -> Self {
+        self.set_component(|b| {
+            let t = b.builder.sub_type_holder.sub_type(sub_type_name.as_ref());
+            let f = b.builder.sub_type_functions(&t)?;
+            OperationComponent::new(b.path.clone(), Operator::SubType(t, operand.into(), f))
+        })
+    }
+
+    /// Finish this component and go on to build the next one.
+    pub fn then(self) -> OperationBuilder {
+        let component = self
+            .component
+            .unwrap_or_else(|| OperationComponent::new(self.path, Operator::Noop()));
+        self.builder.append(component)
+    }
+
+    pub fn build(self) -> Result<Operation> {
+        self.then().build()
+    }
+
+    fn operator(self, operator: Operator) -> Self {
+        self.set_component(|b| OperationComponent::new(b.path.clone(), operator))
+    }
+
+    fn set_component<F>(mut self, build: F) -> Self
+    where
+        F: FnOnce(&Self) -> Result<OperationComponent>,
+    {
+        // keep the error of an invalid path
+        if !matches!(self.component, Some(Err(_))) {
+            self.component = Some(build(&self));
+        }
+        self
+    }
+
+    fn number_add_builder(&self, f: Arc<dyn SubTypeFunctions>) -> NumberAddOperationBuilder {
+        NumberAddOperationBuilder::new(f)
+            .append_all_path_elements(self.path.get_elements().to_vec())
+    }
+
+    fn text_builder(&self, f: Arc<dyn SubTypeFunctions>) -> TextOperationBuilder {
+        TextOperationBuilder::new(f).append_all_path_elements(self.path.get_elements().to_vec())
+    }
+}
+
+// all the keys json0 uses in an operation component
+const OPERATION_KEYS: [&str; 11] = [
+    "p", "na", "t", "o", "lm", "li", "ld", "oi", "od", "om", "test",
+];
+
+/// How [`OperationFactory`] treats keys in an operation component which do not
+/// belong to its operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Reject any key which does not belong to the operator.
+    #[default]
+    Strict,
+    /// Ignore keys unknown to json0, such as metadata added by ShareDB. Keys of
+    /// other json0 operators are still rejected.
+    Lenient,
+}
+
+/// Limits checked when parsing operations from JSON, to protect against
+/// oversized input from untrusted clients. No limit is set by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OperationLimits {
+    /// Max number of elements in the path of a component
+    pub max_path_depth: usize,
+    /// Max number of components in an operation
+    pub max_components: usize,
+    /// Max length in bytes of the JSON encoding of an operand
+    pub max_operand_bytes: usize,
+}
+
+impl OperationLimits {
+    pub fn unlimited() -> OperationLimits {
+        OperationLimits {
+            max_path_depth: usize::MAX,
+            max_components: usize::MAX,
+            max_operand_bytes: usize::MAX,
+        }
+    }
+
+    pub fn max_path_depth(mut self, max: usize) -> Self {
+        self.max_path_depth = max;
+        self
+    }
+
+    pub fn max_components(mut self, max: usize) -> Self {
+        self.max_components = max;
+        self
+    }
+
+    pub fn max_operand_bytes(mut self, max: usize) -> Self {
+        self.max_operand_bytes = max;
+        self
+    }
+
+    fn check(limit: &'static str, max: usize, actual: usize) -> Result<()> {
+        if actual > max {
+            return Err(JsonError::LimitExceeded { limit, max, actual });
+        }
+        Ok(())
+    }
+}
+
+impl Default for OperationLimits {
+    fn default() -> Self {
+        OperationLimits::unlimited()
+    }
+}
+
+pub struct OperationFactory {
+    sub_type_holder: Arc<SubTypeFunctionsHolder>,
+    parse_mode: ParseMode,
+    limits: OperationLimits,
+}
+
+impl OperationFactory {
+    pub fn new(sub_type_holder: Arc<SubTypeFunctionsHolder>) -> OperationFactory {
+        OperationFactory {
+            sub_type_holder,
+            parse_mode: ParseMode::default(),
+            limits: OperationLimits::default(),
+        }
+    }
+
+    pub fn with_parse_mode(mut self, parse_mode: ParseMode) -> Self {
+        self.parse_mode = parse_mode;
+        self
+    }
+
+    pub fn set_parse_mode(&mut self, parse_mode: ParseMode) {
+        self.parse_mode = parse_mode;
+    }
+
+    pub fn parse_mode(&self) -> ParseMode {
+        self.parse_mode
+    }
+
+    pub fn with_limits(mut self, limits: OperationLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn set_limits(&mut self, limits: OperationLimits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> OperationLimits {
+        self.limits
+    }
+
+    /// Build an Operation by JSON Value
+    pub fn from_value(&self, value: Value) -> Result<Operation> {
+        if Self::is_envelope(&value) {
+            return self.operation_from_envelope(value);
+        }
+
+        let mut operations = vec![];
+        match value {
+            Value::Array(arr) => {
+                OperationLimits::check("components", self.limits.max_components, arr.len())?;
+                for v in arr {
+                    let op: OperationComponent = self.operation_component_from_value(v)?;
+                    operations.push(op);
+                }
+            }
+            _ => {
+                operations.push(self.operation_component_from_value(value)?);
+            }
+        }
+        Operation::new(operations)
+    }
+
+    /// Parse an operation from JSON text. Malformed JSON fails with
+    /// `EncodingError` telling the byte offset, line and column of the error.
+    pub fn from_str(&self, s: &str) -> Result<Operation> {
+        self.from_slice(s.as_bytes())
+    }
+
+    /// Like `from_str` but from JSON bytes.
+    pub fn from_slice(&self, bytes: &[u8]) -> Result<Operation> {
+        let value = serde_json::from_slice(bytes).map_err(|e| {
+            // line and column are 1-based, with columns counted in bytes
+            let offset = bytes
+                .split(|b| *b == b'\n')
+                .take(e.line().saturating_sub(1))
+                .map(|l| l.len() + 1)
+                .sum::<usize>()
+                + e.column().saturating_sub(1);
+            JsonError::EncodingError(format!(
+                "invalid JSON at byte offset {offset}, line {}, column {}: {e}",
+                e.line(),
+                e.column()
+            ))
+        })?;
+        self.from_value(value)
+    }
+
+    /// Incrementally read operations from a stream of newline-delimited or
+    /// concatenated JSON values. Reading stops after malformed JSON. Wrap
+    /// unbuffered readers like files or sockets with `io::BufReader`.
+    pub fn read_operations<'a, R: io::Read + 'a>(
+        &'a self,
+        reader: R,
+    ) -> impl Iterator<Item = Result<Operation>> + 'a {
+        serde_json::Deserializer::from_reader(reader)
+            .into_iter::<Value>()
+            .map(|v| {
+                v.map_err(|e| JsonError::EncodingError(e.to_string()))
+                    .and_then(|v| self.from_value(v))
+            })
+    }
+
+    pub fn operation_builder(&self) -> OperationBuilder {
+        OperationBuilder::new(self.sub_type_holder.clone())
+    }
+
+    pub fn list_operation_builder(&self) -> ListOperationBuilder {
+        ListOperationBuilder::new()
+    }
+
+    pub fn object_operation_builder(&self) -> ObjectOperationBuilder {
+        ObjectOperationBuilder::new()
+    }
+
+    /// # Panics
+    ///
+    /// Panics if there's no `na` sub type registered.
+    pub fn number_add_operation_builder(&self) -> NumberAddOperationBuilder {
         let f = self
             .sub_type_holder
-            .get(&SubType::Text)
-            .map(|f| f.value().clone())
-            .unwrap();
-        TextOperationBuilder::new(f)
+            .functions(&SubType::NumberAdd)
+            .expect("na sub type is not registered");
+        NumberAddOperationBuilder::new(f)
     }
 
-    pub fn sub_type_operation_builder(&self, sub_type_name: String) -> SubTypeOperationBuilder {
-        let sub_type = SubType::Custome(sub_type_name);
+    /// # Panics
+    ///
+    /// Panics if there's no `text` sub type registered.
+    pub fn text_operation_builder(&self) -> TextOperationBuilder {
         let f = self
             .sub_type_holder
-            .get(&sub_type)
-            .map(|f| f.value().clone());
+            .functions(&SubType::Text)
+            .expect("text sub type is not registered");
+        TextOperationBuilder::new(f)
+    }
+
+    /// Build text operation components on path which turn old text into new
+    /// text with the least characters inserted and deleted.
+    pub fn text_operation_from_diff<P>(&self, path: P, old: &str, new: &str) -> Result<Operation>
+    where
+        P: TryInto<Path>,
+        P::Error: Into<PathError>,
+    {
+        let path: Path = path.try_into().map_err(|e| e.into())?;
+        let mut operation = Operation::default();
+        // offsets are in bytes of the text being edited
+        let mut offset = 0;
+        for edit in diff(old, new) {
+            let builder = self.text_operation_builder().at(path.clone());
+            match edit {
+                Edit::Equal(s) => {
+                    offset += s.len();
+                    continue;
+                }
+                Edit::Delete(s) => {
+                    operation.push_validated(builder.delete_string(offset, s).build()?)?
+                }
+                Edit::Insert(s) => {
+                    let len = s.len();
+                    operation.push_validated(builder.insert_string(offset, s).build()?)?;
+                    offset += len;
+                }
+            }
+        }
+        Ok(operation)
+    }
+
+    pub fn sub_type_operation_builder(&self, sub_type_name: String) -> SubTypeOperationBuilder {
+        let sub_type = self.sub_type_holder.sub_type(&sub_type_name);
+        let f = self.sub_type_holder.get(&sub_type);
         SubTypeOperationBuilder::new(sub_type, f)
     }
 
+    fn is_envelope(value: &Value) -> bool {
+        value
+            .as_object()
+            .map(|obj| obj.contains_key("op") && !obj.contains_key("p"))
+            .unwrap_or(false)
+    }
+
+    fn operation_from_envelope(&self, value: Value) -> Result<Operation> {
+        let Value::Object(mut obj) = value else {
+            unreachable!()
+        };
+        let meta = obj.get("meta").map(OperationMeta::try_from).transpose()?;
+        let op_value = obj.remove("op").unwrap();
+        if Self::is_envelope(&op_value) {
+            return Err(JsonError::InvalidOperation(
+                "nested operation envelope is not allowed".into(),
+            ));
+        }
+        let mut operation = self.from_value(op_value)?;
+        operation.meta = meta;
+        Ok(operation)
+    }
+
     fn operation_component_from_value(&self, value: Value) -> Result<OperationComponent> {
         let path_value = value.get("p");
 
@@ -843,7 +2466,12 @@ impl OperationFactory {
             return Err(JsonError::InvalidOperation("Missing path".into()));
         }
 
-        let paths = Path::try_from(path_value.unwrap())?;
+        let path_value = path_value.unwrap();
+        if let Value::Array(elements) = path_value {
+            OperationLimits::check("path depth", self.limits.max_path_depth, elements.len())?;
+        }
+        let paths = Path::try_from(path_value)?;
+        self.check_operand_size(&value)?;
         let operator = self.operator_from_value(value)?;
 
         Ok(OperationComponent {
@@ -852,6 +2480,24 @@ impl OperationFactory {
         })
     }
 
+    fn check_operand_size(&self, value: &Value) -> Result<()> {
+        if self.limits.max_operand_bytes == usize::MAX {
+            return Ok(());
+        }
+        if let Value::Object(obj) = value {
+            for (k, v) in obj {
+                if k != "p" && k != "t" {
+                    OperationLimits::check(
+                        "operand bytes",
+                        self.limits.max_operand_bytes,
+                        encoded_len(v),
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn operator_from_value(&self, value: Value) -> Result<Operator> {
         match &value {
             Value::Object(obj) => {
@@ -865,93 +2511,124 @@ impl OperationFactory {
     }
 
     fn map_to_operator(&self, obj: &Map<String, Value>) -> Result<Operator> {
-        if let Some(na) = obj.get("na") {
-            self.validate_operation_object_size(obj, 2)?;
-            return Ok(Operator::SubType(
-                SubType::NumberAdd,
-                na.clone(),
-                self.sub_type_holder
-                    .get(&SubType::NumberAdd)
-                    .map(|f| f.value().clone())
-                    .unwrap(),
-            ));
-        }
-
-        if let Some(t) = obj.get("t") {
-            self.validate_operation_object_size(obj, 3)?;
-            let sub_type = t.try_into()?;
-            let op = obj.get("o").cloned().unwrap_or(Value::Null);
-            let sub_op_func = self
-                .sub_type_holder
-                .get(&sub_type)
-                .map(|f| f.value().clone())
+        let (operator, operator_keys): (Operator, &[&str]) = if let Some(na) = obj.get("na") {
+            (
+                Operator::SubType(
+                    SubType::NumberAdd,
+                    na.clone(),
+                    self.sub_type_holder.functions(&SubType::NumberAdd)?,
+                ),
+                &["na"],
+            )
+        } else if let Some(t) = obj.get("t") {
+            let sub_type = match t {
+                Value::String(name) => self.sub_type_holder.sub_type(name),
+                _ => t.try_into()?,
+            };
+            let op = obj
+                .get("o")
+                .cloned()
                 .ok_or(JsonError::InvalidOperation(format!(
-                    "no sub type functions for sub type: {}",
+                    "missing operand \"o\" for sub type: {}",
                     sub_type
                 )))?;
-            return Ok(Operator::SubType(sub_type, op, sub_op_func));
-        }
-
-        if let Some(lm) = obj.get("lm") {
-            self.validate_operation_object_size(obj, 2)?;
-            let i = Operator::value_to_index(lm)?;
-            return Ok(Operator::ListMove(i));
-        }
-
-        if let Some(li) = obj.get("li") {
+            let sub_op_func = self.sub_type_holder.functions(&sub_type)?;
+            (Operator::SubType(sub_type, op, sub_op_func), &["t", "o"])
+        } else if let Some(v) = obj.get("test") {
+            (Operator::Test(Operand::new(v.clone())), &["test"])
+        } else if let Some(om) = obj.get("om") {
+            let to = om.as_str().ok_or(JsonError::InvalidOperation(format!(
+                "om operand: {} is not a string",
+                om
+            )))?;
+            (Operator::ObjectMove(to.to_string()), &["om"])
+        } else if let Some(lm) = obj.get("lm") {
+            (Operator::ListMove(Operator::value_to_index(lm)?), &["lm"])
+        } else if let Some(li) = obj.get("li") {
             if let Some(ld) = obj.get("ld") {
-                self.validate_operation_object_size(obj, 3)?;
-                return Ok(Operator::ListReplace(li.clone(), ld.clone()));
+                (
+                    Operator::ListReplace(Operand::new(li.clone()), Operand::new(ld.clone())),
+                    &["li", "ld"],
+                )
+            } else {
+                (Operator::ListInsert(Operand::new(li.clone())), &["li"])
             }
-            self.validate_operation_object_size(obj, 2)?;
-            return Ok(Operator::ListInsert(li.clone()));
-        }
-
-        if let Some(ld) = obj.get("ld") {
-            self.validate_operation_object_size(obj, 2)?;
-            return Ok(Operator::ListDelete(ld.clone()));
-        }
-
-        if let Some(oi) = obj.get("oi") {
+        } else if let Some(ld) = obj.get("ld") {
+            (Operator::ListDelete(Operand::new(ld.clone())), &["ld"])
+        } else if let Some(oi) = obj.get("oi") {
             if let Some(od) = obj.get("od") {
-                self.validate_operation_object_size(obj, 3)?;
-                return Ok(Operator::ObjectReplace(oi.clone(), od.clone()));
+                (
+                    Operator::ObjectReplace(Operand::new(oi.clone()), Operand::new(od.clone())),
+                    &["oi", "od"],
+                )
+            } else {
+                (Operator::ObjectInsert(Operand::new(oi.clone())), &["oi"])
             }
-            self.validate_operation_object_size(obj, 2)?;
-            return Ok(Operator::ObjectInsert(oi.clone()));
-        }
-
-        if let Some(od) = obj.get("od") {
-            self.validate_operation_object_size(obj, 2)?;
-            return Ok(Operator::ObjectDelete(od.clone()));
-        }
+        } else if let Some(od) = obj.get("od") {
+            (Operator::ObjectDelete(Operand::new(od.clone())), &["od"])
+        } else {
+            (Operator::Noop(), &[])
+        };
 
-        self.validate_operation_object_size(obj, 1)?;
-        Ok(Operator::Noop())
+        self.validate_operation_keys(obj, operator_keys)?;
+        Ok(operator)
     }
 
-    fn validate_operation_object_size(
+    fn validate_operation_keys(
         &self,
         origin_operation: &Map<String, Value>,
-        expect_size: usize,
+        operator_keys: &[&str],
     ) -> Result<()> {
-        if origin_operation.len() != expect_size {
-            return Err(JsonError::InvalidOperation(
-                "JSON object size bigger than operator required".into(),
-            ));
+        for k in origin_operation.keys() {
+            if k == "p" || operator_keys.contains(&k.as_str()) {
+                continue;
+            }
+            if OPERATION_KEYS.contains(&k.as_str()) {
+                return Err(JsonError::InvalidOperation(format!(
+                    "key: \"{}\" can not be used together with: {:?} in operation: {}",
+                    k,
+                    operator_keys,
+                    Value::Object(origin_operation.clone())
+                )));
+            }
+            if self.parse_mode == ParseMode::Strict {
+                return Err(JsonError::InvalidOperation(format!(
+                    "unknown key: \"{}\" in operation: {}",
+                    k,
+                    Value::Object(origin_operation.clone())
+                )));
+            }
         }
         Ok(())
     }
 }
 
+/// Parse an operation with the built-in sub types only, see
+/// `OperationFactory::from_str` for custom sub types.
+impl FromStr for Operation {
+    type Err = JsonError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        OperationFactory::default().from_str(s)
+    }
+}
+
+impl Default for OperationFactory {
+    fn default() -> Self {
+        OperationFactory::new(Arc::new(SubTypeFunctionsHolder::new()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::json::Appliable;
+    use itertools::Itertools;
     use test_log::test;
 
     #[test]
     fn test_number_add_operator() {
-        let op_factory = OperationFactory::new(Rc::new(SubTypeFunctionsHolder::new()));
+        let op_factory = OperationFactory::new(Arc::new(SubTypeFunctionsHolder::new()));
         let op = op_factory
             .number_add_operation_builder()
             .append_key_path("p1")
@@ -970,7 +2647,7 @@ mod tests {
     #[test]
     fn test_text_operator() {
         let sub_type_operand: Value = serde_json::from_str(r#"{"p":1, "i":"hello"}"#).unwrap();
-        let op_factory = OperationFactory::new(Rc::new(SubTypeFunctionsHolder::new()));
+        let op_factory = OperationFactory::new(Arc::new(SubTypeFunctionsHolder::new()));
         let op = op_factory
             .text_operation_builder()
             .append_key_path("p1")
@@ -985,4 +2662,1014 @@ mod tests {
         assert_eq!(SubType::Text, sub_type);
         assert_eq!(sub_type_operand, op_value);
     }
+
+    #[test]
+    fn test_operation_meta_survives_serialization() {
+        let op_factory = OperationFactory::new(Arc::new(SubTypeFunctionsHolder::new()));
+        let meta = OperationMeta::new()
+            .author("alice")
+            .timestamp(1690000000000)
+            .id("op-1")
+            .seq(3)
+            .clock(VectorClock::new().set("alice", 2).set("bob", 1));
+        let op: Operation = op_factory
+            .object_operation_builder()
+            .append_key_path("k")
+            .insert(Value::String("v".into()))
+            .build()
+            .unwrap()
+            .into();
+        let op = op.with_meta(meta.clone());
+
+        let value = op.to_value();
+        assert_eq!(
+            serde_json::from_str::<Value>(
+                r#"{"op":[{"p":["k"],"oi":"v"}],"meta":{"author":"alice","timestamp":1690000000000,"id":"op-1","seq":3,"clock":{"alice":2,"bob":1}}}"#
+            )
+            .unwrap(),
+            value
+        );
+
+        let parsed = op_factory.from_value(value).unwrap();
+        assert_eq!(Some(&meta), parsed.meta());
+        assert_eq!(op, parsed);
+    }
+
+    #[test]
+    fn test_operation_meta_survives_compose() {
+        let op_factory = OperationFactory::new(Arc::new(SubTypeFunctionsHolder::new()));
+        let meta = OperationMeta::new().id("op-1");
+        let mut base = op_factory
+            .from_value(serde_json::from_str(r#"{"p":["k"],"oi":"v"}"#).unwrap())
+            .unwrap();
+        let other = op_factory
+            .from_value(serde_json::from_str(r#"{"p":["k2"],"oi":"v"}"#).unwrap())
+            .unwrap()
+            .with_meta(meta.clone());
+
+        base.compose(other).unwrap();
+        assert_eq!(Some(&meta), base.meta());
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let op_factory = OperationFactory::new(Arc::new(SubTypeFunctionsHolder::new()));
+        let a = op_factory
+            .from_value(
+                serde_json::from_str(r#"[{"p":["k"],"oi":{"b":1.0,"a":2}},{"p":["n"]}]"#).unwrap(),
+            )
+            .unwrap();
+        let b = op_factory
+            .from_value(serde_json::from_str(r#"[{"p":["k"],"oi":{"a":2,"b":1}}]"#).unwrap())
+            .unwrap()
+            .with_meta(OperationMeta::new().id("resubmitted"));
+        let c = op_factory
+            .from_value(serde_json::from_str(r#"[{"p":["k"],"oi":{"a":2,"b":1.5}}]"#).unwrap())
+            .unwrap();
+
+        assert_eq!(a.content_hash(), b.content_hash());
+        assert_ne!(a.content_hash(), c.content_hash());
+        assert_eq!(1, a.canonicalize().len());
+    }
+
+    #[test]
+    fn test_operation_builder() {
+        let op_factory = OperationFactory::default();
+        let op = op_factory
+            .operation_builder()
+            .at(path!["a"])
+            .object_insert(Value::from(1))
+            .then()
+            .at(path!["a"])
+            .object_delete(Value::from(1))
+            .then()
+            .at(path!["l", 0])
+            .list_insert(Value::from("x"))
+            .then()
+            .at(path!["n"])
+            .number_add_int(2)
+            .then()
+            .at(path!["s"])
+            .text_insert(1, "hi")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            op_factory
+                .from_value(
+                    serde_json::from_str(
+                        r#"[{"p":["l",0],"li":"x"},{"p":["n"],"na":2},{"p":["s"],"t":"text","o":{"p":1,"i":"hi"}}]"#
+                    )
+                    .unwrap()
+                )
+                .unwrap(),
+            op
+        );
+
+        assert_matches!(
+            op_factory
+                .operation_builder()
+                .at(path!["a"])
+                .sub_type("unknown", Value::Null)
+                .build(),
+            Err(JsonError::InvalidOperation(_))
+        );
+    }
+
+    #[test]
+    fn test_builders_accept_convertible_values_and_paths() {
+        let op_factory = OperationFactory::default();
+        let expect = op_factory
+            .from_value(serde_json::from_str(r#"{"p":["a",0],"li":"x"}"#).unwrap())
+            .unwrap();
+
+        let op: Operation = op_factory
+            .list_operation_builder()
+            .at(("a", 0))
+            .insert("x")
+            .build()
+            .unwrap()
+            .into();
+        assert_eq!(expect, op);
+
+        let op: Operation = op_factory
+            .list_operation_builder()
+            .at(r#"["a", 0]"#)
+            .insert("x")
+            .build()
+            .unwrap()
+            .into();
+        assert_eq!(expect, op);
+
+        let op = op_factory
+            .operation_builder()
+            .at(("a", 0))
+            .list_insert("x")
+            .build()
+            .unwrap();
+        assert_eq!(expect, op);
+
+        assert_matches!(
+            op_factory
+                .object_operation_builder()
+                .at("[a")
+                .insert(1)
+                .build(),
+            Err(JsonError::PathError(_))
+        );
+        assert_matches!(
+            op_factory
+                .operation_builder()
+                .at("[a")
+                .object_insert(1)
+                .build(),
+            Err(JsonError::PathError(_))
+        );
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        let strict = OperationFactory::default();
+        let lenient = OperationFactory::default().with_parse_mode(ParseMode::Lenient);
+        let with_unknown_key: Value =
+            serde_json::from_str(r#"{"p":["k"],"oi":1,"src":"client-1"}"#).unwrap();
+
+        let err = strict.from_value(with_unknown_key.clone()).unwrap_err();
+        assert!(err.to_string().contains("\"src\""), "{}", err);
+
+        let op = lenient.from_value(with_unknown_key).unwrap();
+        assert_eq!(
+            Operator::ObjectInsert(Value::from(1).into()),
+            op[0].operator
+        );
+
+        // keys of other operators are rejected in both modes
+        let conflict: Value = serde_json::from_str(r#"{"p":["k"],"oi":1,"li":2}"#).unwrap();
+        assert!(strict.from_value(conflict.clone()).is_err());
+        assert!(lenient.from_value(conflict).is_err());
+
+        // a component with only path is a noop in both modes
+        let noop: Value = serde_json::from_str(r#"{"p":["k"]}"#).unwrap();
+        assert_eq!(
+            Operator::Noop(),
+            strict.from_value(noop).unwrap()[0].operator
+        );
+    }
+
+    #[test]
+    fn test_operation_limits() {
+        let limits = OperationLimits::unlimited()
+            .max_path_depth(2)
+            .max_components(2)
+            .max_operand_bytes(8);
+        let f = OperationFactory::default().with_limits(limits);
+
+        let op: Value = serde_json::from_str(r#"[{"p":["a","b"],"oi":"1234"}]"#).unwrap();
+        assert!(f.from_value(op).is_ok());
+
+        let deep: Value = serde_json::from_str(r#"{"p":["a","b","c"],"oi":1}"#).unwrap();
+        assert_matches!(
+            f.from_value(deep).unwrap_err(),
+            JsonError::LimitExceeded {
+                limit: "path depth",
+                max: 2,
+                actual: 3
+            }
+        );
+
+        let many: Value = serde_json::from_str(r#"[{"p":["a"]},{"p":["b"]},{"p":["c"]}]"#).unwrap();
+        assert_matches!(
+            f.from_value(many).unwrap_err(),
+            JsonError::LimitExceeded {
+                limit: "components",
+                ..
+            }
+        );
+
+        let large: Value = serde_json::from_str(r#"{"p":["a"],"oi":"123456789"}"#).unwrap();
+        assert_matches!(
+            f.from_value(large).unwrap_err(),
+            JsonError::LimitExceeded {
+                limit: "operand bytes",
+                max: 8,
+                actual: 11
+            }
+        );
+
+        let large: Value = serde_json::from_str(r#"{"p":["a"],"od":1,"oi":[1,2,3,4,5]}"#).unwrap();
+        assert!(OperationFactory::default()
+            .from_value(large.clone())
+            .is_ok());
+        assert!(f.from_value(large).is_err());
+    }
+
+    #[test]
+    fn test_read_operations() {
+        let f = OperationFactory::default();
+        let input = r#"[{"p":["a"],"oi":1}]
+{"p":["b",0],"li":2}[{"p":["c"],"od":3}]
+  {"op":[{"p":["d"],"na":1}],"meta":{"author":"u1"}}
+"#;
+        let ops = f
+            .read_operations(io::Cursor::new(input))
+            .collect::<Result<Vec<Operation>>>()
+            .unwrap();
+        assert_eq!(4, ops.len());
+        assert_eq!(
+            Operator::ListInsert(Value::from(2).into()),
+            ops[1][0].operator
+        );
+        assert_eq!(Some("u1"), ops[3].meta().unwrap().author.as_deref());
+
+        let input = r#"[{"p":["a"],"oi":1}] {"oi":2} [{"p":["c"],"od":3}]"#;
+        let mut ops = f.read_operations(io::Cursor::new(input));
+        assert!(ops.next().unwrap().is_ok());
+        assert!(ops.next().unwrap().is_err());
+
+        let mut ops = f.read_operations(io::Cursor::new(r#"[{"p":["a"],"oi":1}] [{"p"#));
+        assert!(ops.next().unwrap().is_ok());
+        assert_matches!(ops.next().unwrap(), Err(JsonError::EncodingError(_)));
+        assert!(ops.next().is_none());
+    }
+
+    #[test]
+    fn test_from_str_and_slice() {
+        let f = OperationFactory::default();
+        let op = f.from_str(r#"[{"p":["a"],"oi":1}]"#).unwrap();
+        assert_eq!(op, f.from_slice(br#"[{"p":["a"],"oi":1}]"#).unwrap());
+        assert_eq!(op, r#"[{"p":["a"],"oi":1}]"#.parse::<Operation>().unwrap());
+
+        let input = "[{\"p\":[\"a\"],\n \"oi\": x}]";
+        let err = f.from_str(input).unwrap_err();
+        assert_eq!(b'x', input.as_bytes()[20]);
+        assert_matches!(
+            &err,
+            JsonError::EncodingError(e) if e.starts_with("invalid JSON at byte offset 20, line 2, column 8")
+        );
+        assert_matches!(
+            f.from_str(r#"[{"p":["a"],"x":1}]"#),
+            Err(JsonError::InvalidOperation(_))
+        );
+    }
+
+    #[test]
+    fn test_root_path_operation() {
+        let f = OperationFactory::default();
+        let op = f
+            .from_value(serde_json::from_str(r#"{"p":[],"oi":{"a":1}}"#).unwrap())
+            .unwrap();
+        assert!(op[0].path.is_root());
+        assert_eq!(
+            Operator::ObjectDelete(serde_json::json!({"a":1}).into()),
+            op[0].invert().unwrap().operator
+        );
+
+        for invalid in [
+            r#"{"p":[],"li":1}"#,
+            r#"{"p":[],"ld":1}"#,
+            r#"{"p":[],"lm":1}"#,
+        ] {
+            assert_matches!(
+                f.from_value(serde_json::from_str(invalid).unwrap()),
+                Err(JsonError::InvalidOperation(_))
+            );
+        }
+    }
+
+    #[test]
+    fn test_end_path_only_for_list_insert() {
+        let f = OperationFactory::default();
+        let op = f
+            .from_value(serde_json::from_str(r#"{"p":["l",-1],"li":1}"#).unwrap())
+            .unwrap();
+        assert_eq!(Some(&PathElement::End(0)), op[0].path.last());
+
+        for invalid in [
+            r#"{"p":["l",-1],"ld":1}"#,
+            r#"{"p":["l",-1],"lm":1}"#,
+            r#"{"p":["l",-1,"k"],"oi":1}"#,
+            r#"{"p":["l",-1,0],"li":1}"#,
+        ] {
+            assert_matches!(
+                f.from_value(serde_json::from_str(invalid).unwrap()),
+                Err(JsonError::InvalidOperation(_))
+            );
+        }
+    }
+
+    #[test]
+    fn test_list_range_operations() {
+        let f = OperationFactory::default();
+        let op = f
+            .operation_builder()
+            .list_delete_range(path!["l"], 1, [2, 3, 4])
+            .list_insert_all(path!["l"], 1, ["a", "b"])
+            .build()
+            .unwrap();
+        assert_eq!(5, op.len());
+        assert_eq!(path!["l", 1], op[2].path);
+        assert_eq!(path!["l", 2], op[4].path);
+
+        let mut value = serde_json::json!({"l": [1, 2, 3, 4, 5]});
+        for c in op.iter() {
+            value.apply(c.path.clone(), c.operator.clone()).unwrap();
+        }
+        assert_eq!(serde_json::json!({"l": [1, "a", "b", 5]}), value);
+
+        assert!(f
+            .operation_builder()
+            .list_insert_all("[", 0, [1])
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_list_move_range() {
+        let f = OperationFactory::default();
+        let doc = serde_json::json!({"l": ["a", "b", "c", "d", "e"]});
+        let move_range = |from, count, to| {
+            f.operation_builder()
+                .list_move_range(path!["l"], from, count, to)
+                .build()
+                .unwrap()
+        };
+        let moved =
+            |from, count, to| move_range(from, count, to).applied_to(&doc).unwrap()["l"].clone();
+        assert_eq!(serde_json::json!(["c", "d", "a", "b", "e"]), moved(0, 2, 2));
+        assert_eq!(serde_json::json!(["b", "c", "d", "a", "e"]), moved(0, 1, 3));
+        assert_eq!(serde_json::json!(["c", "d", "e", "a", "b"]), moved(2, 3, 0));
+        assert_eq!(serde_json::json!(["a", "d", "b", "c", "e"]), moved(3, 1, 1));
+        assert_eq!(doc["l"], moved(1, 0, 3));
+
+        let json0 = crate::Json0::new();
+        for (concurrent, expected) in [
+            (
+                serde_json::json!([{"p": ["l", 1], "li": "x"}]),
+                serde_json::json!(["x", "c", "d", "a", "b", "e"]),
+            ),
+            (
+                serde_json::json!([{"p": ["l", 1], "ld": "b"}]),
+                serde_json::json!(["c", "d", "a", "e"]),
+            ),
+            (
+                serde_json::json!([{"p": ["l", 4], "lm": 0}]),
+                serde_json::json!(["e", "c", "d", "a", "b"]),
+            ),
+        ] {
+            let concurrent = f.from_value(concurrent).unwrap();
+            let converged =
+                crate::testing::check_convergence(&json0, &doc, &move_range(0, 2, 2), &concurrent);
+            assert_eq!(expected, converged["l"]);
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        let f = OperationFactory::default();
+        let op = f
+            .from_value(
+                serde_json::from_str(
+                    r#"[{"p":["a"]},{"p":["l",1],"lm":1},{"p":["o"],"om":"o"},
+                        {"p":["b"],"oi":1,"od":1},{"p":["c"],"oi":1},{"p":["c"],"od":1},
+                        {"p":["n"],"na":1},{"p":["n"],"na":2},{"p":["d"],"od":2},{"p":["d"],"oi":3}]"#,
+                )
+                .unwrap(),
+            )
+            .unwrap()
+            .with_meta(OperationMeta::new().id("1"));
+
+        let expect = f
+            .from_value(
+                serde_json::from_str(r#"[{"p":["n"],"na":3},{"p":["d"],"oi":3,"od":2}]"#).unwrap(),
+            )
+            .unwrap();
+        let normalized = op.normalize();
+        assert_eq!(expect.operations, normalized.operations);
+        assert_eq!(op.meta(), normalized.meta());
+
+        // deleting and putting back an equal value is not merged into a noop
+        for s in [
+            r#"[{"p":["d"],"od":[]},{"p":["d"],"oi":[]}]"#,
+            r#"[{"p":["l",0],"ld":1,"li":null},{"p":["l",0],"ld":null,"li":1}]"#,
+        ] {
+            let op = f.from_value(serde_json::from_str(s).unwrap()).unwrap();
+            assert_eq!(op, op.normalize());
+        }
+    }
+
+    #[test]
+    fn test_composed_and_compose_all() {
+        let f = OperationFactory::default();
+        let ops = [
+            r#"[{"p":["n"],"na":1}]"#,
+            r#"[{"p":["n"],"na":2}]"#,
+            r#"{"op":[{"p":["k"],"oi":1}],"meta":{"id":"3"}}"#,
+        ]
+        .into_iter()
+        .map(|s| f.from_value(serde_json::from_str(s).unwrap()).unwrap())
+        .collect_vec();
+
+        let composed = ops[0].composed(&ops[1]).unwrap();
+        assert_eq!(1, ops[0].len());
+        assert_eq!(serde_json::json!([{"p":["n"],"na":3}]), composed.to_value());
+
+        let all = Operation::compose_all(ops.clone()).unwrap();
+        assert_eq!(composed.composed(&ops[2]).unwrap(), all);
+        assert_eq!(Some("3"), all.meta().unwrap().id.as_deref());
+        assert!(Operation::compose_all(vec![]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_effectively_empty_and_equivalent() {
+        let f = OperationFactory::default();
+        let parse = |s: &str| f.from_value(serde_json::from_str(s).unwrap()).unwrap();
+
+        assert!(Operation::default().is_effectively_empty());
+        assert!(!parse(r#"[{"p":["n"],"na":0}]"#).is_effectively_empty());
+        assert!(parse(r#"[{"p":["l",1],"lm":1},{"p":["k"],"om":"k"}]"#).is_effectively_empty());
+        assert!(parse(r#"[{"p":["k"],"oi":1},{"p":["k"],"od":1}]"#).is_effectively_empty());
+        assert!(!parse(r#"[{"p":["n"],"na":1},{"p":["n"],"na":-1}]"#).is_effectively_empty());
+        assert!(!parse(r#"[{"p":["k"],"test":1}]"#).is_effectively_empty());
+
+        let doc = serde_json::json!({"l": [1, 2], "n": 1});
+        let a = parse(r#"[{"p":["l",0],"ld":1},{"p":["l",0],"li":3}]"#);
+        let b = parse(r#"[{"p":["l",0],"ld":1,"li":3}]"#);
+        let c = parse(
+            r#"[{"p":["l",1],"lm":0},{"p":["l",1],"ld":1},{"p":["l",0],"ld":2},{"p":["l",0],"li":3},{"p":["l",1],"li":2}]"#,
+        );
+        assert!(a.equivalent_on(&b, &doc).unwrap());
+        assert!(a.equivalent_on(&c, &doc).unwrap());
+        assert!(!a
+            .equivalent_on(&parse(r#"[{"p":["l",1],"ld":2,"li":3}]"#), &doc)
+            .unwrap());
+        assert!(a
+            .equivalent_on(
+                &parse(r#"[{"p":["l",0],"ld":1,"li":3},{"p":["n"],"na":0}]"#),
+                &doc
+            )
+            .unwrap());
+
+        let err = a
+            .equivalent_on(&parse(r#"[{"p":["n"],"na":1},{"p":["x"],"test":1}]"#), &doc)
+            .unwrap_err();
+        assert_eq!(1, err.context().unwrap().component_index);
+    }
+
+    #[test]
+    fn test_compose_across_independent_components() {
+        let f = OperationFactory::default();
+        let parse = |s: &str| f.from_value(serde_json::from_str(s).unwrap()).unwrap();
+
+        let mut op = parse(r#"[{"p":["a"],"oi":1},{"p":["b"],"oi":2}]"#);
+        op.compose(parse(r#"[{"p":["a"],"od":1}]"#)).unwrap();
+        assert_eq!(serde_json::json!([{"p":["b"],"oi":2}]), op.to_value());
+
+        let mut op = parse(r#"[{"p":["n"],"na":1},{"p":["l",0],"oi":2}]"#);
+        op.compose(parse(r#"[{"p":["n"],"na":2}]"#)).unwrap();
+        assert_eq!(
+            serde_json::json!([{"p":["n"],"na":3},{"p":["l",0],"oi":2}]),
+            op.to_value()
+        );
+
+        // list insert shifts the index of the following components
+        let mut op = parse(r#"[{"p":["l",1],"li":1},{"p":["l",0],"li":2}]"#);
+        op.compose(parse(r#"[{"p":["l",1],"ld":2}]"#)).unwrap();
+        assert_eq!(3, op.len());
+
+        // a component on the parent path can not be passed
+        let mut op = parse(r#"[{"p":["a","x"],"oi":1},{"p":["a"],"oi":{}}]"#);
+        op.compose(parse(r#"[{"p":["a","x"],"od":1}]"#)).unwrap();
+        assert_eq!(3, op.len());
+    }
+
+    #[test]
+    fn test_compose_text() {
+        let f = OperationFactory::default();
+        let parse = |s: &str| f.from_value(serde_json::from_str(s).unwrap()).unwrap();
+        let compose = |a: &str, b: &str| parse(a).composed(&parse(b)).unwrap().to_value();
+
+        assert_eq!(
+            serde_json::json!([{"p":["t"],"t":"text","o":{"p":1,"i":"ac"}}]),
+            compose(
+                r#"[{"p":["t"],"t":"text","o":{"p":1,"i":"abc"}}]"#,
+                r#"[{"p":["t"],"t":"text","o":{"p":2,"d":"b"}}]"#
+            )
+        );
+        let doc = serde_json::json!({"t": "xy"});
+        let op = parse(r#"[{"p":["t"],"t":"text","o":{"p":1,"i":"ab"}}]"#)
+            .composed(&parse(r#"[{"p":["t"],"t":"text","o":{"p":1,"d":"ab"}}]"#))
+            .unwrap();
+        assert_eq!(1, op.len());
+        assert_eq!(doc, op.applied_to(&doc).unwrap());
+
+        // deletes beyond the inserted text
+        let op = parse(r#"[{"p":["t"],"t":"text","o":{"p":1,"i":"ab"}}]"#)
+            .composed(&parse(r#"[{"p":["t"],"t":"text","o":{"p":2,"d":"by"}}]"#))
+            .unwrap();
+        assert_eq!(2, op.len());
+        assert_eq!(serde_json::json!({"t": "xa"}), op.applied_to(&doc).unwrap());
+    }
+
+    #[test]
+    fn test_operator_accessors() {
+        let f = OperationFactory::default();
+        let op = f
+            .from_value(serde_json::json!([
+                {"p":["a"],"oi":1,"od":2},
+                {"p":["l", 0],"ld":3},
+                {"p":["l", 0],"lm":2},
+                {"p":["a"],"om":"b"},
+                {"p":["b"],"na":1},
+                {"p":["b"],"test":2}
+            ]))
+            .unwrap();
+        let operators = op.iter().map(|c| &c.operator).collect_vec();
+
+        assert_eq!(
+            vec![
+                OperatorKind::ObjectReplace,
+                OperatorKind::ListDelete,
+                OperatorKind::ListMove,
+                OperatorKind::ObjectMove,
+                OperatorKind::SubType,
+                OperatorKind::Test
+            ],
+            operators.iter().map(|o| o.kind()).collect_vec()
+        );
+        assert_eq!(Some(&serde_json::json!(1)), operators[0].inserted_value());
+        assert_eq!(Some(&serde_json::json!(2)), operators[0].deleted_value());
+        assert_eq!(None, operators[1].inserted_value());
+        assert_eq!(Some(&serde_json::json!(3)), operators[1].deleted_value());
+        assert_eq!(Some(PathElement::Index(2)), operators[2].move_target());
+        assert_eq!(Some(PathElement::from("b")), operators[3].move_target());
+        assert_eq!(None, operators[4].move_target());
+        assert_eq!(
+            Some((&SubType::NumberAdd, &serde_json::json!(1))),
+            operators[4].subtype()
+        );
+        assert_eq!(None, operators[5].subtype());
+        assert_eq!(None, operators[5].inserted_value());
+        assert_eq!(OperatorKind::Noop, Operator::Noop().kind());
+    }
+
+    #[test]
+    fn test_split_max() {
+        let f = OperationFactory::default();
+        let op = f
+            .from_value(serde_json::json!({
+                "op": [
+                    {"p":["l", 0],"li":"aaaa"},
+                    {"p":["l", 1],"li":"bb"},
+                    {"p":["o"],"oi":{"k": "cccccccccc"}},
+                    {"p":["l", 0],"ld":"aaaa"},
+                    {"p":["n"],"na":1}
+                ],
+                "meta": {"author": "u1", "seq": 1}
+            }))
+            .unwrap();
+        let components = |chunks: &[Operation]| {
+            chunks
+                .iter()
+                .flat_map(|o| o.iter().cloned())
+                .collect::<Vec<_>>()
+        };
+
+        let chunks = op.split_max(SplitLimit::Components(2));
+        assert_eq!(vec![2, 2, 1], chunks.iter().map(|o| o.len()).collect_vec());
+        assert_eq!(op.components(), components(&chunks));
+        assert_eq!(op.meta(), chunks[0].meta());
+        assert!(chunks[1..].iter().all(|o| o.meta().is_none()));
+        assert_eq!(5, op.split_max(SplitLimit::Components(0)).len());
+
+        for max in [1, 40, 60, 100, 1000] {
+            let chunks = op.split_max(SplitLimit::Bytes(max));
+            assert_eq!(op.components(), components(&chunks));
+            for chunk in &chunks {
+                let len = chunk.to_value().to_string().len();
+                assert!(len <= max || chunk.len() == 1, "{len} > {max}");
+            }
+            let mut expected = serde_json::json!({"l": [], "n": 0});
+            let mut value = expected.clone();
+            crate::Json0::new()
+                .apply(&mut expected, vec![op.clone()])
+                .unwrap();
+            crate::Json0::new()
+                .apply(&mut value, vec![Operation::compose_all(chunks).unwrap()])
+                .unwrap();
+            assert_eq!(expected, value);
+        }
+        assert_eq!(1, op.split_max(SplitLimit::Bytes(1000)).len());
+        assert_eq!(5, op.split_max(SplitLimit::Bytes(1)).len());
+        assert_eq!(
+            vec![Operation::default()],
+            Operation::default().split_max(SplitLimit::Bytes(1))
+        );
+    }
+
+    #[test]
+    fn test_operation_stats() {
+        let f = OperationFactory::default();
+        let op = f
+            .from_value(serde_json::json!([
+                {"p":["a", "x"],"oi":"xy"},
+                {"p":["l", 0, "k"],"od":[1, 2]},
+                {"p":["l", 1],"li":true},
+                {"p":["m"],"om":"n"},
+                {"p":["c"],"na":10}
+            ]))
+            .unwrap();
+        let stats = op.stats();
+        assert_eq!(5, stats.total());
+        assert_eq!(1, stats.count(OperatorKind::ObjectInsert));
+        assert_eq!(0, stats.count(OperatorKind::ListDelete));
+        assert_eq!(3, stats.max_path_depth);
+        // "xy", [1,2], true, "n", 10
+        assert_eq!(4 + 5 + 4 + 3 + 2, stats.operand_bytes);
+        assert_eq!(
+            vec!["a", "c", "l", "m", "n"]
+                .into_iter()
+                .map(PathElement::from)
+                .collect::<BTreeSet<_>>(),
+            stats.top_level_keys
+        );
+        assert!(!stats.touches_root);
+
+        let stats = f
+            .from_value(serde_json::json!([{"p":[],"od":{}}]))
+            .unwrap()
+            .stats();
+        assert!(stats.touches_root);
+        assert!(stats.top_level_keys.is_empty());
+        assert_eq!(OperationStats::default(), Operation::default().stats());
+    }
+
+    #[test]
+    fn test_validate_operator_on_path() {
+        let f = OperationFactory::default();
+        for invalid in [
+            r#"[{"p":["a"],"lm":1}]"#,
+            r#"[{"p":["a"],"ld":1}]"#,
+            r#"[{"p":["a"],"ld":1,"li":2}]"#,
+            r#"[{"p":[],"ld":1}]"#,
+            r#"[{"p":["a",0],"od":1}]"#,
+            r#"[{"p":["a",0],"oi":1,"od":2}]"#,
+            r#"[{"p":["a",0],"om":"b"}]"#,
+        ] {
+            assert_matches!(
+                f.from_str(invalid).unwrap_err(),
+                JsonError::InvalidOperation(_),
+                "{}",
+                invalid
+            );
+        }
+        assert_matches!(
+            OperationComponent::new(path!["a"], Operator::ListMove(1)).unwrap_err(),
+            JsonError::InvalidOperation(_)
+        );
+
+        let op = f
+            .from_str(r#"[{"p":["a",0],"lm":1},{"p":["a",0,"b"],"na":1},{"p":["a",1],"test":1}]"#)
+            .unwrap();
+        assert_eq!(3, op.invert().unwrap().len());
+        // inserts are only checked on apply
+        f.from_str(r#"[{"p":["a"],"li":1},{"p":["l",0],"oi":2}]"#)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_push_validated() {
+        let f = OperationFactory::default();
+        let mut op = f
+            .from_value(serde_json::json!([{"p":["a"],"oi":1}]))
+            .unwrap();
+        let component = op[0].clone();
+        op.push_validated(component).unwrap();
+        assert_eq!(2, op.len());
+        assert_eq!(Some(&op[0]), op.get(1));
+        assert_eq!(None, op.get(2));
+
+        let err = op
+            .push_validated(OperationComponent {
+                path: Path::root(),
+                operator: Operator::ListInsert(Arc::new(serde_json::json!(1))),
+            })
+            .unwrap_err();
+        assert_matches!(err, JsonError::InvalidOperation(_));
+        assert_eq!(2, op.components().len());
+    }
+
+    #[test]
+    fn test_restrict_and_prefix_with() {
+        let f = OperationFactory::default();
+        let op = f
+            .from_value(serde_json::json!([
+                {"p":["w", "a"],"oi":1},
+                {"p":["v"],"oi":2},
+                {"p":["w"],"oi":{"b":3},"od":{"b":4, "c":5}},
+                {"p":["w", "n"],"na":1},
+                {"p":["l", 0],"li":6},
+                {"p":["l", 1, "x"],"oi":7},
+                {"p":["l", 1],"li":8, "ld":{"x":7}}
+            ]))
+            .unwrap();
+
+        let w = op.restrict(&Path::try_from(r#"["w"]"#).unwrap());
+        assert_eq!(
+            serde_json::json!([
+                {"p":["a"],"oi":1},
+                {"p":[],"oi":{"b":3},"od":{"b":4, "c":5}},
+                {"p":["n"],"na":1}
+            ]),
+            w.to_value()
+        );
+        let c = op.restrict(&Path::try_from(r#"["w", "c"]"#).unwrap());
+        assert_eq!(serde_json::json!([{"p":[],"od":5}]), c.to_value());
+
+        let l = op.restrict(&Path::try_from(r#"["l", 1]"#).unwrap());
+        assert_eq!(
+            serde_json::json!([{"p":["x"],"oi":7},{"p":[],"oi":8,"od":{"x":7}}]),
+            l.to_value()
+        );
+        // inserting or deleting the list element at prefix inserts or deletes
+        // the value at prefix
+        let l0 = op.restrict(&Path::try_from(r#"["l", 0]"#).unwrap());
+        assert_eq!(serde_json::json!([{"p":[],"oi":6}]), l0.to_value());
+        let ld = f
+            .from_value(serde_json::json!([{"p":["l", 0],"ld":6},{"p":["l", 0],"lm":1}]))
+            .unwrap();
+        let restricted = ld.restrict(&Path::try_from(r#"["l", 0]"#).unwrap());
+        assert_eq!(serde_json::json!([{"p":[],"od":6}]), restricted.to_value());
+        assert_eq!(
+            serde_json::json!([{"p":["l", 0],"ld":6}]),
+            restricted
+                .prefix_with(&Path::try_from(r#"["l", 0]"#).unwrap())
+                .to_value()
+        );
+        assert_eq!(
+            serde_json::json!([{"p":["l", 1, "x"],"oi":7},{"p":["l", 1],"li":8,"ld":{"x":7}}]),
+            l.prefix_with(&Path::try_from(r#"["l", 1]"#).unwrap())
+                .to_value()
+        );
+        assert!(op.restrict(&Path::try_from(r#"["u"]"#).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_affects_and_affected_paths() {
+        let f = OperationFactory::default();
+        let op = f
+            .from_value(serde_json::json!([
+                {"p":["o", "a", "x"],"oi":1},
+                {"p":["l", 2],"li":2},
+                {"p":["m", 1],"lm":3},
+                {"p":["o", "k"],"om":"j"},
+                {"p":["o", "a"],"t":"na", "o":1},
+                {"p":["t"],"test":1}
+            ]))
+            .unwrap();
+        let affects = |p: &str| op.affects(&Path::try_from(p).unwrap());
+
+        assert!(affects(r#"["o"]"#));
+        assert!(affects(r#"["o", "a", "x", "y"]"#));
+        assert!(!affects(r#"["o", "b"]"#));
+        assert!(affects(r#"["o", "j"]"#));
+        assert!(affects(r#"["l", 3, "z"]"#));
+        assert!(!affects(r#"["l", 1]"#));
+        assert!(affects(r#"["m", 2]"#));
+        assert!(!affects(r#"["m", 4]"#));
+        assert!(!affects(r#"["t"]"#));
+
+        assert_eq!(
+            vec![
+                Path::try_from(r#"["l"]"#).unwrap(),
+                Path::try_from(r#"["m"]"#).unwrap(),
+                Path::try_from(r#"["o"]"#).unwrap(),
+            ],
+            op.affected_paths()
+        );
+    }
+
+    #[test]
+    fn test_text_operation_from_diff() {
+        let f = OperationFactory::default();
+        let op = f
+            .text_operation_from_diff(r#"["s"]"#, "hello world", "hello brave new world")
+            .unwrap();
+        assert_eq!(
+            serde_json::json!([{"p":["s"],"t":"text","o":{"p":6,"i":"brave new "}}]),
+            op.to_value()
+        );
+
+        let op = f
+            .text_operation_from_diff(r#"["s"]"#, "abc", "xbcé")
+            .unwrap();
+        assert_eq!(
+            serde_json::json!([
+                {"p":["s"],"t":"text","o":{"p":0,"d":"a"}},
+                {"p":["s"],"t":"text","o":{"p":0,"i":"x"}},
+                {"p":["s"],"t":"text","o":{"p":3,"i":"é"}}
+            ]),
+            op.to_value()
+        );
+        let mut value = serde_json::json!({"s":"abc"});
+        for c in op.into_iter() {
+            value.apply(c.path, c.operator).unwrap();
+        }
+        assert_eq!(serde_json::json!({"s":"xbcé"}), value);
+
+        assert!(f
+            .text_operation_from_diff(r#"["s"]"#, "same", "same")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_map_position() {
+        let f = OperationFactory::default();
+        let map = |op: serde_json::Value, path: &str, offset: usize| {
+            f.from_value(op)
+                .unwrap()
+                .map_position(&Path::try_from(path).unwrap(), offset)
+                .map(|(p, o)| (p.to_string(), o))
+        };
+        let p =
+            |path: &str, offset: usize| Some((Path::try_from(path).unwrap().to_string(), offset));
+
+        // list index
+        let op =
+            serde_json::json!([{"p":["l",1],"li":0},{"p":["l",4],"ld":0},{"p":["l",0],"li":0}]);
+        assert_eq!(p(r#"["l"]"#, 4), map(op, r#"["l"]"#, 2));
+        let op = serde_json::json!([{"p":["l",0],"lm":3}]);
+        assert_eq!(p(r#"["l"]"#, 1), map(op.clone(), r#"["l"]"#, 2));
+        assert_eq!(p(r#"["l"]"#, 5), map(op, r#"["l"]"#, 5));
+
+        // text offset
+        let op = serde_json::json!([
+            {"p":["t"],"t":"text","o":{"p":0,"i":"ab"}},
+            {"p":["t"],"t":"text","o":{"p":3,"d":"xyz"}},
+            {"p":["t"],"t":"text","o":{"p":9,"i":"c"}}
+        ]);
+        assert_eq!(p(r#"["t"]"#, 3), map(op, r#"["t"]"#, 3));
+        let op = serde_json::json!([
+            {"p":["t"],"t":"text","o":{"p":1,"d":2}},
+            {"p":["t"],"t":"text","o":{"p":0,"d":"x"}}
+        ]);
+        assert_eq!(p(r#"["t"]"#, 0), map(op.clone(), r#"["t"]"#, 2));
+        assert_eq!(p(r#"["t"]"#, 2), map(op.clone(), r#"["t"]"#, 5));
+        assert_eq!(p(r#"["t"]"#, 0), map(op, r#"["t"]"#, 1));
+
+        // text delta
+        let op = serde_json::json!([{"p":["t"],"t":"text-delta","o":[1,{"d":"bc"},"xy"]}]);
+        assert_eq!(p(r#"["t"]"#, 0), map(op.clone(), r#"["t"]"#, 0));
+        assert_eq!(p(r#"["t"]"#, 3), map(op.clone(), r#"["t"]"#, 2));
+        assert_eq!(p(r#"["t"]"#, 4), map(op, r#"["t"]"#, 4));
+
+        // containers on path are moved
+        let op = serde_json::json!([
+            {"p":["k","a",0],"li":{}},
+            {"p":["k","a",3],"lm":0},
+            {"p":["k"],"om":"j"}
+        ]);
+        assert_eq!(p(r#"["j","a",3,"t"]"#, 1), map(op, r#"["k","a",1,"t"]"#, 1));
+        let op = serde_json::json!([{"p":["j","a",1],"ld":{}}]);
+        assert_eq!(None, map(op, r#"["j","a",1,"t"]"#, 1));
+        let op = serde_json::json!([{"p":["j"],"oi":{}, "od":{}}]);
+        assert_eq!(None, map(op, r#"["j","a",1,"t"]"#, 1));
+        let op = serde_json::json!([{"p":["j","a",1,"t"],"od":"x"}]);
+        assert_eq!(None, map(op, r#"["j","a",1,"t"]"#, 1));
+    }
+
+    #[test]
+    fn test_shatter_and_gather() {
+        let f = OperationFactory::default();
+        let op = f
+            .from_value(serde_json::json!({
+                "op": [{"p":["n"],"na":1},{"p":["n"],"na":2},{"p":["l",0],"li":1}],
+                "meta": {"id": "1"}
+            }))
+            .unwrap();
+
+        let ops = op.shatter();
+        assert_eq!(3, ops.len());
+        assert!(ops
+            .iter()
+            .all(|o| o.len() == 1 && o.meta().unwrap().id.as_deref() == Some("1")));
+        // components on the same path are kept apart
+        assert_eq!(op, Operation::gather(ops));
+        assert!(Operation::gather(vec![]).is_empty());
+    }
+
+    #[test]
+    fn test_display_is_wire_form() {
+        let factory = OperationFactory::default();
+        let wire = serde_json::json!([
+            {"p":["a",0],"li":{"k":"v \" ,"},"ld":[1]},
+            {"p":["a",1],"lm":0},
+            {"p":["o"],"oi":null,"od":true},
+            {"p":["o"],"om":"x"},
+            {"p":["n"],"na":-1.5},
+            {"p":["s"],"t":"text","o":{"p":0,"i":"x"}},
+            {"p":[],"test":{}}
+        ]);
+        let op = factory.from_value(wire.clone()).unwrap();
+        let s = op.to_string();
+        assert_eq!(wire, serde_json::from_str::<Value>(&s).unwrap());
+        assert_eq!(
+            op,
+            factory
+                .from_value(serde_json::from_str(&s).unwrap())
+                .unwrap()
+        );
+        assert_eq!(r#"{"p":["a",1],"lm":0}"#, op[1].to_string());
+        assert_eq!(r#"{"om":"x"}"#, op[3].operator.to_string());
+
+        let op = op.with_meta(OperationMeta::new().author("u1"));
+        assert_eq!(
+            op,
+            factory
+                .from_value(serde_json::from_str(&op.to_string()).unwrap())
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_to_canonical_string() {
+        let f = OperationFactory::default();
+        let a = f
+            .from_value(serde_json::json!([{"p":["a"],"oi":{"y":1,"x":[{"d":1,"c":2}]}}]))
+            .unwrap();
+        let b = f
+            .from_value(serde_json::json!([{"oi":{"x":[{"c":2,"d":1}],"y":1},"p":["a"]}]))
+            .unwrap();
+        assert_eq!(
+            r#"[{"oi":{"x":[{"c":2,"d":1}],"y":1},"p":["a"]}]"#,
+            a.to_canonical_string()
+        );
+        assert_eq!(a.to_canonical_string(), b.to_canonical_string());
+    }
+
+    #[test]
+    fn test_operands_are_shared() {
+        let f = OperationFactory::default();
+        let op = f
+            .from_value(serde_json::json!([{"p":["a"],"oi":{"large":[1,2,3]}}]))
+            .unwrap();
+        let operand = |c: &OperationComponent| match &c.operator {
+            Operator::ObjectInsert(v) | Operator::ObjectDelete(v) => v.clone(),
+            o => panic!("unexpected operator {o}"),
+        };
+
+        assert!(Arc::ptr_eq(&operand(&op[0]), &operand(&op.clone()[0])));
+        assert!(Arc::ptr_eq(
+            &operand(&op[0]),
+            &operand(&op[0].invert().unwrap())
+        ));
+
+        // canonicalizing copies the shared operand before changing it
+        let op = f
+            .from_value(serde_json::json!([{"p":["a"],"oi":{"n":1.0}}]))
+            .unwrap();
+        let canonical = op.canonicalize();
+        assert!(!Arc::ptr_eq(&operand(&op[0]), &operand(&canonical[0])));
+        assert_eq!(&serde_json::json!({"n":1.0}), operand(&op[0]).as_ref());
+    }
 }
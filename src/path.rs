@@ -1,6 +1,19 @@
-use std::{cmp::Ordering, fmt::Display};
+use std::{
+    cmp::Ordering,
+    convert::Infallible,
+    fmt::Display,
+    ops::Index,
+    sync::{
+        atomic::{AtomicUsize, Ordering as AtomicOrdering},
+        Arc, OnceLock,
+    },
+};
 
+use dashmap::DashSet;
+use itertools::{Itertools, MinMaxResult};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
+use smallvec::SmallVec;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -10,40 +23,115 @@ pub enum PathError {
     EmptyPath,
     #[error("Invalid path format, reason: \"{reason}\"")]
     ParsePathFromJsonFailed { reason: String },
-    #[error("Index path type should be a non-negative integer number, but is: {0}")]
+    #[error("Index path type should be an integer number, but is: {0}")]
     InvalidIndexPath(String),
+    #[error(
+        "Index on position: {position} of path: {path} is out of the range of usize after shifting"
+    )]
+    IndexOutOfRange { path: String, position: usize },
+    #[error("Path depth: {actual} exceeds the max path depth: {max}")]
+    TooDeep { max: usize, actual: usize },
 }
 
 pub type Result<T> = std::result::Result<T, PathError>;
 
-#[derive(Debug, Clone, PartialEq)]
+impl From<Infallible> for PathError {
+    fn from(e: Infallible) -> Self {
+        match e {}
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum PathElement {
     Index(usize),
-    Key(String),
+    /// Position counted from the end of a list, `End(0)` appends to the list.
+    /// Written as negative number `-(k + 1)` in JSON, so `-1` means append.
+    /// Only allowed as the last path element of list insert. Transform puts
+    /// indexes before every position from the end, so concurrent appends
+    /// converge unless an operation then addresses its appended values by
+    /// index, which can't be ordered without the length of the list.
+    End(usize),
+    /// Object key. Cloning it only copies a pointer, see `intern_keys` to
+    /// share the key among paths created separately.
+    Key(Arc<str>),
 }
 
-impl PartialOrd for PathElement {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+fn interned_keys() -> &'static DashSet<Arc<str>> {
+    static KEYS: OnceLock<DashSet<Arc<str>>> = OnceLock::new();
+    KEYS.get_or_init(DashSet::new)
+}
+
+/// Intern keys known ahead, like the property names of a schema. Key path
+/// elements created afterwards for these keys, including the ones parsed from
+/// operations, share one allocation for each key instead of allocating their
+/// own. Keys not interned are allocated for each path they are parsed into,
+/// so keys of unbounded variety don't grow the interned set.
+pub fn intern_keys<I, S>(keys: I)
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let interned = interned_keys();
+    for key in keys {
+        if !interned.contains(key.as_ref()) {
+            interned.insert(Arc::from(key.as_ref()));
+        }
+    }
+}
+
+static MAX_PATH_DEPTH: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Set the max number of elements of paths parsed from JSON, by
+/// `Path::try_from` and the operations parsed with it, to protect against
+/// oversized input from untrusted clients. No limit is set by default.
+pub fn set_max_path_depth(max: usize) {
+    MAX_PATH_DEPTH.store(max, AtomicOrdering::Relaxed);
+}
+
+pub fn max_path_depth() -> usize {
+    MAX_PATH_DEPTH.load(AtomicOrdering::Relaxed)
+}
+
+impl PathElement {
+    /// Key path element reusing the interned key if there's one.
+    pub fn key<S: AsRef<str>>(key: S) -> PathElement {
+        let key = key.as_ref();
+        PathElement::Key(
+            interned_keys()
+                .get(key)
+                .map(|k| k.key().clone())
+                .unwrap_or_else(|| Arc::from(key)),
+        )
+    }
+
+    fn rank(&self) -> u8 {
         match self {
-            // only index can compare
-            PathElement::Index(a) => match other {
-                PathElement::Index(b) => a.partial_cmp(b),
-                PathElement::Key(_) => None,
-            },
-            PathElement::Key(a) => match other {
-                PathElement::Index(_) => None,
-                PathElement::Key(b) => {
-                    if a == b {
-                        Some(Ordering::Equal)
-                    } else {
-                        None
-                    }
-                }
-            },
+            PathElement::Index(_) => 0,
+            PathElement::End(_) => 1,
+            PathElement::Key(_) => 2,
+        }
+    }
+}
+
+impl Ord for PathElement {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (PathElement::Index(a), PathElement::Index(b)) => a.cmp(b),
+            (PathElement::Key(a), PathElement::Key(b)) => a.cmp(b),
+            // the further from the end, the closer to the front
+            (PathElement::End(a), PathElement::End(b)) => b.cmp(a),
+            // index path always sorts before end path, then key path
+            _ => self.rank().cmp(&other.rank()),
         }
     }
 }
 
+impl PartialOrd for PathElement {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl From<usize> for PathElement {
     fn from(i: usize) -> Self {
         PathElement::Index(i)
@@ -52,7 +140,13 @@ impl From<usize> for PathElement {
 
 impl From<String> for PathElement {
     fn from(k: String) -> Self {
-        PathElement::Key(k)
+        PathElement::key(k)
+    }
+}
+
+impl From<&str> for PathElement {
+    fn from(k: &str) -> Self {
+        PathElement::key(k)
     }
 }
 
@@ -60,18 +154,64 @@ impl Display for PathElement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             PathElement::Index(i) => f.write_fmt(format_args!("{}", i)),
+            PathElement::End(k) => f.write_fmt(format_args!("-{}", k + 1)),
             PathElement::Key(k) => f.write_fmt(format_args!("\"{}\"", k)),
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Paths shorter than this are stored inline without allocation.
+const INLINE_PATH_LEN: usize = 4;
+
+/// Paths are ordered lexicographically by their elements, so a path sorts
+/// before all the paths it is a prefix of and those paths are contiguous.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Path {
-    paths: Vec<PathElement>,
+    paths: SmallVec<[PathElement; INLINE_PATH_LEN]>,
 }
 
 impl Path {
-    pub fn first_key_path(&self) -> Option<&String> {
+    /// The empty path pointing at the whole document.
+    pub fn root() -> Path {
+        Path {
+            paths: SmallVec::new(),
+        }
+    }
+
+    /// Borrowed view of all the elements of this path.
+    pub fn as_slice(&self) -> PathSlice<'_> {
+        PathSlice {
+            elements: &self.paths,
+        }
+    }
+
+    /// Borrowed view of the first len elements of this path.
+    pub fn prefix(&self, len: usize) -> PathSlice<'_> {
+        PathSlice {
+            elements: &self.paths[..len],
+        }
+    }
+
+    /// Borrowed view of the elements of this path from index start.
+    pub fn suffix(&self, start: usize) -> PathSlice<'_> {
+        PathSlice {
+            elements: &self.paths[start..],
+        }
+    }
+
+    pub fn push(&mut self, path_elem: PathElement) {
+        self.paths.push(path_elem);
+    }
+
+    pub fn pop(&mut self) -> Option<PathElement> {
+        self.paths.pop()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn first_key_path(&self) -> Option<&str> {
         self.get_key_at(0)
     }
 
@@ -83,20 +223,20 @@ impl Path {
         self.paths.get(index)
     }
 
-    pub fn get_elements(&self) -> &Vec<PathElement> {
+    pub fn get_elements(&self) -> &[PathElement] {
         &self.paths
     }
 
-    pub fn get_mut_elements(&mut self) -> &mut Vec<PathElement> {
+    pub fn get_mut_elements(&mut self) -> &mut [PathElement] {
         &mut self.paths
     }
 
-    pub fn get_key_at(&self, index: usize) -> Option<&String> {
+    pub fn get_key_at(&self, index: usize) -> Option<&str> {
         let first_path = self.paths.get(index)?;
 
         match first_path {
-            PathElement::Index(_) => None,
             PathElement::Key(k) => Some(k),
+            _ => None,
         }
     }
 
@@ -105,12 +245,12 @@ impl Path {
 
         match first_path {
             PathElement::Index(i) => Some(i),
-            PathElement::Key(_) => None,
+            _ => None,
         }
     }
 
     pub fn last(&self) -> Option<&PathElement> {
-        self.get(self.len() - 1)
+        self.paths.last()
     }
 
     pub fn replace(&mut self, index: usize, path_elem: PathElement) -> Option<PathElement> {
@@ -121,60 +261,86 @@ impl Path {
         None
     }
 
+    /// Increase the index element on position index by one. Returns false if
+    /// it's not an index. Saturates at usize::MAX.
     pub fn increase_index(&mut self, index: usize) -> bool {
-        if let Some(PathElement::Index(i)) = self.paths.get(index) {
-            self.replace(index, PathElement::Index(i + 1));
-            return true;
-        }
-        false
+        self.shift_index(index, |i| Some(i.saturating_add(1)))
+            .unwrap_or(true)
     }
 
+    /// Decrease the index element on position index by one. Returns false if
+    /// it's not an index. Saturates at 0.
     pub fn decrease_index(&mut self, index: usize) -> bool {
-        if let Some(PathElement::Index(i)) = self.paths.get(index) {
-            self.replace(index, PathElement::Index(i - 1));
-            return true;
-        }
-        false
+        self.shift_index(index, |i| Some(i.saturating_sub(1)))
+            .unwrap_or(true)
+    }
+
+    /// Like `increase_index` but fails instead of overflowing.
+    pub fn try_increase_index(&mut self, index: usize) -> Result<bool> {
+        self.shift_index(index, |i| i.checked_add(1))
+    }
+
+    /// Like `decrease_index` but fails if the index is 0.
+    pub fn try_decrease_index(&mut self, index: usize) -> Result<bool> {
+        self.shift_index(index, |i| i.checked_sub(1))
+    }
+
+    fn shift_index<F: Fn(usize) -> Option<usize>>(&mut self, index: usize, f: F) -> Result<bool> {
+        let Some(PathElement::Index(i)) = self.paths.get(index) else {
+            return Ok(false);
+        };
+        let Some(shifted) = f(*i) else {
+            return Err(PathError::IndexOutOfRange {
+                path: self.to_string(),
+                position: index,
+            });
+        };
+        self.replace(index, PathElement::Index(shifted));
+        Ok(true)
     }
 
     pub fn split_at(&self, mid: usize) -> (Path, Path) {
-        let (left, right) = self.paths.split_at(mid);
-        (
-            Path {
-                paths: left.to_vec(),
-            },
-            Path {
-                paths: right.to_vec(),
-            },
-        )
+        (self.prefix(mid).to_path(), self.suffix(mid).to_path())
     }
 
     pub fn max_common_path(&self, path: &Path) -> Path {
-        let mut common_p = vec![];
-        for (i, pa) in path.get_elements().iter().enumerate() {
-            if let Some(pb) = self.get(i) {
-                if pa.eq(pb) {
-                    common_p.push(pb.clone());
-                    continue;
-                }
-            }
-            break;
-        }
-        Path { paths: common_p }
+        self.prefix(self.common_prefix_len(path)).to_path()
     }
 
     pub fn common_path_prefix(&self, path: &Path) -> Path {
-        let mut common_p = vec![];
-        for (i, pa) in path.get_elements().iter().enumerate() {
-            if let Some(pb) = path.get(i) {
-                if pa.eq(pb) {
-                    common_p.push(pb.clone());
-                    continue;
-                }
+        self.max_common_path(path)
+    }
+
+    /// Longest common prefix of all the input paths. As paths are sorted
+    /// lexicographically, only the smallest and the largest one need to be
+    /// compared.
+    pub fn common_prefix_of<'a, I: IntoIterator<Item = &'a Path>>(paths: I) -> Path {
+        match paths.into_iter().minmax() {
+            MinMaxResult::NoElements => Path::root(),
+            MinMaxResult::OneElement(p) => p.clone(),
+            MinMaxResult::MinMax(min, max) => min.max_common_path(max),
+        }
+    }
+
+    /// Sorted and deduplicated input paths which are not descendants of other
+    /// input paths.
+    pub fn outermost_of<I: IntoIterator<Item = Path>>(paths: I) -> Vec<Path> {
+        let mut out: Vec<Path> = vec![];
+        for path in paths.into_iter().sorted() {
+            if !out.last().map(|p| p.is_prefix_of(&path)).unwrap_or(false) {
+                out.push(path);
             }
-            break;
         }
-        Path { paths: common_p }
+        out
+    }
+
+    /// Length of the longest common prefix of self and path.
+    pub fn common_prefix_len(&self, path: &Path) -> usize {
+        self.paths
+            .iter()
+            .zip(path.paths.iter())
+            .take_while(|(a, b)| a == b)
+            .count()
     }
 
     pub fn is_empty(&self) -> bool {
@@ -182,16 +348,39 @@ impl Path {
     }
 
     pub fn is_prefix_of(&self, path: &Path) -> bool {
-        for (i, p) in self.paths.iter().enumerate() {
-            if let Some(p2) = path.paths.get(i) {
-                if p != p2 {
-                    return false;
-                }
-            } else {
-                return false;
-            }
-        }
-        true
+        path.paths.starts_with(&self.paths)
+    }
+
+    pub fn starts_with<'p, P: Into<PathSlice<'p>>>(&self, prefix: P) -> bool {
+        self.paths.starts_with(prefix.into().elements)
+    }
+
+    /// This path followed by all elements of other.
+    pub fn join<'p, P: Into<PathSlice<'p>>>(&self, other: P) -> Path {
+        let mut path = self.clone();
+        path.extend(other.into());
+        path
+    }
+
+    /// This path without prefix, or None if it doesn't start with prefix.
+    pub fn strip_prefix<'p, P: Into<PathSlice<'p>>>(&self, prefix: P) -> Option<Path> {
+        self.relative_to(prefix).map(|p| p.to_path())
+    }
+
+    /// Borrowed view of this path relative to base, or None if base is not a
+    /// prefix of it.
+    pub fn relative_to<'p, P: Into<PathSlice<'p>>>(&self, base: P) -> Option<PathSlice<'_>> {
+        let base = base.into();
+        self.starts_with(base).then(|| self.suffix(base.len()))
+    }
+
+    /// Borrowed view of this path without the last element, or None for root.
+    pub fn parent(&self) -> Option<PathSlice<'_>> {
+        (!self.is_root()).then(|| self.prefix(self.len() - 1))
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, PathElement> {
+        self.paths.iter()
     }
 
     pub fn len(&self) -> usize {
@@ -199,9 +388,20 @@ impl Path {
     }
 
     pub fn next_level(&self) -> Path {
-        Path {
-            paths: self.paths[1..].to_vec(),
-        }
+        self.suffix(1).to_path()
+    }
+
+    pub fn to_value(&self) -> Value {
+        Value::Array(
+            self.paths
+                .iter()
+                .map(|p| match p {
+                    PathElement::Index(i) => Value::from(*i),
+                    PathElement::End(k) => Value::from(-(*k as i64) - 1),
+                    PathElement::Key(k) => Value::String(k.to_string()),
+                })
+                .collect(),
+        )
     }
 }
 
@@ -219,6 +419,184 @@ impl Display for Path {
     }
 }
 
+impl From<Vec<PathElement>> for Path {
+    fn from(paths: Vec<PathElement>) -> Self {
+        Path {
+            paths: SmallVec::from_vec(paths),
+        }
+    }
+}
+
+impl FromIterator<PathElement> for Path {
+    fn from_iter<I: IntoIterator<Item = PathElement>>(iter: I) -> Self {
+        Path {
+            paths: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Extend<PathElement> for Path {
+    fn extend<I: IntoIterator<Item = PathElement>>(&mut self, iter: I) {
+        self.paths.extend(iter);
+    }
+}
+
+impl<'a> Extend<&'a PathElement> for Path {
+    fn extend<I: IntoIterator<Item = &'a PathElement>>(&mut self, iter: I) {
+        self.paths.extend(iter.into_iter().cloned());
+    }
+}
+
+impl Index<usize> for Path {
+    type Output = PathElement;
+
+    fn index(&self, index: usize) -> &PathElement {
+        &self.paths[index]
+    }
+}
+
+impl IntoIterator for Path {
+    type Item = PathElement;
+    type IntoIter = smallvec::IntoIter<[PathElement; INLINE_PATH_LEN]>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.paths.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Path {
+    type Item = &'a PathElement;
+    type IntoIter = std::slice::Iter<'a, PathElement>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.paths.iter()
+    }
+}
+
+impl<'a> From<&'a Path> for PathSlice<'a> {
+    fn from(path: &'a Path) -> Self {
+        path.as_slice()
+    }
+}
+
+/// A borrowed view of consecutive elements of a `Path`, like its prefix or
+/// suffix, which is cheap to create and copy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PathSlice<'a> {
+    elements: &'a [PathElement],
+}
+
+impl<'a> PathSlice<'a> {
+    pub fn is_root(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&'a PathElement> {
+        self.elements.get(index)
+    }
+
+    pub fn first(&self) -> Option<&'a PathElement> {
+        self.elements.first()
+    }
+
+    pub fn last(&self) -> Option<&'a PathElement> {
+        self.elements.last()
+    }
+
+    pub fn get_elements(&self) -> &'a [PathElement] {
+        self.elements
+    }
+
+    /// The view without the first element.
+    pub fn next_level(&self) -> PathSlice<'a> {
+        PathSlice {
+            elements: self.elements.get(1..).unwrap_or_default(),
+        }
+    }
+
+    pub fn split_at(&self, mid: usize) -> (PathSlice<'a>, PathSlice<'a>) {
+        let (left, right) = self.elements.split_at(mid);
+        (PathSlice { elements: left }, PathSlice { elements: right })
+    }
+
+    pub fn is_prefix_of<'b, P: Into<PathSlice<'b>>>(&self, path: P) -> bool {
+        path.into().elements.starts_with(self.elements)
+    }
+
+    pub fn to_path(&self) -> Path {
+        Path {
+            paths: SmallVec::from(self.elements),
+        }
+    }
+}
+
+impl Index<usize> for PathSlice<'_> {
+    type Output = PathElement;
+
+    fn index(&self, index: usize) -> &PathElement {
+        &self.elements[index]
+    }
+}
+
+impl<'a> IntoIterator for PathSlice<'a> {
+    type Item = &'a PathElement;
+    type IntoIter = std::slice::Iter<'a, PathElement>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.elements.iter()
+    }
+}
+
+impl PartialEq<Path> for PathSlice<'_> {
+    fn eq(&self, other: &Path) -> bool {
+        self.elements == other.get_elements()
+    }
+}
+
+impl Display for PathSlice<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.to_path(), f)
+    }
+}
+
+macro_rules! impl_path_from_tuple {
+    ($($elem:ident),+) => {
+        impl<$($elem: Into<PathElement>),+> From<($($elem,)+)> for Path {
+            #[allow(non_snake_case)]
+            fn from(($($elem,)+): ($($elem,)+)) -> Self {
+                Path {
+                    paths: smallvec::smallvec![$($elem.into()),+],
+                }
+            }
+        }
+    };
+}
+
+impl_path_from_tuple!(A);
+impl_path_from_tuple!(A, B);
+impl_path_from_tuple!(A, B, C);
+impl_path_from_tuple!(A, B, C, D);
+impl_path_from_tuple!(A, B, C, D, E);
+impl_path_from_tuple!(A, B, C, D, E, F);
+impl_path_from_tuple!(A, B, C, D, E, F, G);
+impl_path_from_tuple!(A, B, C, D, E, F, G, H);
+
+impl TryFrom<String> for Path {
+    type Error = PathError;
+
+    fn try_from(input: String) -> std::result::Result<Self, Self::Error> {
+        Path::try_from(input.as_str())
+    }
+}
+
 impl TryFrom<&str> for Path {
     type Error = PathError;
 
@@ -238,12 +616,15 @@ impl TryFrom<&Value> for Path {
     fn try_from(value: &Value) -> std::result::Result<Self, Self::Error> {
         match value {
             Value::Array(arr) => {
+                let max = max_path_depth();
+                if arr.len() > max {
+                    return Err(PathError::TooDeep {
+                        max,
+                        actual: arr.len(),
+                    });
+                }
                 if arr.is_empty() {
-                    Err(PathError::ParsePathFromJsonFailed {
-                        reason: format!(
-                            "json value: {value} is a empty array, we do not allow empty path"
-                        ),
-                    })
+                    Ok(Path::root())
                 } else {
                     let paths = arr
                         .iter()
@@ -251,18 +632,20 @@ impl TryFrom<&Value> for Path {
                             Value::Number(n) => {
                                 if let Some(i) = n.as_u64() {
                                     Ok(PathElement::Index(i as usize))
+                                } else if let Some(i) = n.as_i64() {
+                                    Ok(PathElement::End((-(i + 1)) as usize))
                                 } else {
                                     Err(PathError::InvalidIndexPath(pe.to_string()))
                                 }
                             }
-                            Value::String(k) => Ok(PathElement::Key(k.to_string())),
+                            Value::String(k) => Ok(PathElement::key(k)),
                             _ => Err(PathError::ParsePathFromJsonFailed {
                                 reason: format!(
                                     "{pe} is not a non-negative integer number or string",
                                 ),
                             }),
                         })
-                        .collect::<Result<Vec<PathElement>>>()?;
+                        .collect::<Result<SmallVec<[PathElement; INLINE_PATH_LEN]>>>()?;
                     Ok(Path { paths })
                 }
             }
@@ -273,9 +656,24 @@ impl TryFrom<&Value> for Path {
     }
 }
 
+/// Serialized in the JSON form of `to_value`.
+impl Serialize for Path {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.to_value().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Path {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        Path::try_from(&value).map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(Default)]
 pub struct PathBuilder {
     elements: Vec<PathElement>,
+    error: Option<PathError>,
 }
 
 impl PathBuilder {
@@ -285,7 +683,7 @@ impl PathBuilder {
     }
 
     pub fn add_key_path<S: AsRef<str>>(mut self, key: S) -> Self {
-        self = self.add_path(PathElement::Key(key.as_ref().into()));
+        self = self.add_path(PathElement::key(key));
         self
     }
 
@@ -301,13 +699,30 @@ impl PathBuilder {
         self
     }
 
+    /// Append all elements of anything convertible to a Path, such as
+    /// `"[\"a\", 0]"` or `("a", 0)`. A conversion error is reported by `build`.
+    pub fn append_path<P>(mut self, path: P) -> Self
+    where
+        P: TryInto<Path>,
+        P::Error: Into<PathError>,
+    {
+        match path.try_into() {
+            Ok(p) => self.elements.extend(p.paths),
+            Err(e) => {
+                self.error.get_or_insert(e.into());
+            }
+        }
+        self
+    }
+
     pub fn build(self) -> Result<Path> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
         if self.elements.is_empty() {
             return Err(PathError::EmptyPath);
         }
-        Ok(Path {
-            paths: self.elements,
-        })
+        Ok(self.elements.into())
     }
 }
 
@@ -320,7 +735,7 @@ pub trait AppendPath: Sized {
     }
 
     fn append_key_path<S: AsRef<str>>(mut self, key: S) -> Self {
-        self = self.append_path_element(PathElement::Key(key.as_ref().into()));
+        self = self.append_path_element(PathElement::key(key));
         self
     }
 
@@ -339,9 +754,204 @@ impl AppendPath for PathBuilder {
     }
 }
 
+/// One segment of a `PathPattern`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PatternSegment {
+    /// Matches exactly this path element.
+    Element(PathElement),
+    /// `*`, matches any one path element.
+    Any,
+    /// `**`, matches any number of path elements, including none.
+    AnyDepth,
+}
+
+/// A pattern matching a family of paths, like `["users", "*", "email"]` for
+/// the email of every user, or `["settings", "**"]` for settings and
+/// everything under it. Written as a JSON array where `"*"` and `"**"` are
+/// wildcards, so a pattern can't match keys `*` and `**` literally.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PathPattern {
+    segments: Vec<PatternSegment>,
+}
+
+impl PathPattern {
+    pub fn new(segments: Vec<PatternSegment>) -> PathPattern {
+        PathPattern { segments }
+    }
+
+    pub fn segments(&self) -> &[PatternSegment] {
+        &self.segments
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        self.is_accepting(&self.advance(self.start(), path.as_slice()))
+    }
+
+    /// Whether it matches path or any ancestor of path.
+    pub fn matches_ancestor_or_self(&self, path: &Path) -> bool {
+        let mut states = self.start();
+        for elem in path {
+            if self.is_accepting(&states) {
+                return true;
+            }
+            states = self.step(&states, elem);
+        }
+        self.is_accepting(&states)
+    }
+
+    /// Whether it matches path or could match a descendant of path.
+    pub fn may_match_self_or_descendant(&self, path: &Path) -> bool {
+        !self.advance(self.start(), path.as_slice()).is_empty()
+    }
+
+    /// Paths matched by the pattern under base in value, including base, where
+    /// value is the value on base.
+    pub fn matches_in(&self, value: &Value, base: &Path) -> Vec<Path> {
+        let mut matched = vec![];
+        let states = self.advance(self.start(), base.as_slice());
+        self.collect_matches(value, &mut base.clone(), &states, &mut matched);
+        matched
+    }
+
+    fn collect_matches(
+        &self,
+        value: &Value,
+        path: &mut Path,
+        states: &[usize],
+        matched: &mut Vec<Path>,
+    ) {
+        if states.is_empty() {
+            return;
+        }
+        if self.is_accepting(states) {
+            matched.push(path.clone());
+        }
+        let mut visit = |elem: PathElement, child: &Value, path: &mut Path| {
+            let next = self.step(states, &elem);
+            path.push(elem);
+            self.collect_matches(child, path, &next, matched);
+            path.pop();
+        };
+        match value {
+            Value::Object(obj) => {
+                for (k, v) in obj {
+                    visit(PathElement::key(k), v, path);
+                }
+            }
+            Value::Array(array) => {
+                for (i, v) in array.iter().enumerate() {
+                    visit(PathElement::Index(i), v, path);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // The pattern is matched like an NFA, whose states are the indexes of
+    // the next segment to match.
+    fn start(&self) -> Vec<usize> {
+        self.closure(vec![0])
+    }
+
+    fn advance(&self, mut states: Vec<usize>, path: PathSlice) -> Vec<usize> {
+        for elem in path {
+            if states.is_empty() {
+                break;
+            }
+            states = self.step(&states, elem);
+        }
+        states
+    }
+
+    fn step(&self, states: &[usize], elem: &PathElement) -> Vec<usize> {
+        let next = states
+            .iter()
+            .filter_map(|&i| match self.segments.get(i)? {
+                PatternSegment::Element(e) if e == elem => Some(i + 1),
+                PatternSegment::Element(_) => None,
+                PatternSegment::Any => Some(i + 1),
+                PatternSegment::AnyDepth => Some(i),
+            })
+            .collect();
+        self.closure(next)
+    }
+
+    /// Add the states after `**`, which may match nothing.
+    fn closure(&self, mut states: Vec<usize>) -> Vec<usize> {
+        let mut i = 0;
+        while i < states.len() {
+            if let Some(PatternSegment::AnyDepth) = self.segments.get(states[i]) {
+                states.push(states[i] + 1);
+            }
+            i += 1;
+        }
+        states.sort_unstable();
+        states.dedup();
+        states
+    }
+
+    fn is_accepting(&self, states: &[usize]) -> bool {
+        states.last() == Some(&self.segments.len())
+    }
+}
+
+impl From<Path> for PathPattern {
+    fn from(path: Path) -> Self {
+        PathPattern::new(path.into_iter().map(PatternSegment::Element).collect())
+    }
+}
+
+impl Display for PathPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "[{}]",
+            self.segments
+                .iter()
+                .map(|s| match s {
+                    PatternSegment::Element(e) => e.to_string(),
+                    PatternSegment::Any => "*".into(),
+                    PatternSegment::AnyDepth => "**".into(),
+                })
+                .collect::<Vec<String>>()
+                .join(", ")
+        ))
+    }
+}
+
+impl TryFrom<&Value> for PathPattern {
+    type Error = PathError;
+
+    fn try_from(value: &Value) -> std::result::Result<Self, Self::Error> {
+        let path = Path::try_from(value)?;
+        Ok(PathPattern::new(
+            path.into_iter()
+                .map(|elem| match &elem {
+                    PathElement::Key(k) if &**k == "*" => PatternSegment::Any,
+                    PathElement::Key(k) if &**k == "**" => PatternSegment::AnyDepth,
+                    _ => PatternSegment::Element(elem),
+                })
+                .collect(),
+        ))
+    }
+}
+
+impl TryFrom<&str> for PathPattern {
+    type Error = PathError;
+
+    fn try_from(input: &str) -> std::result::Result<Self, Self::Error> {
+        let value = serde_json::from_str::<Value>(input).map_err(|_| {
+            PathError::ParsePathFromJsonFailed {
+                reason: format!("{input} is not a valid path pattern"),
+            }
+        })?;
+        PathPattern::try_from(&value)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::JsonError;
     use test_log::test;
 
     #[test]
@@ -358,10 +968,6 @@ mod tests {
             Path::try_from("").unwrap_err(),
             PathError::ParsePathFromJsonFailed { reason: _ }
         );
-        assert_matches!(
-            Path::try_from("[]").unwrap_err(),
-            PathError::ParsePathFromJsonFailed { reason: _ }
-        );
         assert_matches!(
             Path::try_from("hello").unwrap_err(),
             PathError::ParsePathFromJsonFailed { reason: _ }
@@ -444,5 +1050,285 @@ mod tests {
     #[test]
     fn test_empty_path() {
         assert_matches!(PathBuilder::default().build(), Err(PathError::EmptyPath));
+
+        let root = Path::try_from("[]").unwrap();
+        assert_eq!(Path::root(), root);
+        assert!(root.is_root());
+        assert!(root.last().is_none());
+        assert!(root.is_prefix_of(&Path::try_from(r#"["a"]"#).unwrap()));
+        assert_eq!(Value::Array(vec![]), root.to_value());
+    }
+
+    #[test]
+    fn test_path_ordering() {
+        let a = Path::try_from(r#"["a", 1]"#).unwrap();
+        let b = Path::try_from(r#"["a", 2, "c"]"#).unwrap();
+        let c = Path::try_from(r#"["a", "b"]"#).unwrap();
+        let d = Path::try_from(r#"["a"]"#).unwrap();
+        let mut paths = vec![c.clone(), b.clone(), a.clone(), d.clone()];
+        paths.sort();
+        assert_eq!(vec![d.clone(), a.clone(), b.clone(), c.clone()], paths);
+
+        assert_eq!(d, Path::common_prefix_of(&paths));
+        assert_eq!(
+            Path::try_from(r#"["a", 2]"#).unwrap(),
+            Path::common_prefix_of([&b, &Path::try_from(r#"["a", 2, 3]"#).unwrap()])
+        );
+
+        let e = Path::try_from(r#"["a", -1]"#).unwrap();
+        let f = Path::try_from(r#"["a", -2]"#).unwrap();
+        assert_eq!(Some(&PathElement::End(0)), e.last());
+        assert_eq!(r#"["a",-2]"#, f.to_value().to_string());
+        assert!(b < f && f < e && e < c);
+
+        let mut indexed = std::collections::HashMap::new();
+        indexed.insert(a.clone(), 1);
+        assert_eq!(
+            Some(&1),
+            indexed.get(&Path::try_from(r#"["a", 1]"#).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_path_macro() {
+        assert_eq!(
+            Path::try_from(r#"["users", 0, "name"]"#).unwrap(),
+            path!["users", 0, "name"]
+        );
+        let key = String::from("k");
+        assert_eq!(Path::try_from(r#"["k", 1]"#).unwrap(), path![key, 1,]);
+        assert!(path![].is_empty());
+    }
+
+    #[test]
+    fn test_path_conversions() {
+        let expect = Path::try_from(r#"["a", 0, "b"]"#).unwrap();
+        assert_eq!(expect, ("a", 0, "b").into());
+        assert_eq!(
+            expect,
+            Path::try_from(r#"["a", 0, "b"]"#.to_string()).unwrap()
+        );
+        assert_eq!(
+            expect,
+            PathBuilder::default()
+                .append_path(("a",))
+                .append_path(r#"[0, "b"]"#)
+                .build()
+                .unwrap()
+        );
+        assert_matches!(
+            PathBuilder::default().append_path("[a").build(),
+            Err(PathError::ParsePathFromJsonFailed { reason: _ })
+        );
+    }
+
+    #[test]
+    fn test_path_slice() {
+        let path = path!["a", 1, "b", 2, "c"];
+        let (left, right) = path.as_slice().split_at(2);
+        assert_eq!(left, path!["a", 1]);
+        assert_eq!(right, path!["b", 2, "c"]);
+        assert_eq!(path.prefix(2), left);
+        assert_eq!(path.suffix(2), right);
+        assert_eq!(path.suffix(3), right.next_level());
+        assert!(left.is_prefix_of(&path));
+        assert!(!right.is_prefix_of(&path));
+        assert_eq!(Some(&PathElement::from("c")), right.last());
+        assert_eq!(r#"["b", 2, "c"]"#, right.to_string());
+
+        let root = path.prefix(0);
+        assert!(root.is_root());
+        assert!(root.next_level().is_root());
+        assert_eq!(Path::root(), root.to_path());
+
+        let mut p = left.to_path();
+        p.push(PathElement::key("x"));
+        assert_eq!(path!["a", 1, "x"], p);
+        assert_eq!(Some(PathElement::key("x")), p.pop());
+    }
+
+    #[test]
+    fn test_path_pattern() {
+        let pattern = PathPattern::try_from(r#"["users", "*", "email"]"#).unwrap();
+        assert!(pattern.matches(&path!["users", 0, "email"]));
+        assert!(pattern.matches(&path!["users", "k", "email"]));
+        assert!(!pattern.matches(&path!["users", 0]));
+        assert!(!pattern.matches(&path!["users", 0, "email", "x"]));
+        assert!(pattern.matches_ancestor_or_self(&path!["users", 0, "email", "x"]));
+        assert!(!pattern.matches_ancestor_or_self(&path!["users", 0]));
+        assert!(pattern.may_match_self_or_descendant(&path!["users"]));
+        assert!(!pattern.may_match_self_or_descendant(&path!["groups"]));
+        assert_eq!(r#"["users", *, "email"]"#, pattern.to_string());
+
+        let pattern = PathPattern::try_from(r#"["a", "**", "b"]"#).unwrap();
+        assert!(pattern.matches(&path!["a", "b"]));
+        assert!(pattern.matches(&path!["a", 1, "c", "b"]));
+        assert!(!pattern.matches(&path!["a", "b", "c"]));
+        assert!(pattern.may_match_self_or_descendant(&path!["a", "b", "c"]));
+
+        let pattern = PathPattern::try_from(r#"["**"]"#).unwrap();
+        assert!(pattern.matches(&Path::root()));
+        assert!(pattern.matches(&path!["x", 1]));
+
+        let pattern = PathPattern::from(path!["a", 0]);
+        assert!(pattern.matches(&path!["a", 0]));
+        assert!(!pattern.matches(&path!["a", 1]));
+
+        let value = serde_json::json!({"a": {"x": {"b": 1}, "b": [{"b": 2}]}});
+        let pattern = PathPattern::try_from(r#"["a", "**", "b"]"#).unwrap();
+        assert_eq!(
+            vec![
+                path!["a", "b"],
+                path!["a", "b", 0, "b"],
+                path!["a", "x", "b"]
+            ],
+            pattern
+                .matches_in(&value, &Path::root())
+                .into_iter()
+                .sorted()
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![path!["a", "x", "b"]],
+            pattern.matches_in(&value["a"]["x"], &path!["a", "x"])
+        );
+
+        assert_matches!(
+            PathPattern::try_from(r#"{"a": 1}"#),
+            Err(PathError::ParsePathFromJsonFailed { .. })
+        );
+    }
+
+    #[test]
+    fn test_path_from_iter() {
+        let path = Path::from_iter(["a".into(), 0.into()]);
+        assert_eq!(path!["a", 0], path);
+        assert_eq!(
+            path!["a", 0, "b"],
+            ["a", "b"]
+                .into_iter()
+                .map(PathElement::from)
+                .interleave([PathElement::Index(0)])
+                .collect::<Path>()
+        );
+        assert_eq!(Path::root(), std::iter::empty().collect::<Path>());
+
+        let mut path = Path::from(("a", 0usize, "b"));
+        path.extend([PathElement::Index(1), "c".into()]);
+        assert_eq!(path!["a", 0, "b", 1, "c"], path);
+        path.extend(&path!["d"]);
+        assert_eq!(path!["a", 0, "b", 1, "c", "d"], path);
+    }
+
+    #[test]
+    fn test_shift_index() {
+        let mut path = path!["a", 0];
+        assert!(!path.try_increase_index(0).unwrap());
+        assert_matches!(
+            path.try_decrease_index(1),
+            Err(PathError::IndexOutOfRange { position: 1, .. })
+        );
+        assert_eq!(path!["a", 0], path);
+        assert!(path.decrease_index(1));
+        assert_eq!(path!["a", 0], path);
+        assert!(path.try_increase_index(1).unwrap());
+        assert_eq!(path!["a", 1], path);
+        assert!(path.try_decrease_index(1).unwrap());
+        assert_eq!(path!["a", 0], path);
+
+        let mut path = path![usize::MAX];
+        assert!(path.try_increase_index(0).is_err());
+        assert!(path.increase_index(0));
+        assert_eq!(path![usize::MAX], path);
+    }
+
+    #[test]
+    fn test_path_algebra() {
+        let path = path!["a", 1, "b"];
+        let base = path!["a", 1];
+        assert!(path.starts_with(&base));
+        assert!(path.starts_with(&Path::root()));
+        assert!(!base.starts_with(&path));
+        assert_eq!(path, base.join(&path!["b"]));
+        assert_eq!(path, Path::root().join(&path));
+        assert_eq!(path, path.join(Path::root().as_slice()));
+
+        assert_eq!(Some(path!["b"]), path.strip_prefix(&base));
+        assert_eq!(Some(Path::root()), path.strip_prefix(&path));
+        assert_eq!(None, path.strip_prefix(&path!["a", 2]));
+        assert_eq!(None, base.strip_prefix(&path));
+        assert_eq!(Some(path.suffix(1)), path.relative_to(path.prefix(1)));
+
+        assert_eq!(Some(base.as_slice()), path.parent());
+        assert_eq!(Some(Path::root().as_slice()), path!["a"].parent());
+        assert_eq!(None, Path::root().parent());
+
+        assert_eq!(PathElement::Index(1), path[1]);
+        assert_eq!(PathElement::key("b"), path.suffix(1)[1]);
+        assert_eq!(
+            vec!["\"a\"", "1", "\"b\""],
+            path.iter().map(|p| p.to_string()).collect::<Vec<_>>()
+        );
+        assert_eq!(3, (&path).into_iter().count());
+        assert_eq!(2, path.suffix(1).into_iter().count());
+        assert_eq!(
+            vec![
+                PathElement::key("a"),
+                PathElement::Index(1),
+                PathElement::key("b")
+            ],
+            path.into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_max_path_depth() {
+        // deeper than any path of the other tests, which share the limit
+        set_max_path_depth(64);
+        let deep = Value::Array(vec![Value::from(0); 65]);
+        let e = Path::try_from(&deep).unwrap_err();
+        assert_matches!(
+            e,
+            PathError::TooDeep {
+                max: 64,
+                actual: 65
+            }
+        );
+        assert_matches!(
+            JsonError::from(e),
+            JsonError::LimitExceeded {
+                limit: "path depth",
+                max: 64,
+                actual: 65
+            }
+        );
+        assert_eq!(
+            64,
+            Path::try_from(&Value::Array(vec![Value::from(0); 64]))
+                .unwrap()
+                .len()
+        );
+        set_max_path_depth(usize::MAX);
+        assert!(Path::try_from(&deep).is_ok());
+    }
+
+    #[test]
+    fn test_intern_keys() {
+        let key_of = |p: &Path| match p.last() {
+            Some(PathElement::Key(k)) => k.clone(),
+            _ => unreachable!(),
+        };
+        let a = Path::try_from(r#"["interned", "title"]"#).unwrap();
+        let b = Path::try_from(r#"["interned", "title"]"#).unwrap();
+        assert!(!Arc::ptr_eq(&key_of(&a), &key_of(&b)));
+        // clone shares the key
+        assert!(Arc::ptr_eq(&key_of(&a), &key_of(&a.clone())));
+
+        intern_keys(["title", "body"]);
+        let a = Path::try_from(r#"["interned", "title"]"#).unwrap();
+        let b = path!["interned", String::from("title")];
+        assert!(Arc::ptr_eq(&key_of(&a), &key_of(&b)));
+        assert_eq!(Path::try_from(r#"["interned", "title"]"#).unwrap(), a);
+        assert_eq!(Some("title"), a.get_key_at(1));
     }
 }
@@ -0,0 +1,232 @@
+//! Documents backed by persistent data structures. Applying an operation
+//! makes a new version of the document which shares every subtree the
+//! operation leaves unchanged with the old version, so keeping many
+//! historical versions costs memory only for what each of them changed.
+
+use std::{borrow::Cow, sync::Arc};
+
+use im::{OrdMap, Vector};
+use serde_json::Value;
+
+use crate::{
+    document::JsonDocument,
+    error::{ErrorContext, JsonError, Result},
+    json::{Appliable, Routable},
+    operation::Operation,
+    path::Path,
+};
+
+/// A JSON value with objects and arrays whose clones share their content.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PersistentValue {
+    /// Null, bool, number or string.
+    Leaf(Arc<Value>),
+    Array(Vector<PersistentValue>),
+    Object(OrdMap<String, PersistentValue>),
+}
+
+impl From<Value> for PersistentValue {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Array(array) => {
+                PersistentValue::Array(array.into_iter().map(PersistentValue::from).collect())
+            }
+            Value::Object(obj) => PersistentValue::Object(
+                obj.into_iter()
+                    .map(|(k, v)| (k, PersistentValue::from(v)))
+                    .collect(),
+            ),
+            v => PersistentValue::Leaf(Arc::new(v)),
+        }
+    }
+}
+
+impl From<&PersistentValue> for Value {
+    fn from(value: &PersistentValue) -> Self {
+        match value {
+            PersistentValue::Leaf(v) => Value::clone(v),
+            PersistentValue::Array(array) => Value::Array(array.iter().map(Value::from).collect()),
+            PersistentValue::Object(obj) => Value::Object(
+                obj.iter()
+                    .map(|(k, v)| (k.clone(), Value::from(v)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl JsonDocument for PersistentValue {
+    fn from_value(value: Value) -> Self {
+        value.into()
+    }
+
+    fn to_value(&self) -> Cow<'_, Value> {
+        match self {
+            PersistentValue::Leaf(v) => Cow::Borrowed(v),
+            v => Cow::Owned(v.into()),
+        }
+    }
+
+    fn is_object(&self) -> bool {
+        matches!(self, PersistentValue::Object(_))
+    }
+
+    fn is_array(&self) -> bool {
+        matches!(self, PersistentValue::Array(_))
+    }
+
+    fn is_null(&self) -> bool {
+        matches!(self, PersistentValue::Leaf(v) if v.is_null())
+    }
+
+    fn get_key(&self, key: &str) -> Option<&Self> {
+        match self {
+            PersistentValue::Object(obj) => obj.get(key),
+            _ => None,
+        }
+    }
+
+    fn get_key_mut(&mut self, key: &str) -> Option<&mut Self> {
+        match self {
+            PersistentValue::Object(obj) => obj.get_mut(key),
+            _ => None,
+        }
+    }
+
+    fn insert_key(&mut self, key: String, value: Self) {
+        if let PersistentValue::Object(obj) = self {
+            obj.insert(key, value);
+        }
+    }
+
+    fn remove_key(&mut self, key: &str) -> Option<Self> {
+        match self {
+            PersistentValue::Object(obj) => obj.remove(key),
+            _ => None,
+        }
+    }
+
+    fn get_index(&self, index: usize) -> Option<&Self> {
+        match self {
+            PersistentValue::Array(array) => array.get(index),
+            _ => None,
+        }
+    }
+
+    fn get_index_mut(&mut self, index: usize) -> Option<&mut Self> {
+        match self {
+            PersistentValue::Array(array) => array.get_mut(index),
+            _ => None,
+        }
+    }
+
+    fn array_len(&self) -> usize {
+        match self {
+            PersistentValue::Array(array) => array.len(),
+            _ => 0,
+        }
+    }
+
+    fn insert_index(&mut self, index: usize, value: Self) {
+        if let PersistentValue::Array(array) = self {
+            array.insert(index, value);
+        }
+    }
+
+    fn remove_index(&mut self, index: usize) -> Self {
+        match self {
+            PersistentValue::Array(array) => array.remove(index),
+            _ => PersistentValue::Leaf(Arc::new(Value::Null)),
+        }
+    }
+}
+
+/// An immutable version of a document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PersistentDoc {
+    root: PersistentValue,
+}
+
+impl PersistentDoc {
+    pub fn new(value: Value) -> PersistentDoc {
+        PersistentDoc { root: value.into() }
+    }
+
+    /// Returns a new version with operation applied, which shares the
+    /// unchanged subtrees with this version. This version is left unchanged
+    /// on error.
+    pub fn apply(&self, operation: &Operation) -> Result<PersistentDoc> {
+        let mut root = self.root.clone();
+        for (i, c) in operation.iter().enumerate() {
+            root.apply(c.path.clone(), c.operator.clone())
+                .map_err(|e| JsonError::from(e).with_context(|| ErrorContext::new(0, i, c)))?;
+        }
+        Ok(PersistentDoc { root })
+    }
+
+    pub fn root(&self) -> &PersistentValue {
+        &self.root
+    }
+
+    /// The value on path, or None if there's nothing.
+    pub fn get(&self, path: &Path) -> Result<Option<&PersistentValue>> {
+        self.root.route_get(path).map_err(JsonError::RouteError)
+    }
+
+    pub fn to_value(&self) -> Value {
+        Value::from(&self.root)
+    }
+}
+
+impl From<Value> for PersistentDoc {
+    fn from(value: Value) -> Self {
+        PersistentDoc::new(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operation::OperationFactory;
+    use test_log::test;
+
+    #[test]
+    fn test_persistent_doc_shares_unchanged_subtrees() {
+        let f = OperationFactory::default();
+        let v0 = PersistentDoc::new(serde_json::json!({
+            "a": {"large": [1, 2, 3]},
+            "b": {"l": [1, 2], "t": "ab"},
+        }));
+        let op = f
+            .from_value(serde_json::json!([
+                {"p": ["b", "l", 0], "li": 0},
+                {"p": ["b", "t"], "t": "text", "o": {"p": 2, "i": "c"}},
+            ]))
+            .unwrap();
+        let v1 = v0.apply(&op).unwrap();
+
+        assert_eq!(
+            serde_json::json!({"a": {"large": [1, 2, 3]}, "b": {"l": [1, 2], "t": "ab"}}),
+            v0.to_value()
+        );
+        assert_eq!(
+            serde_json::json!({"a": {"large": [1, 2, 3]}, "b": {"l": [0, 1, 2], "t": "abc"}}),
+            v1.to_value()
+        );
+        let object = |doc: &PersistentDoc, path| match doc.get(&path).unwrap() {
+            Some(PersistentValue::Object(obj)) => obj.clone(),
+            v => panic!("expect object, got {:?}", v),
+        };
+        assert!(object(&v0, path!["a"]).ptr_eq(&object(&v1, path!["a"])));
+        assert!(!object(&v0, path!["b"]).ptr_eq(&object(&v1, path!["b"])));
+
+        let failed = f
+            .from_value(
+                serde_json::json!([{"p": ["a", "x"], "oi": 1}, {"p": ["b", "t"], "test": "x"}]),
+            )
+            .unwrap();
+        let err = v1.apply(&failed).unwrap_err();
+        assert_eq!(1, err.context().unwrap().component_index);
+        assert_eq!(None, v1.get(&path!["a", "x"]).unwrap());
+    }
+}
@@ -0,0 +1,218 @@
+//! Python bindings with pyo3, to validate, apply and transform stored
+//! operations from Python tooling. Documents and operations are passed as
+//! JSON strings, and errors are raised as `ValueError` with the error code
+//! and message. Build the extension module with maturin, enabling
+//! `python` and `pyo3/extension-module`.
+
+// pymethods expands fallible methods to a conversion of PyErr into itself
+#![allow(clippy::useless_conversion)]
+
+use pyo3::{exceptions::PyValueError, prelude::*};
+use serde_json::Value;
+
+use crate::{error::JsonError, operation, path};
+
+fn to_py_err(e: JsonError) -> PyErr {
+    PyValueError::new_err((e.code().as_str(), e.to_string()))
+}
+
+fn parse_json(input: &str) -> PyResult<Value> {
+    serde_json::from_str(input).map_err(|e| {
+        to_py_err(JsonError::InvalidOperation(format!(
+            "invalid JSON: {input}, reason: {e}"
+        )))
+    })
+}
+
+#[pyclass(name = "Json0", unsendable)]
+struct PyJson0 {
+    json0: crate::Json0,
+}
+
+#[pymethods]
+impl PyJson0 {
+    #[new]
+    fn new() -> Self {
+        PyJson0 {
+            json0: crate::Json0::new(),
+        }
+    }
+
+    /// Parse an operation from its JSON.
+    fn operation(&self, op: &str) -> PyResult<PyOperation> {
+        let operation = self
+            .json0
+            .operation_factory()
+            .from_str(op)
+            .map_err(to_py_err)?;
+        Ok(PyOperation { operation })
+    }
+
+    /// Apply operations in order on the document and return the new document.
+    fn apply(&self, doc: &str, operations: Vec<PyRef<PyOperation>>) -> PyResult<String> {
+        let mut doc = parse_json(doc)?;
+        let operations = operations.iter().map(|o| o.operation.clone()).collect();
+        self.json0.apply(&mut doc, operations).map_err(to_py_err)?;
+        Ok(doc.to_string())
+    }
+
+    /// Raise `ValueError` if operation can't be applied on the document.
+    fn check(&self, doc: &str, operation: &PyOperation) -> PyResult<()> {
+        self.json0
+            .check(&parse_json(doc)?, &operation.operation)
+            .map_err(to_py_err)
+    }
+
+    /// Transform two concurrent operations against each other. Returns
+    /// operation transformed against base, and base transformed against
+    /// operation.
+    fn transform(
+        &self,
+        operation: &PyOperation,
+        base: &PyOperation,
+    ) -> PyResult<(PyOperation, PyOperation)> {
+        let (a, b) = self
+            .json0
+            .transform(&operation.operation, &base.operation)
+            .map_err(to_py_err)?;
+        Ok((PyOperation { operation: a }, PyOperation { operation: b }))
+    }
+}
+
+#[pyclass(name = "Operation", unsendable)]
+#[derive(Clone)]
+struct PyOperation {
+    operation: operation::Operation,
+}
+
+#[pymethods]
+impl PyOperation {
+    fn to_json(&self) -> String {
+        self.operation.to_string()
+    }
+
+    fn compose(&self, other: &PyOperation) -> PyResult<PyOperation> {
+        let operation = self
+            .operation
+            .composed(&other.operation)
+            .map_err(to_py_err)?;
+        Ok(PyOperation { operation })
+    }
+
+    fn invert(&self) -> PyResult<PyOperation> {
+        let operation = self.operation.invert().map_err(to_py_err)?;
+        Ok(PyOperation { operation })
+    }
+
+    /// Paths of the components.
+    fn paths(&self) -> Vec<PyPath> {
+        self.operation
+            .iter()
+            .map(|c| PyPath {
+                path: c.path.clone(),
+            })
+            .collect()
+    }
+
+    fn __len__(&self) -> usize {
+        self.operation.len()
+    }
+
+    fn __str__(&self) -> String {
+        self.operation.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Operation({})", self.operation)
+    }
+
+    fn __eq__(&self, other: &PyOperation) -> bool {
+        self.operation == other.operation
+    }
+}
+
+#[pyclass(name = "Path", unsendable)]
+#[derive(Clone)]
+struct PyPath {
+    path: path::Path,
+}
+
+#[pymethods]
+impl PyPath {
+    /// Parse a path from its JSON, like `'["a", 0]'`.
+    #[new]
+    fn new(path: &str) -> PyResult<Self> {
+        let path = path::Path::try_from(path).map_err(|e| to_py_err(e.into()))?;
+        Ok(PyPath { path })
+    }
+
+    fn to_json(&self) -> String {
+        self.path.to_value().to_string()
+    }
+
+    fn is_prefix_of(&self, other: &PyPath) -> bool {
+        self.path.is_prefix_of(&other.path)
+    }
+
+    fn __len__(&self) -> usize {
+        self.path.len()
+    }
+
+    fn __str__(&self) -> String {
+        self.path.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Path({})", self.path)
+    }
+
+    fn __eq__(&self, other: &PyPath) -> bool {
+        self.path == other.path
+    }
+}
+
+#[pymodule]
+fn json0_rs(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyJson0>()?;
+    m.add_class::<PyOperation>()?;
+    m.add_class::<PyPath>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_python_module() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let m = PyModule::new_bound(py, "json0_rs").unwrap();
+            json0_rs(&m).unwrap();
+            let locals = pyo3::types::PyDict::new_bound(py);
+            locals.set_item("json0_rs", m).unwrap();
+            py.run_bound(
+                r#"
+j = json0_rs.Json0()
+a = j.operation('[{"p": ["l", 0], "li": 1}]')
+b = j.operation('[{"p": ["l", 0], "li": 2}]')
+a2, b2 = j.transform(a, b)
+assert a2.to_json() == '[{"p":["l",0],"li":1}]', a2.to_json()
+assert j.apply('{"l": []}', [b, a2]) == j.apply('{"l": []}', [a, b2])
+assert a.compose(a.invert()).to_json() == '[]'
+assert a.paths()[0] == json0_rs.Path('["l", 0]')
+assert json0_rs.Path('["l"]').is_prefix_of(a.paths()[0])
+try:
+    j.check('{"l": 1}', a)
+    assert False
+except ValueError as e:
+    assert e.args[0] == "invalid_apply_target", e.args
+"#,
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+        });
+    }
+}
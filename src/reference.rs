@@ -0,0 +1,336 @@
+//! Differential testing against the reference ottypes/json0 JavaScript
+//! implementation, run by an embedded QuickJS engine.
+//!
+//! The reference is not shipped with this crate. Load it from a single file
+//! CommonJS bundle of ot-json0, which assigns the json0 type, or an object
+//! with the type under `type`, to `module.exports`. `from_env` loads the file
+//! named by the `JSON0_REFERENCE_JS` environment variable.
+//!
+//! The reference knows neither object moves, test components nor custom sub
+//! types, so cases using them are skipped. Text operations are translated to
+//! the `text0` sub type the reference embeds.
+
+use std::fs;
+
+use rquickjs::{Context, Runtime};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    operation::Operation,
+    testing::{random_document, random_operation, Rng},
+    Json0,
+};
+
+/// Environment variable naming the file of the reference json0 bundle.
+pub const REFERENCE_ENV: &str = "JSON0_REFERENCE_JS";
+
+pub type ReferenceResult<T> = std::result::Result<T, String>;
+
+/// The reference json0 type loaded in a JavaScript context.
+pub struct ReferenceJson0 {
+    context: Context,
+    // the context must not outlive its runtime
+    _runtime: Runtime,
+}
+
+impl ReferenceJson0 {
+    /// Load the reference from the source of its CommonJS bundle.
+    pub fn from_source(source: &str) -> ReferenceResult<ReferenceJson0> {
+        let runtime = Runtime::new().map_err(|e| e.to_string())?;
+        let context = Context::full(&runtime).map_err(|e| e.to_string())?;
+        let reference = ReferenceJson0 {
+            context,
+            _runtime: runtime,
+        };
+        reference.eval(&format!(
+            "var module = {{ exports: {{}} }};\nvar exports = module.exports;\n{}\n;\
+             globalThis.json0 = module.exports.type || module.exports;\n\
+             if (typeof json0.transformX !== 'function') throw new Error('json0 type not found');",
+            source
+        ))?;
+        Ok(reference)
+    }
+
+    /// Load the reference from the file named by `JSON0_REFERENCE_JS`, or
+    /// None if it is not set.
+    pub fn from_env() -> Option<ReferenceResult<ReferenceJson0>> {
+        let path = std::env::var_os(REFERENCE_ENV)?;
+        Some(
+            fs::read_to_string(&path)
+                .map_err(|e| format!("read {:?} failed: {}", path, e))
+                .and_then(|source| ReferenceJson0::from_source(&source)),
+        )
+    }
+
+    pub fn apply(&self, doc: &Value, operation: &Operation) -> ReferenceResult<Value> {
+        let op = to_reference(operation).ok_or("operation is not supported by reference")?;
+        self.call("json0.apply(args[0], args[1])", &[doc.clone(), op])
+    }
+
+    /// Same as `Json0::transform_x`.
+    pub fn transform_x(
+        &self,
+        left: &Operation,
+        right: &Operation,
+    ) -> ReferenceResult<(Value, Value)> {
+        let left = to_reference(left).ok_or("left is not supported by reference")?;
+        let right = to_reference(right).ok_or("right is not supported by reference")?;
+        let out = self.call("json0.transformX(args[0], args[1])", &[left, right])?;
+        match out {
+            Value::Array(mut pair) if pair.len() == 2 => {
+                let right = pair.pop().unwrap();
+                let left = pair.pop().unwrap();
+                Ok((from_reference(left), from_reference(right)))
+            }
+            v => Err(format!("transformX returned {}", v)),
+        }
+    }
+
+    pub fn compose(&self, operation: &Operation, other: &Operation) -> ReferenceResult<Value> {
+        let op = to_reference(operation).ok_or("operation is not supported by reference")?;
+        let other = to_reference(other).ok_or("other is not supported by reference")?;
+        self.call("json0.compose(args[0], args[1])", &[op, other])
+            .map(from_reference)
+    }
+
+    fn call(&self, expr: &str, args: &[Value]) -> ReferenceResult<Value> {
+        let out = self.eval(&format!(
+            "(function (args) {{\n\
+               try {{ return JSON.stringify({{ ok: {} }}); }}\n\
+               catch (e) {{ return JSON.stringify({{ error: String(e) }}); }}\n\
+             }})(JSON.parse({}))",
+            expr,
+            // a JSON string is a valid JavaScript string literal
+            Value::String(Value::Array(args.to_vec()).to_string())
+        ))?;
+        let mut out: Map<String, Value> = serde_json::from_str(&out).map_err(|e| e.to_string())?;
+        match out.remove("error") {
+            Some(e) => Err(e.as_str().unwrap_or_default().to_string()),
+            None => Ok(out.remove("ok").unwrap_or(Value::Null)),
+        }
+    }
+
+    fn eval(&self, source: &str) -> ReferenceResult<String> {
+        self.context.with(|ctx| {
+            ctx.eval::<Option<String>, _>(source)
+                .map(|v| v.unwrap_or_default())
+                .map_err(|e| match e {
+                    rquickjs::Error::Exception => format!("{:?}", ctx.catch()),
+                    e => e.to_string(),
+                })
+        })
+    }
+}
+
+/// Assert json0 gives the same results as the reference for concurrent
+/// operations a and b on doc: the documents after applying a, the operations
+/// transformed by `transform_x` and the documents after applying a composed
+/// with b transformed against a. Returns false if the reference does not
+/// support a or b and nothing is checked.
+pub fn check_against_reference(
+    json0: &Json0,
+    reference: &ReferenceJson0,
+    doc: &Value,
+    a: &Operation,
+    b: &Operation,
+) -> bool {
+    if to_reference(a).is_none() || to_reference(b).is_none() {
+        return false;
+    }
+    let context = || format!("doc: {}, a: {}, b: {}", doc, a, b);
+    let parse = |v: Value| {
+        json0
+            .operation_factory()
+            .from_value(v)
+            .unwrap_or_else(|e| panic!("parse reference output failed: {}, {}", e, context()))
+    };
+
+    let mut applied = doc.clone();
+    json0
+        .apply(&mut applied, vec![a.clone()])
+        .unwrap_or_else(|e| panic!("apply a failed: {}, {}", e, context()));
+    let expect = reference
+        .apply(doc, a)
+        .unwrap_or_else(|e| panic!("reference apply a failed: {}, {}", e, context()));
+    assert_eq!(expect, applied, "apply differs, {}", context());
+
+    let (a_prime, b_prime) = json0
+        .transform_x(a, b)
+        .unwrap_or_else(|e| panic!("transform failed: {}, {}", e, context()));
+    let (expect_a, expect_b) = reference
+        .transform_x(a, b)
+        .unwrap_or_else(|e| panic!("reference transform failed: {}, {}", e, context()));
+    assert_eq!(
+        parse(expect_a).normalize(),
+        a_prime.normalize(),
+        "transformed a differs, {}",
+        context()
+    );
+    assert_eq!(
+        parse(expect_b).normalize(),
+        b_prime.normalize(),
+        "transformed b differs, {}",
+        context()
+    );
+
+    // composed operations may merge components differently, so compare what
+    // they do instead
+    let composed = a
+        .composed(&b_prime)
+        .unwrap_or_else(|e| panic!("compose failed: {}, {}", e, context()));
+    let expect_composed = reference
+        .compose(a, &b_prime)
+        .unwrap_or_else(|e| panic!("reference compose failed: {}, {}", e, context()));
+    let mut applied = doc.clone();
+    json0
+        .apply(&mut applied, vec![composed])
+        .unwrap_or_else(|e| panic!("apply composed failed: {}, {}", e, context()));
+    let mut expect = doc.clone();
+    json0
+        .apply(&mut expect, vec![parse(expect_composed)])
+        .unwrap_or_else(|e| panic!("apply reference composed failed: {}, {}", e, context()));
+    assert_eq!(expect, applied, "compose differs, {}", context());
+    true
+}
+
+/// Run `check_against_reference` on iterations pairs of random operations on
+/// random documents. Returns how many pairs were checked.
+pub fn fuzz_against_reference(
+    json0: &Json0,
+    reference: &ReferenceJson0,
+    seed: u64,
+    iterations: usize,
+) -> usize {
+    let mut rng = Rng::new(seed);
+    let mut checked = 0;
+    for _ in 0..iterations {
+        let doc = random_document(&mut rng);
+        let a = random_operation(&doc, &mut rng);
+        let b = random_operation(&doc, &mut rng);
+        if check_against_reference(json0, reference, &doc, &a, &b) {
+            checked += 1;
+        }
+    }
+    checked
+}
+
+/// Wire form of operation for the reference, or None if the reference does
+/// not support it.
+fn to_reference(operation: &Operation) -> Option<Value> {
+    let mut components = vec![];
+    for c in operation.iter() {
+        let Value::Object(mut obj) = c.to_value() else {
+            return None;
+        };
+        if obj.contains_key("om") || obj.contains_key("test") {
+            return None;
+        }
+        match obj.get("t").and_then(|t| t.as_str()) {
+            None => {}
+            Some("text") => {
+                let o = obj.remove("o")?;
+                obj.insert("t".into(), json!("text0"));
+                obj.insert("o".into(), json!([o]));
+            }
+            Some(_) => return None,
+        }
+        components.push(Value::Object(obj));
+    }
+    Some(Value::Array(components))
+}
+
+/// Operation in the wire form of json0 from the output of the reference.
+fn from_reference(value: Value) -> Value {
+    let Value::Array(components) = value else {
+        return value;
+    };
+    let mut out = vec![];
+    for c in components {
+        match c {
+            Value::Object(obj) if obj.get("t") == Some(&json!("text0")) => {
+                for o in obj["o"].as_array().into_iter().flatten() {
+                    out.push(json!({"p": obj["p"], "t": "text", "o": o}));
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    Value::Array(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operation::OperationFactory;
+    use test_log::test;
+
+    /// Stands in for the reference: applies nothing, transforms nothing and
+    /// composes by concatenation.
+    const STUB: &str = "module.exports = { type: {
+        apply: function (doc, op) { return doc; },
+        transformX: function (left, right) { return [left, right]; },
+        compose: function (op, other) { return op.concat(other); },
+    } };";
+
+    #[test]
+    fn test_reference_wire_form() {
+        let json0 = Json0::new();
+        let reference = ReferenceJson0::from_source(STUB).unwrap();
+        let parse = |v| json0.operation_factory().from_value(v).unwrap();
+        let a = parse(
+            json!([{"p": ["t"], "t": "text", "o": {"p": 1, "i": "x"}}, {"p": ["n"], "na": 1}]),
+        );
+        let b = parse(json!([{"p": ["l", 0], "li": 1}]));
+
+        assert_eq!(
+            json!({"t": "ab"}),
+            reference.apply(&json!({"t": "ab"}), &a).unwrap()
+        );
+        let (left, right) = reference.transform_x(&a, &b).unwrap();
+        assert_eq!(a, parse(left));
+        assert_eq!(b, parse(right));
+        assert_eq!(
+            json!([{"p": ["t"], "t": "text", "o": {"p": 1, "i": "x"}}, {"p": ["n"], "na": 1}, {"p": ["l", 0], "li": 1}]),
+            reference.compose(&a, &b).unwrap()
+        );
+
+        // the reference knows no object moves
+        let om = parse(json!([{"p": ["a"], "om": "b"}]));
+        assert!(reference.apply(&json!({"a": 1}), &om).is_err());
+        assert!(!check_against_reference(
+            &json0,
+            &reference,
+            &json!({"a": 1}),
+            &om,
+            &b
+        ));
+    }
+
+    #[test]
+    fn test_reference_errors() {
+        assert!(ReferenceJson0::from_source("module.exports = {};").is_err());
+        assert!(ReferenceJson0::from_source("syntax error(").is_err());
+
+        let reference = ReferenceJson0::from_source(
+            "module.exports = { transformX: function () {}, apply: function () { throw new Error('bad op'); } };",
+        )
+        .unwrap();
+        let op = OperationFactory::default()
+            .from_value(json!([{"p": ["n"], "na": 1}]))
+            .unwrap();
+        assert_eq!(
+            Err("Error: bad op".to_string()),
+            reference.apply(&json!({"n": 1}), &op)
+        );
+    }
+
+    #[test]
+    fn test_fuzz_against_reference() {
+        let Some(reference) = ReferenceJson0::from_env() else {
+            log::info!("{} is not set, skip", REFERENCE_ENV);
+            return;
+        };
+        let checked = fuzz_against_reference(&Json0::new(), &reference.unwrap(), 42, 1000);
+        assert!(checked > 0);
+    }
+}
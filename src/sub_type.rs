@@ -1,30 +1,203 @@
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::hash::Hash;
-use std::sync::Arc;
+use std::ops::Range;
+use std::sync::{Arc, RwLock};
 use std::vec;
 
-use dashmap::mapref::one::Ref;
-use dashmap::DashMap;
-use serde_json::{Map, Value};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
 
 use crate::error::{JsonError, Result};
 use crate::json::{ApplyOperationError, ApplyResult};
 use crate::path::Path;
+use crate::text_delta::{TextDeltaSubType, TEXT_DELTA_SUB_TYPE_NAME};
 use crate::transformer::TransformSide;
 
 const NUMBER_ADD_SUB_TYPE_NAME: &str = "na";
 const TEXT_SUB_TYPE_NAME: &str = "text";
 
-pub trait SubTypeFunctions {
+/// Functions of a sub type. They are shared by the operations using them and
+/// by the registry, which may be shared between threads.
+pub trait SubTypeFunctions: Send + Sync {
     fn invert(&self, path: &Path, sub_type_operand: &Value) -> Result<Value>;
 
     fn merge(&self, base_operand: &Value, other_operand: &Value) -> Option<Value>;
 
+    /// Compose base operand and other operand applied after it into one
+    /// operand with the same effect, or None if they can't be. Used when
+    /// composing consecutive components on the same path. Defaults to
+    /// `merge`.
+    fn compose(&self, base_operand: &Value, other_operand: &Value) -> Option<Value> {
+        self.merge(base_operand, other_operand)
+    }
+
     fn transform(&self, new: &Value, base: &Value, side: TransformSide) -> Result<Vec<Value>>;
 
     fn apply(&self, val: Option<&Value>, sub_type_operand: &Value) -> ApplyResult<Option<Value>>;
 
     fn validate_operand(&self, val: &Value) -> Result<()>;
+
+    /// Version of the operand format, for peers to tell whether they agree on
+    /// it. Defaults to 1.
+    fn version(&self) -> u32 {
+        1
+    }
+
+    /// Defaults to inverting only, as `compose` may never compose operands.
+    fn capabilities(&self) -> SubTypeCapabilities {
+        SubTypeCapabilities {
+            compose: false,
+            invert: true,
+        }
+    }
+}
+
+/// What a sub type supports beyond apply and transform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubTypeCapabilities {
+    /// Operands on the same path are composed into one.
+    pub compose: bool,
+    pub invert: bool,
+}
+
+/// A registered sub type, for servers and clients to negotiate the sub types
+/// they can exchange when they connect.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubTypeInfo {
+    pub sub_type: SubType,
+    /// Other names of the sub type on the wire.
+    pub aliases: Vec<String>,
+    pub version: u32,
+    pub capabilities: SubTypeCapabilities,
+}
+
+impl SubTypeInfo {
+    /// The name of the sub type on the wire, like `text`.
+    pub fn name(&self) -> String {
+        self.sub_type.to_string()
+    }
+
+    pub fn to_value(&self) -> Value {
+        let mut obj = Map::new();
+        obj.insert("name".into(), Value::String(self.name()));
+        obj.insert(
+            "aliases".into(),
+            Value::Array(self.aliases.iter().cloned().map(Value::String).collect()),
+        );
+        obj.insert("version".into(), Value::from(self.version));
+        obj.insert("compose".into(), Value::Bool(self.capabilities.compose));
+        obj.insert("invert".into(), Value::Bool(self.capabilities.invert));
+        Value::Object(obj)
+    }
+}
+
+/// `SubTypeFunctions` on typed operands, which are converted from and to
+/// `Value` with serde. Derive `SubTypeFunctions` with the `derive` feature to
+/// implement `SubTypeFunctions` with it. Operands failing to deserialize are
+/// invalid.
+pub trait TypedSubTypeFunctions {
+    type Operand: Serialize + DeserializeOwned;
+
+    fn invert(&self, path: &Path, operand: &Self::Operand) -> Result<Self::Operand>;
+
+    /// Merge other into base when they can be applied as one operand.
+    fn merge(&self, _base: &Self::Operand, _other: &Self::Operand) -> Option<Self::Operand> {
+        None
+    }
+
+    fn transform(
+        &self,
+        new: &Self::Operand,
+        base: &Self::Operand,
+        side: TransformSide,
+    ) -> Result<Vec<Self::Operand>>;
+
+    /// Apply operand on val, or on nothing if val is None. Returns the new
+    /// value.
+    fn apply(&self, val: Option<&Value>, operand: &Self::Operand) -> Result<Option<Value>>;
+
+    /// Validate an operand beyond its type.
+    fn validate(&self, _operand: &Self::Operand) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// `SubTypeFunctions` of a `TypedSubTypeFunctions`, called by the code
+/// derived for it.
+#[doc(hidden)]
+pub mod typed {
+    use super::*;
+
+    pub use crate::json::ApplyResult;
+
+    fn operand<T: TypedSubTypeFunctions + ?Sized>(val: &Value) -> Result<T::Operand> {
+        T::Operand::deserialize(val).map_err(|e| {
+            JsonError::InvalidOperation(format!(
+                "invalid operand: {val} for sub type {}, reason: {e}",
+                std::any::type_name::<T>()
+            ))
+        })
+    }
+
+    fn to_value<T: TypedSubTypeFunctions + ?Sized>(operand: &T::Operand) -> Result<Value> {
+        serde_json::to_value(operand).map_err(|e| {
+            JsonError::InvalidOperation(format!(
+                "serialize operand of sub type {} failed, reason: {e}",
+                std::any::type_name::<T>()
+            ))
+        })
+    }
+
+    pub fn invert<T: TypedSubTypeFunctions + ?Sized>(
+        f: &T,
+        path: &Path,
+        val: &Value,
+    ) -> Result<Value> {
+        to_value::<T>(&f.invert(path, &operand::<T>(val)?)?)
+    }
+
+    pub fn merge<T: TypedSubTypeFunctions + ?Sized>(
+        f: &T,
+        base: &Value,
+        other: &Value,
+    ) -> Option<Value> {
+        let merged = f.merge(&operand::<T>(base).ok()?, &operand::<T>(other).ok()?)?;
+        to_value::<T>(&merged).ok()
+    }
+
+    pub fn transform<T: TypedSubTypeFunctions + ?Sized>(
+        f: &T,
+        new: &Value,
+        base: &Value,
+        side: TransformSide,
+    ) -> Result<Vec<Value>> {
+        f.transform(&operand::<T>(new)?, &operand::<T>(base)?, side)?
+            .iter()
+            .map(to_value::<T>)
+            .collect()
+    }
+
+    pub fn apply<T: TypedSubTypeFunctions + ?Sized>(
+        f: &T,
+        val: Option<&Value>,
+        sub_type_operand: &Value,
+    ) -> ApplyResult<Option<Value>> {
+        operand::<T>(sub_type_operand)
+            .and_then(|o| f.apply(val, &o))
+            .map_err(
+                |e| ApplyOperationError::InvalidApplySubtypeOperationTarget {
+                    subtype_name: std::any::type_name::<T>().into(),
+                    target_value: Box::new(val.cloned().unwrap_or(Value::Null)),
+                    subtype_operand: Box::new(sub_type_operand.clone()),
+                    reason: e.to_string(),
+                },
+            )
+    }
+
+    pub fn validate_operand<T: TypedSubTypeFunctions + ?Sized>(f: &T, val: &Value) -> Result<()> {
+        f.validate(&operand::<T>(val)?)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -39,15 +212,7 @@ impl TryFrom<&Value> for SubType {
 
     fn try_from(value: &Value) -> std::result::Result<Self, Self::Error> {
         match value {
-            Value::String(sub) => {
-                if sub.eq(NUMBER_ADD_SUB_TYPE_NAME) {
-                    return Ok(SubType::NumberAdd);
-                }
-                if sub.eq(TEXT_SUB_TYPE_NAME) {
-                    return Ok(SubType::Text);
-                }
-                Ok(SubType::Custome(sub.to_string()))
-            }
+            Value::String(sub) => Ok(SubType::from_name(sub)),
             _ => Err(JsonError::InvalidOperation(format!(
                 "invalid sub type: {}",
                 value
@@ -56,6 +221,16 @@ impl TryFrom<&Value> for SubType {
     }
 }
 
+impl SubType {
+    fn from_name(name: &str) -> SubType {
+        match name {
+            NUMBER_ADD_SUB_TYPE_NAME => SubType::NumberAdd,
+            TEXT_SUB_TYPE_NAME => SubType::Text,
+            _ => SubType::Custome(name.into()),
+        }
+    }
+}
+
 impl Display for SubType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s: String = match self {
@@ -68,18 +243,54 @@ impl Display for SubType {
     }
 }
 
+/// Functions of sub types by sub type, as registered at some point.
+pub type SubTypeSnapshot = Arc<HashMap<SubType, Arc<dyn SubTypeFunctions>>>;
+
+/// Registry of the functions of sub types by their names, like `na` and
+/// `text`. Lookups read an immutable snapshot of the registry which is only
+/// copied when it's changed while a snapshot taken before is still alive.
+///
+/// The registry is `Send` and `Sync`. Lookups only hold a read lock to clone
+/// the snapshot, and changes hold the write lock while replacing it, so a
+/// lookup sees the registry either before or after each change. Changes of
+/// sub types and aliases are made one at a time and may be seen apart.
 pub struct SubTypeFunctionsHolder {
-    subtype_operators: DashMap<SubType, Arc<dyn SubTypeFunctions>>,
+    subtype_operators: RwLock<SubTypeSnapshot>,
+    aliases: RwLock<HashMap<String, SubType>>,
 }
 
 impl SubTypeFunctionsHolder {
+    /// A registry with the built-in `na`, `text` and `text-delta` sub types.
     pub fn new() -> SubTypeFunctionsHolder {
-        let subtype_operators: DashMap<SubType, Arc<dyn SubTypeFunctions>> = DashMap::new();
-        subtype_operators.insert(SubType::NumberAdd, Arc::new(NumberAddSubType {}));
+        let mut subtype_operators: HashMap<SubType, Arc<dyn SubTypeFunctions>> = HashMap::new();
+        subtype_operators.insert(
+            SubType::NumberAdd,
+            Arc::new(NumberAddSubType {
+                overflow: NumberOverflow::default(),
+            }),
+        );
         subtype_operators.insert(SubType::Text, Arc::new(TextSubType {}));
-        SubTypeFunctionsHolder { subtype_operators }
+        subtype_operators.insert(
+            SubType::Custome(TEXT_DELTA_SUB_TYPE_NAME.into()),
+            Arc::new(TextDeltaSubType {}),
+        );
+        SubTypeFunctionsHolder {
+            subtype_operators: RwLock::new(Arc::new(subtype_operators)),
+            aliases: RwLock::default(),
+        }
     }
 
+    /// A registry without any sub type, not even the built-in ones.
+    pub fn empty() -> SubTypeFunctionsHolder {
+        SubTypeFunctionsHolder {
+            subtype_operators: RwLock::default(),
+            aliases: RwLock::default(),
+        }
+    }
+
+    /// Register functions of sub type, replacing and returning the ones
+    /// registered before. Registering `na` or `text` overrides the built-in
+    /// implementation.
     pub fn register_subtype<S, T>(
         &self,
         sub_type: S,
@@ -89,38 +300,112 @@ impl SubTypeFunctionsHolder {
         S: AsRef<str>,
         T: SubTypeFunctions + 'static,
     {
-        if sub_type.as_ref().eq(NUMBER_ADD_SUB_TYPE_NAME)
-            || sub_type.as_ref().eq(TEXT_SUB_TYPE_NAME)
-        {
-            return Err(JsonError::ConflictSubType(sub_type.as_ref().into()));
-        }
-
-        Ok(self
-            .subtype_operators
-            .insert(SubType::Custome(sub_type.as_ref().into()), Arc::new(o)))
+        Ok(self.update(|m| m.insert(SubType::from_name(sub_type.as_ref()), Arc::new(o))))
     }
 
     pub fn unregister_subtype<S: AsRef<str>>(
         &self,
         sub_type: S,
     ) -> Option<Arc<dyn SubTypeFunctions>> {
-        if sub_type.as_ref().eq(NUMBER_ADD_SUB_TYPE_NAME)
-            || sub_type.as_ref().eq(TEXT_SUB_TYPE_NAME)
-        {
-            return None;
+        self.update(|m| m.remove(&SubType::from_name(sub_type.as_ref())))
+    }
+
+    /// Register alias as another name of sub type, so operations using alias
+    /// are parsed as sub type, and written with its name. Returns the sub type
+    /// alias was registered for before. The names of the built-in sub types
+    /// can't be aliases.
+    pub fn register_alias<S: AsRef<str>>(
+        &self,
+        alias: S,
+        sub_type: SubType,
+    ) -> Result<Option<SubType>> {
+        let alias = alias.as_ref();
+        if !matches!(SubType::from_name(alias), SubType::Custome(_)) {
+            return Err(JsonError::ConflictSubType(alias.into()));
+        }
+        let sub_type = match sub_type {
+            SubType::Custome(name) => self.sub_type(&name),
+            t => t,
+        };
+        if sub_type == SubType::Custome(alias.into()) {
+            return Err(JsonError::ConflictSubType(alias.into()));
+        }
+        Ok(self.aliases.write().unwrap().insert(alias.into(), sub_type))
+    }
+
+    pub fn unregister_alias<S: AsRef<str>>(&self, alias: S) -> Option<SubType> {
+        self.aliases.write().unwrap().remove(alias.as_ref())
+    }
+
+    /// The sub type named name, resolving aliases.
+    pub fn sub_type(&self, name: &str) -> SubType {
+        match self.aliases.read().unwrap().get(name) {
+            Some(t) => t.clone(),
+            None => SubType::from_name(name),
         }
+    }
 
-        self.subtype_operators
-            .remove(&SubType::Custome(sub_type.as_ref().into()))
-            .map(|s| s.1)
+    pub fn get(&self, sub_type: &SubType) -> Option<Arc<dyn SubTypeFunctions>> {
+        self.snapshot().get(sub_type).cloned()
     }
 
-    pub fn get(&self, sub_type: &SubType) -> Option<Ref<SubType, Arc<dyn SubTypeFunctions>>> {
-        self.subtype_operators.get(sub_type)
+    /// The registered sub types, sorted by name.
+    pub fn registered(&self) -> Vec<SubType> {
+        let mut sub_types = self.snapshot().keys().cloned().collect::<Vec<_>>();
+        sub_types.sort_by_key(|t| t.to_string());
+        sub_types
+    }
+
+    /// Version, capabilities and aliases of the registered sub types, sorted
+    /// by name.
+    pub fn infos(&self) -> Vec<SubTypeInfo> {
+        let snapshot = self.snapshot();
+        let aliases = self.aliases.read().unwrap();
+        let mut infos = snapshot
+            .iter()
+            .map(|(sub_type, f)| {
+                let mut names = aliases
+                    .iter()
+                    .filter(|(_, t)| *t == sub_type)
+                    .map(|(alias, _)| alias.clone())
+                    .collect::<Vec<_>>();
+                names.sort();
+                SubTypeInfo {
+                    aliases: names,
+                    version: f.version(),
+                    capabilities: f.capabilities(),
+                    sub_type: sub_type.clone(),
+                }
+            })
+            .collect::<Vec<_>>();
+        infos.sort_by_key(|info| info.sub_type.to_string());
+        infos
+    }
+
+    /// The registered functions as of now, which are not affected by changes
+    /// of the registry afterwards.
+    pub fn snapshot(&self) -> SubTypeSnapshot {
+        self.subtype_operators.read().unwrap().clone()
+    }
+
+    pub(crate) fn functions(&self, sub_type: &SubType) -> Result<Arc<dyn SubTypeFunctions>> {
+        self.get(sub_type)
+            .ok_or(JsonError::InvalidOperation(format!(
+                "no sub type functions for sub type: {}",
+                sub_type
+            )))
     }
 
     pub fn clear(&self) {
-        self.subtype_operators.clear();
+        self.update(|m| m.clear());
+        self.aliases.write().unwrap().clear();
+    }
+
+    fn update<R>(
+        &self,
+        f: impl FnOnce(&mut HashMap<SubType, Arc<dyn SubTypeFunctions>>) -> R,
+    ) -> R {
+        f(Arc::make_mut(&mut self.subtype_operators.write().unwrap()))
     }
 }
 
@@ -130,20 +415,122 @@ impl Default for SubTypeFunctionsHolder {
     }
 }
 
-struct NumberAddSubType {}
+/// How NumberAdd handles adding two i64 whose sum is out of the range of
+/// i64. Numbers never overflow with the arbitrary-precision feature.
+///
+/// Concurrent `na` are not transformed, as their sum is the same in any order.
+/// Only wrapping keeps that past the bounds: from `i64::MAX - 1`, `+2` then
+/// `-5` saturates to `i64::MAX - 5` while `-5` then `+2` gives `i64::MAX - 4`.
+/// So operations always wrap, and the other policies are only taken by a
+/// local apply through `ApplyOptions::number_overflow`, on values which are
+/// not edited concurrently.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NumberOverflow {
+    /// Wrap around like `i64::wrapping_add`.
+    #[default]
+    Wrap,
+    /// Clamp the sum to i64::MIN or i64::MAX.
+    Saturate,
+    /// Add them as f64 instead, like JavaScript does. Sums past the range of
+    /// i64 lose precision.
+    PromoteToFloat,
+    /// Fail to apply.
+    Error,
+}
+
+/// NumberAdd handling overflow by policy, for `ApplyOptions::number_overflow`.
+pub(crate) fn number_add(overflow: NumberOverflow) -> Arc<dyn SubTypeFunctions> {
+    Arc::new(NumberAddSubType { overflow })
+}
+
+struct NumberAddSubType {
+    overflow: NumberOverflow,
+}
+
+/// Sum of a and b, as i64 if both of them are i64 or as f64 otherwise. None
+/// if it overflows and overflow is `NumberOverflow::Error`.
+#[cfg(not(feature = "arbitrary-precision"))]
+fn add_numbers(a: &Number, b: &Number, overflow: NumberOverflow) -> Option<Value> {
+    if let (Some(a), Some(b)) = (a.as_i64(), b.as_i64()) {
+        return match (a.checked_add(b), overflow) {
+            (Some(sum), _) => Some(Value::from(sum)),
+            (None, NumberOverflow::Wrap) => Some(Value::from(a.wrapping_add(b))),
+            (None, NumberOverflow::Saturate) => Some(Value::from(a.saturating_add(b))),
+            (None, NumberOverflow::PromoteToFloat) => {
+                serde_json::to_value(a as f64 + b as f64).ok()
+            }
+            (None, NumberOverflow::Error) => None,
+        };
+    }
+    serde_json::to_value(a.as_f64()? + b.as_f64()?).ok()
+}
+
+#[cfg(not(feature = "arbitrary-precision"))]
+fn negate_number(n: &Number, overflow: NumberOverflow) -> Option<Value> {
+    if let Some(i) = n.as_i64() {
+        match (i.checked_neg(), overflow) {
+            (Some(neg), _) => Some(Value::from(neg)),
+            (None, NumberOverflow::Wrap) => Some(Value::from(i.wrapping_neg())),
+            (None, NumberOverflow::Saturate) => Some(Value::from(i64::MAX)),
+            (None, NumberOverflow::PromoteToFloat) => serde_json::to_value(-(i as f64)).ok(),
+            (None, NumberOverflow::Error) => None,
+        }
+    } else if n.is_f64() {
+        serde_json::to_value(-n.as_f64().unwrap()).ok()
+    } else {
+        None
+    }
+}
+
+#[cfg(feature = "arbitrary-precision")]
+fn to_decimal(n: &Number) -> Option<bigdecimal::BigDecimal> {
+    n.to_string().parse().ok()
+}
+
+#[cfg(feature = "arbitrary-precision")]
+fn is_integer(n: &Number) -> bool {
+    !n.to_string().contains(['.', 'e', 'E'])
+}
+
+/// Numbers are compared by their text with arbitrary_precision, so the
+/// result is written like serde_json writes a f64 if it is not an integer:
+/// without trailing zeros and with at least one fractional digit.
+#[cfg(feature = "arbitrary-precision")]
+fn from_decimal(d: bigdecimal::BigDecimal, integer: bool) -> Option<Value> {
+    let mut s = d.normalized().to_plain_string();
+    if !integer && !s.contains('.') {
+        s.push_str(".0");
+    }
+    serde_json::from_str::<Number>(&s).ok().map(Value::Number)
+}
+
+/// Sum of a and b as arbitrary precision decimals.
+#[cfg(feature = "arbitrary-precision")]
+fn add_numbers(a: &Number, b: &Number, _: NumberOverflow) -> Option<Value> {
+    let integer = is_integer(a) && is_integer(b);
+    from_decimal(to_decimal(a)? + to_decimal(b)?, integer)
+}
+
+#[cfg(feature = "arbitrary-precision")]
+fn negate_number(n: &Number, _: NumberOverflow) -> Option<Value> {
+    from_decimal(-to_decimal(n)?, is_integer(n))
+}
 
 impl SubTypeFunctions for NumberAddSubType {
+    fn capabilities(&self) -> SubTypeCapabilities {
+        SubTypeCapabilities {
+            compose: true,
+            invert: true,
+        }
+    }
+
     fn invert(&self, _: &Path, sub_type_operand: &Value) -> Result<Value> {
         if let Value::Number(n) = sub_type_operand {
-            if n.is_i64() {
-                Ok(serde_json::to_value(-n.as_i64().unwrap()).unwrap())
-            } else if n.is_f64() {
-                Ok(serde_json::to_value(-n.as_f64().unwrap()).unwrap())
-            } else {
-                Err(JsonError::InvalidOperation(format!(
+            negate_number(n, self.overflow).ok_or_else(|| {
+                JsonError::InvalidOperation(format!(
                     "invalid number value:\"{sub_type_operand}\" in NumberAdd sub type operand",
-                )))
-            }
+                ))
+            })
         } else {
             Err(JsonError::InvalidOperation(format!(
                 "invalid operand:\"{sub_type_operand}\" for NumberAdd sub type",
@@ -152,15 +539,21 @@ impl SubTypeFunctions for NumberAddSubType {
     }
 
     fn merge(&self, base_operand: &Value, other_operand: &Value) -> Option<Value> {
-        if base_operand.is_i64() && other_operand.is_i64() {
-            let new_v = base_operand.as_i64().unwrap() + other_operand.as_i64().unwrap();
-            Some(serde_json::to_value(new_v).unwrap())
-        } else if base_operand.is_f64() || other_operand.is_f64() {
-            let new_v = base_operand.as_f64().unwrap() + other_operand.as_f64().unwrap();
-            Some(serde_json::to_value(new_v).unwrap())
-        } else {
-            None
+        let (Value::Number(base), Value::Number(other)) = (base_operand, other_operand) else {
+            return None;
+        };
+        // u64 beyond i64 would lose precision as f64
+        #[cfg(not(feature = "arbitrary-precision"))]
+        if !(base.is_f64() || other.is_f64() || base.is_i64() && other.is_i64()) {
+            return None;
         }
+        // merged components must overflow like applying them one by one, so
+        // leave them to apply unless wrapping or promoting to f64 anyway
+        let overflow = match self.overflow {
+            NumberOverflow::Saturate => NumberOverflow::Error,
+            overflow => overflow,
+        };
+        add_numbers(base, other, overflow)
     }
 
     fn transform(&self, new: &Value, _: &Value, _: TransformSide) -> Result<Vec<Value>> {
@@ -170,29 +563,19 @@ impl SubTypeFunctions for NumberAddSubType {
     fn apply(&self, val: Option<&Value>, sub_type_operand: &Value) -> ApplyResult<Option<Value>> {
         if let Value::Number(new_n) = sub_type_operand {
             if let Some(old_v) = val {
-                match old_v {
-                    Value::Number(old_n) => {
-                        if old_n.is_i64() && new_n.is_i64() {
-                            return Ok(Some(
-                                serde_json::to_value(
-                                    old_n.as_i64().unwrap() + new_n.as_i64().unwrap(),
-                                )
-                                .unwrap(),
-                            ));
-                        }
-
-                        Ok(Some(
-                            serde_json::to_value(old_n.as_f64().unwrap() + new_n.as_f64().unwrap())
-                                .unwrap(),
-                        ))
-                    }
-                    _ => Err(ApplyOperationError::InvalidApplySubtypeOperationTarget {
+                let error =
+                    |reason: &str| ApplyOperationError::InvalidApplySubtypeOperationTarget {
                         subtype_name: SubType::NumberAdd.to_string(),
-                        target_value: old_v.clone(),
-                        subtype_operand: sub_type_operand.clone(),
-                        reason: "NumberAdd operation must apply to a number value".to_string(),
-                    }),
-                }
+                        target_value: Box::new(old_v.clone()),
+                        subtype_operand: Box::new(sub_type_operand.clone()),
+                        reason: reason.to_string(),
+                    };
+                let Value::Number(old_n) = old_v else {
+                    return Err(error("NumberAdd operation must apply to a number value"));
+                };
+                add_numbers(old_n, new_n, self.overflow)
+                    .map(Some)
+                    .ok_or_else(|| error("sum of NumberAdd operation overflows i64"))
             } else {
                 Ok(Some(sub_type_operand.clone()))
             }
@@ -211,11 +594,56 @@ impl SubTypeFunctions for NumberAddSubType {
     }
 }
 
+/// What a text delete removes, the text or only its length in bytes.
+#[derive(Debug, Clone, PartialEq)]
+enum TextDelete {
+    Text(String),
+    Len(usize),
+}
+
+impl TextDelete {
+    fn len(&self) -> usize {
+        match self {
+            TextDelete::Text(s) => s.len(),
+            TextDelete::Len(len) => *len,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The part of bytes in range of the deleted text.
+    fn slice(&self, range: Range<usize>) -> TextDelete {
+        match self {
+            TextDelete::Text(s) => TextDelete::Text(s[range].into()),
+            TextDelete::Len(_) => TextDelete::Len(range.len()),
+        }
+    }
+
+    /// The deleted text with bytes in range cut out.
+    fn without(&self, range: Range<usize>) -> TextDelete {
+        match self {
+            TextDelete::Text(s) => {
+                TextDelete::Text(format!("{}{}", &s[..range.start], &s[range.end..]))
+            }
+            TextDelete::Len(len) => TextDelete::Len(len - range.len()),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match self {
+            TextDelete::Text(s) => Value::String(s.clone()),
+            TextDelete::Len(len) => Value::from(*len),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 struct TextOperand {
     offset: usize,
     insert: Option<String>,
-    delete: Option<String>,
+    delete: Option<TextDelete>,
 }
 
 impl TextOperand {
@@ -226,7 +654,7 @@ impl TextOperand {
             delete: None,
         }
     }
-    fn new_delete(offset: usize, delete: String) -> TextOperand {
+    fn new_delete(offset: usize, delete: TextDelete) -> TextOperand {
         TextOperand {
             offset,
             insert: None,
@@ -242,13 +670,20 @@ impl TextOperand {
     fn get_insert(&self) -> &Option<String> {
         &self.insert
     }
-    fn get_delete(&self) -> &Option<String> {
+    fn get_delete(&self) -> &Option<TextDelete> {
         &self.delete
     }
+    /// The deleted text, None if it's not a delete or only of the length.
+    fn get_delete_text(&self) -> Option<&String> {
+        match &self.delete {
+            Some(TextDelete::Text(s)) => Some(s),
+            _ => None,
+        }
+    }
     fn uncheck_get_insert(&self) -> String {
         self.get_insert().as_ref().unwrap().clone()
     }
-    fn uncheck_get_delete(&self) -> String {
+    fn uncheck_get_delete(&self) -> TextDelete {
         self.get_delete().as_ref().unwrap().clone()
     }
     fn to_value(&self) -> Value {
@@ -260,7 +695,7 @@ impl TextOperand {
         }
 
         if let Some(d) = &self.delete {
-            op.insert("d".into(), Value::String(d.clone()));
+            op.insert("d".into(), d.to_value());
         }
         Value::Object(op)
     }
@@ -282,14 +717,12 @@ impl TryFrom<&Value> for TextOperand {
                 "text sub type operand does not contains Offset".into(),
             ));
         }
-        if !p.unwrap().is_i64() {
+        let Some(offset) = p.unwrap().as_u64().and_then(|p| usize::try_from(p).ok()) else {
             return Err(JsonError::InvalidOperation(format!(
-                "offset: {} in text sub type operand is not value number",
+                "offset: {} in text sub type operand is not a non-negative integer",
                 p.unwrap()
             )));
-        }
-
-        let offset = p.unwrap().as_i64().unwrap() as usize;
+        };
 
         if let Some(insert) = val.get("i") {
             if val.get("d").is_some() {
@@ -312,17 +745,19 @@ impl TryFrom<&Value> for TextOperand {
         }
 
         if let Some(delete) = val.get("d") {
+            if let Some(len) = text_delete_len(val) {
+                return Ok(TextOperand::new_delete(offset, TextDelete::Len(len)));
+            }
             if !delete.is_string() {
                 return Err(JsonError::InvalidOperation(format!(
                     "text delete non-string value: {}",
                     delete
                 )));
             }
-            return Ok(TextOperand {
+            return Ok(TextOperand::new_delete(
                 offset,
-                insert: None,
-                delete: Some(delete.as_str().unwrap().into()),
-            });
+                TextDelete::Text(delete.as_str().unwrap().into()),
+            ));
         }
         Err(JsonError::InvalidOperation(format!(
             "invalid text operand: {}",
@@ -336,9 +771,17 @@ struct TextSubType {}
 impl TextSubType {
     fn invert_object(&self, op: &TextOperand) -> Result<TextOperand> {
         if let Some(i) = op.get_insert() {
-            Ok(TextOperand::new_delete(op.offset, i.clone()))
-        } else if let Some(d) = op.get_delete() {
+            Ok(TextOperand::new_delete(
+                op.offset,
+                TextDelete::Text(i.clone()),
+            ))
+        } else if let Some(d) = op.get_delete_text() {
             Ok(TextOperand::new_insert(op.offset, d.clone()))
+        } else if op.is_delete() {
+            Err(JsonError::InvalidOperation(format!(
+                "text delete of length: \"{}\" must be resolved against the document to invert",
+                op.to_value()
+            )))
         } else {
             Err(JsonError::InvalidOperation(format!(
                 "invalid sub type operand:\"{}\" for TextSubType",
@@ -366,6 +809,13 @@ impl TextSubType {
 }
 
 impl SubTypeFunctions for TextSubType {
+    fn capabilities(&self) -> SubTypeCapabilities {
+        SubTypeCapabilities {
+            compose: true,
+            invert: true,
+        }
+    }
+
     fn invert(&self, _: &Path, sub_type_operand: &Value) -> Result<Value> {
         let s: TextOperand = sub_type_operand.try_into()?;
         Ok(self.invert_object(&s)?.to_value())
@@ -389,24 +839,45 @@ impl SubTypeFunctions for TextSubType {
 
             return Some(TextOperand::new_insert(base_op.offset, s).to_value());
         }
-        if base_op.is_delete()
-            && other_op.is_delete()
-            && other_op <= base_op
-            && base_op.offset <= other_op.offset + other_op.uncheck_get_delete().len()
-        {
-            let s = format!(
-                "{}{}{}",
-                &other_op.uncheck_get_delete()[0..base_op.offset - other_op.offset],
-                &base_op.uncheck_get_delete(),
-                &other_op.uncheck_get_delete()[base_op.offset - other_op.offset..],
-            );
-
-            return Some(TextOperand::new_delete(other_op.offset, s).to_value());
+        if let (Some(base_d), Some(other_d)) = (base_op.get_delete(), other_op.get_delete()) {
+            if other_op <= base_op && base_op.offset <= other_op.offset + other_d.len() {
+                let split = base_op.offset - other_op.offset;
+                let d = match (base_d, other_d) {
+                    (TextDelete::Text(base_d), TextDelete::Text(other_d)) => TextDelete::Text(
+                        format!("{}{}{}", &other_d[0..split], base_d, &other_d[split..]),
+                    ),
+                    (TextDelete::Len(base_len), TextDelete::Len(other_len)) => {
+                        TextDelete::Len(base_len + other_len)
+                    }
+                    _ => return None,
+                };
+                return Some(TextOperand::new_delete(other_op.offset, d).to_value());
+            }
         }
 
         None
     }
 
+    fn compose(&self, base: &Value, other_operand: &Value) -> Option<Value> {
+        if let Some(merged) = self.merge(base, other_operand) {
+            return Some(merged);
+        }
+        let base_op: TextOperand = base.try_into().ok()?;
+        let other_op: TextOperand = other_operand.try_into().ok()?;
+        // deletes a part of the inserted text
+        let (Some(insert), Some(delete)) = (base_op.get_insert(), other_op.get_delete_text())
+        else {
+            return None;
+        };
+        let start = other_op.offset.checked_sub(base_op.offset)?;
+        let end = start + delete.len();
+        if insert.get(start..end)? != delete {
+            return None;
+        }
+        let s = format!("{}{}", &insert[..start], &insert[end..]);
+        Some(TextOperand::new_insert(base_op.offset, s).to_value())
+    }
+
     fn transform(&self, new: &Value, base: &Value, side: TransformSide) -> Result<Vec<Value>> {
         let new_operand: TextOperand = new.try_into()?;
         let base_operand: TextOperand = base.try_into()?;
@@ -424,14 +895,14 @@ impl SubTypeFunctions for TextSubType {
                 let base_p = base_operand.offset;
                 let new_p = new_operand.offset;
                 if new_operand < base_operand {
+                    // the part deleted before the inserted text, which may be
+                    // all of it
+                    let before = (base_p - new_p).min(d_str.len());
                     ops.push(
-                        TextOperand::new_delete(
-                            new_operand.offset,
-                            d_str[0..(base_p - new_p)].into(),
-                        )
-                        .to_value(),
+                        TextOperand::new_delete(new_operand.offset, d_str.slice(0..before))
+                            .to_value(),
                     );
-                    d_str = d_str[base_p - new_p..].into();
+                    d_str = d_str.slice(before..d_str.len());
                 }
                 if !d_str.is_empty() {
                     ops.push(
@@ -450,18 +921,15 @@ impl SubTypeFunctions for TextSubType {
                 } else if new_operand.offset + d_str.len() <= base_operand.offset {
                     ops.push(new.clone())
                 } else {
-                    let mut new_d = "";
-                    if new_operand.offset < base_operand.offset {
-                        new_d = &d_str[0..base_operand.offset - new_operand.offset]
-                    }
-                    if new_operand.offset + d_str.len() > base_operand.offset + base_d_str.len() {
-                        new_d =
-                            &d_str[base_operand.offset + base_d_str.len() - new_operand.offset..]
-                    }
+                    // keep the parts not deleted by base on both sides
+                    let start = base_operand.offset.saturating_sub(new_operand.offset);
+                    let end = (base_operand.offset + base_d_str.len() - new_operand.offset)
+                        .min(d_str.len());
+                    let new_d = d_str.without(start..end);
 
                     if !new_d.is_empty() {
                         let p = self.transform_position(new_operand.offset, &base_operand, false);
-                        ops.push(TextOperand::new_delete(p, new_d.into()).to_value());
+                        ops.push(TextOperand::new_delete(p, new_d).to_value());
                     }
                 }
             }
@@ -470,55 +938,90 @@ impl SubTypeFunctions for TextSubType {
     }
 
     fn apply(&self, val: Option<&Value>, sub_type_operand: &Value) -> ApplyResult<Option<Value>> {
-        let sub_operand: TextOperand = sub_type_operand.try_into().unwrap();
+        let invalid_operator =
+            |target_value: &Value, reason: String| ApplyOperationError::InvalidSubtypeOperator {
+                subtype_name: SubType::Text.to_string(),
+                subtype_operand: Box::new(sub_type_operand.clone()),
+                target_value: Box::new(target_value.clone()),
+                reason,
+            };
+        let not_string =
+            |target_value: &Value| ApplyOperationError::InvalidApplySubtypeOperationTarget {
+                subtype_name: SubType::Text.to_string(),
+                target_value: Box::new(target_value.clone()),
+                subtype_operand: Box::new(sub_type_operand.clone()),
+                reason: "Text operation must apply to a string value".to_string(),
+            };
+        if let Some(len) = text_delete_len(sub_type_operand) {
+            let p = sub_type_operand
+                .get("p")
+                .and_then(|p| p.as_u64())
+                .unwrap_or(0) as usize;
+            return match val {
+                None | Some(Value::Null) => Ok(None),
+                Some(v @ Value::String(s)) => match delete_range(s, p, len) {
+                    Some(deleted) => Ok(Some(Value::String(format!(
+                        "{}{}",
+                        &s[0..p],
+                        &s[p + deleted.len()..]
+                    )))),
+                    None => Err(invalid_operator(
+                        v,
+                        format!(
+                            "range: {}..{} to delete is out of text of length: {} or splits a char",
+                            p,
+                            p.saturating_add(len),
+                            s.len()
+                        ),
+                    )),
+                },
+                Some(v) => Err(not_string(v)),
+            };
+        }
+        let sub_operand: TextOperand = sub_type_operand
+            .try_into()
+            .map_err(|e: JsonError| invalid_operator(&Value::Null, e.to_string()))?;
         let p = sub_operand.offset;
         if let Some(v) = val {
             match v {
                 Value::Null => {}
                 Value::String(s) => {
                     if let Some(insert) = sub_operand.get_insert() {
-                        if p <= s.len() {
-                            return Ok(Some(Value::String(format!(
-                                "{}{}{}",
-                                &s[0..p],
-                                insert,
-                                &s[p..]
-                            ))));
-                        } else {
+                        if p > s.len() {
                             return Ok(Some(Value::String(format!("{}{}", s, insert))));
                         }
-                    } else {
-                        let to_delete = sub_operand.uncheck_get_delete();
-                        let deleted = &s[p..to_delete.len()];
-                        if !to_delete.eq(deleted) {
-                            return Err(ApplyOperationError::InvalidSubtypeOperator {
-                                subtype_name: SubType::Text.to_string(),
-                                subtype_operand: sub_type_operand.clone(),
-                                target_value: v.clone(),
-                                reason: "text to delete in text operation is not match target text"
-                                    .into(),
-                            });
+                        if !s.is_char_boundary(p) {
+                            return Err(invalid_operator(
+                                v,
+                                format!("offset: {} to insert at splits a char", p),
+                            ));
                         }
+                        return Ok(Some(Value::String(format!(
+                            "{}{}{}",
+                            &s[0..p],
+                            insert,
+                            &s[p..]
+                        ))));
+                    }
 
-                        if p <= s.len() {
-                            return Ok(Some(Value::String(format!(
-                                "{}{}",
-                                &s[0..p],
-                                &s[p + to_delete.len()..]
-                            ))));
-                        } else {
-                            return Ok(Some(v.clone()));
-                        }
+                    // deletes of the length form are applied above
+                    let to_delete = sub_operand.get_delete_text().cloned().unwrap_or_default();
+                    let actual = p.checked_add(to_delete.len()).and_then(|end| s.get(p..end));
+                    if actual != Some(to_delete.as_str()) {
+                        return Err(ApplyOperationError::TextDeleteMismatch {
+                            offset: p,
+                            expected: to_delete.clone(),
+                            actual: actual.map(String::from),
+                            text_len: s.len(),
+                        });
                     }
+                    return Ok(Some(Value::String(format!(
+                        "{}{}",
+                        &s[0..p],
+                        &s[p + to_delete.len()..]
+                    ))));
                 }
-                _ => {
-                    return Err(ApplyOperationError::InvalidApplySubtypeOperationTarget {
-                        subtype_name: SubType::Text.to_string(),
-                        target_value: v.clone(),
-                        subtype_operand: sub_type_operand.clone(),
-                        reason: "Text operation must apply to a string value".to_string(),
-                    });
-                }
+                _ => return Err(not_string(v)),
             }
         }
 
@@ -529,11 +1032,16 @@ impl SubTypeFunctions for TextSubType {
     }
 
     fn validate_operand(&self, val: &Value) -> Result<()> {
-        let p = val.get("p");
-        if p.is_none() {
+        let Some(p) = val.get("p") else {
             return Err(JsonError::InvalidOperation(
                 "text sub type operand does not contains Offset".into(),
             ));
+        };
+        if !p.is_u64() {
+            return Err(JsonError::InvalidOperation(format!(
+                "offset: {} in text sub type operand is not a non-negative integer",
+                p
+            )));
         }
 
         if let Some(insert) = val.get("i") {
@@ -546,7 +1054,7 @@ impl SubTypeFunctions for TextSubType {
         }
 
         if let Some(delete) = val.get("d") {
-            if !delete.is_string() {
+            if !delete.is_string() && !delete.is_u64() {
                 return Err(JsonError::InvalidOperation(format!(
                     "text delete non-string value: {}",
                     delete
@@ -556,3 +1064,28 @@ impl SubTypeFunctions for TextSubType {
         Ok(())
     }
 }
+
+/// Length in bytes of a text delete operand of the length form, like
+/// `{"p":1,"d":2}`.
+fn text_delete_len(operand: &Value) -> Option<usize> {
+    operand
+        .get("d")
+        .and_then(|d| d.as_u64())
+        .and_then(|d| usize::try_from(d).ok())
+}
+
+// text of len bytes from p, if it's in text and not splitting a char
+fn delete_range(text: &str, p: usize, len: usize) -> Option<&str> {
+    text.get(p..p.checked_add(len)?)
+}
+
+/// Turn a text delete operand of the length form into the form of the text
+/// it deletes from target, like `{"p":1,"d":2}` on `"abcd"` into
+/// `{"p":1,"d":"bc"}`. Returns None if operand is not of the length form or
+/// the range is not in target.
+pub(crate) fn resolve_text_delete(operand: &Value, target: Option<&Value>) -> Option<Value> {
+    let len = text_delete_len(operand)?;
+    let p = operand.get("p")?.as_u64()? as usize;
+    let deleted = delete_range(target?.as_str()?, p, len)?;
+    Some(TextOperand::new_delete(p, TextDelete::Text(deleted.into())).to_value())
+}
@@ -0,0 +1,561 @@
+//! Random documents and operations for fuzzing code built on json0, like
+//! checking custom sub types or transforms converge.
+
+use serde_json::{json, Map, Value};
+
+use crate::{
+    json::Appliable,
+    operation::{Operation, OperationFactory},
+    path::{Path, PathElement},
+    Json0,
+};
+
+/// Keys of random objects are taken from a few letters, so random operations
+/// often conflict with each other.
+const KEYS: &[&str] = &["a", "b", "c", "d", "e"];
+
+/// A small deterministic pseudo random number generator (splitmix64), so a
+/// failing case can be reproduced from its seed.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Random number in `0..n`. n must not be zero.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// True with a chance of 1 in n.
+    pub fn one_in(&mut self, n: usize) -> bool {
+        self.below(n) == 0
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+/// Random JSON value nested no deeper than depth.
+pub fn random_value(rng: &mut Rng, depth: usize) -> Value {
+    let kinds = if depth == 0 { 5 } else { 7 };
+    match rng.below(kinds) {
+        0 => Value::Null,
+        1 => Value::Bool(rng.one_in(2)),
+        2 | 3 => json!(rng.below(100) as i64 - 50),
+        4 => Value::String(random_text(rng, 5)),
+        5 => Value::Array(
+            (0..rng.below(4))
+                .map(|_| random_value(rng, depth - 1))
+                .collect(),
+        ),
+        _ => Value::Object(
+            (0..rng.below(4))
+                .map(|_| (rng.pick(KEYS).to_string(), random_value(rng, depth - 1)))
+                .collect(),
+        ),
+    }
+}
+
+/// Random object with some nested lists, objects, numbers and texts.
+pub fn random_document(rng: &mut Rng) -> Value {
+    let mut doc = Map::new();
+    for key in KEYS {
+        if !rng.one_in(3) {
+            doc.insert(key.to_string(), random_value(rng, 3));
+        }
+    }
+    Value::Object(doc)
+}
+
+/// Random valid operation of one to three components on doc. Every component
+/// is valid on doc with the components before it applied.
+pub fn random_operation(doc: &Value, rng: &mut Rng) -> Operation {
+    let factory = OperationFactory::default();
+    let mut doc = doc.clone();
+    let mut components = vec![];
+    let mut appended = false;
+    for _ in 0..1 + rng.below(3) {
+        let component = random_component(&doc, rng);
+        // an index can't be ordered with the values appended before it
+        // concurrently, see `PathElement::End`
+        let path = component["p"].as_array().unwrap();
+        if appended && path.iter().any(Value::is_u64) {
+            continue;
+        }
+        appended |= path.iter().any(|p| p.as_i64().is_some_and(|i| i < 0));
+        let op = factory
+            .from_value(component.clone())
+            .expect("random component is valid");
+        for c in op.iter() {
+            doc.apply(c.path.clone(), c.operator.clone())
+                .expect("random component applies");
+        }
+        components.push(component);
+    }
+    factory
+        .from_value(Value::Array(components))
+        .expect("random operation is valid")
+}
+
+/// Random history of n operations on doc, each valid with the operations
+/// before it applied. Returns it with the document all of them applied to.
+pub fn random_history(doc: &Value, n: usize, rng: &mut Rng) -> (Vec<Operation>, Value) {
+    let mut doc = doc.clone();
+    let mut history = Vec::with_capacity(n);
+    for _ in 0..n {
+        let op = random_operation(&doc, rng);
+        for c in op.iter() {
+            doc.apply(c.path.clone(), c.operator.clone())
+                .expect("random operation applies");
+        }
+        history.push(op);
+    }
+    (history, doc)
+}
+
+/// Objects nested depth levels deep under key "a", with a list of list_len
+/// numbers at the bottom. Returns it with the path of the list.
+pub fn nested_document(depth: usize, list_len: usize) -> (Value, Path) {
+    let mut doc = Value::Array((0..list_len).map(|i| json!(i)).collect());
+    let mut path = Path::root();
+    for _ in 0..depth {
+        doc = json!({ "a": doc });
+        path.push(PathElement::key("a"));
+    }
+    (doc, path)
+}
+
+/// Operation of n random inserts, deletes and replaces on the list of numbers
+/// 0 to len at path. Every component is valid with the components before it
+/// applied.
+pub fn random_list_operation(path: &Path, len: usize, n: usize, rng: &mut Rng) -> Operation {
+    let mut list = (0..len).map(|i| json!(i)).collect::<Vec<Value>>();
+    let mut components = Vec::with_capacity(n);
+    for _ in 0..n {
+        let mut p = path.to_value();
+        if list.is_empty() || rng.one_in(3) {
+            let index = rng.below(list.len() + 1);
+            let v = random_value(rng, 1);
+            p.as_array_mut().unwrap().push(json!(index));
+            components.push(json!({"p": p, "li": v}));
+            list.insert(index, v);
+            continue;
+        }
+        let index = rng.below(list.len());
+        p.as_array_mut().unwrap().push(json!(index));
+        if rng.one_in(2) {
+            components.push(json!({"p": p, "ld": list.remove(index)}));
+        } else {
+            let v = random_value(rng, 1);
+            components.push(json!({"p": p, "li": v, "ld": list[index]}));
+            list[index] = v;
+        }
+    }
+    OperationFactory::default()
+        .from_value(Value::Array(components))
+        .expect("random list operation is valid")
+}
+
+/// Operation of n random text inserts and deletes on text at path. Every
+/// component is valid with the components before it applied.
+pub fn random_text_operation(path: &Path, text: &str, n: usize, rng: &mut Rng) -> Operation {
+    let mut text = text.to_string();
+    let mut components = Vec::with_capacity(n);
+    for _ in 0..n {
+        let p = path.to_value();
+        let at = random_char_boundary(&text, rng);
+        if at == text.len() || rng.one_in(2) {
+            let insert = random_text(rng, 5);
+            text.insert_str(at, &insert);
+            components.push(json!({"p": p, "t": "text", "o": {"p": at, "i": insert}}));
+            continue;
+        }
+        let mut to = at;
+        for _ in 0..1 + rng.below(5) {
+            to = text[to..].chars().next().map_or(to, |c| to + c.len_utf8());
+        }
+        let delete = text.drain(at..to).collect::<String>();
+        components.push(json!({"p": p, "t": "text", "o": {"p": at, "d": delete}}));
+    }
+    OperationFactory::default()
+        .from_value(Value::Array(components))
+        .expect("random text operation is valid")
+}
+
+fn random_char_boundary(text: &str, rng: &mut Rng) -> usize {
+    let mut at = rng.below(text.len() + 1);
+    while !text.is_char_boundary(at) {
+        at -= 1;
+    }
+    at
+}
+
+fn random_component(doc: &Value, rng: &mut Rng) -> Value {
+    let (path, container) = random_container(doc, rng);
+    let mut p = path.to_value();
+    let p_elems = p.as_array_mut().unwrap();
+    match container {
+        Value::Object(obj) => {
+            let key = rng.pick(KEYS).to_string();
+            p_elems.push(Value::String(key.clone()));
+            match obj.get(&key) {
+                None => json!({"p": p, "oi": random_value(rng, 2)}),
+                Some(old) => match rng.below(4) {
+                    0 => json!({"p": p, "od": old}),
+                    1 => json!({"p": p, "oi": random_value(rng, 2), "od": old}),
+                    2 => match KEYS.iter().find(|k| !obj.contains_key(**k)) {
+                        Some(to) => json!({"p": p, "om": to}),
+                        None => json!({"p": p, "od": old}),
+                    },
+                    _ => random_edit(p, old, rng),
+                },
+            }
+        }
+        Value::Array(list) => {
+            if list.is_empty() || rng.one_in(3) {
+                // append from the end of the list sometimes
+                let index = match rng.below(list.len() + 2) {
+                    i if i > list.len() => -1,
+                    i => i as i64,
+                };
+                p_elems.push(json!(index));
+                return json!({"p": p, "li": random_value(rng, 2)});
+            }
+            let index = rng.below(list.len());
+            p_elems.push(json!(index));
+            let old = &list[index];
+            match rng.below(4) {
+                0 => json!({"p": p, "ld": old}),
+                1 => json!({"p": p, "li": random_value(rng, 2), "ld": old}),
+                2 => json!({"p": p, "lm": rng.below(list.len())}),
+                _ => random_edit(p, old, rng),
+            }
+        }
+        // the whole document is replaced
+        _ => json!({"p": [], "oi": random_document(rng), "od": container}),
+    }
+}
+
+/// Sub type operation on value if there's one for it, or replaces value.
+fn random_edit(p: Value, value: &Value, rng: &mut Rng) -> Value {
+    match value {
+        Value::Number(_) => json!({"p": p, "na": rng.below(20) as i64 - 10}),
+        Value::String(s) => {
+            // offsets are in bytes and must be on character boundaries
+            let boundaries = s
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain([s.len()])
+                .collect::<Vec<usize>>();
+            if rng.one_in(4) {
+                return json!({"p": p, "t": "text-delta", "o": random_text_delta(s, &boundaries, rng)});
+            }
+            let start = rng.below(boundaries.len());
+            if start + 1 == boundaries.len() || rng.one_in(2) {
+                let offset = boundaries[start];
+                return json!({"p": p, "t": "text", "o": {"p": offset, "i": random_text(rng, 3)}});
+            }
+            let end = start + 1 + rng.below(boundaries.len() - start - 1);
+            let (offset, to) = (boundaries[start], boundaries[end]);
+            // deletes of the length form sometimes
+            let delete = match rng.one_in(3) {
+                true => json!(to - offset),
+                false => json!(&s[offset..to]),
+            };
+            json!({"p": p, "t": "text", "o": {"p": offset, "d": delete}})
+        }
+        _ => {
+            let last = p.as_array().unwrap().last().unwrap();
+            if last.is_string() {
+                json!({"p": p, "oi": random_value(rng, 2), "od": value})
+            } else {
+                json!({"p": p, "li": random_value(rng, 2), "ld": value})
+            }
+        }
+    }
+}
+
+/// Random text-delta operand of a few edits on s, which are on the character
+/// boundaries of s.
+fn random_text_delta(s: &str, boundaries: &[usize], rng: &mut Rng) -> Value {
+    let mut edits = vec![];
+    let mut at = 0;
+    for _ in 0..1 + rng.below(2) {
+        let start = at + rng.below(boundaries.len() - at);
+        if start > at {
+            edits.push(json!(boundaries[start] - boundaries[at]));
+        }
+        let end = start + rng.below(boundaries.len() - start);
+        if end > start {
+            edits.push(json!({"d": &s[boundaries[start]..boundaries[end]]}));
+        }
+        if end == start || rng.one_in(2) {
+            edits.push(json!(random_text(rng, 3)));
+        }
+        at = end;
+    }
+    Value::Array(edits)
+}
+
+/// Assert that concurrent operations a and b, both on doc, converge: applying
+/// a then b transformed against a gives the same document as applying b then
+/// a transformed against b. Returns the converged document.
+///
+/// Transforms and applies with json0, so sub types registered to it are
+/// checked too.
+pub fn check_convergence(json0: &Json0, doc: &Value, a: &Operation, b: &Operation) -> Value {
+    let context = || format!("doc: {}, a: {}, b: {}", doc, a, b);
+    let (a_prime, b_prime) = json0
+        .transform(a, b)
+        .unwrap_or_else(|e| panic!("transform failed: {}, {}", e, context()));
+
+    let mut left = doc.clone();
+    json0
+        .apply(&mut left, vec![a.clone(), b_prime.clone()])
+        .unwrap_or_else(|e| panic!("apply a then b' failed: {}, {}", e, context()));
+    let mut right = doc.clone();
+    json0
+        .apply(&mut right, vec![b.clone(), a_prime.clone()])
+        .unwrap_or_else(|e| panic!("apply b then a' failed: {}, {}", e, context()));
+
+    assert_eq!(
+        left,
+        right,
+        "operations diverged, a': {}, b': {}, {}",
+        a_prime,
+        b_prime,
+        context()
+    );
+    left
+}
+
+/// Run `check_convergence` on iterations pairs of random operations on random
+/// documents. The same seed checks the same operations.
+pub fn fuzz_convergence(json0: &Json0, seed: u64, iterations: usize) {
+    let mut rng = Rng::new(seed);
+    for _ in 0..iterations {
+        let doc = random_document(&mut rng);
+        let a = random_operation(&doc, &mut rng);
+        let b = random_operation(&doc, &mut rng);
+        check_convergence(json0, &doc, &a, &b);
+    }
+}
+
+/// Walk down doc to a random list or object, or the root if it's neither.
+fn random_container<'a>(doc: &'a Value, rng: &mut Rng) -> (Path, &'a Value) {
+    let mut path = Path::root();
+    let mut current = doc;
+    loop {
+        let children: Vec<(PathElement, &Value)> = match current {
+            Value::Object(obj) => obj
+                .iter()
+                .map(|(k, v)| (PathElement::key(k), v))
+                .filter(|(_, v)| v.is_object() || v.is_array())
+                .collect(),
+            Value::Array(list) => list
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (PathElement::Index(i), v))
+                .filter(|(_, v)| v.is_object() || v.is_array())
+                .collect(),
+            _ => vec![],
+        };
+        if children.is_empty() || rng.one_in(3) {
+            return (path, current);
+        }
+        let (elem, child) = children[rng.below(children.len())].clone();
+        path.push(elem);
+        current = child;
+    }
+}
+
+/// Random ASCII text no longer than max_len.
+fn random_text(rng: &mut Rng, max_len: usize) -> String {
+    (0..rng.below(max_len + 1))
+        .map(|_| (b'a' + rng.below(26) as u8) as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn test_random_operation() {
+        let json0 = Json0::new();
+        let mut rng = Rng::new(42);
+        for _ in 0..500 {
+            let mut doc = random_document(&mut rng);
+            for _ in 0..5 {
+                let op = random_operation(&doc, &mut rng);
+                assert!(!op.is_empty());
+                let origin = doc.clone();
+                json0.apply(&mut doc, vec![op.clone()]).unwrap();
+                // random operations carry the values they delete, so they
+                // are invertible, except for appends which need the length
+                // of the list
+                if op
+                    .iter()
+                    .any(|c| matches!(c.path.last(), Some(PathElement::End(_))))
+                {
+                    continue;
+                }
+                // text deletes of the length form need the deleted text
+                let op = json0.resolve_text_deletes(&origin, &op).unwrap();
+                let inverted = op
+                    .iter()
+                    .rev()
+                    .map(|c| c.invert().unwrap())
+                    .collect::<Vec<_>>();
+                let mut reverted = doc.clone();
+                json0
+                    .apply(&mut reverted, vec![Operation::from(inverted)])
+                    .unwrap();
+                assert_eq!(origin, reverted);
+            }
+        }
+
+        // same seed, same operations
+        let doc = random_document(&mut Rng::new(7));
+        assert_eq!(
+            random_operation(&doc, &mut Rng::new(7)),
+            random_operation(&doc, &mut Rng::new(7))
+        );
+    }
+
+    #[test]
+    fn test_workload_generators() {
+        let json0 = Json0::new();
+        let mut rng = Rng::new(42);
+
+        let (mut doc, path) = nested_document(10, 100);
+        assert_eq!(10, path.len());
+        let op = random_list_operation(&path, 100, 200, &mut rng);
+        assert_eq!(200, op.len());
+        json0.apply(&mut doc, vec![op]).unwrap();
+
+        let mut doc = json!({"t": "héllo wörld"});
+        let path = Path::try_from(&json!(["t"])).unwrap();
+        let op = random_text_operation(&path, "héllo wörld", 200, &mut rng);
+        assert_eq!(200, op.len());
+        json0.apply(&mut doc, vec![op]).unwrap();
+
+        let doc = random_document(&mut rng);
+        let (history, expect) = random_history(&doc, 50, &mut rng);
+        let mut applied = doc.clone();
+        json0
+            .apply(&mut applied, vec![Operation::compose_all(history).unwrap()])
+            .unwrap();
+        assert_eq!(expect, applied);
+    }
+
+    #[test]
+    fn test_check_convergence() {
+        let json0 = Json0::new();
+        let doc = json!({"list": [1, 2], "text": "abc"});
+        let parse = |v| json0.operation_factory().from_value(v).unwrap();
+        let a = parse(
+            json!([{"p": ["list", 0], "li": 0}, {"p": ["text"], "t": "text", "o": {"p": 1, "i": "x"}}]),
+        );
+        let b = parse(
+            json!([{"p": ["list", 1], "ld": 2}, {"p": ["text"], "t": "text", "o": {"p": 2, "d": "c"}}]),
+        );
+        assert_eq!(
+            json!({"list": [0, 1], "text": "axb"}),
+            check_convergence(&json0, &doc, &a, &b)
+        );
+    }
+
+    #[test]
+    fn test_fuzz_convergence() {
+        let json0 = Json0::new();
+        for seed in 0..20 {
+            fuzz_convergence(&json0, seed, 100);
+        }
+    }
+
+    #[test]
+    fn test_fuzz_regressions() {
+        let json0 = Json0::new();
+        let parse = |v| json0.operation_factory().from_value(v).unwrap();
+        let cases = [
+            // move to a key concurrently replaced after deleting it
+            (
+                json!({"a": 1, "b": 2}),
+                json!([{"p": ["b"], "od": 2}, {"p": ["a"], "om": "b"}]),
+                json!([{"p": ["b"], "oi": 3, "od": 2}]),
+                json!({"b": 1}),
+            ),
+            // delete vs replace of the same key
+            (
+                json!({"c": [41, true, []], "b": []}),
+                json!([{"p": ["c"], "od": [41, true, []]}, {"p": ["b", 0], "li": -7}]),
+                json!([{"p": ["c"], "oi": [null], "od": [41, true, []]}]),
+                json!({"b": [-7], "c": [null]}),
+            ),
+            // tie of equal inserts, one of them edited after
+            (
+                json!({"c": true, "e": null}),
+                json!([{"p": ["e"], "od": null}, {"p": ["a"], "oi": {}}]),
+                json!([
+                    {"p": ["e"], "oi": -9, "od": null},
+                    {"p": ["a"], "oi": {}},
+                    {"p": ["a", "d"], "oi": null}
+                ]),
+                json!({"a": {}, "c": true, "e": -9}),
+            ),
+            // moves transformed into moving to the same index, then deleted
+            (
+                json!({"c": ["x", null, "y"]}),
+                json!([
+                    {"p": ["c", 0], "lm": 0},
+                    {"p": ["c", 2], "li": ["z"]},
+                    {"p": ["c", 0], "lm": 1}
+                ]),
+                json!([{"p": ["c", 1], "lm": 2}, {"p": ["c"], "od": ["x", "y", null]}]),
+                json!({}),
+            ),
+            // text delete covering a concurrent one on both sides
+            (
+                json!({"c": "fgwlc"}),
+                json!([{"p": ["c"], "t": "text", "o": {"p": 0, "d": "fgw"}}]),
+                json!([{"p": ["c"], "t": "text", "o": {"p": 1, "d": "g"}}]),
+                json!({"c": "lc"}),
+            ),
+            // text and text-delta on the same text
+            (
+                json!({"e": "oa"}),
+                json!([{"p": ["e"], "t": "text", "o": {"p": 0, "d": 1}}]),
+                json!([{"p": ["e"], "t": "text-delta", "o": [{"d": "o"}, {"d": "a"}, "xtd"]}]),
+                json!({"e": "xtd"}),
+            ),
+            (
+                json!({"e": "abc"}),
+                json!([{"p": ["e"], "t": "text-delta", "o": [1, "x", {"d": "b"}]}]),
+                json!([{"p": ["e"], "t": "text", "o": {"p": 1, "i": "y"}}]),
+                json!({"e": "axyc"}),
+            ),
+        ];
+        for (doc, a, b, expect) in cases {
+            assert_eq!(
+                expect,
+                check_convergence(&json0, &doc, &parse(a), &parse(b))
+            );
+        }
+    }
+}
@@ -0,0 +1,623 @@
+use serde_json::{Map, Value};
+
+use crate::{
+    error::{JsonError, Result},
+    json::{ApplyOperationError, ApplyResult},
+    path::Path,
+    sub_type::{SubTypeCapabilities, SubTypeFunctions},
+    transformer::TransformSide,
+};
+
+/// Name of the text sub type whose operand is a `TextDelta`.
+pub const TEXT_DELTA_SUB_TYPE_NAME: &str = "text-delta";
+
+/// One step of a `TextDelta`. Lengths and offsets are in bytes, like the ones
+/// of the `text` sub type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextEdit {
+    /// Keep the next bytes of the text.
+    Retain(usize),
+    Insert(String),
+    /// Remove the text, which must be the next bytes of the text.
+    Delete(String),
+}
+
+impl TextEdit {
+    fn len(&self) -> usize {
+        match self {
+            TextEdit::Retain(n) => *n,
+            TextEdit::Insert(s) | TextEdit::Delete(s) => s.len(),
+        }
+    }
+}
+
+/// An edit of a whole text as a sequence of retains, inserts and deletes
+/// walking it from the start, like ot-text. Unlike the offset form of the
+/// `text` sub type, which takes a component for each insert or delete, any
+/// number of edits on a text are composed into one delta, and deltas are
+/// composed and transformed in one pass over both of them.
+///
+/// Its operand form is an array of numbers for retains, strings for inserts
+/// and `{"d": text}` for deletes, like `[2, "ab", {"d": "c"}]`. The text after
+/// the last edit is retained.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextDelta {
+    edits: Vec<TextEdit>,
+}
+
+impl TextDelta {
+    pub fn new() -> TextDelta {
+        TextDelta::default()
+    }
+
+    pub fn retain(mut self, n: usize) -> Self {
+        self.push(TextEdit::Retain(n));
+        self
+    }
+
+    pub fn insert<S: Into<String>>(mut self, s: S) -> Self {
+        self.push(TextEdit::Insert(s.into()));
+        self
+    }
+
+    pub fn delete<S: Into<String>>(mut self, s: S) -> Self {
+        self.push(TextEdit::Delete(s.into()));
+        self
+    }
+
+    pub fn edits(&self) -> &[TextEdit] {
+        &self.edits
+    }
+
+    pub fn is_noop(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// Append edit, merging it with the last edit of the same kind. An insert
+    /// right after a delete is put before it, so equal edits always have the
+    /// same form.
+    fn push(&mut self, edit: TextEdit) {
+        if edit.len() == 0 {
+            return;
+        }
+        if let TextEdit::Insert(s) = &edit {
+            if let Some(TextEdit::Delete(_)) = self.edits.last() {
+                let delete = self.edits.pop().unwrap();
+                self.push(TextEdit::Insert(s.clone()));
+                self.edits.push(delete);
+                return;
+            }
+        }
+        match (self.edits.last_mut(), edit) {
+            (Some(TextEdit::Retain(n)), TextEdit::Retain(m)) => *n += m,
+            (Some(TextEdit::Insert(s)), TextEdit::Insert(t))
+            | (Some(TextEdit::Delete(s)), TextEdit::Delete(t)) => s.push_str(&t),
+            (_, edit) => self.edits.push(edit),
+        }
+    }
+
+    // trailing retains are implied
+    fn trim(mut self) -> Self {
+        if let Some(TextEdit::Retain(_)) = self.edits.last() {
+            self.edits.pop();
+        }
+        self
+    }
+
+    /// Convert a text operand of the offset form, like `{"p":1,"i":"a"}`.
+    pub fn from_offset_operand(operand: &Value) -> Result<TextDelta> {
+        let p = operand
+            .get("p")
+            .and_then(|p| p.as_u64())
+            .and_then(|p| usize::try_from(p).ok())
+            .ok_or_else(|| {
+                JsonError::InvalidOperation(format!("invalid offset in text operand: {}", operand))
+            })?;
+        let delta = TextDelta::new().retain(p);
+        match (operand.get("i"), operand.get("d")) {
+            (Some(Value::String(i)), None) => Ok(delta.insert(i.as_str())),
+            (None, Some(Value::String(d))) => Ok(delta.delete(d.as_str())),
+            _ => Err(JsonError::InvalidOperation(format!(
+                "invalid text operand: {}",
+                operand
+            ))),
+        }
+    }
+
+    /// Compose text operands of the offset form, applied one after another,
+    /// into one delta.
+    pub fn from_offset_operands<'a, I>(operands: I) -> Result<TextDelta>
+    where
+        I: IntoIterator<Item = &'a Value>,
+    {
+        operands
+            .into_iter()
+            .try_fold(TextDelta::new(), |delta, operand| {
+                delta.compose(&TextDelta::from_offset_operand(operand)?)
+            })
+    }
+
+    /// Convert to text operands of the offset form, which have the same
+    /// effect when applied one after another.
+    pub fn to_offset_operands(&self) -> Vec<Value> {
+        let mut operands = vec![];
+        let mut p = 0;
+        for edit in self.edits.iter() {
+            let (key, s) = match edit {
+                TextEdit::Retain(n) => {
+                    p += n;
+                    continue;
+                }
+                TextEdit::Insert(s) => ("i", s),
+                TextEdit::Delete(s) => ("d", s),
+            };
+            let mut operand = Map::new();
+            operand.insert("p".into(), Value::from(p));
+            operand.insert(key.into(), Value::String(s.clone()));
+            operands.push(Value::Object(operand));
+            if key == "i" {
+                p += s.len();
+            }
+        }
+        operands
+    }
+
+    pub fn to_value(&self) -> Value {
+        Value::Array(
+            self.edits
+                .iter()
+                .map(|edit| match edit {
+                    TextEdit::Retain(n) => Value::from(*n),
+                    TextEdit::Insert(s) => Value::String(s.clone()),
+                    TextEdit::Delete(s) => {
+                        let mut d = Map::new();
+                        d.insert("d".into(), Value::String(s.clone()));
+                        Value::Object(d)
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    pub fn invert(&self) -> TextDelta {
+        let mut inverted = TextDelta::new();
+        for edit in self.edits.iter() {
+            inverted.push(match edit {
+                TextEdit::Retain(n) => TextEdit::Retain(*n),
+                TextEdit::Insert(s) => TextEdit::Delete(s.clone()),
+                TextEdit::Delete(s) => TextEdit::Insert(s.clone()),
+            });
+        }
+        inverted
+    }
+
+    pub fn apply(&self, text: &str) -> Result<String> {
+        self.apply_str(text).map_err(|reason| {
+            ApplyOperationError::InvalidSubtypeOperator {
+                subtype_name: TEXT_DELTA_SUB_TYPE_NAME.into(),
+                subtype_operand: Box::new(self.to_value()),
+                target_value: Box::new(Value::String(text.into())),
+                reason,
+            }
+            .into()
+        })
+    }
+
+    fn apply_str(&self, text: &str) -> std::result::Result<String, String> {
+        let mut out = String::with_capacity(text.len());
+        let mut p: usize = 0;
+        for edit in self.edits.iter() {
+            match edit {
+                TextEdit::Retain(n) => {
+                    let Some(kept) = p.checked_add(*n).and_then(|end| text.get(p..end)) else {
+                        return Err(format!(
+                            "retain of {} bytes from offset: {} is out of text of length: {} or splits a char",
+                            n,
+                            p,
+                            text.len()
+                        ));
+                    };
+                    out.push_str(kept);
+                    p += n;
+                }
+                TextEdit::Insert(s) => out.push_str(s),
+                TextEdit::Delete(s) => {
+                    if p.checked_add(s.len()).and_then(|end| text.get(p..end)) != Some(s) {
+                        return Err(format!(
+                            "text to delete: {:?} does not match text at offset: {}",
+                            s, p
+                        ));
+                    }
+                    p += s.len();
+                }
+            }
+        }
+        match text.get(p..) {
+            Some(rest) => out.push_str(rest),
+            None => return Err(format!("offset: {} splits a char", p)),
+        }
+        Ok(out)
+    }
+
+    /// The delta with the same effect as applying self and then other.
+    pub fn compose(&self, other: &TextDelta) -> Result<TextDelta> {
+        let mut out = TextDelta::new();
+        let mut base = Edits::new(&self.edits);
+        for edit in other.edits.iter() {
+            match edit {
+                TextEdit::Retain(n) => {
+                    let mut n = *n;
+                    while n > 0 {
+                        pass_deletes(&mut base, &mut out);
+                        let Some(piece) = base.take(n)? else {
+                            out.push(TextEdit::Retain(n));
+                            break;
+                        };
+                        n -= piece.len();
+                        out.push(piece);
+                    }
+                }
+                TextEdit::Insert(s) => out.push(TextEdit::Insert(s.clone())),
+                TextEdit::Delete(s) => {
+                    let mut rest = s.as_str();
+                    while !rest.is_empty() {
+                        pass_deletes(&mut base, &mut out);
+                        let Some(piece) = base.take(rest.len())? else {
+                            out.push(TextEdit::Delete(rest.into()));
+                            break;
+                        };
+                        let (deleted, after) = split_str(rest, piece.len())?;
+                        match piece {
+                            TextEdit::Retain(_) => out.push(TextEdit::Delete(deleted.into())),
+                            // inserted by self and deleted by other
+                            TextEdit::Insert(inserted) if inserted == deleted => {}
+                            _ => {
+                                return Err(JsonError::InvalidOperation(format!(
+                                    "text to delete: {:?} does not match the inserted text",
+                                    deleted
+                                )))
+                            }
+                        }
+                        rest = after;
+                    }
+                }
+            }
+        }
+        while let Some(piece) = base.take(usize::MAX)? {
+            out.push(piece);
+        }
+        Ok(out.trim())
+    }
+
+    /// Transform self against other, which edits the same text. On inserts at
+    /// the same position, self's text goes first if side is `Left`.
+    pub fn transform(&self, other: &TextDelta, side: TransformSide) -> Result<TextDelta> {
+        let side = match side {
+            TransformSide::Symmetric { .. } => side.resolve(&self.to_value(), &other.to_value()),
+            side => side,
+        };
+        let left = side == TransformSide::Left;
+        let mut out = TextDelta::new();
+        let mut new = Edits::new(&self.edits);
+        for edit in other.edits.iter() {
+            match edit {
+                TextEdit::Insert(s) => {
+                    if left {
+                        pass_inserts(&mut new, &mut out);
+                    }
+                    out.push(TextEdit::Retain(s.len()));
+                }
+                TextEdit::Retain(_) | TextEdit::Delete(_) => {
+                    let deleted = matches!(edit, TextEdit::Delete(_));
+                    let mut n = edit.len();
+                    while n > 0 {
+                        pass_inserts(&mut new, &mut out);
+                        let Some(piece) = new.take(n)? else {
+                            break;
+                        };
+                        n -= piece.len();
+                        // bytes deleted by other are neither retained nor
+                        // deleted again
+                        if !deleted {
+                            out.push(piece);
+                        }
+                    }
+                }
+            }
+        }
+        while let Some(piece) = new.take(usize::MAX)? {
+            out.push(piece);
+        }
+        Ok(out.trim())
+    }
+}
+
+impl TryFrom<&Value> for TextDelta {
+    type Error = JsonError;
+
+    fn try_from(value: &Value) -> std::result::Result<Self, Self::Error> {
+        let Value::Array(edits) = value else {
+            return Err(JsonError::InvalidOperation(format!(
+                "text delta operand: {} is not an array",
+                value
+            )));
+        };
+        let mut delta = TextDelta::new();
+        for edit in edits {
+            delta.push(
+                match edit {
+                    Value::Number(n) => n
+                        .as_u64()
+                        .and_then(|n| usize::try_from(n).ok())
+                        .map(TextEdit::Retain),
+                    Value::String(s) => Some(TextEdit::Insert(s.clone())),
+                    Value::Object(o) if o.len() == 1 => o
+                        .get("d")
+                        .and_then(|d| d.as_str())
+                        .map(|d| TextEdit::Delete(d.into())),
+                    _ => None,
+                }
+                .ok_or_else(|| {
+                    JsonError::InvalidOperation(format!("invalid edit: {} in text delta", edit))
+                })?,
+            );
+        }
+        Ok(delta.trim())
+    }
+}
+
+/// Walks edits, splitting them to take a number of bytes at a time.
+struct Edits<'a> {
+    edits: &'a [TextEdit],
+    // bytes of the current edit taken
+    offset: usize,
+}
+
+impl<'a> Edits<'a> {
+    fn new(edits: &'a [TextEdit]) -> Edits<'a> {
+        Edits { edits, offset: 0 }
+    }
+
+    fn peek(&self) -> Option<&'a TextEdit> {
+        self.edits.first()
+    }
+
+    /// Take at most max bytes of the current edit. None at the end.
+    fn take(&mut self, max: usize) -> Result<Option<TextEdit>> {
+        let Some(edit) = self.edits.first() else {
+            return Ok(None);
+        };
+        let n = (edit.len() - self.offset).min(max);
+        let piece = match edit {
+            TextEdit::Retain(_) => TextEdit::Retain(n),
+            TextEdit::Insert(s) => TextEdit::Insert(sub_str(s, self.offset, n)?.into()),
+            TextEdit::Delete(s) => TextEdit::Delete(sub_str(s, self.offset, n)?.into()),
+        };
+        self.offset += n;
+        if self.offset == edit.len() {
+            self.edits = &self.edits[1..];
+            self.offset = 0;
+        }
+        Ok(Some(piece))
+    }
+}
+
+// deletes of the base don't take any bytes of the text the next delta edits
+fn pass_deletes(base: &mut Edits, out: &mut TextDelta) {
+    while let Some(TextEdit::Delete(_)) = base.peek() {
+        if let Ok(Some(piece)) = base.take(usize::MAX) {
+            out.push(piece);
+        }
+    }
+}
+
+// inserts don't take any bytes of the text the other delta edits
+fn pass_inserts(new: &mut Edits, out: &mut TextDelta) {
+    while let Some(TextEdit::Insert(_)) = new.peek() {
+        if let Ok(Some(piece)) = new.take(usize::MAX) {
+            out.push(piece);
+        }
+    }
+}
+
+fn sub_str(s: &str, start: usize, len: usize) -> Result<&str> {
+    s.get(start..start + len).ok_or_else(|| {
+        JsonError::InvalidOperation(format!(
+            "edits of text deltas split a char of text: {:?}",
+            s
+        ))
+    })
+}
+
+fn split_str(s: &str, at: usize) -> Result<(&str, &str)> {
+    Ok((sub_str(s, 0, at)?, sub_str(s, at, s.len() - at)?))
+}
+
+/// Functions of the `text-delta` sub type.
+pub(crate) struct TextDeltaSubType {}
+
+impl SubTypeFunctions for TextDeltaSubType {
+    fn capabilities(&self) -> SubTypeCapabilities {
+        SubTypeCapabilities {
+            compose: true,
+            invert: true,
+        }
+    }
+
+    fn invert(&self, _: &Path, sub_type_operand: &Value) -> Result<Value> {
+        Ok(TextDelta::try_from(sub_type_operand)?.invert().to_value())
+    }
+
+    fn merge(&self, base_operand: &Value, other: &Value) -> Option<Value> {
+        let base = TextDelta::try_from(base_operand).ok()?;
+        let other = TextDelta::try_from(other).ok()?;
+        Some(base.compose(&other).ok()?.to_value())
+    }
+
+    fn transform(&self, new: &Value, base: &Value, side: TransformSide) -> Result<Vec<Value>> {
+        let new = TextDelta::try_from(new)?;
+        let base = TextDelta::try_from(base)?;
+        Ok(vec![new.transform(&base, side)?.to_value()])
+    }
+
+    fn apply(&self, val: Option<&Value>, sub_type_operand: &Value) -> ApplyResult<Option<Value>> {
+        let invalid_operator =
+            |target_value: Value, reason: String| ApplyOperationError::InvalidSubtypeOperator {
+                subtype_name: TEXT_DELTA_SUB_TYPE_NAME.into(),
+                subtype_operand: Box::new(sub_type_operand.clone()),
+                target_value: Box::new(target_value),
+                reason,
+            };
+        let delta = TextDelta::try_from(sub_type_operand)
+            .map_err(|e| invalid_operator(Value::Null, e.to_string()))?;
+        let text = match val {
+            None | Some(Value::Null) => "",
+            Some(Value::String(s)) => s,
+            Some(v) => {
+                return Err(ApplyOperationError::InvalidApplySubtypeOperationTarget {
+                    subtype_name: TEXT_DELTA_SUB_TYPE_NAME.into(),
+                    target_value: Box::new(v.clone()),
+                    subtype_operand: Box::new(sub_type_operand.clone()),
+                    reason: "Text operation must apply to a string value".to_string(),
+                })
+            }
+        };
+        delta
+            .apply_str(text)
+            .map(|s| Some(Value::String(s)))
+            .map_err(|reason| invalid_operator(Value::String(text.into()), reason))
+    }
+
+    fn validate_operand(&self, val: &Value) -> Result<()> {
+        TextDelta::try_from(val).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{operation::OperationFactory, testing::Rng, Json0};
+    use serde_json::json;
+    use test_log::test;
+
+    fn delta(v: Value) -> TextDelta {
+        TextDelta::try_from(&v).unwrap()
+    }
+
+    #[test]
+    fn test_text_delta_operand() {
+        let d = delta(json!([2, "ab", {"d": "c"}, 3]));
+        assert_eq!(json!([2, "ab", {"d": "c"}]), d.to_value());
+        assert_eq!(
+            delta(json!(["a", {"d": "x"}, "b"])),
+            TextDelta::new().insert("ab").delete("x")
+        );
+        assert_eq!("12abd", d.apply("12cd").unwrap());
+        assert_eq!("12cd", d.invert().apply("12abd").unwrap());
+        assert!(d.apply("12xd").is_err());
+        assert!(d.apply("1").is_err());
+        assert!(delta(json!([1, "x"])).apply("é").is_err());
+        for invalid in [json!({}), json!([-1]), json!([{"d": 1}]), json!([null])] {
+            assert!(TextDelta::try_from(&invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn test_text_delta_offset_form() {
+        let operands = [
+            json!({"p": 1, "i": "xy"}),
+            json!({"p": 0, "d": "a"}),
+            json!({"p": 3, "d": "c"}),
+            json!({"p": 3, "i": "z"}),
+        ];
+        let d = TextDelta::from_offset_operands(operands.iter()).unwrap();
+        assert_eq!(json!(["xy", {"d": "a"}, 1, "z", {"d": "c"}]), d.to_value());
+        assert_eq!("xybzd", d.apply("abcd").unwrap());
+
+        let mut text = "abcd".to_string();
+        for operand in d.to_offset_operands() {
+            text = TextDelta::from_offset_operand(&operand)
+                .unwrap()
+                .apply(&text)
+                .unwrap();
+        }
+        assert_eq!("xybzd", text);
+    }
+
+    // random delta on text made of chars, which are never split
+    fn random_delta(text: &str, rng: &mut Rng) -> TextDelta {
+        let chars = ["a", "é", "中"];
+        let mut d = TextDelta::new();
+        for c in text.chars() {
+            match rng.below(4) {
+                0 => d = d.delete(c.to_string()),
+                1 => d = d.insert(chars[rng.below(3)]).retain(c.len_utf8()),
+                _ => d = d.retain(c.len_utf8()),
+            }
+        }
+        if rng.one_in(2) {
+            d = d.insert(chars[rng.below(3)]);
+        }
+        d.trim()
+    }
+
+    #[test]
+    fn test_text_delta_compose_and_transform() {
+        let mut rng = Rng::new(3);
+        for _ in 0..500 {
+            let text = random_delta("", &mut rng).apply("").unwrap()
+                + &random_delta("", &mut rng).apply("").unwrap();
+            let a = random_delta(&text, &mut rng);
+            let after_a = a.apply(&text).unwrap();
+            let b = random_delta(&after_a, &mut rng);
+            assert_eq!(
+                b.apply(&after_a).unwrap(),
+                a.compose(&b).unwrap().apply(&text).unwrap()
+            );
+
+            let c = random_delta(&text, &mut rng);
+            let a1 = a.transform(&c, TransformSide::Left).unwrap();
+            let c1 = c.transform(&a, TransformSide::Right).unwrap();
+            assert_eq!(
+                a1.apply(&c.apply(&text).unwrap()).unwrap(),
+                c1.apply(&after_a).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_text_delta_sub_type() {
+        let json0 = Json0::new();
+        let f: &OperationFactory = json0.operation_factory();
+        let parse = |v: Value| f.from_value(v).unwrap();
+        let mut edits = parse(json!([{"p": ["t"], "t": "text-delta", "o": [1, "x"]}]));
+        for o in [json!([2, "y"]), json!([{"d": "a"}]), json!([2, {"d": "b"}])] {
+            edits
+                .compose(parse(json!([{"p": ["t"], "t": "text-delta", "o": o}])))
+                .unwrap();
+        }
+        assert_eq!(1, edits.len());
+
+        let mut value = json!({"t": "abc"});
+        json0.apply(&mut value, vec![edits.clone()]).unwrap();
+        assert_eq!(json!({"t": "xyc"}), value);
+        json0
+            .apply(&mut value, vec![edits.invert().unwrap()])
+            .unwrap();
+        assert_eq!(json!({"t": "abc"}), value);
+
+        let a = parse(json!([{"p": ["t"], "t": "text-delta", "o": [1, "a"]}]));
+        let b = parse(json!([{"p": ["t"], "t": "text-delta", "o": [1, "b"]}]));
+        let (a1, b1) = json0.transform(&a, &b).unwrap();
+        let mut v1 = json!({"t": "xy"});
+        json0.apply(&mut v1, vec![a.clone(), b1]).unwrap();
+        let mut v2 = json!({"t": "xy"});
+        json0.apply(&mut v2, vec![b, a1]).unwrap();
+        assert_eq!(json!({"t": "xaby"}), v1);
+        assert_eq!(v1, v2);
+
+        assert!(f
+            .from_value(json!([{"p": ["t"], "t": "text-delta", "o": [{"x": 1}]}]))
+            .is_err());
+    }
+}
@@ -0,0 +1,289 @@
+use serde_json::{json, Value};
+
+use crate::{
+    error::{JsonError, Result},
+    json::{ApplyOptions, Routable},
+    operation::{Operation, OperationComponent, Operator},
+    path::{Path, PathElement},
+    Json0,
+};
+
+/// Keep the values removed by deletes and replaces as tombstones instead of
+/// dropping them, so they can be restored. A tombstone is appended to the list
+/// under key of the root object for each removed value, as
+/// `{"path": [...], "value": ..., "deleted_at": ...}`. Operations are left
+/// unchanged, only the way they are applied is.
+///
+/// `deleted_at` is the timestamp in the meta of the removing operation, so
+/// replicas applying the same operations keep the same tombstones. It's the
+/// timestamp set here for operations without one, or null if there's none.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SoftDelete {
+    key: String,
+    timestamp: Option<u64>,
+}
+
+impl SoftDelete {
+    pub fn new<S: Into<String>>(key: S) -> SoftDelete {
+        SoftDelete {
+            key: key.into(),
+            timestamp: None,
+        }
+    }
+
+    /// Stamp the tombstones of operations without a timestamp in their meta
+    /// with timestamp.
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+}
+
+/// Components adding the tombstone of the value op deletes or replaces in
+/// value, which are applied right after op. Timestamp is the one in the meta
+/// of the operation of op. Empty if op removes nothing, or it removes the root
+/// or the tombstones themselves.
+pub(crate) fn tombstone_components(
+    value: &Value,
+    op: &OperationComponent,
+    timestamp: Option<u64>,
+    soft_delete: &SoftDelete,
+) -> Result<Vec<OperationComponent>> {
+    if !matches!(
+        op.operator,
+        Operator::ObjectDelete(_)
+            | Operator::ListDelete(_)
+            | Operator::ObjectReplace(_, _)
+            | Operator::ListReplace(_, _)
+    ) || op.path.is_empty()
+        || op.path.first_key_path() == Some(soft_delete.key.as_str())
+    {
+        return Ok(vec![]);
+    }
+    let Some(removed) = value.route_get(&op.path)? else {
+        return Ok(vec![]);
+    };
+    let tombstone = json!({
+        "path": op.path.to_value(),
+        "value": removed,
+        "deleted_at": timestamp.or(soft_delete.timestamp),
+    });
+
+    let list_path = Path::from(vec![PathElement::key(&soft_delete.key)]);
+    let mut components = vec![];
+    let len = match value.get(&soft_delete.key) {
+        Some(list) => list.as_array().map(|l| l.len()).unwrap_or(0),
+        None => {
+            components.push(OperationComponent::new(
+                list_path.clone(),
+                Operator::ObjectInsert(Value::Array(vec![]).into()),
+            )?);
+            0
+        }
+    };
+    components.push(OperationComponent::new(
+        list_path.join(&Path::from((len,))),
+        Operator::ListInsert(tombstone.into()),
+    )?);
+    Ok(components)
+}
+
+fn tombstones<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value
+        .get(key)
+        .and_then(|l| l.as_array())
+        .map(|l| l.as_slice())
+        .unwrap_or_default()
+}
+
+impl Json0 {
+    /// Delete the tombstones under key of value deleted before the
+    /// timestamp, including the ones without a timestamp, or all of them if
+    /// it's None. Returns the applied operation.
+    pub fn purge_tombstones(
+        &self,
+        value: &mut Value,
+        key: &str,
+        before: Option<u64>,
+    ) -> Result<Operation> {
+        let mut components = vec![];
+        for (i, tombstone) in tombstones(value, key).iter().enumerate().rev() {
+            let deleted_at = tombstone.get("deleted_at").and_then(|t| t.as_u64());
+            if before.is_none() || deleted_at < before {
+                components.push(OperationComponent::new(
+                    Path::from(vec![PathElement::key(key), PathElement::Index(i)]),
+                    Operator::ListDelete(tombstone.clone().into()),
+                )?);
+            }
+        }
+        let operation = Operation::from(components);
+        if !operation.is_empty() {
+            self.apply(value, vec![operation.clone()])?;
+        }
+        Ok(operation)
+    }
+
+    /// Put the value of the tombstone at index under key of value back to
+    /// the path it was deleted from, and delete the tombstone. Nothing is
+    /// changed if the value can't be put back. Returns the applied operation.
+    pub fn restore_tombstone(
+        &self,
+        value: &mut Value,
+        key: &str,
+        index: usize,
+    ) -> Result<Operation> {
+        let tombstone = tombstones(value, key).get(index).ok_or_else(|| {
+            JsonError::InvalidOperation(format!("no tombstone at index: {index} under: {key}"))
+        })?;
+        let path = tombstone
+            .get("path")
+            .ok_or_else(|| {
+                JsonError::InvalidOperation(format!("tombstone: {tombstone} has no path"))
+            })
+            .and_then(|p| Ok(Path::try_from(p)?))?;
+        let restored = tombstone.get("value").cloned().unwrap_or_default();
+        let operator = match path.last() {
+            Some(PathElement::Index(_)) => Operator::ListInsert(restored.into()),
+            _ => Operator::ObjectInsert(restored.into()),
+        };
+        let operation = Operation::from(vec![
+            OperationComponent::new(
+                Path::from(vec![PathElement::key(key), PathElement::Index(index)]),
+                Operator::ListDelete(tombstone.clone().into()),
+            )?,
+            OperationComponent::new(path, operator)?,
+        ]);
+        self.apply_with(
+            value,
+            vec![operation.clone()],
+            &ApplyOptions::new().atomic(true),
+        )?;
+        Ok(operation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use test_log::test;
+
+    #[test]
+    fn test_soft_delete() {
+        let json0 = Json0::new();
+        let options = ApplyOptions::new().soft_delete(Some(SoftDelete::new("_trash").timestamp(7)));
+        let op = json0
+            .operation_factory()
+            .from_value(json!([
+                {"p": ["a"], "od": 1},
+                {"p": ["l", 0], "ld": "x"},
+                {"p": ["b"], "od": 2, "oi": 3}
+            ]))
+            .unwrap();
+        let mut value = json!({"a": 1, "b": 2, "l": ["x", "y"]});
+        let origin = value.clone();
+        let undo = json0.apply_with(&mut value, vec![op], &options).unwrap();
+        assert_eq!(
+            json!({
+                "b": 3,
+                "l": ["y"],
+                "_trash": [
+                    {"path": ["a"], "value": 1, "deleted_at": 7},
+                    {"path": ["l", 0], "value": "x", "deleted_at": 7},
+                    {"path": ["b"], "value": 2, "deleted_at": 7}
+                ]
+            }),
+            value
+        );
+        let mut reverted = value.clone();
+        json0.apply(&mut reverted, vec![undo]).unwrap();
+        assert_eq!(origin, reverted);
+
+        // deleting tombstones doesn't make new ones
+        let op = json0
+            .operation_factory()
+            .from_value(
+                json!([{"p": ["_trash", 0], "ld": {"path": ["a"], "value": 1, "deleted_at": 7}}]),
+            )
+            .unwrap();
+        json0.apply_with(&mut value, vec![op], &options).unwrap();
+        assert_eq!(2, value["_trash"].as_array().unwrap().len());
+    }
+
+    #[test]
+    fn test_soft_delete_timestamp_from_meta() {
+        let json0 = Json0::new();
+        let op = json0
+            .operation_factory()
+            .from_value(json!([{"p": ["l", 0], "ld": 1, "li": 2}]))
+            .unwrap();
+        let stamped = op
+            .clone()
+            .with_meta(crate::operation::OperationMeta::new().timestamp(100));
+
+        // replicas with their own default timestamp agree on the timestamp of
+        // the operation
+        let mut replicas = [json!({"l": [1]}), json!({"l": [1]})];
+        for (replica, timestamp) in replicas.iter_mut().zip([1, 2]) {
+            let options = ApplyOptions::new()
+                .soft_delete(Some(SoftDelete::new("_trash").timestamp(timestamp)));
+            json0
+                .apply_with(replica, vec![stamped.clone()], &options)
+                .unwrap();
+        }
+        assert_eq!(replicas[0], replicas[1]);
+        assert_eq!(
+            json!([{"path": ["l", 0], "value": 1, "deleted_at": 100}]),
+            replicas[0]["_trash"]
+        );
+
+        let mut value = json!({"l": [1]});
+        let options = ApplyOptions::new().soft_delete(Some(SoftDelete::new("_trash")));
+        json0.apply_with(&mut value, vec![op], &options).unwrap();
+        assert_eq!(json!(null), value["_trash"][0]["deleted_at"]);
+    }
+
+    #[test]
+    fn test_restore_and_purge_tombstones() {
+        let json0 = Json0::new();
+        let mut value = json!({
+            "l": ["y"],
+            "_trash": [
+                {"path": ["a"], "value": 1, "deleted_at": 5},
+                {"path": ["l", 0], "value": "x", "deleted_at": 7},
+                {"path": ["m", 0], "value": "z", "deleted_at": 9}
+            ]
+        });
+        json0.restore_tombstone(&mut value, "_trash", 1).unwrap();
+        assert_eq!(json!(["x", "y"]), value["l"]);
+        assert_eq!(2, value["_trash"].as_array().unwrap().len());
+
+        // the list to restore into is gone
+        let origin = value.clone();
+        assert!(json0.restore_tombstone(&mut value, "_trash", 1).is_err());
+        assert_eq!(origin, value);
+        assert_matches!(
+            json0.restore_tombstone(&mut value, "_trash", 2),
+            Err(JsonError::InvalidOperation(_))
+        );
+
+        let op = json0
+            .purge_tombstones(&mut value, "_trash", Some(6))
+            .unwrap();
+        assert_eq!(1, op.len());
+        assert_eq!(
+            json!([{"path": ["m", 0], "value": "z", "deleted_at": 9}]),
+            value["_trash"]
+        );
+        json0.purge_tombstones(&mut value, "_trash", None).unwrap();
+        assert_eq!(json!([]), value["_trash"]);
+        assert!(json0
+            .purge_tombstones(&mut value, "missing", None)
+            .unwrap()
+            .is_empty());
+    }
+}
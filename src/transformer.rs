@@ -1,133 +1,567 @@
+use std::{cmp::Ordering, collections::HashMap, fmt::Display, sync::Arc};
+
+use itertools::Itertools;
+
+use crate::canonical::NumberEquality;
 use crate::common::Validation;
-use crate::error::Result;
+use crate::error::{ErrorContext, JsonError, Result};
 use crate::json::Appliable;
 use crate::operation::{Operation, OperationComponent, Operator};
 use crate::path::{Path, PathElement};
+use crate::sub_type::SubType;
+use crate::text_delta::{TextDelta, TEXT_DELTA_SUB_TYPE_NAME};
+
+/// Max times a base component can be split while transforming it against one
+/// slot of components, so rules splitting components into each other can't
+/// loop forever.
+const MAX_SPLIT_DEPTH: usize = 64;
+
+fn is_same_operand(op_a: &OperationComponent, op_b: &OperationComponent) -> bool {
+    if let Operator::SubType(_, _, _) | Operator::Test(_) = op_a.operator {
+        return false;
+    }
 
-fn is_equivalent_to_noop(op: &OperationComponent) -> bool {
-    match &op.operator {
-        Operator::Noop() => true,
-        Operator::SubType(_, _, _) => false,
+    if let Operator::SubType(_, _, _) | Operator::Test(_) = op_b.operator {
+        return false;
+    }
+
+    op_a.path.len() == op_b.path.len()
+}
+
+/// Shift the index of a list move by delta, failing instead of wrapping
+/// around, which only happens on malformed operations.
+fn shift_index(index: usize, delta: isize) -> Result<usize> {
+    index.checked_add_signed(delta).ok_or_else(|| {
+        JsonError::InvalidOperation(format!(
+            "list move index: {index} is out of the range of usize after shifting by {delta}"
+        ))
+    })
+}
+
+/// Whether base_op changes the value asserted by test_op, instead of only
+/// moving it to another position.
+fn test_is_affected(test_op: &OperationComponent, base_op: &OperationComponent) -> bool {
+    if test_op.path.is_prefix_of(&base_op.path) && test_op.path.len() < base_op.path.len() {
+        return true;
+    }
+    if !base_op.path.is_prefix_of(&test_op.path) {
+        return false;
+    }
+    !matches!(
+        base_op.operator,
+        Operator::ListInsert(_) | Operator::ListMove(_) | Operator::ObjectMove(_)
+    )
+}
+
+/// Length of the prefix of the path of component, which is the root of the
+/// subtree it may change. Components shifting their siblings change the
+/// parent of their path.
+/// Text sub type components equivalent to new_op and base_op, if one of them
+/// is of the text sub type and the other of text-delta, which are transformed
+/// in the offset form of text.
+fn as_text_components(
+    new_op: &OperationComponent,
+    base_op: &OperationComponent,
+) -> Result<Option<(Vec<OperationComponent>, Vec<OperationComponent>)>> {
+    let text_f = match (&new_op.operator, &base_op.operator) {
+        (Operator::SubType(SubType::Text, _, f), Operator::SubType(t, _, _))
+        | (Operator::SubType(t, _, _), Operator::SubType(SubType::Text, _, f))
+            if *t == SubType::Custome(TEXT_DELTA_SUB_TYPE_NAME.into()) =>
+        {
+            f.clone()
+        }
+        _ => return Ok(None),
+    };
+    let expand = |op: &OperationComponent| -> Result<Vec<OperationComponent>> {
+        let Operator::SubType(t, operand, _) = &op.operator else {
+            unreachable!()
+        };
+        if *t == SubType::Text {
+            return Ok(vec![op.clone()]);
+        }
+        TextDelta::try_from(operand)?
+            .to_offset_operands()
+            .into_iter()
+            .map(|o| {
+                OperationComponent::new(
+                    op.path.clone(),
+                    Operator::SubType(SubType::Text, o, text_f.clone()),
+                )
+            })
+            .collect()
+    };
+    Ok(Some((expand(new_op)?, expand(base_op)?)))
+}
+
+fn operate_len(op: &OperationComponent) -> usize {
+    match op.operator {
         Operator::ListInsert(_)
         | Operator::ListDelete(_)
-        | Operator::ObjectInsert(_)
-        | Operator::ObjectDelete(_) => false,
-        Operator::ListReplace(new_v, old_v) | Operator::ObjectReplace(new_v, old_v) => {
-            new_v.eq(old_v)
+        | Operator::ListMove(_)
+        | Operator::ObjectMove(_)
+            if !op.path.is_empty() =>
+        {
+            op.path.len() - 1
         }
-        Operator::ListMove(lm) => op
-            .path
-            .last()
-            .map(|p| p == &PathElement::Index(*lm))
-            .unwrap_or(false),
+        _ => op.path.len(),
     }
 }
 
-fn is_same_operand(op_a: &OperationComponent, op_b: &OperationComponent) -> bool {
-    if let Operator::SubType(_, _, _) = op_a.operator {
-        return false;
+fn find_group(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
     }
+    i
+}
 
-    if let Operator::SubType(_, _, _) = op_b.operator {
-        return false;
+/// Group indexes of components of operation and base_operation by the
+/// subtree they change. The path prefixes of all components are indexed in a
+/// trie, and every component joins the group of the component changing the
+/// outermost subtree containing its path. Transforming only moves a path
+/// inside the subtree changed by the other component, so components in
+/// different groups never affect each other, and only the pairs in the same
+/// group need to be transformed.
+fn group_by_subtree(
+    operation: &Operation,
+    base_operation: &Operation,
+) -> Vec<(Vec<usize>, Vec<usize>)> {
+    let components = operation.iter().chain(base_operation.iter()).collect_vec();
+    // trie of the path prefixes, node 0 is the root
+    let mut trie: HashMap<(usize, &PathElement), usize> = HashMap::new();
+    let prefixes = components
+        .iter()
+        .map(|op| {
+            let mut nodes = vec![0];
+            for elem in op.path.iter() {
+                let next = trie.len() + 1;
+                let node = *trie.entry((*nodes.last().unwrap(), elem)).or_insert(next);
+                nodes.push(node);
+            }
+            nodes
+        })
+        .collect_vec();
+    // the first component changing the subtree under each node
+    let mut changed_by: HashMap<usize, usize> = HashMap::new();
+    for (i, op) in components.iter().enumerate() {
+        changed_by.entry(prefixes[i][operate_len(op)]).or_insert(i);
     }
 
-    op_a.path.len() == op_b.path.len()
+    let mut parents = (0..components.len()).collect_vec();
+    for (i, nodes) in prefixes.iter().enumerate() {
+        let Some(&changer) = nodes.iter().find_map(|n| changed_by.get(n)) else {
+            continue;
+        };
+        let (a, b) = (
+            find_group(&mut parents, i),
+            find_group(&mut parents, changer),
+        );
+        parents[a.max(b)] = a.min(b);
+    }
+
+    let mut groups: Vec<(Vec<usize>, Vec<usize>)> = vec![];
+    let mut index: HashMap<usize, usize> = HashMap::new();
+    for i in 0..components.len() {
+        let root = find_group(&mut parents, i);
+        if i < operation.len() {
+            let g = *index.entry(root).or_insert_with(|| {
+                groups.push((vec![], vec![]));
+                groups.len() - 1
+            });
+            groups[g].0.push(i);
+        } else if let Some(g) = index.get(&root) {
+            groups[*g].1.push(i - operation.len());
+        }
+    }
+    groups
 }
 
-#[derive(PartialEq)]
+/// Which of two concurrent operations wins a tie, which is when both of them
+/// insert on the same position of a list, or insert, replace or move to the
+/// same key of an object. In a list the winner's value goes first and the
+/// other one is inserted after it. On an object key the winner's value is
+/// kept and the other one is dropped.
+///
+/// Sub types are only given `Left` or `Right`, as `Symmetric` is resolved to
+/// one of them before transform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TransformSide {
+    /// The operation being transformed wins ties.
     Left,
+    /// The base operation, which the other one is transformed against, wins
+    /// ties.
     Right,
+    /// The operation with the smaller key wins ties. If the keys are equal,
+    /// the operation which is smaller serialized wins. Peers with a unique
+    /// key each, like a client id, converge no matter which operation is
+    /// transformed against which.
+    Symmetric { key: u64, base_key: u64 },
+}
+
+impl TransformSide {
+    fn opposite(self) -> TransformSide {
+        match self {
+            TransformSide::Left => TransformSide::Right,
+            _ => TransformSide::Left,
+        }
+    }
+
+    /// Resolve `Symmetric` to `Left` or `Right` for transforming operation
+    /// against base_operation. Swapping them resolves to the other side,
+    /// unless they are serialized the same.
+    pub fn resolve<T: Display + ?Sized>(self, operation: &T, base_operation: &T) -> TransformSide {
+        let TransformSide::Symmetric { key, base_key } = self else {
+            return self;
+        };
+        let order = key
+            .cmp(&base_key)
+            .then_with(|| operation.to_string().cmp(&base_operation.to_string()));
+        if order == Ordering::Greater {
+            TransformSide::Right
+        } else {
+            TransformSide::Left
+        }
+    }
+}
+
+/// The rule `Transformer` applied to transform a component against a base
+/// component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransformRule {
+    /// Either component is a noop or base is a test, nothing changes.
+    Noop,
+    /// A test on the value changed by base keeps its expected value.
+    TestAffected,
+    /// Either component replaces the whole document.
+    Root,
+    /// Follows or conflicts with an object move.
+    ObjectMove,
+    /// The components change unrelated values.
+    Independent,
+    /// Base changes a value inside the value component operates on, which
+    /// component takes in.
+    Consume,
+    /// Transformed by the sub type of base.
+    SubType,
+    ListReplace,
+    /// Shifted by the index base inserts to.
+    ListInsert,
+    /// Shifted by the index base deletes, or dropped if deleted.
+    ListDelete,
+    /// Shifted by the indexes base moves from and to.
+    ListMove,
+    ObjectReplace,
+    ObjectInsert,
+    /// Dropped under the deleted value, or inserts instead of replacing it.
+    ObjectDelete,
+}
+
+impl TransformRule {
+    fn of(base_operator: &Operator) -> TransformRule {
+        match base_operator {
+            Operator::Noop() | Operator::Test(_) => TransformRule::Noop,
+            Operator::SubType(_, _, _) => TransformRule::SubType,
+            Operator::ListInsert(_) => TransformRule::ListInsert,
+            Operator::ListDelete(_) => TransformRule::ListDelete,
+            Operator::ListReplace(_, _) => TransformRule::ListReplace,
+            Operator::ListMove(_) => TransformRule::ListMove,
+            Operator::ObjectInsert(_) => TransformRule::ObjectInsert,
+            Operator::ObjectDelete(_) => TransformRule::ObjectDelete,
+            Operator::ObjectReplace(_, _) => TransformRule::ObjectReplace,
+            Operator::ObjectMove(_) => TransformRule::ObjectMove,
+        }
+    }
+}
+
+/// One component transformed against a base component.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransformStep {
+    /// The component before transform.
+    pub operation: OperationComponent,
+    pub base: OperationComponent,
+    pub side: TransformSide,
+    pub rule: TransformRule,
+    /// What operation is transformed into, empty if it is dropped.
+    pub result: Vec<OperationComponent>,
+}
+
+impl Display for TransformStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?}: {} against {} => [{}]",
+            self.rule,
+            self.operation,
+            self.base,
+            self.result.iter().join(",")
+        )
+    }
+}
+
+/// Steps recorded while transforming, None if not tracing.
+type Trace = Option<Vec<TransformStep>>;
+
+/// Where a list insert goes relative to a concurrent insert on the same
+/// position, as decided by an insert order policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InsertOrder {
+    Before,
+    After,
+    /// Not decided by the policy, so it's decided by `TransformSide`.
+    BySide,
 }
 
-pub struct Transformer {}
+/// Decides the order of an insert, the first argument, relative to a
+/// concurrent insert on the same position, the second one.
+pub type InsertOrderPolicy = Box<dyn Fn(&OperationComponent, &OperationComponent) -> InsertOrder>;
+
+pub struct Transformer {
+    insert_order: Option<InsertOrderPolicy>,
+    number_equality: NumberEquality,
+}
 
 impl Transformer {
     pub fn new() -> Transformer {
-        Transformer {}
+        Transformer {
+            insert_order: None,
+            number_equality: NumberEquality::default(),
+        }
+    }
+
+    pub fn set_insert_order(&mut self, policy: Option<InsertOrderPolicy>) {
+        self.insert_order = policy;
+    }
+
+    pub fn set_number_equality(&mut self, number_equality: NumberEquality) {
+        self.number_equality = number_equality;
+    }
+
+    /// Whether the value new_op inserts goes before the one base_op inserts
+    /// on the same position.
+    fn inserts_before(
+        &self,
+        new_op: &OperationComponent,
+        base_op: &OperationComponent,
+        side: TransformSide,
+    ) -> bool {
+        match self.insert_order.as_ref().map(|f| f(new_op, base_op)) {
+            Some(InsertOrder::Before) => true,
+            Some(InsertOrder::After) => false,
+            _ => side == TransformSide::Left,
+        }
     }
 
     pub fn transform(
         &self,
         operation: &Operation,
         base_operation: &Operation,
+    ) -> Result<(Operation, Operation)> {
+        self.transform_with_side(operation, base_operation, TransformSide::Left)
+    }
+
+    /// Like `transform` but with operation on side instead of always on the
+    /// left.
+    pub fn transform_with_side(
+        &self,
+        operation: &Operation,
+        base_operation: &Operation,
+        side: TransformSide,
+    ) -> Result<(Operation, Operation)> {
+        let (mut a, mut b) =
+            self.transform_operations(operation, base_operation, side, &mut None)?;
+        a.set_meta(operation.meta().cloned());
+        b.set_meta(base_operation.meta().cloned());
+        Ok((a, b))
+    }
+
+    /// Like `transform` but also returns every step taken, in order, with the
+    /// rule applied to each pair of components.
+    pub fn transform_traced(
+        &self,
+        operation: &Operation,
+        base_operation: &Operation,
+    ) -> Result<(Operation, Operation, Vec<TransformStep>)> {
+        let mut trace = Some(vec![]);
+        let (mut a, mut b) =
+            self.transform_operations(operation, base_operation, TransformSide::Left, &mut trace)?;
+        a.set_meta(operation.meta().cloned());
+        b.set_meta(base_operation.meta().cloned());
+        Ok((a, b, trace.unwrap_or_default()))
+    }
+
+    /// Transform operation against base_operation only, with operation on
+    /// side.
+    pub fn transform_side(
+        &self,
+        operation: &Operation,
+        base_operation: &Operation,
+        side: TransformSide,
+    ) -> Result<Operation> {
+        operation.validates()?;
+        base_operation.validates()?;
+        let side = side.resolve(operation, base_operation);
+
+        let operation = operation.normalize_with(self.number_equality);
+        let base_operation = base_operation.normalize_with(self.number_equality);
+        let meta = operation.meta().cloned();
+        let mut a = if operation.len() == 1 && base_operation.len() == 1 {
+            let op = &operation[0];
+            self.transform_component(op.clone(), &base_operation[0], side, &mut None)
+                .map_err(|e| e.with_context(|| ErrorContext::new(0, 0, op)))?
+                .into()
+        } else {
+            self.transform_matrix(operation, base_operation, side, &mut None)?
+                .0
+        };
+        a.set_meta(meta);
+        Ok(a)
+    }
+
+    /// Transform branch, a sequence of operations made after onto's parent,
+    /// to apply after onto. onto wins when both of them insert on the same
+    /// position.
+    pub fn rebase(&self, branch: &[Operation], onto: &[Operation]) -> Result<Vec<Operation>> {
+        // onto is transformed along to follow each rebased operation, so the
+        // next operation of branch is transformed against what onto means
+        // after the previous ones
+        let mut onto = onto.to_vec();
+        branch
+            .iter()
+            .map(|operation| {
+                let mut operation = operation.clone();
+                for base in onto.iter_mut() {
+                    let (a, b) =
+                        self.transform_with_side(&operation, base, TransformSide::Right)?;
+                    operation = a;
+                    *base = b;
+                }
+                Ok(operation)
+            })
+            .collect()
+    }
+
+    fn transform_operations(
+        &self,
+        operation: &Operation,
+        base_operation: &Operation,
+        side: TransformSide,
+        trace: &mut Trace,
     ) -> Result<(Operation, Operation)> {
         if base_operation.is_empty() {
             return Ok((operation.clone(), Operation::default()));
         }
+        let side = side.resolve(operation, base_operation);
 
         operation.validates()?;
         base_operation.validates()?;
 
+        let operation = operation.normalize_with(self.number_equality);
+        let base_operation = base_operation.normalize_with(self.number_equality);
         if operation.len() == 1 && base_operation.len() == 1 {
-            let a = self.transform_component(
-                operation.get(0).unwrap().clone(),
-                base_operation.get(0).unwrap(),
-                TransformSide::Left,
-            )?;
-            let b = self.transform_component(
-                base_operation.get(0).unwrap().clone(),
-                operation.get(0).unwrap(),
-                TransformSide::Right,
-            )?;
+            let op = operation.into_iter().next().unwrap();
+            let base_op = base_operation.into_iter().next().unwrap();
+            let a = self
+                .transform_component(op.clone(), &base_op, side, trace)
+                .map_err(|e| e.with_context(|| ErrorContext::new(0, 0, &op)))?;
+            let b = self
+                .transform_component(base_op.clone(), &op, side.opposite(), trace)
+                .map_err(|e| e.with_context(|| ErrorContext::new(1, 0, &base_op)))?;
 
             return Ok((a.into(), b.into()));
         }
 
-        self.transform_matrix(operation.clone(), base_operation.clone())
+        self.transform_matrix(operation, base_operation, side, trace)
     }
 
     fn transform_matrix(
         &self,
         operation: Operation,
         base_operation: Operation,
+        side: TransformSide,
+        trace: &mut Trace,
     ) -> Result<(Operation, Operation)> {
         if operation.is_empty() || base_operation.is_empty() {
             return Ok((operation, base_operation));
         }
 
-        let mut out_b = vec![];
-        let mut ops = operation;
-        for base_op in base_operation {
-            let (a, b) = self.transform_multi(ops, base_op)?;
-            ops = a;
-
-            if let Some(o) = b {
-                out_b.push(o);
+        let groups = group_by_subtree(&operation, &base_operation);
+        // every component of operation is transformed into a slot of components
+        let mut slots = operation.into_iter().map(|op| vec![op]).collect_vec();
+        let mut base_ops = base_operation.into_iter().map(|b| vec![b]).collect_vec();
+        for (op_indexes, base_indexes) in groups {
+            for bi in base_indexes {
+                let mut bases = std::mem::take(&mut base_ops[bi])
+                    .into_iter()
+                    .filter_map(|b| b.not_noop())
+                    .collect_vec();
+                for &oi in op_indexes.iter() {
+                    if bases.is_empty() {
+                        break;
+                    }
+                    let mut transformed = vec![];
+                    for b in bases {
+                        let out = self.transform_multi(&mut slots[oi], b, side, 0, trace);
+                        transformed.extend(out.map_err(|e| match slots[oi].first() {
+                            Some(op) => e.with_context(|| ErrorContext::new(0, oi, op)),
+                            None => e,
+                        })?);
+                    }
+                    bases = transformed;
+                }
+                base_ops[bi] = bases;
             }
         }
 
-        Ok((ops, out_b.into()))
+        Ok((
+            slots.into_iter().flatten().collect_vec().into(),
+            base_ops.into_iter().flatten().collect_vec().into(),
+        ))
     }
 
+    /// Transform operation against base_op in place and returns base_op
+    /// transformed against operation, which may be split into several
+    /// components or be dropped.
     fn transform_multi(
         &self,
-        operation: Operation,
+        operation: &mut Vec<OperationComponent>,
         base_op: OperationComponent,
-    ) -> Result<(Operation, Option<OperationComponent>)> {
-        let mut out: Vec<OperationComponent> = vec![];
-
-        let mut base = base_op.not_noop();
-        for op in operation {
-            match base {
-                Some(b) => {
-                    let backup = op.clone();
-                    let mut a = self.transform_component(op, &b, TransformSide::Left)?;
-                    let mut b = self.transform_component(b, &backup, TransformSide::Right)?;
-                    assert!(b.len() == 1);
-                    base = b.pop();
-
-                    out.append(&mut a);
-                }
-                None => {
-                    out.push(op.clone());
-                    continue;
+        side: TransformSide,
+        depth: usize,
+        trace: &mut Trace,
+    ) -> Result<Vec<OperationComponent>> {
+        if depth > MAX_SPLIT_DEPTH {
+            return Err(JsonError::InvalidOperation(format!(
+                "transform against {} does not terminate",
+                base_op
+            )));
+        }
+        let mut base = base_op;
+        let mut i = 0;
+        while i < operation.len() {
+            if operation[i].is_independent_of(&base) {
+                i += 1;
+                continue;
+            }
+            let a = self.transform_component(operation[i].clone(), &base, side, trace)?;
+            let mut b = self.transform_component(base, &operation[i], side.opposite(), trace)?;
+            let n = a.len();
+            operation.splice(i..=i, a);
+            i += n;
+            if b.len() != 1 {
+                // the rest of operation is transformed against each part of
+                // base_op in turn
+                let mut rest = operation.split_off(i);
+                let mut bases = vec![];
+                for b in b {
+                    bases.extend(self.transform_multi(&mut rest, b, side, depth + 1, trace)?);
                 }
+                operation.append(&mut rest);
+                return Ok(bases);
             }
+            base = b.pop().unwrap();
         }
 
-        Ok((out.into(), base))
+        Ok(vec![base])
     }
 
     fn transform_component(
@@ -135,23 +569,84 @@ impl Transformer {
         new_op: OperationComponent,
         base_op: &OperationComponent,
         side: TransformSide,
+        trace: &mut Trace,
     ) -> Result<Vec<OperationComponent>> {
+        let Some(steps) = trace else {
+            return Ok(self.transform_component_by_rule(new_op, base_op, side)?.1);
+        };
+        let operation = new_op.clone();
+        let (rule, result) = self.transform_component_by_rule(new_op, base_op, side)?;
+        steps.push(TransformStep {
+            operation,
+            base: base_op.clone(),
+            side,
+            rule,
+            result: result.clone(),
+        });
+        Ok(result)
+    }
+
+    fn transform_component_by_rule(
+        &self,
+        new_op: OperationComponent,
+        base_op: &OperationComponent,
+        side: TransformSide,
+    ) -> Result<(TransformRule, Vec<OperationComponent>)> {
         let mut new_op = new_op;
-        if is_equivalent_to_noop(&new_op) || is_equivalent_to_noop(base_op) {
-            return Ok(vec![new_op]);
+        // a replace by an equal value still drops the concurrent changes
+        // under its path, like the ones transform makes to win a tie
+        let is_noop = |op: &OperationComponent| {
+            !matches!(
+                op.operator,
+                Operator::ListReplace(..) | Operator::ObjectReplace(..)
+            ) && op.is_equivalent_to_noop_with(self.number_equality)
+        };
+        if is_noop(&new_op) && new_op.operator != Operator::Noop() {
+            // it may route through a path base_op deletes
+            return Ok((TransformRule::Noop, vec![]));
+        }
+        if is_noop(&new_op) || is_noop(base_op) {
+            return Ok((TransformRule::Noop, vec![new_op]));
         }
 
-        let max_common_path = base_op.path.max_common_path(&new_op.path);
+        if let Operator::Test(_) = base_op.operator {
+            // assertion changes nothing
+            return Ok((TransformRule::Noop, vec![new_op]));
+        }
+        if let Operator::Test(_) = new_op.operator {
+            if test_is_affected(&new_op, base_op) {
+                // keep expecting the original value, so the assertion fails
+                // on the value changed by base_op
+                return Ok((TransformRule::TestAffected, vec![new_op]));
+            }
+        }
+
+        // object operators on root path replace the whole document
+        let new_op_on_root =
+            new_op.path.is_root() && !matches!(new_op.operator, Operator::SubType(_, _, _));
+        let base_op_on_root =
+            base_op.path.is_root() && !matches!(base_op.operator, Operator::SubType(_, _, _));
+        if new_op_on_root && !base_op_on_root {
+            self.consume(&mut new_op, 0, base_op)?;
+            return Ok((TransformRule::Root, vec![new_op]));
+        }
+        if base_op_on_root && !new_op_on_root {
+            return Ok((TransformRule::Root, vec![]));
+        }
+
+        if let Some(ops) = self.transform_object_move(&mut new_op, base_op, &side)? {
+            return Ok((TransformRule::ObjectMove, ops));
+        }
+
+        let common_len = base_op.path.common_prefix_len(&new_op.path);
         let new_operate_path_len = new_op.operate_path_len();
         let base_operate_path_len = base_op.operate_path_len();
 
-        if max_common_path.len() < new_operate_path_len
-            && max_common_path.len() < base_operate_path_len
-        {
+        if common_len < new_operate_path_len && common_len < base_operate_path_len {
             // common path must be equal to new_op's or base_op's operate path
             // or base_op and new_op is operating on orthogonal value
             // they don't need transform
-            return Ok(vec![new_op]);
+            return Ok((TransformRule::Independent, vec![new_op]));
         }
 
         // such as:
@@ -161,9 +656,9 @@ impl Transformer {
         if base_operate_path_len > new_operate_path_len {
             // if base_op's path is longger and contains new_op's path, new_op should include base_op's effect
             if new_op.path.is_prefix_of(&base_op.path) {
-                self.consume(&mut new_op, &max_common_path, base_op)?;
+                self.consume(&mut new_op, common_len, base_op)?;
             }
-            return Ok(vec![new_op]);
+            return Ok((TransformRule::Consume, vec![new_op]));
         }
 
         // from here, base_op's path is shorter or equal to new_op, such as:
@@ -172,6 +667,27 @@ impl Transformer {
         // [p1,p2,p4], [p1,p2,p3]. same operand
         // [p1,p2,p3,p4,..], [p1,p2,p3], base_op is prefix of new_op
         // [p1,p2,p4,p5,..], [p1,p2,p3]
+        let ops = self.transform_by_base_operator(
+            new_op,
+            base_op,
+            side,
+            new_operate_path_len,
+            base_operate_path_len,
+        )?;
+        Ok((TransformRule::of(&base_op.operator), ops))
+    }
+
+    /// Transform new_op against base_op whose path is shorter than or equal to
+    /// new_op's, by the operator of base_op.
+    fn transform_by_base_operator(
+        &self,
+        new_op: OperationComponent,
+        base_op: &OperationComponent,
+        side: TransformSide,
+        new_operate_path_len: usize,
+        base_operate_path_len: usize,
+    ) -> Result<Vec<OperationComponent>> {
+        let mut new_op = new_op;
         let same_operand = is_same_operand(base_op, &new_op);
         let base_op_is_prefix = base_op.path.is_prefix_of(&new_op.path);
         match &base_op.operator {
@@ -193,33 +709,55 @@ impl Transformer {
                             })
                             .collect::<Result<Vec<OperationComponent>>>();
                     }
-                }
-            }
-            Operator::ListReplace(li_v, _) => {
-                if base_op_is_prefix {
-                    if !same_operand {
-                        return Ok(vec![]);
-                    }
-                    if let Operator::ListReplace(new_li, _) = &new_op.operator {
-                        if side == TransformSide::Left {
-                            return Ok(vec![OperationComponent::new(
-                                new_op.path,
-                                Operator::ListReplace(new_li.clone(), li_v.clone()),
-                            )?]);
-                        } else {
-                            return Ok(vec![]);
+                    if base_op.path == new_op.path {
+                        if let Some((mut news, bases)) = as_text_components(&new_op, base_op)? {
+                            for base in bases {
+                                self.transform_multi(&mut news, base, side, 0, &mut None)?;
+                            }
+                            return Ok(news);
                         }
                     }
-                    if let Operator::ListDelete(_) = &new_op.operator {
+                }
+            }
+            Operator::ListReplace(li_v, _) if base_op_is_prefix => {
+                if !same_operand {
+                    return Ok(vec![]);
+                }
+                if let Operator::ListReplace(new_li, _) = &new_op.operator {
+                    if side == TransformSide::Left {
+                        return Ok(vec![OperationComponent::new(
+                            new_op.path,
+                            Operator::ListReplace(new_li.clone(), li_v.clone()),
+                        )?]);
+                    } else {
                         return Ok(vec![]);
                     }
                 }
+                if let Operator::ListDelete(_) = &new_op.operator {
+                    return Ok(vec![]);
+                }
             }
             Operator::ListInsert(_) => {
+                if let (Some(PathElement::End(base_k)), Some(PathElement::End(new_k))) = (
+                    base_op.path.get(base_operate_path_len),
+                    new_op.path.get(base_operate_path_len),
+                ) {
+                    // positions counted from the end only change when new_op
+                    // goes before the value inserted by base_op
+                    if new_k > base_k
+                        || (new_k == base_k && self.inserts_before(&new_op, base_op, side))
+                    {
+                        let k = new_k + 1;
+                        new_op
+                            .path
+                            .replace(base_operate_path_len, PathElement::End(k));
+                    }
+                    return Ok(vec![new_op]);
+                }
                 if let Operator::ListInsert(_) = &new_op.operator {
                     if same_operand && base_op_is_prefix {
-                        if side == TransformSide::Right {
-                            new_op.path.increase_index(base_operate_path_len);
+                        if !self.inserts_before(&new_op, base_op, side) {
+                            new_op.path.try_increase_index(base_operate_path_len)?;
                         }
                         return Ok(vec![new_op]);
                     }
@@ -231,7 +769,7 @@ impl Transformer {
                     .and_then(|p1| new_op.path.get(base_operate_path_len).map(|p2| p1 <= p2))
                     .unwrap_or(false)
                 {
-                    new_op.path.increase_index(base_operate_path_len);
+                    new_op.path.try_increase_index(base_operate_path_len)?;
                 }
 
                 if let Operator::ListMove(lm) = &mut new_op.operator {
@@ -242,13 +780,13 @@ impl Transformer {
                             .map(|p| p <= &PathElement::Index(*lm))
                             .unwrap_or(false)
                     {
-                        new_op.operator = Operator::ListMove(*lm + 1);
+                        new_op.operator = Operator::ListMove(shift_index(*lm, 1)?);
                     }
                 }
             }
             Operator::ListDelete(_) => {
-                let base_op_operate_path = base_op.path.get(base_operate_path_len).unwrap();
-                let new_op_operate_path = new_op.path.get(base_operate_path_len).unwrap();
+                let base_op_operate_path = &base_op.path[base_operate_path_len];
+                let new_op_operate_path = &new_op.path[base_operate_path_len];
                 if let Operator::ListMove(lm) = new_op.operator {
                     if same_operand {
                         if base_op_is_prefix {
@@ -259,13 +797,13 @@ impl Transformer {
                         if base_op_operate_path < &to
                             || (base_op_operate_path.eq(&to) && new_op_operate_path < &to)
                         {
-                            new_op.operator = Operator::ListMove(lm - 1);
+                            new_op.operator = Operator::ListMove(shift_index(lm, -1)?);
                         }
                     }
                 }
 
                 if base_op_operate_path < new_op_operate_path {
-                    new_op.path.decrease_index(base_operate_path_len);
+                    new_op.path.try_decrease_index(base_operate_path_len)?;
                 } else if base_op_is_prefix {
                     if !same_operand {
                         // we're below the deleted element, so -> noop
@@ -278,105 +816,100 @@ impl Transformer {
                     if let Operator::ListReplace(li, _) = new_op.operator {
                         // we're replacing, they're deleting. we become an insert.
                         return Ok(vec![OperationComponent::new(
-                            new_op.path.clone(),
-                            Operator::ListInsert(li.clone()),
+                            new_op.path,
+                            Operator::ListInsert(li),
                         )?]);
                     }
                 }
             }
-            Operator::ObjectReplace(oi, _) => {
-                if base_op_is_prefix {
-                    if !same_operand {
-                        return Ok(vec![]);
-                    }
-
-                    match &new_op.operator {
-                        Operator::ObjectReplace(new_oi, _) | Operator::ObjectInsert(new_oi) => {
-                            if side == TransformSide::Right {
-                                return Ok(vec![]);
-                            }
-                            return Ok(vec![OperationComponent {
-                                path: new_op.path.clone(),
-                                operator: Operator::ObjectReplace(new_oi.clone(), oi.clone()),
-                            }]);
-                        }
-                        _ => {
-                            return Ok(vec![]);
-                        }
-                    }
+            Operator::ObjectReplace(oi, _) if base_op_is_prefix => {
+                if !same_operand {
+                    return Ok(vec![]);
                 }
-            }
-            Operator::ObjectInsert(base_oi) => {
-                if base_op_is_prefix {
-                    if let Operator::ObjectReplace(new_oi, _) | Operator::ObjectInsert(new_oi) =
-                        &new_op.operator
-                    {
-                        if side == TransformSide::Left {
-                            if same_operand {
-                                return Ok(vec![OperationComponent {
-                                    path: base_op.path.clone(),
-                                    operator: Operator::ObjectReplace(
-                                        new_oi.clone(),
-                                        base_oi.clone(),
-                                    ),
-                                }]);
-                            }
-                            // Here, we are different from original json0
-                            // eg: new_op = [{"p": ["p1", "p2"],"oi": "v1"}], base_op = [{"p": ["p1"],"oi": "v2"}]
-                            // after execution of these op, the result should be {"p1":{"p2":"v1"}}, so new_op after left transform
-                            // is [{"p": ["p1"],"od": "v2"}, {"p": ["p1", "p2"],"oi": "v1"}]
-                            // but original json0 is [{"p": ["p1", "p2"],"od": "v2"}, {"p": ["p1", "p2"],"oi": "v1"}]
-                            // the problem of original json0 is "v2" inserted by base_op is under path p1, not [p1, p2]
-                            return Ok(vec![
-                                OperationComponent {
-                                    path: base_op.path.clone(),
-                                    operator: Operator::ObjectDelete(base_oi.clone()),
-                                },
-                                new_op,
-                            ]);
-                        } else {
-                            return Ok(vec![]);
-                        }
-                    } else if let Operator::ObjectDelete(_) = &new_op.operator {
+
+                match &new_op.operator {
+                    Operator::ObjectReplace(new_oi, _) | Operator::ObjectInsert(new_oi) => {
                         if side == TransformSide::Right {
                             return Ok(vec![]);
                         }
+                        return Ok(vec![OperationComponent {
+                            path: new_op.path.clone(),
+                            operator: Operator::ObjectReplace(new_oi.clone(), oi.clone()),
+                        }]);
                     }
-                }
-            }
-            Operator::ObjectDelete(_) => {
-                if base_op_is_prefix {
-                    if !same_operand {
+                    _ => {
                         return Ok(vec![]);
                     }
-                    if let Operator::ObjectReplace(new_oi, _) | Operator::ObjectInsert(new_oi) =
-                        &new_op.operator
-                    {
-                        if side == TransformSide::Left {
+                }
+            }
+            Operator::ObjectInsert(base_oi) if base_op_is_prefix => {
+                if let Operator::ObjectReplace(new_oi, _) | Operator::ObjectInsert(new_oi) =
+                    &new_op.operator
+                {
+                    if side == TransformSide::Left {
+                        if same_operand {
                             return Ok(vec![OperationComponent {
-                                path: new_op.path.clone(),
-                                operator: Operator::ObjectInsert(new_oi.clone()),
+                                path: base_op.path.clone(),
+                                operator: Operator::ObjectReplace(new_oi.clone(), base_oi.clone()),
                             }]);
-                        } else {
-                            return Ok(vec![]);
                         }
+                        // Here, we are different from original json0
+                        // eg: new_op = [{"p": ["p1", "p2"],"oi": "v1"}], base_op = [{"p": ["p1"],"oi": "v2"}]
+                        // after execution of these op, the result should be {"p1":{"p2":"v1"}}, so new_op after left transform
+                        // is [{"p": ["p1"],"od": "v2"}, {"p": ["p1", "p2"],"oi": "v1"}]
+                        // but original json0 is [{"p": ["p1", "p2"],"od": "v2"}, {"p": ["p1", "p2"],"oi": "v1"}]
+                        // the problem of original json0 is "v2" inserted by base_op is under path p1, not [p1, p2]
+                        return Ok(vec![
+                            OperationComponent {
+                                path: base_op.path.clone(),
+                                operator: Operator::ObjectDelete(base_oi.clone()),
+                            },
+                            new_op,
+                        ]);
                     } else {
                         return Ok(vec![]);
                     }
+                } else if let Operator::ObjectDelete(_) = &new_op.operator {
+                    if side == TransformSide::Right {
+                        return Ok(vec![]);
+                    }
+                }
+            }
+            Operator::ObjectDelete(_) if base_op_is_prefix => {
+                if !same_operand {
+                    return Ok(vec![]);
+                }
+                match &new_op.operator {
+                    // replacing the deleted value wins over deleting it, as
+                    // the delete is dropped when transformed against the
+                    // replace
+                    Operator::ObjectReplace(new_oi, _) => {
+                        return Ok(vec![OperationComponent {
+                            path: new_op.path.clone(),
+                            operator: Operator::ObjectInsert(new_oi.clone()),
+                        }]);
+                    }
+                    Operator::ObjectInsert(new_oi) if side == TransformSide::Left => {
+                        return Ok(vec![OperationComponent {
+                            path: new_op.path.clone(),
+                            operator: Operator::ObjectInsert(new_oi.clone()),
+                        }]);
+                    }
+                    _ => return Ok(vec![]),
                 }
             }
             Operator::ListMove(lm) => {
                 if same_operand {
                     match &mut new_op.operator {
                         Operator::ListMove(new_op_lm) => {
-                            let other_from = base_op.path.get(new_operate_path_len).unwrap();
+                            let other_from = &base_op.path[new_operate_path_len];
                             let other_to = PathElement::Index(*lm);
 
                             if other_from == &other_to {
                                 return Ok(vec![new_op]);
                             }
 
-                            let from = new_op.path.get(new_operate_path_len).unwrap().clone();
+                            let from = new_op.path[new_operate_path_len].clone();
                             let to: PathElement = PathElement::Index(*new_op_lm);
 
                             if &from == other_from {
@@ -395,32 +928,32 @@ impl Transformer {
                             } else {
                                 let mut n_lm = *new_op_lm;
                                 if &from > other_from {
-                                    new_op.path.decrease_index(base_operate_path_len);
+                                    new_op.path.try_decrease_index(base_operate_path_len)?;
                                 }
                                 if from > other_to {
-                                    new_op.path.increase_index(base_operate_path_len);
+                                    new_op.path.try_increase_index(base_operate_path_len)?;
                                 } else if from == other_to && other_from > &other_to {
-                                    new_op.path.increase_index(base_operate_path_len);
+                                    new_op.path.try_increase_index(base_operate_path_len)?;
                                     if from == to {
-                                        n_lm += 1;
+                                        n_lm = shift_index(n_lm, 1)?;
                                     }
                                 }
                                 if &to > other_from || (&to == other_from && to > from) {
-                                    n_lm -= 1;
+                                    n_lm = shift_index(n_lm, -1)?;
                                 }
                                 if to > other_to {
-                                    n_lm += 1;
+                                    n_lm = shift_index(n_lm, 1)?;
                                 } else if to == other_to {
                                     if (&other_to > other_from && to > from)
                                         || (&other_to < other_from && to < from)
                                     {
                                         if side == TransformSide::Right {
-                                            n_lm += 1;
+                                            n_lm = shift_index(n_lm, 1)?;
                                         }
                                     } else if to > from {
-                                        n_lm += 1;
+                                        n_lm = shift_index(n_lm, 1)?;
                                     } else if &to == other_from {
-                                        n_lm -= 1;
+                                        n_lm = shift_index(n_lm, -1)?;
                                     }
                                 }
                                 new_op.operator = Operator::ListMove(n_lm);
@@ -429,31 +962,31 @@ impl Transformer {
                         }
                         Operator::ListInsert(_) => {
                             let operate_index = base_operate_path_len;
-                            let from = base_op.path.get(operate_index).unwrap();
+                            let from = &base_op.path[operate_index];
                             let to = *lm;
-                            let p = new_op.path.get(operate_index).unwrap().clone();
+                            let p = new_op.path[operate_index].clone();
                             if &p > from {
-                                new_op.path.decrease_index(operate_index);
+                                new_op.path.try_decrease_index(operate_index)?;
                             }
                             if p > PathElement::Index(to) {
-                                new_op.path.increase_index(operate_index);
+                                new_op.path.try_increase_index(operate_index)?;
                             }
                             return Ok(vec![new_op]);
                         }
                         _ => {}
                     }
                 }
-                let from = base_op.path.get(base_operate_path_len).unwrap();
+                let from = &base_op.path[base_operate_path_len];
                 let to = PathElement::Index(*lm);
-                let p = new_op.path.get(base_operate_path_len).unwrap().clone();
+                let p = new_op.path[base_operate_path_len].clone();
                 if &p == from {
                     new_op.path.replace(base_operate_path_len, to.clone());
                 } else {
                     if &p > from {
-                        new_op.path.decrease_index(base_operate_path_len);
+                        new_op.path.try_decrease_index(base_operate_path_len)?;
                     }
                     if p > to || (p == to && from > &to) {
-                        new_op.path.increase_index(base_operate_path_len);
+                        new_op.path.try_increase_index(base_operate_path_len)?;
                     }
                 }
             }
@@ -463,10 +996,99 @@ impl Transformer {
         Ok(vec![new_op])
     }
 
+    /// Transform new_op against base_op when either of them is an object
+    /// move. A move carries whatever value is at its key, so components on
+    /// the moved key follow it, and the move survives inserts and replaces of
+    /// its key. The move also wins over inserting to its destination, whose
+    /// value is deleted. None if the general rules apply.
+    fn transform_object_move(
+        &self,
+        new_op: &mut OperationComponent,
+        base_op: &OperationComponent,
+        side: &TransformSide,
+    ) -> Result<Option<Vec<OperationComponent>>> {
+        if let Operator::ObjectMove(to) = &base_op.operator {
+            let parent = base_op.path.parent().unwrap();
+            let Some(key) = new_op
+                .path
+                .relative_to(parent)
+                .and_then(|p| p.first())
+                .cloned()
+            else {
+                return Ok(None);
+            };
+            let depth = parent.len();
+            let same_operand = new_op.path.len() == base_op.path.len();
+            let from = base_op.path.last().unwrap();
+            let to_path = PathElement::key(to);
+            let move_back = || {
+                let PathElement::Key(from) = from else {
+                    unreachable!()
+                };
+                OperationComponent::new(
+                    parent.to_path().join(&Path::from(vec![to_path.clone()])),
+                    Operator::ObjectMove(from.to_string()),
+                )
+            };
+
+            if &key == from {
+                if let Operator::ObjectMove(new_to) = &new_op.operator {
+                    // both moved the same key, left side wins
+                    if same_operand && (new_to == to || side == &TransformSide::Right) {
+                        return Ok(Some(vec![]));
+                    }
+                }
+                // follow the moved value
+                new_op.path.replace(depth, to_path);
+                return Ok(Some(vec![new_op.clone()]));
+            }
+            if !same_operand {
+                return Ok(None);
+            }
+            return Ok(match &new_op.operator {
+                // move wins over insert to the destination
+                Operator::ObjectInsert(_) | Operator::ObjectReplace(..) if key == to_path => {
+                    Some(vec![])
+                }
+                // both moved to the same key, left side wins and the other
+                // value goes back
+                Operator::ObjectMove(new_to) if new_to == to => match side {
+                    TransformSide::Right => Some(vec![]),
+                    _ => Some(vec![move_back()?, new_op.clone()]),
+                },
+                _ => None,
+            });
+        }
+
+        let Operator::ObjectMove(to) = &new_op.operator else {
+            return Ok(None);
+        };
+        let mut dest = new_op.path.clone();
+        dest.replace(dest.len() - 1, PathElement::key(to));
+        Ok(match &base_op.operator {
+            // the moved value is deleted
+            Operator::ObjectDelete(_) if base_op.path == new_op.path => Some(vec![]),
+            // move the inserted value
+            Operator::ObjectInsert(_) | Operator::ObjectReplace(..)
+                if base_op.path == new_op.path =>
+            {
+                Some(vec![new_op.clone()])
+            }
+            // move wins over insert to the destination
+            Operator::ObjectInsert(v) | Operator::ObjectReplace(v, _) if base_op.path == dest => {
+                Some(vec![
+                    OperationComponent::new(dest, Operator::ObjectDelete(v.clone()))?,
+                    new_op.clone(),
+                ])
+            }
+            _ => None,
+        })
+    }
+
     pub fn consume(
         &self,
         op: &mut OperationComponent,
-        common_path: &Path,
+        common_len: usize,
         other: &OperationComponent,
     ) -> Result<()> {
         match &mut op.operator {
@@ -474,13 +1096,77 @@ impl Transformer {
             | Operator::ListReplace(_, v)
             | Operator::ObjectDelete(v)
             | Operator::ObjectReplace(_, v) => {
-                let (_, p2) = other.path.split_at(common_path.len());
                 // v maybe cannot apply other.operator
                 // if that happen we do not consume other just leave origin op
-                _ = v.apply(p2, other.operator.clone());
+                _ = Arc::make_mut(v).apply(
+                    other.path.suffix(common_len).to_path(),
+                    other.operator.clone(),
+                );
             }
             _ => {}
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operation::OperationFactory;
+    use serde_json::json;
+    use test_log::test;
+
+    #[test]
+    fn test_group_by_subtree() {
+        let factory = OperationFactory::default();
+        let operation = factory
+            .from_value(json!([
+                {"p":["a","x",0],"li":1},
+                {"p":["a","y"],"oi":2},
+                {"p":["a","z","n"],"na":1}
+            ]))
+            .unwrap();
+        let base_operation = factory
+            .from_value(json!([
+                {"p":["a","y","k"],"oi":3},
+                {"p":["a","x",2,"k"],"od":4},
+                {"p":["a","w"],"oi":5},
+                {"p":["a","z","n"],"na":2}
+            ]))
+            .unwrap();
+        assert_eq!(
+            vec![(vec![0], vec![1]), (vec![1], vec![0]), (vec![2], vec![3])],
+            group_by_subtree(&operation, &base_operation)
+        );
+
+        // moving a key changes every key of the object
+        let base_operation = factory
+            .from_value(json!([{"p":["a","w"],"om":"y"}]))
+            .unwrap();
+        assert_eq!(
+            vec![(vec![0, 1, 2], vec![0])],
+            group_by_subtree(&operation, &base_operation)
+        );
+    }
+
+    #[test]
+    fn test_transform_delete_against_replace() {
+        let json0 = crate::Json0::new();
+        let doc = json!({"p1": "v1"});
+        let delete = json0
+            .operation_factory()
+            .from_value(json!([{"p": ["p1"], "od": "v1"}]))
+            .unwrap();
+        let replace = json0
+            .operation_factory()
+            .from_value(json!([{"p": ["p1"], "oi": "v2", "od": "v1"}]))
+            .unwrap();
+        // the replace is kept on either side, as the delete is dropped
+        for (a, b) in [(&delete, &replace), (&replace, &delete)] {
+            assert_eq!(
+                json!({"p1": "v2"}),
+                crate::testing::check_convergence(&json0, &doc, a, b)
+            );
+        }
+    }
+}
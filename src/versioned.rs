@@ -0,0 +1,180 @@
+use std::{collections::HashSet, ops::Range};
+
+use serde_json::Value;
+
+use crate::{
+    canonical::document_checksum,
+    operation::{Operation, OperationComponent, OperationMeta, Operator},
+    path::Path,
+};
+
+/// A document with the operations applied on it so far. The version of the
+/// document is the number of applied operations, and an operation built on
+/// version v is concurrent with the applied operations from v on.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VersionedValue {
+    value: Value,
+    history: Vec<Operation>,
+    // author and seq of the operations in history
+    submitted: HashSet<(String, u64)>,
+}
+
+impl VersionedValue {
+    /// Create a document at version 0.
+    pub fn new(value: Value) -> VersionedValue {
+        VersionedValue {
+            value,
+            history: vec![],
+            submitted: HashSet::new(),
+        }
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub fn version(&self) -> u64 {
+        self.history.len() as u64
+    }
+
+    /// The operations applied since version, in order. Empty if version is not
+    /// before the current version.
+    pub fn history_since(&self, version: u64) -> &[Operation] {
+        let start = usize::try_from(version)
+            .unwrap_or(usize::MAX)
+            .min(self.history.len());
+        &self.history[start..]
+    }
+
+    /// An operation with the author and seq in meta is already applied. Meta
+    /// without either of them never matches.
+    pub fn is_applied(&self, meta: &OperationMeta) -> bool {
+        match (&meta.author, meta.seq) {
+            (Some(author), Some(seq)) => self.submitted.contains(&(author.clone(), seq)),
+            _ => false,
+        }
+    }
+
+    /// `document_checksum` of the current value.
+    pub fn checksum(&self) -> [u8; 32] {
+        document_checksum(&self.value)
+    }
+
+    /// Compare the checksum of the current value with expected, the checksum
+    /// of the value at version on the server, and call resync with this
+    /// document if they differ so it can be replaced by a fresh snapshot.
+    /// Returns whether they match, or None without comparing if version is not
+    /// the current version.
+    pub fn verify_checksum<F: FnOnce(&VersionedValue)>(
+        &self,
+        version: u64,
+        expected: &[u8; 32],
+        resync: F,
+    ) -> Option<bool> {
+        if version != self.version() {
+            return None;
+        }
+        let matched = self.checksum() == *expected;
+        if !matched {
+            resync(self);
+        }
+        Some(matched)
+    }
+
+    /// Versions in range of the operations which changed the value at path,
+    /// with the operations, in order. Path is the one in the current value and
+    /// is traced back through the inserts, deletes and moves shifting it, down
+    /// to the operation creating it.
+    pub fn ops_touching(&self, path: &Path, range: Range<u64>) -> Vec<(u64, &Operation)> {
+        self.edits_of(path)
+            .into_iter()
+            .filter(|v| range.contains(v))
+            .map(|v| (v, &self.history[v as usize]))
+            .collect()
+    }
+
+    /// The version and the operation which last changed the value at path, or
+    /// None if it's unchanged since version 0.
+    pub fn blame(&self, path: &Path) -> Option<(u64, &Operation)> {
+        let version = self.edits_of(path).pop()?;
+        Some((version, &self.history[version as usize]))
+    }
+
+    /// The author in the meta of the operation blamed for the value at path.
+    pub fn last_editor_of(&self, path: &Path) -> Option<&str> {
+        self.blame(path)?.1.meta()?.author.as_deref()
+    }
+
+    fn edits_of(&self, path: &Path) -> Vec<u64> {
+        let mut path = path.clone();
+        let mut versions = vec![];
+        'history: for (version, operation) in self.history.iter().enumerate().rev() {
+            let mut changed = false;
+            for op in operation.iter().rev() {
+                changed |= changes_value_at(op, &path);
+                match path_before(op, &path) {
+                    Some(p) => path = p,
+                    None => {
+                        if changed {
+                            versions.push(version as u64);
+                        }
+                        break 'history;
+                    }
+                }
+            }
+            if changed {
+                versions.push(version as u64);
+            }
+        }
+        versions.reverse();
+        versions
+    }
+
+    pub fn into_value(self) -> Value {
+        self.value
+    }
+
+    pub(crate) fn value_mut(&mut self) -> &mut Value {
+        &mut self.value
+    }
+
+    pub(crate) fn push_history(&mut self, operation: Operation) {
+        if let Some(OperationMeta {
+            author: Some(author),
+            seq: Some(seq),
+            ..
+        }) = operation.meta()
+        {
+            self.submitted.insert((author.clone(), *seq));
+        }
+        self.history.push(operation);
+    }
+}
+
+/// Whether op changed the value at path, which is the path after op is
+/// applied. Shifting the value by inserting, deleting or moving siblings
+/// doesn't change it.
+fn changes_value_at(op: &OperationComponent, path: &Path) -> bool {
+    match &op.operator {
+        Operator::Noop() | Operator::Test(_) => false,
+        Operator::ListMove(_) | Operator::ObjectMove(_) => op
+            .affected_path()
+            .is_some_and(|parent| path.is_prefix_of(&parent)),
+        Operator::ListDelete(_) => path.is_prefix_of(&op.path) && *path != op.path,
+        _ => path.is_prefix_of(&op.path) || op.path.is_prefix_of(path),
+    }
+}
+
+/// Path of the value at path before op is applied. None if there was no
+/// value there.
+fn path_before(op: &OperationComponent, path: &Path) -> Option<Path> {
+    match &op.operator {
+        Operator::ListInsert(_)
+        | Operator::ListDelete(_)
+        | Operator::ListMove(_)
+        | Operator::ObjectMove(_) => Operation::from(vec![op.invert().ok()?])
+            .map_position(path, 0)
+            .map(|(p, _)| p),
+        _ => Some(path.clone()),
+    }
+}
@@ -0,0 +1,37 @@
+//! JavaScript bindings with wasm-bindgen, with the same functions and
+//! semantics as the json0 OT type of ShareDB. Documents and operations are
+//! passed as JSON strings.
+//!
+//! Offsets of the text sub type count UTF-8 bytes, while JS json0 counts
+//! UTF-16 code units. They only agree on ASCII text, so clients editing other
+//! text must convert the offsets of their text operations before passing
+//! them in.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{bindings, error::JsonError};
+
+/// Apply op on snapshot and return the new snapshot.
+#[wasm_bindgen]
+pub fn apply(snapshot: &str, op: &str) -> Result<String, JsError> {
+    bindings::apply(snapshot, op).map_err(to_js_error)
+}
+
+/// Transform a against b, like `transform(a, b, side)` of JS json0. Side is
+/// `"left"`, the default, or `"right"`, telling whether a wins when both of
+/// them insert on the same position.
+#[wasm_bindgen]
+pub fn transform(a: &str, b: &str, side: Option<String>) -> Result<String, JsError> {
+    bindings::transform(a, b, side.as_deref().unwrap_or("left")).map_err(to_js_error)
+}
+
+/// Compose op1 and op2 into one operation which is applied like op1 followed
+/// by op2.
+#[wasm_bindgen]
+pub fn compose(op1: &str, op2: &str) -> Result<String, JsError> {
+    bindings::compose(op1, op2).map_err(to_js_error)
+}
+
+fn to_js_error(e: JsonError) -> JsError {
+    JsError::new(&e.to_string())
+}
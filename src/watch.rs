@@ -0,0 +1,158 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use serde_json::Value;
+
+use crate::{
+    error::Result,
+    json::Routable,
+    operation::OperationComponent,
+    path::{Path, PathPattern},
+};
+
+/// Identifies a registered watcher so it can be removed later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WatchId(u64);
+
+/// Passed to watchers after a component changed the watched path.
+#[derive(Debug)]
+pub struct WatchEvent<'a> {
+    /// The watched path, or the path matched by the watched pattern. A
+    /// pattern watcher gets an event for each matched path changed.
+    pub path: &'a Path,
+    /// The component applied.
+    pub component: &'a OperationComponent,
+    /// Value on the watched path before the component is applied.
+    pub old_value: Option<&'a Value>,
+    /// Value on the watched path after the component is applied.
+    pub new_value: Option<&'a Value>,
+}
+
+type Callback = Rc<dyn Fn(&WatchEvent)>;
+
+enum Watched {
+    Path(Path),
+    Pattern(PathPattern),
+}
+
+#[derive(Default)]
+pub(crate) struct WatchRegistry {
+    next_id: Cell<u64>,
+    watchers: RefCell<Vec<(WatchId, Watched, Callback)>>,
+}
+
+impl WatchRegistry {
+    pub fn watch(&self, path: Path, callback: Callback) -> WatchId {
+        self.add(Watched::Path(path), callback)
+    }
+
+    pub fn watch_pattern(&self, pattern: PathPattern, callback: Callback) -> WatchId {
+        self.add(Watched::Pattern(pattern), callback)
+    }
+
+    fn add(&self, watched: Watched, callback: Callback) -> WatchId {
+        let id = WatchId(self.next_id.get());
+        self.next_id.set(id.0 + 1);
+        self.watchers.borrow_mut().push((id, watched, callback));
+        id
+    }
+
+    pub fn unwatch(&self, id: WatchId) -> bool {
+        let mut watchers = self.watchers.borrow_mut();
+        let len = watchers.len();
+        watchers.retain(|(i, _, _)| *i != id);
+        watchers.len() != len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watchers.borrow().is_empty()
+    }
+
+    /// Apply component on value by the apply function and notify watchers on
+    /// the paths affected by the component.
+    pub fn apply<F>(&self, value: &mut Value, component: OperationComponent, apply: F) -> Result<()>
+    where
+        F: FnOnce(&mut Value, OperationComponent) -> Result<()>,
+    {
+        // callbacks are collected first so they can watch or unwatch freely
+        let mut matched = vec![];
+        let mut patterns = vec![];
+        for (_, watched, callback) in self.watchers.borrow().iter() {
+            match watched {
+                Watched::Path(p) if component.affects(p) => {
+                    matched.push((p.clone(), callback.clone()))
+                }
+                Watched::Path(_) => {}
+                Watched::Pattern(pattern) => {
+                    for p in affected_matches(pattern, value, &component) {
+                        matched.push((p, callback.clone()));
+                    }
+                    patterns.push((pattern.clone(), callback.clone()));
+                }
+            }
+        }
+        if matched.is_empty() && patterns.is_empty() {
+            return apply(value, component);
+        }
+
+        // routing fails when an ancestor of path is not a container, which
+        // means there's no value on path
+        let mut old_values = matched
+            .iter()
+            .map(|(p, _)| value.route_get(p).ok().flatten().cloned())
+            .collect::<Vec<Option<Value>>>();
+        apply(value, component.clone())?;
+        // paths matched by patterns only after the component is applied, had
+        // no value before
+        for (pattern, callback) in patterns {
+            for p in affected_matches(&pattern, value, &component) {
+                if !matched
+                    .iter()
+                    .any(|(mp, mc)| mp == &p && Rc::ptr_eq(mc, &callback))
+                {
+                    matched.push((p, callback.clone()));
+                    old_values.push(None);
+                }
+            }
+        }
+        for ((path, callback), old_value) in matched.iter().zip(old_values.iter()) {
+            let new_value = value.route_get(path).ok().flatten();
+            callback(&WatchEvent {
+                path,
+                component: &component,
+                old_value: old_value.as_ref(),
+                new_value,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Paths in value matched by pattern which component affects. They are the
+/// ancestors of the path component changes and the paths under it.
+fn affected_matches(
+    pattern: &PathPattern,
+    value: &Value,
+    component: &OperationComponent,
+) -> Vec<Path> {
+    let Some(affected) = component.affected_path() else {
+        return vec![];
+    };
+    let mut matched = (0..affected.len())
+        .map(|len| affected.prefix(len).to_path())
+        .filter(|p| pattern.matches(p))
+        .collect::<Vec<Path>>();
+    if let Ok(Some(v)) = value.route_get(&affected) {
+        matched.extend(
+            pattern
+                .matches_in(v, &affected)
+                .into_iter()
+                .filter(|p| component.affects(p)),
+        );
+    } else if pattern.matches(&affected) {
+        matched.push(affected);
+    }
+    matched
+}
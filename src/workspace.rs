@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    error::{JsonError, Result},
+    json::{self, ApplyOptions, ChangeEvent, Reporting},
+    operation::Operation,
+    Json0,
+};
+
+pub type DocId = String;
+
+/// An operation addressed to the document of the id.
+pub type DocOperation = (DocId, Operation);
+
+/// A change made on a document of a workspace, serialized as the change event
+/// with the id of the document in `"doc"`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkspaceChange {
+    pub doc: DocId,
+    #[serde(flatten)]
+    pub event: ChangeEvent,
+}
+
+/// Named documents sharing a `Json0`, with operations addressed to them by
+/// id. Changes made on all the documents are collected into a single feed.
+pub struct Workspace {
+    json0: Json0,
+    docs: BTreeMap<DocId, Value>,
+    changes: Vec<WorkspaceChange>,
+}
+
+impl Default for Workspace {
+    fn default() -> Self {
+        Workspace::new(Json0::new())
+    }
+}
+
+impl Workspace {
+    pub fn new(json0: Json0) -> Workspace {
+        Workspace {
+            json0,
+            docs: BTreeMap::new(),
+            changes: vec![],
+        }
+    }
+
+    pub fn json0(&self) -> &Json0 {
+        &self.json0
+    }
+
+    /// Add a document, returning the one replaced with the same id.
+    pub fn insert(&mut self, id: impl Into<DocId>, doc: Value) -> Option<Value> {
+        self.docs.insert(id.into(), doc)
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<Value> {
+        self.docs.remove(id)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Value> {
+        self.docs.get(id)
+    }
+
+    /// Ids of the documents in order.
+    pub fn ids(&self) -> impl Iterator<Item = &DocId> {
+        self.docs.keys()
+    }
+
+    /// Apply each operation on the document of its id in order. Either all of
+    /// them are applied or, when any fails, the changes already made on all
+    /// the documents are reverted.
+    pub fn apply(&mut self, operations: Vec<DocOperation>) -> Result<()> {
+        let mut applied: Vec<DocOperation> = vec![];
+        let mut changes = vec![];
+        for (id, operation) in operations {
+            match self.apply_one(&id, operation) {
+                Ok((undo, events)) => {
+                    changes.extend(events.into_iter().map(|event| WorkspaceChange {
+                        doc: id.clone(),
+                        event,
+                    }));
+                    applied.push((id, undo));
+                }
+                Err(e) => {
+                    for (id, undo) in applied.into_iter().rev() {
+                        if let Some(doc) = self.docs.get_mut(&id) {
+                            _ = self.json0.apply(doc, vec![undo]);
+                        }
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        self.changes.extend(changes);
+        Ok(())
+    }
+
+    fn apply_one(
+        &mut self,
+        id: &str,
+        operation: Operation,
+    ) -> Result<(Operation, Vec<ChangeEvent>)> {
+        let doc = self
+            .docs
+            .get_mut(id)
+            .ok_or_else(|| JsonError::InvalidOperation(format!("unknown document: {id}")))?;
+        let reporting = Reporting::new(&self.json0);
+        let undo = json::apply_with_undo(
+            doc,
+            vec![operation],
+            &ApplyOptions::new().atomic(true),
+            &reporting,
+        )?;
+        Ok((undo, reporting.into_report().events))
+    }
+
+    /// Transform operations against the concurrent base operations, each only
+    /// against the ones on the same document. Returns both of them transformed
+    /// so that applying operations then the transformed base operations gives
+    /// the same documents as the other way around.
+    pub fn transform(
+        &self,
+        operations: &[DocOperation],
+        base_operations: &[DocOperation],
+    ) -> Result<(Vec<DocOperation>, Vec<DocOperation>)> {
+        let mut base_operations = base_operations.to_vec();
+        let mut transformed = Vec::with_capacity(operations.len());
+        for (id, operation) in operations {
+            let mut operation = operation.clone();
+            for (base_id, base) in base_operations.iter_mut() {
+                if base_id != id {
+                    continue;
+                }
+                let (o, b) = self.json0.transform(&operation, base)?;
+                operation = o;
+                *base = b;
+            }
+            transformed.push((id.clone(), operation));
+        }
+        Ok((transformed, base_operations))
+    }
+
+    /// Take the changes made by the operations applied since the last call,
+    /// in the order they are made.
+    pub fn take_changes(&mut self) -> Vec<WorkspaceChange> {
+        std::mem::take(&mut self.changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use test_log::test;
+
+    fn op(w: &Workspace, value: Value) -> Operation {
+        w.json0().operation_factory().from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_workspace_apply() {
+        let mut w = Workspace::default();
+        w.insert("a", json!({"n": 1}));
+        w.insert("b", json!([]));
+        assert_eq!(vec!["a", "b"], w.ids().collect::<Vec<_>>());
+
+        w.apply(vec![
+            ("b".into(), op(&w, json!([{"p": [0], "li": "x"}]))),
+            ("a".into(), op(&w, json!([{"p": ["n"], "na": 2}]))),
+        ])
+        .unwrap();
+        assert_eq!(Some(&json!({"n": 3})), w.get("a"));
+        assert_eq!(Some(&json!(["x"])), w.get("b"));
+        assert_eq!(
+            json!([
+                {"doc": "b", "type": "inserted", "path": [0], "value": "x"},
+                {"doc": "a", "type": "number_added", "path": ["n"], "old": 1, "new": 3}
+            ]),
+            serde_json::to_value(w.take_changes()).unwrap()
+        );
+        assert!(w.take_changes().is_empty());
+
+        // the failure on b reverts the change on a
+        assert!(w
+            .apply(vec![
+                ("a".into(), op(&w, json!([{"p": ["n"], "na": 2}]))),
+                ("b".into(), op(&w, json!([{"p": ["k"], "oi": 1}]))),
+            ])
+            .is_err());
+        assert_eq!(Some(&json!({"n": 3})), w.get("a"));
+        assert!(w.take_changes().is_empty());
+
+        assert_matches!(
+            w.apply(vec![("c".into(), op(&w, json!([])))]).unwrap_err(),
+            JsonError::InvalidOperation(_)
+        );
+    }
+
+    #[test]
+    fn test_workspace_transform() {
+        let mut w = Workspace::default();
+        w.insert("a", json!(["x"]));
+        w.insert("b", json!(["y"]));
+        let ops = vec![
+            ("a".to_string(), op(&w, json!([{"p": [0], "li": 1}]))),
+            ("b".to_string(), op(&w, json!([{"p": [1], "li": 2}]))),
+        ];
+        let base = vec![
+            ("a".to_string(), op(&w, json!([{"p": [0], "li": 3}]))),
+            ("b".to_string(), op(&w, json!([{"p": [0], "ld": "y"}]))),
+        ];
+        let (ops_t, base_t) = w.transform(&ops, &base).unwrap();
+
+        let mut other = Workspace::default();
+        other.insert("a", json!(["x"]));
+        other.insert("b", json!(["y"]));
+        w.apply(ops).unwrap();
+        w.apply(base_t).unwrap();
+        other.apply(base).unwrap();
+        other.apply(ops_t).unwrap();
+        assert_eq!(w.get("a"), other.get("a"));
+        assert_eq!(w.get("b"), other.get("b"));
+        assert_eq!(Some(&json!([2])), w.get("b"));
+    }
+}
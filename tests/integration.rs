@@ -5,43 +5,59 @@ use json0_rs::Json0;
 use log::{debug, info};
 use serde_json::Value;
 use std::fmt::Display;
-use std::fs::File;
-use std::io::{self, BufRead, Error};
+use std::fs;
+use std::io::{self, Error};
 use std::path::{Path, PathBuf};
 use std::vec;
 use test_log::test;
 
 const COMMENT_PREFIX: char = '#';
 
-fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
+/// Read file with comment lines blanked, so line numbers are kept.
+fn read_without_comments<P>(file_name: P) -> String
 where
     P: AsRef<Path>,
 {
-    let file = File::open(filename)?;
-    Ok(io::BufReader::new(file).lines())
+    fs::read_to_string(file_name)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            if line.starts_with(COMMENT_PREFIX) {
+                ""
+            } else {
+                line
+            }
+        })
+        .join("\n")
 }
 
+/// Read all JSON values in file along with the line number each value starts
+/// at. Values may span multiple lines; lines starting with `#` are comments.
 fn read_json_value<P>(file_name: P) -> Result<Vec<(usize, Value)>>
 where
     P: AsRef<Path>,
 {
+    let content = read_without_comments(file_name);
+
     let mut out = vec![];
-    let mut line_number = 0;
-    if let Ok(lines) = read_lines(file_name) {
-        for line in lines.flatten() {
-            line_number += 1;
-            if !line.is_empty() && !line.starts_with(COMMENT_PREFIX) {
-                let val = serde_json::from_str(&line)
-                    .map_err(|e| {
-                        Error::new(
-                            io::ErrorKind::InvalidInput,
-                            format!("parse line: {} failed. {}", line, e),
-                        );
-                    })
-                    .unwrap();
-                out.push((line_number, val));
-            }
-        }
+    let mut stream = serde_json::Deserializer::from_str(&content).into_iter::<Value>();
+    loop {
+        let start = stream.byte_offset();
+        let Some(val) = stream.next() else {
+            break;
+        };
+        let val = val
+            .map_err(|e| {
+                Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("parse value after offset: {} failed. {}", start, e),
+                )
+            })
+            .unwrap();
+        // skip whitespaces before the value to find the line it starts at
+        let value_start = start + content[start..].len() - content[start..].trim_start().len();
+        let line_number = content[..value_start].matches('\n').count() + 1;
+        out.push((line_number, val));
     }
     Ok(out)
 }
@@ -246,8 +262,8 @@ struct InvertOperationExecutor {
 impl Test<InvertOperationExecutor> for InvertOperationTest {
     fn test(&self, _: &InvertOperationExecutor) {
         assert_eq!(
-            *self.expect_invert_op.get(0).unwrap(),
-            self.origin_op.get(0).unwrap().invert().unwrap(),
+            self.expect_invert_op[0],
+            self.origin_op[0].invert().unwrap(),
             "invert failed"
         );
     }
@@ -461,3 +477,22 @@ fn test_other_transform_case() {
     let pattern = TransformTestPattern::new("tests/resources/other_transform_case.json");
     run_test(&pattern).unwrap();
 }
+
+#[test]
+fn test_read_operations() {
+    let json0 = Json0::new();
+    let file = "tests/resources/compose_op_case.json";
+    let expect = read_json_value(file)
+        .unwrap()
+        .into_iter()
+        .map(|(_, v)| json0.operation_factory().from_value(v).unwrap())
+        .collect_vec();
+
+    let oplog = read_without_comments(file);
+    let ops = json0
+        .operation_factory()
+        .read_operations(io::BufReader::new(oplog.as_bytes()))
+        .collect::<Result<Vec<Operation>>>()
+        .unwrap();
+    assert_eq!(expect, ops);
+}